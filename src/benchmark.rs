@@ -0,0 +1,58 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Timings of one model, see `GltfViewer::benchmark`
+pub struct BenchmarkResult {
+    pub file: String,
+    /// Parsing and GPU upload
+    pub load_time: Duration,
+    /// Per frame of the camera path
+    pub frame_times: Vec<Duration>,
+}
+
+impl BenchmarkResult {
+    fn frame_time_stats(&self) -> (Duration, Duration, Duration) {
+        if self.frame_times.is_empty() {
+            return (Duration::default(), Duration::default(), Duration::default())
+        }
+        let avg = self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32;
+        let min = *self.frame_times.iter().min().unwrap();
+        let max = *self.frame_times.iter().max().unwrap();
+        (avg, min, max)
+    }
+}
+
+/// Writes the results as CSV (times in milliseconds)
+pub fn write_csv<W: Write>(writer: &mut W, results: &[BenchmarkResult]) -> io::Result<()> {
+    writeln!(writer, "file,load_ms,frames,avg_frame_ms,min_frame_ms,max_frame_ms")?;
+    for result in results {
+        let (avg, min, max) = result.frame_time_stats();
+        writeln!(writer, "{},{:.3},{},{:.3},{:.3},{:.3}",
+            result.file, millis(result.load_time), result.frame_times.len(),
+            millis(avg), millis(min), millis(max))?;
+    }
+    Ok(())
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv() {
+        let results = [BenchmarkResult {
+            file: "Box.gltf".into(),
+            load_time: Duration::from_millis(120),
+            frame_times: vec![Duration::from_micros(1500), Duration::from_micros(2500)],
+        }];
+        let mut csv = vec![];
+        write_csv(&mut csv, &results).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(),
+            "file,load_ms,frames,avg_frame_ms,min_frame_ms,max_frame_ms\n\
+             Box.gltf,120.000,2,2.000,1.500,2.500\n");
+    }
+}
@@ -3,7 +3,7 @@ use std::f32::consts::PI;
 use cgmath::{vec3, Deg};
 use cgmath::prelude::*;
 
-use log::{warn, trace};
+use log::trace;
 use num_traits::clamp;
 
 // type Point3 = cgmath::Point3<f32>;
@@ -39,6 +39,8 @@ const ZOOM_SENSITIVITY: f32 = 0.1;
 pub const ZOOM: f32 = 45.0;
 const MIN_ZOOM: f32 = 1.0;
 const MAZ_ZOOM: f32 = 170.0;
+/// Scale of the orthographic view volume per (sensitivity-adjusted) scroll unit
+const ORTHO_ZOOM_FACTOR: f32 = 1.02;
 
 #[derive(Clone)]
 pub enum NavState {
@@ -208,8 +210,11 @@ impl OrbitControls {
             let distance = 50.0 * delta.y * target_distance / self.screen_size.height as f32;
             self.pan_up(-distance);
         } else {
-            // TODO!: orthographic camera pan
-            warn!("unimplemented: orthographic camera pan")
+            // the view volume is 2 * ymag high
+            let height = self.screen_size.height as f32 * self.camera.letterbox_scale().1;
+            let pixel_size = 2.0 * self.camera.ymag.unwrap() / height;
+            self.pan_left(-delta.x * pixel_size);
+            self.pan_up(-delta.y * pixel_size);
         }
     }
 
@@ -224,6 +229,12 @@ impl OrbitControls {
     // Processes input received from a mouse scroll-wheel event. Only requires input on the vertical wheel-axis
     pub fn process_mouse_scroll(&mut self, mut yoffset: f32) {
         yoffset *= ZOOM_SENSITIVITY;
+        if let Some(ymag) = self.camera.ymag {
+            // orthographic: scale the view volume instead of the field of view
+            self.camera.ymag = Some(ymag * ORTHO_ZOOM_FACTOR.powf(-yoffset));
+            self.camera.update_projection_matrix();
+            return
+        }
         if self.camera.fovy.0 >= MIN_ZOOM && self.camera.fovy.0 <= MAZ_ZOOM {
             self.camera.fovy.0 -= yoffset;
         }
@@ -0,0 +1,184 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::render::math::*;
+use crate::render::Vertex;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Obj,
+    /// ASCII PLY
+    Ply,
+}
+
+impl ExportFormat {
+    /// Determined by the file extension (`.obj` or `.ply`)
+    pub fn from_path(path: &str) -> Option<ExportFormat> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "obj" => Some(ExportFormat::Obj),
+            "ply" => Some(ExportFormat::Ply),
+            _ => None,
+        }
+    }
+}
+
+/// Triangles of all exported primitives, merged into one mesh in world space
+#[derive(Default)]
+pub struct MergedMesh {
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    tex_coords: Vec<Vector2>,
+    indices: Vec<u32>,
+}
+
+impl MergedMesh {
+    /// Appends a triangle list (non-indexed if `indices` is `None`), transforming the
+    /// positions by `transform` and the normals by its inverse transpose
+    pub fn add(&mut self, vertices: &[Vertex], indices: Option<&[u32]>, transform: &Matrix4) {
+        let normal_matrix = transform.invert().unwrap_or_else(Matrix4::identity).transpose();
+        let offset = self.positions.len() as u32;
+        for vertex in vertices {
+            self.positions.push(transform.transform_point(Point3::from_vec(vertex.position)).to_vec());
+            let normal = (normal_matrix * vertex.normal.extend(0.0)).truncate();
+            self.normals.push(if normal.magnitude2() > 0.0 { normal.normalize() } else { normal });
+            self.tex_coords.push(vertex.tex_coord_0);
+        }
+        let sequential_indices: Vec<u32>;
+        let indices = match indices {
+            Some(indices) => indices,
+            None => {
+                sequential_indices = (0..vertices.len() as u32).collect();
+                &sequential_indices
+            }
+        };
+        // mirroring transforms flip the winding order
+        let mirrored = transform.determinant() < 0.0;
+        for t in indices.chunks(3).filter(|t| t.len() == 3) {
+            if mirrored {
+                self.indices.extend(&[t[0] + offset, t[2] + offset, t[1] + offset]);
+            } else {
+                self.indices.extend(&[t[0] + offset, t[1] + offset, t[2] + offset]);
+            }
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W, format: ExportFormat) -> io::Result<()> {
+        match format {
+            ExportFormat::Obj => self.write_obj(writer),
+            ExportFormat::Ply => self.write_ply(writer),
+        }
+    }
+
+    /// Wavefront OBJ with positions, normals and UVs (V flipped, OBJ's origin is bottom left)
+    pub fn write_obj<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "# gltf-viewer export")?;
+        for p in &self.positions {
+            writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for n in &self.normals {
+            writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        for uv in &self.tex_coords {
+            writeln!(writer, "vt {} {}", uv.x, 1.0 - uv.y)?;
+        }
+        for t in self.indices.chunks(3) {
+            // OBJ indices start at 1
+            let (a, b, c) = (t[0] + 1, t[1] + 1, t[2] + 1);
+            writeln!(writer, "f {}/{}/{} {}/{}/{} {}/{}/{}", a, a, a, b, b, b, c, c, c)?;
+        }
+        Ok(())
+    }
+
+    /// ASCII PLY with positions, normals and UVs (V flipped like OBJ)
+    pub fn write_ply<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format ascii 1.0")?;
+        writeln!(writer, "comment gltf-viewer export")?;
+        writeln!(writer, "element vertex {}", self.positions.len())?;
+        for property in &["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+            writeln!(writer, "property float {}", property)?;
+        }
+        writeln!(writer, "element face {}", self.triangle_count())?;
+        writeln!(writer, "property list uchar uint vertex_indices")?;
+        writeln!(writer, "end_header")?;
+        for ((p, n), uv) in self.positions.iter().zip(&self.normals).zip(&self.tex_coords) {
+            writeln!(writer, "{} {} {} {} {} {} {} {}", p.x, p.y, p.z, n.x, n.y, n.z, uv.x, 1.0 - uv.y)?;
+        }
+        for t in self.indices.chunks(3) {
+            writeln!(writer, "3 {} {} {}", t[0], t[1], t[2])?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts the indices of a triangle strip or fan to a triangle list
+/// (`None` for points and lines)
+pub fn triangle_list(mode: u32, indices: &[u32]) -> Option<Vec<u32>> {
+    match mode {
+        gl::TRIANGLES => Some(indices.to_vec()),
+        gl::TRIANGLE_STRIP => Some((2..indices.len())
+            // every other triangle is reversed to keep the winding order consistent
+            .flat_map(|i| if i % 2 == 0 {
+                vec![indices[i - 2], indices[i - 1], indices[i]]
+            } else {
+                vec![indices[i - 1], indices[i - 2], indices[i]]
+            })
+            .collect()),
+        gl::TRIANGLE_FAN => Some((2..indices.len())
+            .flat_map(|i| vec![indices[0], indices[i - 1], indices[i]])
+            .collect()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Vec<Vertex> {
+        [vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)].iter()
+            .map(|&position| Vertex { position, normal: vec3(0.0, 0.0, 1.0), ..Vertex::default() })
+            .collect()
+    }
+
+    #[test]
+    fn obj() {
+        let mut mesh = MergedMesh::default();
+        mesh.add(&triangle(), None, &Matrix4::identity());
+        mesh.add(&triangle(), Some(&[0, 2, 1]), &(Matrix4::from_translation(vec3(0.0, 0.0, 2.0)) *
+            Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0)));
+        assert_eq!((mesh.vertex_count(), mesh.triangle_count()), (6, 2));
+
+        let mut obj = vec![];
+        mesh.write_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert!(obj.contains("\nv -1 0 2\n"));
+        assert!(obj.contains("\nvt 0 1\n"));
+        assert!(obj.ends_with("f 1/1/1 2/2/2 3/3/3\nf 4/4/4 5/5/5 6/6/6\n"));
+
+        let mut ply = vec![];
+        mesh.write_ply(&mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        assert!(ply.contains("element vertex 6\n"));
+        assert!(ply.ends_with("end_header\n0 0 0 0 0 1 0 1\n1 0 0 0 0 1 0 1\n0 1 0 0 0 1 0 1\n\
+            0 0 2 0 0 1 0 1\n-1 0 2 0 0 1 0 1\n0 1 2 0 0 1 0 1\n3 0 1 2\n3 3 4 5\n"));
+    }
+
+    #[test]
+    fn strips_and_fans() {
+        assert_eq!(triangle_list(gl::TRIANGLE_STRIP, &[0, 1, 2, 3]), Some(vec![0, 1, 2, 2, 1, 3]));
+        assert_eq!(triangle_list(gl::TRIANGLE_FAN, &[0, 1, 2, 3]), Some(vec![0, 1, 2, 0, 2, 3]));
+        assert_eq!(triangle_list(gl::LINES, &[0, 1]), None);
+        assert_eq!(ExportFormat::from_path("out/Scene.OBJ"), Some(ExportFormat::Obj));
+        assert_eq!(ExportFormat::from_path("scene.stl"), None);
+    }
+}
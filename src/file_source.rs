@@ -0,0 +1,28 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::importdata::Source;
+
+/// Source for glTF files on the local file system. External buffers and images
+/// are resolved relative to the directory of the file.
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new<P: AsRef<Path>>(path: P) -> FileSource {
+        FileSource { path: path.as_ref().to_owned() }
+    }
+}
+
+impl Source for FileSource {
+    fn source_gltf(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.path)
+    }
+
+    fn source_external_data(&self, uri: &str) -> io::Result<Vec<u8>> {
+        let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        fs::read(dir.join(uri))
+    }
+}
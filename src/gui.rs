@@ -0,0 +1,261 @@
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use egui::{Color32, Event, Modifiers, PointerButton, Pos2, Rect, Slider, Vec2};
+use egui::color_picker::{self, Alpha};
+use glutin::dpi::PhysicalSize;
+use glutin::{MouseButton, VirtualKeyCode};
+
+use crate::render::{Material, ToneMapping};
+use crate::render::math::*;
+use crate::shader::LightOptions;
+use crate::window::{InputEvent, WindowBackend};
+
+/// Viewer settings shown in the inspector (the viewer applies the changed ones)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InspectorValues {
+    /// Manual exposure in stops
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+    pub lights: LightOptions,
+    pub background: [f32; 4],
+}
+
+/// egui panel drawn over the scene for tweaking the exposure, tone mapping, lights, background
+/// and the factors of the materials while viewing (`gui` feature). Material changes are stored
+/// as overrides in `Material::animated`, like animated factors.
+pub struct Inspector {
+    context: egui::Context,
+    painter: egui_glow::Painter,
+    /// Input since the last frame
+    events: Vec<Event>,
+    pointer_position: Pos2,
+    start_time: Instant,
+    pub visible: bool,
+}
+
+impl Inspector {
+    /// Creates the panel for the current GL context of `backend`
+    pub fn new(backend: &dyn WindowBackend) -> Inspector {
+        let gl = unsafe { glow::Context::from_loader_function(|symbol| backend.proc_address(symbol)) };
+        let painter = egui_glow::Painter::new(Arc::new(gl), "", None, false)
+            .unwrap_or_else(|err| panic!("Failed to create the inspector panel: {}", err));
+        Inspector {
+            context: egui::Context::default(),
+            painter,
+            events: vec![],
+            pointer_position: Pos2::ZERO,
+            start_time: Instant::now(),
+            visible: true,
+        }
+    }
+
+    /// Passes `event` on to the panel. Returns whether it is meant only for the panel, e.g. a
+    /// click on it or typing a value (releases always reach the viewer too).
+    pub fn handle_event(&mut self, event: &InputEvent, dpi_factor: f64) -> bool {
+        if !self.visible {
+            return false
+        }
+        let wants_pointer = self.context.egui_wants_pointer_input();
+        let wants_keyboard = self.context.egui_wants_keyboard_input();
+        match *event {
+            InputEvent::CursorMoved(position) => {
+                self.pointer_position = Pos2::new((position.x / dpi_factor) as f32, (position.y / dpi_factor) as f32);
+                self.events.push(Event::PointerMoved(self.pointer_position));
+                false
+            }
+            InputEvent::MouseButton { button, pressed } => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                self.events.push(Event::PointerButton {
+                    pos: self.pointer_position, button, pressed, modifiers: Modifiers::default()
+                });
+                pressed && wants_pointer
+            }
+            InputEvent::Scroll(delta) if wants_pointer => {
+                // 3 per line, see `GlutinWindow`
+                self.events.push(Event::MouseWheel {
+                    unit: egui::MouseWheelUnit::Line,
+                    delta: Vec2::new(0.0, delta / 3.0),
+                    phase: egui::TouchPhase::Move,
+                    modifiers: Modifiers::default(),
+                });
+                true
+            }
+            InputEvent::Key { key, pressed } if wants_keyboard => {
+                if let Some(key) = editing_key(key) {
+                    self.events.push(Event::Key {
+                        key, physical_key: None, pressed, repeat: false, modifiers: Modifiers::default()
+                    });
+                }
+                pressed
+            }
+            InputEvent::Character(c) if wants_keyboard => {
+                if !c.is_control() {
+                    self.events.push(Event::Text(c.to_string()));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Draws the panel into the bound framebuffer, editing `values` and the factors of `materials`
+    pub fn draw(&mut self, size: PhysicalSize, dpi_factor: f64, values: &mut InspectorValues,
+        materials: &[Rc<Material>])
+    {
+        if !self.visible {
+            self.events.clear();
+            return
+        }
+        let pixels_per_point = dpi_factor as f32;
+        let screen_size = Vec2::new(size.width as f32, size.height as f32) / pixels_per_point;
+        let mut input = egui::RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, screen_size)),
+            max_texture_side: Some(self.painter.max_texture_side()),
+            time: Some(self.start_time.elapsed().as_secs_f64()),
+            events: self.events.drain(..).collect(),
+            ..egui::RawInput::default()
+        };
+        input.viewports.entry(input.viewport_id).or_default().native_pixels_per_point = Some(pixels_per_point);
+
+        let output = self.context.run_ui(input, |ui| {
+            let context = ui.ctx().clone();
+            egui::Window::new("Inspector (`)")
+                .default_width(260.0)
+                .show(&context, |ui| inspector_ui(ui, values, materials));
+        });
+        let primitives = self.context.tessellate(output.shapes, output.pixels_per_point);
+        let mut textures_delta = output.textures_delta;
+        self.painter.paint_and_update_textures([size.width as u32, size.height as u32],
+            output.pixels_per_point, &primitives, &mut textures_delta);
+
+        // the painter changes these
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+        }
+    }
+}
+
+impl Drop for Inspector {
+    fn drop(&mut self) {
+        self.painter.destroy();
+    }
+}
+
+fn inspector_ui(ui: &mut egui::Ui, values: &mut InspectorValues, materials: &[Rc<Material>]) {
+    ui.add(Slider::new(&mut values.exposure, -8.0..=8.0).text("Exposure (stops)"));
+    egui::ComboBox::from_label("Tone mapping")
+        .selected_text(format!("{:?}", values.tone_mapping))
+        .show_ui(ui, |ui| {
+            for &tone_mapping in &[ToneMapping::None, ToneMapping::Reinhard, ToneMapping::Aces] {
+                ui.selectable_value(&mut values.tone_mapping, tone_mapping, format!("{:?}", tone_mapping));
+            }
+        });
+
+    ui.separator();
+    let lights = &mut values.lights;
+    let direction: &mut [f32; 3] = lights.direction.as_mut();
+    for (axis, value) in ["x", "y", "z"].iter().zip(direction) {
+        ui.add(Slider::new(value, -1.0..=1.0).text(format!("Light direction {}", axis)));
+    }
+    // the intensity is part of the color (its largest component)
+    let old_intensity = lights.color.x.max(lights.color.y).max(lights.color.z);
+    let mut intensity = old_intensity;
+    if ui.add(Slider::new(&mut intensity, 0.0..=20.0).text("Light intensity")).changed() {
+        let hue = if old_intensity > 0.0 { lights.color / old_intensity } else { vec3(1.0, 1.0, 1.0) };
+        lights.color = hue * intensity;
+    }
+    ui.add(Slider::new(&mut lights.ambient_intensity, 0.0..=2.0).text("Ambient intensity"));
+
+    // the clear color is used as is, i.e. like sRGB
+    let [r, g, b, a] = values.background.map(|channel| (channel * 255.0).round() as u8);
+    let mut background = Color32::from_rgba_unmultiplied(r, g, b, a);
+    ui.horizontal(|ui| {
+        if color_picker::color_edit_button_srgba(ui, &mut background, Alpha::OnlyBlend).changed() {
+            values.background = background.to_srgba_unmultiplied().map(|channel| f32::from(channel) / 255.0);
+        }
+        ui.label("Background");
+    });
+
+    ui.separator();
+    egui::CollapsingHeader::new(format!("Materials ({})", materials.len())).show(ui, |ui| {
+        egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+            for (i, material) in materials.iter().enumerate() {
+                ui.push_id(i, |ui| material_ui(ui, material));
+            }
+        });
+    });
+}
+
+/// Base color, metallic and roughness factor of a material
+fn material_ui(ui: &mut egui::Ui, material: &Material) {
+    let title = format!("{} {}",
+        material.index.map_or("default".to_owned(), |index| index.to_string()),
+        material.name.as_deref().unwrap_or(""));
+    ui.collapsing(title, |ui| {
+        let mut factors = material.animated.get();
+        let mut base_color: [f32; 4] = factors.base_color_factor.unwrap_or(material.base_color_factor).into();
+        let mut metallic = factors.metallic_factor.unwrap_or(material.metallic_factor);
+        let mut roughness = factors.roughness_factor.unwrap_or(material.roughness_factor);
+
+        let mut changed = ui.horizontal(|ui| {
+            let changed = ui.color_edit_button_rgba_unmultiplied(&mut base_color).changed();
+            ui.label("Base color");
+            changed
+        }).inner;
+        changed |= ui.add(Slider::new(&mut metallic, 0.0..=1.0).text("Metallic")).changed();
+        changed |= ui.add(Slider::new(&mut roughness, 0.0..=1.0).text("Roughness")).changed();
+        if changed {
+            factors.base_color_factor = Some(base_color.into());
+            factors.metallic_factor = Some(metallic);
+            factors.roughness_factor = Some(roughness);
+            material.animated.set(factors);
+        }
+    });
+}
+
+/// Keys for editing values in the panel
+fn editing_key(key: VirtualKeyCode) -> Option<egui::Key> {
+    Some(match key {
+        VirtualKeyCode::Back => egui::Key::Backspace,
+        VirtualKeyCode::Delete => egui::Key::Delete,
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => egui::Key::Enter,
+        VirtualKeyCode::Escape => egui::Key::Escape,
+        VirtualKeyCode::Tab => egui::Key::Tab,
+        VirtualKeyCode::Left => egui::Key::ArrowLeft,
+        VirtualKeyCode::Right => egui::Key::ArrowRight,
+        VirtualKeyCode::Home => egui::Key::Home,
+        VirtualKeyCode::End => egui::Key::End,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_change_only_when_edited() {
+        // (no GL needed for the layout)
+        let context = egui::Context::default();
+        let original = InspectorValues {
+            exposure: 0.5,
+            tone_mapping: ToneMapping::Aces,
+            lights: LightOptions::default(),
+            background: [0.1, 0.2, 0.3, 1.0],
+        };
+        let mut values = original;
+        for _ in 0..2 {
+            let output = context.run_ui(egui::RawInput::default(), |ui| inspector_ui(ui, &mut values, &[]));
+            output.drop_without_applying_deltas();
+        }
+        assert_eq!(values, original);
+    }
+}
@@ -1,97 +1,166 @@
-use std;
-use std::boxed::Box;
-use std::io::{self, Read, Write};
 use std::fmt;
+use std::io::{self, Read};
+use std::ops::Range;
 
-use futures::Future;
+use log::{info, warn};
+use url::Url;
 
-extern crate futures_cpupool;
-use self::futures_cpupool::CpuPool;
-
-use gltf::import::{Source};
-
-extern crate reqwest;
+use crate::importdata::Source;
 
+/// Source for glTF files on a web server. External buffers and images are resolved
+/// relative to the URL of the file. Only the used parts of large buffers are downloaded
+/// (HTTP Range requests) if the server supports it.
 pub struct HttpSource {
-    url: reqwest::Url,
-    cpu_pool: CpuPool,
+    url: Url,
+    agent: ureq::Agent,
 }
 
 impl fmt::Debug for HttpSource {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "HttpSource {{ url: {} }}", self.url)
     }
 }
 
 impl HttpSource {
     pub fn new(url: &str) -> HttpSource {
-        // Use 8 threads - like max parallel requests per domain in some browsers
-        // Seems to be a sweet spot (tested with VC.gltf)
-        let pool = CpuPool::new(8);
-
         HttpSource {
-            url: reqwest::Url::parse(url)
-                .expect("Failed to parse URL"),
-            cpu_pool: pool
+            url: Url::parse(url).expect("Failed to parse URL"),
+            agent: ureq::Agent::new(),
         }
     }
-}
 
-#[derive(Debug)]
-pub enum Error {
-    HttpError(String),
-}
+    fn resolve(&self, uri: &str) -> io::Result<Url> {
+        self.url.join(uri).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("invalid uri '{}': {}", uri, err)))
+    }
 
-impl HttpSource {
-    fn fetch_data(&self, url: String) -> Box<Future<Item=Box<[u8]>, Error=Error>> {
-        let future = self.cpu_pool.spawn_fn(move || {
-            let mut resp = reqwest::get(&url)
-                .expect(&format!("Network problem on GET {}", url));
-            if !resp.status().is_success() {
-                return Err(Error::HttpError(format!("{}: {}", resp.status(), url)));
+    /// GET request, `range`: only these bytes. The status is 206 if the range was respected.
+    fn get(&self, url: &Url, range: Option<&Range<usize>>) -> io::Result<(u16, Vec<u8>)> {
+        let mut request = self.agent.get(url.as_str());
+        if let Some(range) = range {
+            // the end of HTTP ranges is inclusive
+            request = request.set("Range", &format!("bytes={}-{}", range.start, range.end - 1));
+        }
+        let response = request.call().map_err(|err| http_error(url, err))?;
+        let status = response.status();
+        let mut data = vec![];
+        response.into_reader().read_to_end(&mut data)?;
+        Ok((status, data))
+    }
+
+    /// Whether the server announces `Accept-Ranges: bytes` for the resource
+    fn accepts_ranges(&self, url: &Url) -> bool {
+        match self.agent.head(url.as_str()).call() {
+            Ok(response) => response.header("Accept-Ranges").is_some_and(|value| value.trim() == "bytes"),
+            Err(err) => {
+                warn!("{}", http_error(url, err));
+                false
             }
-            let mut data = vec![];
-            let _ = resp.read_to_end(&mut data);
-            print!(".");
-            let _ = io::stdout().flush();
-            Ok(data.into_boxed_slice())
-        });
-        Box::new(future)
+        }
     }
 }
 
+fn http_error(url: &Url, err: ureq::Error) -> io::Error {
+    let kind = match err {
+        ureq::Error::Status(404, _) => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("GET {} failed: {}", url, err))
+}
+
 impl Source for HttpSource {
-    type Error = Error;
-    fn source_gltf(&self) -> Box<Future<Item=Box<[u8]>, Error=Self::Error>> {
-        println!("Downloading");
-        let _ = io::stdout().flush();
-        self.fetch_data(self.url.to_string())
+    fn source_gltf(&self) -> io::Result<Vec<u8>> {
+        info!("Downloading {}", self.url);
+        self.get(&self.url, None).map(|(_, data)| data)
     }
 
-    fn source_external_data(&self, uri: &str) -> Box<Future<Item=Box<[u8]>, Error=Self::Error>> {
-        let mut new_url = self.url.clone();
-        new_url.path_segments_mut()
-            .expect("URL cannot be base")
-            .pop().push(uri);
-        self.fetch_data(new_url.to_string())
+    fn source_external_data(&self, uri: &str) -> io::Result<Vec<u8>> {
+        self.get(&self.resolve(uri)?, None).map(|(_, data)| data)
     }
-}
 
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::HttpError(ref status) => status
+    fn source_external_ranges(&self, uri: &str, length: usize, ranges: &[Range<usize>]) -> io::Result<Vec<u8>> {
+        let url = self.resolve(uri)?;
+        if !self.accepts_ranges(&url) {
+            return self.get(&url, None).map(|(_, data)| data)
+        }
+        let mut data = vec![0; length];
+        for range in ranges {
+            match self.get(&url, Some(range))? {
+                (206, part) if part.len() == range.len() => data[range.clone()].copy_from_slice(&part),
+                (206, part) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                    format!("GET {} (bytes {:?}) returned {} bytes", url, range, part.len()))),
+                // the range was ignored after all
+                (_, all) => return Ok(all),
+            }
         }
+        let downloaded: usize = ranges.iter().map(|range| range.len()).sum();
+        info!("Downloaded {} of {} bytes of {} ({} range requests)", downloaded, length, uri, ranges.len());
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// Serves `data` on a local port (for any path), honoring Range requests if `ranges` is set.
+    /// Returns the base URL and the log of the requests ("<method> <range>").
+    fn serve(data: Vec<u8>, ranges: bool) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let log = Arc::new(Mutex::new(vec![]));
+        let requests = log.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines().map(Result::unwrap);
+                let method = lines.next().unwrap().split(' ').next().unwrap().to_owned();
+                let range = lines.by_ref()
+                    .take_while(|line| !line.is_empty())
+                    .find_map(|line| line.strip_prefix("Range: bytes=").map(str::to_owned));
+                requests.lock().unwrap().push(format!("{} {}", method, range.as_deref().unwrap_or("-")));
+
+                let (status, body) = match range.filter(|_| ranges) {
+                    Some(range) => {
+                        let (start, end) = range.split_once('-').unwrap();
+                        let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+                        ("206 Partial Content", &data[start..=end])
+                    }
+                    None => ("200 OK", &data[..]),
+                };
+                let accept_ranges = if ranges { "Accept-Ranges: bytes\r\n" } else { "" };
+                write!(stream, "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status, accept_ranges, body.len()).unwrap();
+                if method != "HEAD" {
+                    stream.write_all(body).unwrap();
+                }
+            }
+        });
+        (url, log)
     }
 
-    fn cause(&self) -> Option<&std::error::Error> {
-        None
+    #[test]
+    fn range_requests() {
+        let data: Vec<u8> = (0..100).collect();
+        let (url, log) = serve(data.clone(), true);
+        let source = HttpSource::new(&format!("{}model.gltf", url));
+        let buffer = source.source_external_ranges("model.bin", 100, &[2..4, 90..100]).unwrap();
+        assert_eq!(buffer[2..4], data[2..4]);
+        assert_eq!(buffer[90..], data[90..]);
+        assert!(buffer[4..90].iter().all(|&byte| byte == 0));
+        assert_eq!(*log.lock().unwrap(), ["HEAD -", "GET 2-3", "GET 90-99"]);
     }
-}
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use std::error::Error;
-        write!(f, "{}", self.description())
+    #[test]
+    fn full_download_without_range_support() {
+        let data: Vec<u8> = (0..100).collect();
+        let (url, log) = serve(data.clone(), false);
+        let source = HttpSource::new(&format!("{}model.gltf", url));
+        assert_eq!(source.source_external_ranges("model.bin", 100, &[2..4, 90..100]).unwrap(), data);
+        assert_eq!(*log.lock().unwrap(), ["HEAD -", "GET -"]);
     }
 }
@@ -1,9 +1,540 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
 use gltf;
+use gltf::image::Format;
+use image::DynamicImage::*;
+use image::{GenericImageView, ImageFormat};
+use log::warn;
+use serde_json::Value;
+
+use gltf::json::mesh::Semantic;
+use gltf::json::validation::Checked;
+
+use crate::render::is_pointer_channel;
+
+/// Settings that affect how a file is loaded
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    /// Skip primitives, nodes and textures that fail to load (see `ImportData::skip_on_error`)
+    /// instead of aborting
+    pub safe_mode: bool,
+    /// Maximum angle (in degrees) between adjacent faces that share a smoothed normal
+    /// when generating missing normals (0: flat shading)
+    pub smooth_angle: f32,
+    /// Textures larger than this (in either dimension) are downscaled before the upload
+    pub max_texture_size: Option<u32>,
+    /// Upload only the coarse mip levels at first and stream the finer ones over the
+    /// following frames (see `Scene::stream_textures`)
+    pub stream_textures: bool,
+    /// Number of rays per vertex for baking ambient occlusion into the vertices
+    /// (see `bake_vertex_occlusion`, `None`: disabled)
+    pub vertex_ao_samples: Option<u32>,
+    /// Fraction of the triangles to keep in simplified preview meshes, which are shown
+    /// instead of the originals (see `Primitive::simplify`, `None`: disabled)
+    pub simplify: Option<f32>,
+    /// Skip all images and materials (every primitive uses the default material), for quickly
+    /// checking the shape of texture-heavy assets
+    pub geometry_only: bool,
+    /// Replace non-finite (NaN or infinite) vertex positions, normals and animation samples
+    /// with a warning (see `sanitize_vertices` and `Sampler::sanitize`)
+    pub sanitize: bool,
+    /// Custom vertex attribute with feature or batch ids (e.g. `_BATCHID` of 3D Tiles), which
+    /// picking reports. `None`: `_FEATURE_ID_0` or `_BATCHID`, see `DEFAULT_FEATURE_ID_ATTRIBUTES`
+    pub feature_id_attribute: Option<String>,
+    pub default_material: DefaultMaterial,
+}
+
+/// Overrides of the glTF default material (white, fully metallic and rough),
+/// which is used for primitives without a material
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultMaterial {
+    /// Linear RGB
+    pub base_color: Option<[f32; 3]>,
+    pub metallic: Option<f32>,
+    pub roughness: Option<f32>,
+}
+
+/// How far loading has progressed, see `import_source_with_progress` and `Root::from_gltf_with_progress`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// One of `LOAD_STAGES`
+    pub stage: &'static str,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Stages of loading a file in order
+pub const LOAD_STAGES: [&str; 3] = ["buffers", "images", "nodes"];
+
+impl Progress {
+    /// Overall progress (0-1), assuming all stages take about equally long
+    pub fn fraction(&self) -> f32 {
+        let stage = LOAD_STAGES.iter().position(|&stage| stage == self.stage).unwrap_or(0);
+        let stage_fraction = if self.total > 0 { self.done as f32 / self.total as f32 } else { 1.0 };
+        (stage as f32 + stage_fraction) / LOAD_STAGES.len() as f32
+    }
+}
 
 /// Helps to simplify the signature of import related functions.
 pub struct ImportData {
     pub doc: gltf::Document,
     pub buffers: Vec<gltf::buffer::Data>,
     pub images: Vec<gltf::image::Data>,
+
+    /// Raw JSON of the document, for extensions not supported by the `gltf` crate
+    pub json: Value,
+
+    pub options: ImportOptions,
+    /// Number of items skipped in safe mode
+    pub skipped: Cell<usize>,
+    /// Textures that failed to load in safe mode, so that they aren't tried (and counted) again
+    /// for every material slot that uses them
+    pub failed_textures: RefCell<HashSet<usize>>,
+}
+
+impl ImportData {
+    pub fn new(doc: gltf::Document, buffers: Vec<gltf::buffer::Data>, images: Vec<gltf::image::Data>, json: Value)
+        -> ImportData
+    {
+        ImportData { doc, buffers, images, json, options: ImportOptions::default(), skipped: Cell::new(0),
+            failed_textures: RefCell::new(HashSet::new()) }
+    }
+
+    /// Returns the loaded item. An error is logged and the item (described by `what`) is
+    /// skipped in safe mode, otherwise loading is aborted with a panic.
+    pub fn skip_on_error<T>(&self, what: &str, result: Result<T, String>) -> Option<T> {
+        match result {
+            Ok(item) => Some(item),
+            Err(err) if self.options.safe_mode => {
+                warn!("Skipping {}: {}", what, err);
+                self.skipped.set(self.skipped.get() + 1);
+                None
+            }
+            Err(err) => panic!("Failed to load {}: {}", what, err),
+        }
+    }
+
+    /// Returns the JSON of extension `name` of the object with the given index in
+    /// `collection` (e.g. "materials").
+    pub fn extension(&self, collection: &str, index: usize, name: &str) -> Option<&Value> {
+        self.json.get(collection)?
+            .get(index)?
+            .get("extensions")?
+            .get(name)
+    }
+}
+
+/// Parses the raw JSON of a .gltf or .glb file
+pub fn read_json_from_slice(data: &[u8]) -> Value {
+    let json = if data.starts_with(b"glTF") {
+        match gltf::binary::Glb::from_slice(data) {
+            Ok(glb) => serde_json::from_slice(&glb.json),
+            Err(err) => {
+                warn!("Failed to parse GLB for extension parsing: {:?}", err);
+                return Value::Null
+            }
+        }
+    } else {
+        serde_json::from_slice(data)
+    };
+    json.unwrap_or_else(|err| {
+        warn!("Failed to parse JSON for extension parsing: {}", err);
+        Value::Null
+    })
+}
+
+/// Provides the data of a glTF asset, for loading it from somewhere else than
+/// the file system (see `import_source`).
+pub trait Source {
+    /// Returns the main document (.gltf or .glb)
+    fn source_gltf(&self) -> io::Result<Vec<u8>>;
+
+    /// Returns the data of a buffer or image referenced by `uri` (not a data URI)
+    fn source_external_data(&self, uri: &str) -> io::Result<Vec<u8>>;
+
+    /// Returns `length` bytes of the buffer referenced by `uri`, of which only `ranges` (sorted,
+    /// not overlapping) are used. Sources that can fetch parts of the data (e.g. `HttpSource`)
+    /// may leave the rest zeroed, the default implementation reads everything.
+    fn source_external_ranges(&self, uri: &str, _length: usize, _ranges: &[Range<usize>]) -> io::Result<Vec<u8>> {
+        self.source_external_data(uri)
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Gltf(gltf::Error),
+    Io(io::Error),
+    Base64(base64::DecodeError),
+    Image(image::ImageError),
+    /// The `BIN` chunk of a .glb is referenced but doesn't exist
+    MissingBlob,
+    BufferLength { buffer: usize, expected: usize, actual: usize },
+    /// The buffer view of an image exceeds its buffer
+    ImageView { image: usize },
+    /// `asset.version` or `asset.minVersion` is newer than `SUPPORTED_VERSION`
+    UnsupportedVersion { version: String, generator: Option<String> },
+    /// The requested scene doesn't exist (files without scenes have only an empty scene 0)
+    SceneIndex { index: usize, count: usize },
 }
 
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Gltf(err) => write!(f, "{:?}", err),
+            ImportError::Io(err) => write!(f, "{}", err),
+            ImportError::Base64(err) => write!(f, "invalid data URI: {}", err),
+            ImportError::Image(err) => write!(f, "image decoding failed: {}", err),
+            ImportError::MissingBlob => write!(f, "missing BIN section of binary glTF"),
+            ImportError::BufferLength { buffer, expected, actual } =>
+                write!(f, "buffer {} is too short (expected {} bytes, got {})", buffer, expected, actual),
+            ImportError::ImageView { image } => write!(f, "buffer view of image {} is out of bounds", image),
+            ImportError::UnsupportedVersion { version, generator } => {
+                write!(f, "the file requires glTF {} (supported: {}.{})", version,
+                    SUPPORTED_VERSION.0, SUPPORTED_VERSION.1)?;
+                if let Some(generator) = generator {
+                    write!(f, ", written by {}", generator)?;
+                }
+                Ok(())
+            }
+            ImportError::SceneIndex { index, count } =>
+                write!(f, "scene index {} is too high, the file has only {} scene(s)", index, count),
+        }
+    }
+}
+
+/// Like `gltf::import`, but reads all data from `source`
+#[allow(dead_code)]
+pub fn import_source(source: &dyn Source) -> Result<ImportData, ImportError> {
+    import_source_with_progress(source, ImportOptions::default(), &mut |_| ())
+}
+
+/// Like `import_source`, with the given `options` (images are skipped with `geometry_only`)
+/// and calling `on_progress` after each buffer and image
+pub fn import_source_with_progress(source: &dyn Source, options: ImportOptions, on_progress: &mut dyn FnMut(Progress))
+    -> Result<ImportData, ImportError>
+{
+    let data = source.source_gltf().map_err(ImportError::Io)?;
+    let json = read_json_from_slice(&data);
+    check_version(&json["asset"])?;
+    let gltf::Gltf { document, mut blob } = match without_unsupported(&json) {
+        Some(sanitized) => {
+            let root = gltf::json::deserialize::from_value(sanitized)
+                .map_err(|err| ImportError::Gltf(err.into()))?;
+            let document = gltf::Document::from_json(root).map_err(ImportError::Gltf)?;
+            let blob = if data.starts_with(b"glTF") {
+                gltf::binary::Glb::from_slice(&data).map_err(ImportError::Gltf)?.bin.map(|bin| bin.into_owned())
+            } else {
+                None
+            };
+            gltf::Gltf { document, blob }
+        }
+        None => gltf::Gltf::from_slice(&data).map_err(ImportError::Gltf)?,
+    };
+
+    let buffer_count = document.buffers().len();
+    let buffers = document.buffers()
+        .map(|buffer| {
+            on_progress(Progress { stage: "buffers", done: buffer.index(), total: buffer_count });
+            let mut data = match buffer.source() {
+                gltf::buffer::Source::Uri(uri) => match read_buffer_uri(source, &document, &buffer, uri, &options) {
+                    Ok(data) => data,
+                    // hand-edited .glb files sometimes still reference the file packed into the BIN chunk
+                    Err(err) if buffer.index() == 0 && blob.is_some() => {
+                        warn!("Failed to load buffer 0 ({}): {}, using the BIN chunk instead", display_uri(uri), err);
+                        blob.take().unwrap()
+                    }
+                    Err(err) => return Err(err),
+                },
+                gltf::buffer::Source::Bin => blob.take().ok_or(ImportError::MissingBlob)?,
+            };
+            if data.len() < buffer.length() {
+                return Err(ImportError::BufferLength {
+                    buffer: buffer.index(),
+                    expected: buffer.length(),
+                    actual: data.len(),
+                })
+            }
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+            Ok(gltf::buffer::Data(data))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // missing or undecodable images are replaced by a placeholder, so that the rest of the asset is usable
+    let image_count = if options.geometry_only { 0 } else { document.images().len() };
+    let images = document.images()
+        .take(image_count)
+        .inspect(|image| on_progress(Progress { stage: "images", done: image.index(), total: image_count }))
+        .map(|image| match image.source() {
+            // embedded in a buffer (e.g. the BIN chunk of a .glb)
+            gltf::image::Source::View { view, mime_type } => {
+                let begin = view.offset();
+                let decoded = begin.checked_add(view.length())
+                    .and_then(|end| buffers[view.buffer().index()].0.get(begin..end))
+                    .ok_or(ImportError::ImageView { image: image.index() })
+                    .and_then(|data| decode_image(data, Some(mime_type)));
+                // not valid glTF, but images packed into a buffer by hand sometimes keep their uri
+                let uri = json["images"][image.index()]["uri"].as_str();
+                match (decoded, uri) {
+                    (Ok(data), _) => Ok(data),
+                    (Err(err), Some(uri)) => {
+                        warn!("Failed to load image {} (buffer view {}): {}, using its uri ({}) instead",
+                            image.index(), view.index(), err, display_uri(uri));
+                        Ok(load_image_uri(source, image.index(), uri, Some(mime_type)))
+                    }
+                    (Err(err @ ImportError::ImageView { .. }), None) => Err(err),
+                    (Err(err), None) => {
+                        warn!("Failed to load image {} (buffer view {}): {}", image.index(), view.index(), err);
+                        Ok(missing_image())
+                    }
+                }
+            }
+            gltf::image::Source::Uri { uri, mime_type } => Ok(load_image_uri(source, image.index(), uri, mime_type)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut imp = ImportData::new(document, buffers, images, json);
+    imp.options = options;
+    Ok(imp)
+}
+
+/// Newest glTF version the viewer implements
+const SUPPORTED_VERSION: (u32, u32) = (2, 0);
+
+/// Refuses files that require a newer glTF version (`minVersion`, or another major `version`).
+/// Newer minor versions are backwards compatible, so they only cause a warning.
+fn check_version(asset: &Value) -> Result<(), ImportError> {
+    if asset.is_null() {
+        return Ok(()) // raw JSON not available
+    }
+    let generator = asset["generator"].as_str().map(|s| s.to_owned());
+    let unsupported = |version: &str| ImportError::UnsupportedVersion {
+        version: version.to_owned(),
+        generator: generator.clone(),
+    };
+    if let Some(min_version) = asset["minVersion"].as_str() {
+        match parse_version(min_version) {
+            Some(min) if min > SUPPORTED_VERSION => return Err(unsupported(min_version)),
+            Some(_) => (),
+            None => warn!("Invalid asset.minVersion '{}'", min_version),
+        }
+    }
+    let version = asset["version"].as_str().unwrap_or("");
+    match parse_version(version) {
+        Some((major, _)) if major != SUPPORTED_VERSION.0 => return Err(unsupported(version)),
+        Some(parsed) if parsed > SUPPORTED_VERSION => {
+            warn!("The file uses glTF {} (supported: {}.{}){}, newer features may not render correctly",
+                version, SUPPORTED_VERSION.0, SUPPORTED_VERSION.1,
+                generator.as_ref().map(|g| format!(", written by {}", g)).unwrap_or_default());
+        }
+        Some(_) => (),
+        None => warn!("Invalid asset.version '{}', assuming glTF {}.{}", version,
+            SUPPORTED_VERSION.0, SUPPORTED_VERSION.1),
+    }
+    Ok(())
+}
+
+/// Parses `<major>.<minor>`
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.splitn(2, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Copy of the document without the parts the `gltf` crate rejects (`None` if there are none):
+/// KHR_animation_pointer channels (they have no target node, see `Animation::from_gltf`) and
+/// vertex attributes with unknown names (custom ones must start with an underscore)
+fn without_unsupported(json: &Value) -> Option<Value> {
+    let channels = || json["animations"].as_array().into_iter().flatten()
+        .filter_map(|animation| animation["channels"].as_array())
+        .flatten();
+    let attributes = || json["meshes"].as_array().into_iter().flatten()
+        .filter_map(|mesh| mesh["primitives"].as_array())
+        .flatten()
+        .filter_map(|primitive| primitive["attributes"].as_object())
+        .flat_map(|attributes| attributes.keys());
+    if !channels().any(is_pointer_channel) && attributes().all(|name| is_valid_attribute(name)) {
+        return None
+    }
+    let mut json = json.clone();
+    // (indexing mutably would insert missing properties)
+    for animation in json.get_mut("animations").and_then(Value::as_array_mut).into_iter().flatten() {
+        if let Some(channels) = animation["channels"].as_array_mut() {
+            channels.retain(|channel| !is_pointer_channel(channel));
+        }
+    }
+    for (mesh_index, mesh) in json.get_mut("meshes").and_then(Value::as_array_mut).into_iter().flatten().enumerate() {
+        let primitives = mesh.get_mut("primitives").and_then(Value::as_array_mut);
+        for (primitive_index, primitive) in primitives.into_iter().flatten().enumerate() {
+            if let Some(attributes) = primitive.get_mut("attributes").and_then(Value::as_object_mut) {
+                let unknown: Vec<String> = attributes.keys().filter(|name| !is_valid_attribute(name)).cloned().collect();
+                for name in unknown {
+                    warn!("Ignoring unknown attribute {} of primitive {} of mesh {} (custom attributes must \
+                           start with an underscore)", name, primitive_index, mesh_index);
+                    attributes.remove(&name);
+                }
+            }
+        }
+    }
+    Some(json)
+}
+
+fn is_valid_attribute(name: &str) -> bool {
+    let semantic: Result<Checked<Semantic>, _> = serde_json::from_value(Value::String(name.to_owned()));
+    matches!(semantic, Ok(Checked::Valid(_)))
+}
+
+/// Reads and decodes an image, or returns the placeholder if that fails
+fn load_image_uri(source: &dyn Source, index: usize, uri: &str, mime_type: Option<&str>) -> gltf::image::Data {
+    read_uri(source, uri)
+        .and_then(|data| decode_image(&data, mime_type))
+        .unwrap_or_else(|err| {
+            warn!("Failed to load image {} ({}): {}", index, display_uri(uri), err);
+            missing_image()
+        })
+}
+
+/// The uri for log messages (data URIs would be too long)
+fn display_uri(uri: &str) -> &str {
+    if uri.starts_with("data:") { "data URI" } else { uri }
+}
+
+/// Buffers smaller than this are always read completely
+const PARTIAL_BUFFER_MIN_SIZE: usize = 1024 * 1024;
+/// Unused gaps smaller than this between the used parts of a buffer are read anyway
+/// (one request instead of two)
+const PARTIAL_BUFFER_MAX_GAP: usize = 64 * 1024;
+
+/// Like `read_uri`, but for large external buffers only the parts used by accessors
+/// and images (see `used_ranges`) are read
+fn read_buffer_uri(source: &dyn Source, doc: &gltf::Document, buffer: &gltf::Buffer, uri: &str, options: &ImportOptions)
+    -> Result<Vec<u8>, ImportError>
+{
+    if uri.starts_with("data:") || buffer.length() < PARTIAL_BUFFER_MIN_SIZE {
+        return read_uri(source, uri)
+    }
+    let ranges = used_ranges(doc, buffer, options);
+    if ranges.iter().map(|range| range.len()).sum::<usize>() == buffer.length() {
+        return read_uri(source, uri)
+    }
+    source.source_external_ranges(uri, buffer.length(), &ranges).map_err(ImportError::Io)
+}
+
+/// Sorted byte ranges of `buffer` that are referenced by accessors (including sparse ones)
+/// and images (unless `geometry_only`). Close ranges are merged, see `PARTIAL_BUFFER_MAX_GAP`.
+fn used_ranges(doc: &gltf::Document, buffer: &gltf::Buffer, options: &ImportOptions) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut add_view = |view: gltf::buffer::View| if view.buffer().index() == buffer.index() {
+        ranges.push(view.offset()..(view.offset() + view.length()).min(buffer.length()));
+    };
+    for accessor in doc.accessors() {
+        add_view(accessor.view());
+        if let Some(sparse) = accessor.sparse() {
+            add_view(sparse.indices().view());
+            add_view(sparse.values().view());
+        }
+    }
+    for image in doc.images().filter(|_| !options.geometry_only) {
+        if let gltf::image::Source::View { view, .. } = image.source() {
+            add_view(view);
+        }
+    }
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = vec![];
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end + PARTIAL_BUFFER_MAX_GAP => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Decodes data URIs or gets the data from `source`
+fn read_uri(source: &dyn Source, uri: &str) -> Result<Vec<u8>, ImportError> {
+    if uri.starts_with("data:") {
+        let encoded = uri.split(',').nth(1).unwrap_or("");
+        base64::decode(encoded).map_err(ImportError::Base64)
+    } else {
+        source.source_external_data(uri).map_err(ImportError::Io)
+    }
+}
+
+/// Decodes an encoded image. The format is guessed if the MIME type is unknown.
+fn decode_image(data: &[u8], mime_type: Option<&str>) -> Result<gltf::image::Data, ImportError> {
+    let decoded = match mime_type {
+        Some("image/png") => image::load_from_memory_with_format(data, ImageFormat::PNG),
+        Some("image/jpeg") => image::load_from_memory_with_format(data, ImageFormat::JPEG),
+        _ => image::load_from_memory(data),
+    };
+    decoded.map(image_data).map_err(ImportError::Image)
+}
+
+/// Magenta/black checkerboard that stands in for images that failed to load
+pub fn missing_image() -> gltf::image::Data {
+    const SIZE: u32 = 8;
+    let pixels = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % SIZE, i / SIZE);
+            if (x / 2 + y / 2) % 2 == 0 { vec![255, 0, 255] } else { vec![0, 0, 0] }
+        })
+        .collect();
+    gltf::image::Data { pixels, format: Format::R8G8B8, width: SIZE, height: SIZE }
+}
+
+/// Converts to the same format `gltf::import` produces
+fn image_data(image: image::DynamicImage) -> gltf::image::Data {
+    let (width, height) = image.dimensions();
+    let (format, pixels) = match image {
+        ImageLuma8(img) => (Format::R8, img.into_raw()),
+        ImageLumaA8(img) => (Format::R8G8, img.into_raw()),
+        ImageRgb8(img) => (Format::R8G8B8, img.into_raw()),
+        ImageRgba8(img) => (Format::R8G8B8A8, img.into_raw()),
+        other => (Format::R8G8B8A8, other.to_rgba().into_raw()),
+    };
+    gltf::image::Data { pixels, format, width, height }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn progress() {
+        assert_eq!(Progress { stage: "buffers", done: 0, total: 2 }.fraction(), 0.0);
+        assert_eq!(Progress { stage: "images", done: 0, total: 0 }.fraction(), 2.0 / 3.0);
+        assert_eq!(Progress { stage: "nodes", done: 3, total: 6 }.fraction(), 2.5 / 3.0);
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(parse_version("2.0"), Some((2, 0)));
+        assert_eq!(parse_version("2.10"), Some((2, 10)));
+        assert_eq!(parse_version("2"), None);
+
+        assert!(check_version(&json!({ "version": "2.0" })).is_ok());
+        assert!(check_version(&json!({ "version": "2.1" })).is_ok()); // warning only
+        assert!(check_version(&json!({ "version": "2.1", "minVersion": "2.0" })).is_ok());
+        let err = check_version(&json!({ "version": "2.1", "minVersion": "2.1", "generator": "Exporter 9" }))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "the file requires glTF 2.1 (supported: 2.0), written by Exporter 9");
+        assert!(check_version(&json!({ "version": "3.0" })).is_err());
+    }
+
+    #[test]
+    fn unknown_attributes() {
+        let attributes = json!({ "POSITION": 0, "_BATCHID": 1, "FEATURE_ID_0": 2, "TEXCOORD_X": 3 });
+        let json = json!({ "meshes": [{ "primitives": [{ "attributes": attributes }] }] });
+        let sanitized = without_unsupported(&json).unwrap();
+        assert_eq!(sanitized["meshes"][0]["primitives"][0]["attributes"], json!({ "POSITION": 0, "_BATCHID": 1 }));
+        assert!(without_unsupported(&sanitized).is_none());
+    }
+}
@@ -3,13 +3,16 @@
 // #![feature(test)]
 #![allow(clippy::cast_lossless, clippy::cyclomatic_complexity)]
 
+use std::fs::File;
+use std::io;
+
 use clap::crate_version;
 use cgmath;
 use cgmath::Deg;
 
 use clap::{Arg, App, AppSettings};
 
-use log::warn;
+use log::{error, warn};
 
 use simplelog::{TermLogger, LevelFilter, Config as LogConfig};
 
@@ -20,13 +23,23 @@ use crate::viewer::{GltfViewer, CameraOptions};
 mod shader;
 mod controls;
 mod framebuffer;
-mod macros;
 mod importdata;
-// TODO!: adapt Source...
-// mod http_source;
-// use http_source::HttpSource;
+mod memory_source;
+mod viewconfig;
+mod file_source;
+mod http_source;
+mod benchmark;
+mod export;
+mod window;
+#[cfg(feature = "gui")]
+mod gui;
+use crate::window::GlContextOptions;
+use crate::viewconfig::ViewConfig;
+use crate::importdata::{DefaultMaterial, ImportOptions};
 mod render;
 use crate::render::math::*;
+use crate::render::{BloomOptions, CullMode, ExposureOptions, FaceCulling, OutlineOptions, OutputColorSpace,
+    PostProcessOptions, SsaoOptions, ToneMapping, UpAxis};
 
 pub fn main() {
     let args = App::new("gltf-viewer")
@@ -35,10 +48,11 @@ pub fn main() {
         .setting(AppSettings::DeriveDisplayOrder)
         .before_help("glTF 2.0 viewer\n\nNavigate with the mouse (left/right click + drag, mouse wheel) \
                     or WASD/cursor keys.")
-        .arg(Arg::with_name("FILE") // TODO!: re-add URL when fixed...
+        .arg(Arg::with_name("FILE")
             .required(true)
             .takes_value(true)
-            .help("glTF file name"))
+            .multiple(true)
+            .help("glTF file name or URL. Multiple files are shown side by side (or benchmarked with --benchmark)"))
         .arg(Arg::with_name("verbose")
             .long("verbose")
             .short("v")
@@ -67,9 +81,26 @@ pub fn main() {
             .default_value("1")
             .help("Saves N screenshots of size WxH, rotating evenly spaced around the object")
             .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("RENDER-SCALE")
+            .long("render-scale")
+            .takes_value(true)
+            .default_value("1.0")
+            .help("Render at a multiple of the window/screenshot resolution and downsample the result \
+                   (supersampling anti-aliasing), e.g. 2.0 for crisper screenshots")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(scale) if scale > 0.0 => Ok(()),
+                Ok(_) => Err("must be greater than 0".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
         .arg(Arg::with_name("headless")
             .long("headless")
             .help("Use real headless rendering for screenshots (default is a hidden window) [EXPERIMENTAL - see README for details]"))
+        .arg(Arg::with_name("GL")
+            .long("gl")
+            .takes_value(true)
+            .help("OpenGL context version and profile to request (core or compat), e.g. 3.3-core or 4.1-compat \
+                   [default: 3.3-core]")
+            .validator(|value| value.parse::<GlContextOptions>().map(|_| ())))
         .arg(Arg::with_name("straight")
             .long("straight")
             .help("Position camera in front of model if using default camera (i.e. glTF doesn't contain a camera or `--cam-index -1` is passed)"))
@@ -92,18 +123,343 @@ pub fn main() {
             .long("cam-pos")
             .takes_value(true)
             .allow_hyphen_values(true)
-            .help("Camera (aka eye) position override as comma-separated Vector3. Example: 1.2,3.4,5.6"))
+            .help("Camera (aka eye) position override as comma-separated Vector3. Example: 1.2,3.4,5.6")
+            .validator(|value| parse_vec3(&value).map(|_| ()).map_err(|err| err.to_string())))
         .arg(Arg::with_name("CAM-TARGET")
             .long("cam-target")
             .takes_value(true)
             .allow_hyphen_values(true)
-            .help("Camera target (aka center) override as comma-separated Vector3. Example: 1.2,3.4,5.6"))
+            .help("Camera target (aka center) override as comma-separated Vector3. Example: 1.2,3.4,5.6")
+            .validator(|value| parse_vec3(&value).map(|_| ()).map_err(|err| err.to_string())))
         .arg(Arg::with_name("CAM-FOVY")
             .long("cam-fovy")
             .takes_value(true)
             .default_value("75")
             .help("Vertical field of view ('zoom') in degrees.")
             .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("isolate")
+            .long("isolate")
+            .value_name("NODE")
+            .help("Only render the node with the given index or name (including its children)"))
+        .arg(Arg::with_name("show")
+            .long("show")
+            .value_name("NODE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Show the node with the given index or name, e.g. one hidden by its extras (\"hidden\": true). \
+                   Can be repeated"))
+        .arg(Arg::with_name("hide")
+            .long("hide")
+            .value_name("NODE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Hide the node with the given index or name (including its children). Can be repeated"))
+        .arg(Arg::with_name("variant")
+            .long("variant")
+            .takes_value(true)
+            .value_name("VARIANT")
+            .help("Select the material variant (KHR_materials_variants) with the given name or index. \
+                   Tab steps through them"))
+        .arg(Arg::with_name("print-tree")
+            .long("print-tree")
+            .help("Print the node hierarchy of the scene after loading (can also be printed with H)"))
+        .arg(Arg::with_name("UP")
+            .long("up")
+            .takes_value(true)
+            .possible_values(&["y", "z"])
+            .default_value("y")
+            .help("Up axis of the model (the scene is rotated for Z-up models). Can be toggled with U"))
+        .arg(Arg::with_name("NEAR")
+            .long("near")
+            .takes_value(true)
+            .help("Near plane distance (default: fitted to the scene)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("FAR")
+            .long("far")
+            .takes_value(true)
+            .help("Far plane distance (default: fitted to the scene)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("log-depth")
+            .long("log-depth")
+            .help("Use a logarithmic depth buffer (for scenes with an extreme depth range)"))
+        .arg(Arg::with_name("flat-shading")
+            .long("flat-shading")
+            .help("Shade with geometric face normals instead of the vertex normals. Can be toggled with F"))
+        .arg(Arg::with_name("xray")
+            .long("xray")
+            .help("X-ray mode: draw all surfaces semi-transparent without depth test, so that hidden parts are \
+                   visible. Can be toggled with I"))
+        .arg(Arg::with_name("XRAY-OPACITY")
+            .long("xray-opacity")
+            .takes_value(true)
+            .default_value("0.2")
+            .help("Opacity of the surfaces in x-ray mode (0-1)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("overdraw")
+            .long("overdraw")
+            .help("Show the number of overlapping fragments per pixel as a heatmap (black: none, then blue, cyan, \
+                   green, yellow, red, white: 6 or more). Can be toggled with F5"))
+        .arg(Arg::with_name("texel-density")
+            .long("texel-density")
+            .help("Color the surfaces by the texel density of their base color texture (green: \
+                   --texel-density-target, blue: 8x fewer, red: 8x more, gray: untextured). Can be toggled with F10"))
+        .arg(Arg::with_name("TEXEL-DENSITY-TARGET")
+            .long("texel-density-target")
+            .takes_value(true)
+            .default_value("512")
+            .help("Texels per meter shown green in the texel density view")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(target) if target > 0.0 => Ok(()),
+                Ok(_) => Err("must be positive".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("occlusion-culling")
+            .long("occlusion-culling")
+            .help("Skip opaque primitives hidden behind others, tested against the depth of a pre-pass \
+                   (hierarchical Z), for large scenes. Can be toggled with F11"))
+        .arg(Arg::with_name("CULL")
+            .long("cull")
+            .takes_value(true)
+            .possible_values(&["auto", "off", "back", "front"])
+            .default_value("auto")
+            .help("Face culling, independent of the materials (auto: back faces unless double-sided). \
+                   Can be cycled with Q"))
+        .arg(Arg::with_name("FRONT-FACE")
+            .long("front-face")
+            .takes_value(true)
+            .possible_values(&["auto", "ccw", "cw"])
+            .default_value("auto")
+            .help("Winding order of front faces (auto: counter-clockwise, clockwise for mirroring transforms). \
+                   Can be cycled with Z"))
+        .arg(Arg::with_name("transmission-back-faces")
+            .long("transmission-back-faces")
+            .help("Also draw the back faces of single-sided transmissive materials (e.g. thin glass panels), \
+                   like those of double-sided ones"))
+        .arg(Arg::with_name("oit")
+            .long("oit")
+            .help("Draw alpha blended materials with weighted blended order-independent transparency \
+                   instead of in draw order (needs two extra render targets)"))
+        .arg(Arg::with_name("LOD-THRESHOLD")
+            .long("lod-threshold")
+            .takes_value(true)
+            .default_value("0.25")
+            .help("Screen coverage (fraction of the window height) below which the first coarser MSFT_lod level \
+                   is used if the file doesn't define MSFT_screencoverage. Each further level halves it.")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("recenter")
+            .long("recenter")
+            .help("Move the center of the scene to the origin (avoids precision issues with huge coordinates)"))
+        .arg(Arg::with_name("SCALE")
+            .long("scale")
+            .takes_value(true)
+            .help("Multiply all coordinates, to correct models that aren't in meters (e.g. 0.001 for millimeters)")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(scale) if scale > 0.0 => Ok(()),
+                Ok(_) => Err("must be positive".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("scale-bar")
+            .long("scale-bar")
+            .help("Show a scale bar and the model size in meters (can be toggled with F9)"))
+        .arg(Arg::with_name("no-vsync")
+            .long("no-vsync")
+            .help("Disable vertical synchronization"))
+        .arg(Arg::with_name("FPS-CAP")
+            .long("fps-cap")
+            .takes_value(true)
+            .help("Limit the frame rate to the given number of frames per second")
+            .validator(|value| value.parse::<u32>()
+                .map_err(|err| err.to_string())
+                .and_then(|fps| if fps > 0 { Ok(()) } else { Err("must be greater than 0".to_owned()) })))
+        .arg(Arg::with_name("ANIMATION-STEP")
+            .long("animation-step")
+            .takes_value(true)
+            .help("Step the paused animation by this many seconds with ; and ' instead of to the previous/next \
+                   keyframe")
+            .validator(|value| value.parse::<f32>()
+                .map_err(|err| err.to_string())
+                .and_then(|step| if step > 0.0 { Ok(()) } else { Err("must be greater than 0".to_owned()) })))
+        .arg(Arg::with_name("bloom")
+            .long("bloom")
+            .help("Enable bloom (can be toggled with B)"))
+        .arg(Arg::with_name("BLOOM-THRESHOLD")
+            .long("bloom-threshold")
+            .takes_value(true)
+            .default_value("1.0")
+            .help("Brightness above which pixels contribute to the bloom")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("BLOOM-INTENSITY")
+            .long("bloom-intensity")
+            .takes_value(true)
+            .default_value("0.5")
+            .help("Strength of the bloom")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("EXPOSURE")
+            .long("exposure")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .default_value("0")
+            .help("Exposure in stops (can be changed with +/-)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("auto-exposure")
+            .long("auto-exposure")
+            .help("Adapt the exposure to the average scene luminance (can be toggled with E)"))
+        .arg(Arg::with_name("ADAPTATION-SPEED")
+            .long("adaptation-speed")
+            .takes_value(true)
+            .default_value("2.0")
+            .help("Speed of the auto exposure adaptation (1/seconds)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("OUTPUT-COLOR-SPACE")
+            .long("output-color-space")
+            .takes_value(true)
+            .possible_values(&["srgb", "display-p3"])
+            .default_value("srgb")
+            .help("Color space of the display (display-p3: wide gamut displays, e.g. of recent Macs)"))
+        .arg(Arg::with_name("TONE-MAPPING")
+            .long("tone-mapping")
+            .takes_value(true)
+            .possible_values(&["none", "reinhard", "aces"])
+            .default_value("none")
+            .help("Operator that maps the HDR colors to the display range (none: colors are clamped)"))
+        .arg(Arg::with_name("ssao")
+            .long("ssao")
+            .help("Enable screen-space ambient occlusion (can be toggled with O)"))
+        .arg(Arg::with_name("SSAO-RADIUS")
+            .long("ssao-radius")
+            .takes_value(true)
+            .default_value("0.02")
+            .help("SSAO sampling radius relative to the scene size")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("SSAO-BIAS")
+            .long("ssao-bias")
+            .takes_value(true)
+            .default_value("0.05")
+            .help("SSAO depth bias relative to the radius")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("SSAO-STRENGTH")
+            .long("ssao-strength")
+            .takes_value(true)
+            .default_value("1.0")
+            .help("How much SSAO darkens the ambient light (0-1)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("outline")
+            .long("outline")
+            .help("Outline silhouettes and creases (F8 cycles off, whole scene and picked node)"))
+        .arg(Arg::with_name("outline-selected")
+            .long("outline-selected")
+            .help("Only outline the node picked with a left click (implies --outline)"))
+        .arg(Arg::with_name("OUTLINE-COLOR")
+            .long("outline-color")
+            .takes_value(true)
+            .default_value("1,0.5,0")
+            .help("Outline color (linear RGB)")
+            .validator(|value| parse_vec3(&value).map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("OUTLINE-THICKNESS")
+            .long("outline-thickness")
+            .takes_value(true)
+            .default_value("2")
+            .help("Outline width in pixels")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("save-config")
+            .long("save-config")
+            .help("Save the camera, lights etc. as JSON to <FILE>.view.json on exit (loaded automatically when present; \
+                   not for URLs)"))
+        .arg(Arg::with_name("safe-mode")
+            .long("safe-mode")
+            .help("Skip primitives, node meshes and textures with invalid data instead of aborting"))
+        .arg(Arg::with_name("SMOOTH-ANGLE")
+            .long("smooth-angle")
+            .takes_value(true)
+            .default_value("0")
+            .help("Maximum angle in degrees between adjacent faces that share a smoothed normal when generating \
+                   missing normals. Edges with larger angles stay hard (0: flat shading)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("DEFAULT-COLOR")
+            .long("default-color")
+            .takes_value(true)
+            .help("Base color (linear RGB) of primitives without a material. Example: 0.8,0.8,0.8")
+            .validator(|value| parse_vec3(&value).map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("DEFAULT-METALLIC")
+            .long("default-metallic")
+            .takes_value(true)
+            .help("Metallic factor of primitives without a material (glTF default: 1)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("DEFAULT-ROUGHNESS")
+            .long("default-roughness")
+            .takes_value(true)
+            .help("Roughness factor of primitives without a material (glTF default: 1)")
+            .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("MAX-TEXTURE-SIZE")
+            .long("max-texture-size")
+            .takes_value(true)
+            .help("Downscale textures larger than the given size in pixels (e.g. for GPUs with little memory)")
+            .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("stream-textures")
+            .long("stream-textures")
+            .help("Show textures at a low resolution right away and upload the finer mip levels over the following \
+                   frames, starting with textures close to the camera (ignored for screenshots)"))
+        .arg(Arg::with_name("geometry-only")
+            .long("geometry-only")
+            .help("Skip all images and materials and render everything with the default material, for quickly \
+                   checking the shape of texture-heavy assets"))
+        .arg(Arg::with_name("sanitize")
+            .long("sanitize")
+            .help("Replace NaN and infinite vertex positions, normals and animation keyframes (which usually \
+                   render nothing or garbage) and warn with their counts per primitive and channel"))
+        .arg(Arg::with_name("FEATURE-ID-ATTRIBUTE")
+            .long("feature-id-attribute")
+            .takes_value(true)
+            .value_name("NAME")
+            .help("Custom vertex attribute with feature or batch ids, reported when clicking on a primitive \
+                   [default: _FEATURE_ID_0 or _BATCHID]")
+            .validator(|value| if value.starts_with('_') {
+                Ok(())
+            } else {
+                Err("custom attribute names start with an underscore".to_owned())
+            }))
+        .arg(Arg::with_name("VERTEX-AO")
+            .long("vertex-ao")
+            .takes_value(true)
+            .value_name("SAMPLES")
+            .help("Bake ambient occlusion into the vertices at load time by casting the given number of rays per \
+                   vertex (e.g. 64). Slow for large meshes, but cheaper than SSAO when rendering")
+            .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("SIMPLIFY")
+            .long("simplify")
+            .takes_value(true)
+            .value_name("RATIO")
+            .help("Decimate the triangle meshes at load time to the given fraction of their triangles (e.g. 0.1) \
+                   for a quicker preview of huge models. Y toggles back to the original meshes")
+            .validator(|value| match value.parse::<f32>() {
+                Ok(ratio) if ratio > 0.0 && ratio <= 1.0 => Ok(()),
+                Ok(_) => Err("must be greater than 0 and at most 1".to_owned()),
+                Err(err) => Err(err.to_string()),
+            }))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .help("Reload the model whenever its file changes (only the .gltf/.glb file is watched). F7 reloads \
+                   manually"))
+        .arg(Arg::with_name("BENCHMARK")
+            .long("benchmark")
+            .takes_value(true)
+            .value_name("FRAMES")
+            .help("Measure the load time of each FILE and the frame times while rotating the camera once \
+                   around the scene in the given number of frames. Prints CSV")
+            .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|err| err.to_string())))
+        .arg(Arg::with_name("BENCHMARK-CSV")
+            .long("benchmark-csv")
+            .takes_value(true)
+            .value_name("FILE")
+            .requires("BENCHMARK")
+            .help("Write the benchmark results to a CSV file instead of stdout"))
+        .arg(Arg::with_name("EXPORT")
+            .long("export")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with("BENCHMARK")
+            .help("Write the visible geometry of the scene in world space to an OBJ or PLY file \
+                   (by extension) and exit"))
         .get_matches();
     let source = args.value_of("FILE").unwrap();
 
@@ -111,14 +467,16 @@ pub fn main() {
     let height: u32 = args.value_of("HEIGHT").unwrap().parse().unwrap();
     let count: u32 = args.value_of("COUNT").unwrap().parse().unwrap();
 
-    let scene: usize = args.value_of("scene").unwrap().parse().unwrap();
+    let mut scene: usize = args.value_of("scene").unwrap().parse().unwrap();
 
-    let camera_options = CameraOptions {
+    let mut camera_options = CameraOptions {
         index: args.value_of("CAM-INDEX").map(|n| n.parse().unwrap()).unwrap(),
         position: args.value_of("CAM-POS").map(|v| parse_vec3(v).unwrap()),
         target: args.value_of("CAM-TARGET").map(|v| parse_vec3(v).unwrap()),
         fovy: args.value_of("CAM-FOVY").map(|n| Deg(n.parse().unwrap())).unwrap(),
         straight: args.is_present("straight"),
+        znear: args.value_of("NEAR").map(|n| n.parse().unwrap()),
+        zfar: args.value_of("FAR").map(|n| n.parse().unwrap()),
     };
 
     let log_level = match args.occurrences_of("verbose") {
@@ -130,13 +488,168 @@ pub fn main() {
 
     let _ = TermLogger::init(log_level, LogConfig { time: None, target: None, ..LogConfig::default() });
 
+    // CLI flags take precedence over the sidecar config
+    let config_path = viewconfig::sidecar_path(source);
+    let config = config_path.as_deref().and_then(ViewConfig::load).unwrap_or_default();
+    if args.occurrences_of("scene") == 0 {
+        scene = config.scene.unwrap_or(scene);
+    }
+    if let Some(ref camera) = config.camera {
+        // the saved view replaces the glTF camera unless one is chosen explicitly
+        if args.occurrences_of("CAM-INDEX") == 0 {
+            camera_options.index = -1;
+        }
+        if camera_options.index == -1 {
+            camera_options.position = camera_options.position.or_else(|| Some(camera.position.into()));
+            camera_options.target = camera_options.target.or_else(|| Some(camera.target.into()));
+        }
+        if args.occurrences_of("CAM-FOVY") == 0 {
+            camera_options.fovy = Deg(camera.fovy);
+        }
+    }
+
     let mut viewer = GltfViewer::new(source, width, height,
         args.is_present("headless"),
-        !args.is_present("screenshot"),
+        !args.is_present("screenshot") && !args.is_present("BENCHMARK") && !args.is_present("EXPORT"),
+        !args.is_present("no-vsync") && !args.is_present("BENCHMARK"),
         camera_options,
-        scene);
+        scene,
+        ImportOptions {
+            safe_mode: args.is_present("safe-mode"),
+            smooth_angle: args.value_of("SMOOTH-ANGLE").unwrap().parse().unwrap(),
+            max_texture_size: args.value_of("MAX-TEXTURE-SIZE").map(|size| size.parse().unwrap()),
+            stream_textures: args.is_present("stream-textures") && !args.is_present("screenshot"),
+            vertex_ao_samples: args.value_of("VERTEX-AO").map(|samples| samples.parse().unwrap()),
+            simplify: args.value_of("SIMPLIFY").map(|ratio| ratio.parse().unwrap()),
+            geometry_only: args.is_present("geometry-only"),
+            sanitize: args.is_present("sanitize"),
+            feature_id_attribute: args.value_of("FEATURE-ID-ATTRIBUTE").map(str::to_owned),
+            default_material: DefaultMaterial {
+                base_color: args.value_of("DEFAULT-COLOR").map(|v| parse_vec3(v).unwrap().into()),
+                metallic: args.value_of("DEFAULT-METALLIC").map(|v| v.parse().unwrap()),
+                roughness: args.value_of("DEFAULT-ROUGHNESS").map(|v| v.parse().unwrap()),
+            },
+        },
+        args.value_of("GL").map(|gl| gl.parse().unwrap()));
 
-    if args.is_present("screenshot") {
+    if args.value_of("UP") == Some("z") {
+        viewer.set_up_axis(UpAxis::Z);
+    }
+    viewer.set_render_scale(args.value_of("RENDER-SCALE").unwrap().parse().unwrap());
+    viewer.set_log_depth(args.is_present("log-depth"));
+    viewer.set_flat_shading(args.is_present("flat-shading"));
+    viewer.set_xray(args.is_present("xray"), args.value_of("XRAY-OPACITY").unwrap().parse().unwrap());
+    viewer.set_overdraw_view(args.is_present("overdraw"));
+    viewer.set_texel_density_view(args.is_present("texel-density"),
+        args.value_of("TEXEL-DENSITY-TARGET").unwrap().parse().unwrap());
+    viewer.set_occlusion_culling(args.is_present("occlusion-culling"));
+    viewer.set_face_culling(FaceCulling {
+        cull_mode: match args.value_of("CULL").unwrap() {
+            "off" => CullMode::Off,
+            "back" => CullMode::Back,
+            "front" => CullMode::Front,
+            _ => CullMode::Auto,
+        },
+        front_face: match args.value_of("FRONT-FACE").unwrap() {
+            "ccw" => Some(gl::CCW),
+            "cw" => Some(gl::CW),
+            _ => None,
+        },
+    });
+    viewer.set_order_independent_transparency(args.is_present("oit"));
+    viewer.set_lod_threshold(args.value_of("LOD-THRESHOLD").unwrap().parse().unwrap());
+    viewer.set_watch(args.is_present("watch"));
+    if let Some(scale) = args.value_of("SCALE") {
+        viewer.set_unit_scale(scale.parse().unwrap());
+    }
+    if args.is_present("recenter") {
+        viewer.set_recenter(true);
+    }
+    viewer.set_scale_bar_visible(args.is_present("scale-bar"));
+    if !args.is_present("BENCHMARK") {
+        for file in args.values_of("FILE").unwrap().skip(1) {
+            viewer.add_model(file);
+        }
+    }
+    for node in args.values_of("show").into_iter().flatten() {
+        viewer.set_node_visible(node, true);
+    }
+    for node in args.values_of("hide").into_iter().flatten() {
+        viewer.set_node_visible(node, false);
+    }
+    if args.is_present("transmission-back-faces") {
+        viewer.set_transmission_back_faces(None, true);
+    }
+    if let Some(node) = args.value_of("isolate") {
+        viewer.isolate_node(node);
+    }
+    if let Some(variant) = args.value_of("variant") {
+        viewer.set_variant(variant);
+    }
+    if args.is_present("print-tree") {
+        viewer.print_hierarchy();
+    }
+    viewer.set_post_process_options(PostProcessOptions {
+        bloom: BloomOptions {
+            enabled: args.is_present("bloom"),
+            threshold: args.value_of("BLOOM-THRESHOLD").unwrap().parse().unwrap(),
+            intensity: args.value_of("BLOOM-INTENSITY").unwrap().parse().unwrap(),
+        },
+        ssao: SsaoOptions {
+            enabled: args.is_present("ssao"),
+            radius: args.value_of("SSAO-RADIUS").unwrap().parse().unwrap(),
+            bias: args.value_of("SSAO-BIAS").unwrap().parse().unwrap(),
+            strength: args.value_of("SSAO-STRENGTH").unwrap().parse().unwrap(),
+        },
+        exposure: ExposureOptions {
+            stops: match config.exposure {
+                Some(stops) if args.occurrences_of("EXPOSURE") == 0 => stops,
+                _ => args.value_of("EXPOSURE").unwrap().parse().unwrap(),
+            },
+            auto: args.is_present("auto-exposure"),
+            adaptation_speed: args.value_of("ADAPTATION-SPEED").unwrap().parse().unwrap(),
+        },
+        output_color_space: match args.value_of("OUTPUT-COLOR-SPACE").unwrap() {
+            "display-p3" => OutputColorSpace::DisplayP3,
+            _ => OutputColorSpace::Srgb,
+        },
+        tone_mapping: match args.value_of("TONE-MAPPING").unwrap() {
+            "reinhard" => ToneMapping::Reinhard,
+            "aces" => ToneMapping::Aces,
+            _ => ToneMapping::None,
+        },
+    });
+    viewer.set_outline_options(OutlineOptions {
+        enabled: args.is_present("outline") || args.is_present("outline-selected"),
+        color: parse_vec3(args.value_of("OUTLINE-COLOR").unwrap()).unwrap(),
+        thickness: args.value_of("OUTLINE-THICKNESS").unwrap().parse().unwrap(),
+        selected_only: args.is_present("outline-selected"),
+    });
+    viewer.set_animation_step(args.value_of("ANIMATION-STEP").map(|step| step.parse().unwrap()));
+    viewer.set_fps_cap(args.value_of("FPS-CAP").map(|fps| fps.parse().unwrap()));
+    if let Some(light) = config.light {
+        viewer.set_lights(light.into());
+    }
+    if let Some(background) = config.background {
+        viewer.set_background(background);
+    }
+    if let Some(ref animation) = config.animation {
+        viewer.seek_animation(animation.index, animation.time, animation.playing);
+    }
+
+    if let Some(frames) = args.value_of("BENCHMARK") {
+        let files: Vec<&str> = args.values_of("FILE").unwrap().collect();
+        let results = viewer.benchmark(&files, frames.parse().unwrap());
+        let written = match args.value_of("BENCHMARK-CSV") {
+            Some(path) => File::create(path).and_then(|mut file| benchmark::write_csv(&mut file, &results)),
+            None => benchmark::write_csv(&mut io::stdout(), &results),
+        };
+        if let Err(err) = written {
+            error!("Failed to write benchmark results: {}", err);
+        }
+    } else if let Some(filename) = args.value_of("EXPORT") {
+        viewer.export(filename);
+    } else if args.is_present("screenshot") {
         let filename = args.value_of("screenshot").unwrap();
 
         if !filename.to_lowercase().ends_with(".png") {
@@ -147,10 +660,19 @@ pub fn main() {
         } else {
             viewer.screenshot(filename)
         }
-        return;
+    } else {
+        viewer.start_render_loop();
     }
 
-    viewer.start_render_loop();
+    if args.is_present("save-config") {
+        match config_path {
+            Some(path) => match viewer.view_config().save(&path) {
+                Ok(()) => println!("Saved view config to {}", path.display()),
+                Err(err) => error!("Failed to save view config to {}: {}", path.display(), err),
+            },
+            None => warn!("Not saving the view config: sidecar files are only supported for local files, not URLs"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::importdata::Source;
+
+/// Source for glTF data that is already in memory (e.g. from a database or
+/// embedded resource)
+#[allow(dead_code)]
+pub struct MemorySource {
+    gltf: Vec<u8>,
+    external_data: HashMap<String, Vec<u8>>,
+}
+
+#[allow(dead_code)]
+impl MemorySource {
+    /// `gltf`: content of a .gltf or .glb file
+    pub fn new(gltf: Vec<u8>) -> MemorySource {
+        MemorySource {
+            gltf,
+            external_data: HashMap::new(),
+        }
+    }
+
+    /// Adds the data of a buffer or image that the document references by `uri`
+    pub fn with_external_data(mut self, uri: &str, data: Vec<u8>) -> MemorySource {
+        self.external_data.insert(uri.to_owned(), data);
+        self
+    }
+}
+
+impl Source for MemorySource {
+    fn source_gltf(&self) -> io::Result<Vec<u8>> {
+        Ok(self.gltf.clone())
+    }
+
+    fn source_external_data(&self, uri: &str) -> io::Result<Vec<u8>> {
+        self.external_data.get(uri)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+                format!("no external data for uri '{}'", uri)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::ops::Range;
+    use crate::importdata::{import_source, import_source_with_progress, ImportError, ImportOptions};
+    use image::ColorType;
+    use image::png::PNGEncoder;
+
+    const GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "buffers": [{ "uri": "data.bin", "byteLength": 3 }]
+    }"#;
+
+    #[test]
+    fn import_with_external_data() {
+        let source = MemorySource::new(GLTF.as_bytes().to_vec())
+            .with_external_data("data.bin", vec![1, 2, 3]);
+        let imp = import_source(&source).unwrap();
+        assert_eq!(imp.buffers.len(), 1);
+        assert_eq!(imp.buffers[0].0, vec![1, 2, 3, 0]); // padded to 4 bytes
+        assert_eq!(imp.json["buffers"][0]["uri"], "data.bin");
+    }
+
+    #[test]
+    fn missing_external_data() {
+        let source = MemorySource::new(GLTF.as_bytes().to_vec());
+        match import_source(&source) {
+            Err(ImportError::Io(ref err)) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(_) => panic!("import should fail"),
+        }
+    }
+
+    /// Binary glTF with the given JSON and BIN chunks
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        // chunks are padded to 4 bytes
+        let mut json = json.as_bytes().to_vec();
+        json.extend(vec![b' '; (4 - json.len() % 4) % 4]);
+        let mut bin = bin.to_vec();
+        bin.extend(vec![0; (4 - bin.len() % 4) % 4]);
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        let mut glb = b"glTF".to_vec();
+        glb.extend(&2u32.to_le_bytes());
+        glb.extend(&(length as u32).to_le_bytes());
+        glb.extend(&(json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend(&(bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+        glb
+    }
+
+    /// 2x1 PNG with a red and a green pixel
+    fn test_png() -> Vec<u8> {
+        let mut png = vec![];
+        PNGEncoder::new(&mut png).encode(&[255, 0, 0, 255, 0, 255, 0, 255], 2, 1, ColorType::RGBA(8)).unwrap();
+        png
+    }
+
+    /// GLB with `png` in the BIN chunk, referenced by a buffer view of `view_length` bytes
+    fn embedded_image_glb(png: &[u8], view_length: usize) -> Vec<u8> {
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ "byteLength": {} }}],
+            "bufferViews": [{{ "buffer": 0, "byteLength": {} }}],
+            "images": [{{ "bufferView": 0, "mimeType": "image/png" }}]
+        }}"#, png.len(), view_length);
+        glb(&json, png)
+    }
+
+    #[test]
+    fn import_image_from_buffer_view() {
+        let png = test_png();
+        let imp = import_source(&MemorySource::new(embedded_image_glb(&png, png.len()))).unwrap();
+        assert_eq!(imp.images.len(), 1);
+        assert_eq!((imp.images[0].width, imp.images[0].height), (2, 1));
+        assert_eq!(&imp.images[0].pixels[..4], &[255, 0, 0, 255]);
+
+        match import_source(&MemorySource::new(embedded_image_glb(&png, png.len() + 100))) {
+            Err(ImportError::ImageView { image: 0 }) => (),
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(_) => panic!("import should fail"),
+        }
+    }
+
+    #[test]
+    fn fallback_sources() {
+        // the external buffer is missing, but packed into the BIN chunk
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "uri": "data.bin", "byteLength": 3 }]
+        }"#;
+        let imp = import_source(&MemorySource::new(glb(json, &[1, 2, 3]))).unwrap();
+        assert_eq!(imp.buffers[0].0, vec![1, 2, 3, 0]);
+
+        // the buffer view of the image is out of bounds, but its uri is still there
+        let png = test_png();
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 4 }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 100 }],
+            "images": [{ "bufferView": 0, "mimeType": "image/png", "uri": "image.png" }]
+        }"#;
+        let source = MemorySource::new(glb(json, &[0; 4])).with_external_data("image.png", png);
+        let imp = import_source(&source).unwrap();
+        assert_eq!((imp.images[0].width, imp.images[0].height), (2, 1));
+    }
+
+    #[test]
+    fn geometry_only_skips_images() {
+        let png = test_png();
+        let options = ImportOptions { geometry_only: true, ..ImportOptions::default() };
+        let imp = import_source_with_progress(&MemorySource::new(embedded_image_glb(&png, png.len())),
+            options, &mut |_| ()).unwrap();
+        assert!(imp.images.is_empty());
+        assert!(imp.options.geometry_only);
+    }
+
+    #[test]
+    fn missing_image_placeholder() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "uri": "missing.png" }, { "uri": "data:image/png;base64,AAAA" }]
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        assert_eq!(imp.images.len(), 2);
+        for image in &imp.images {
+            assert_eq!((image.width, image.height), (8, 8));
+            assert_eq!(&image.pixels[0..3], &[255, 0, 255]);
+        }
+    }
+
+    /// Returns only the requested parts of large buffers, like `HttpSource` if the server supports ranges
+    struct RangeSource {
+        source: MemorySource,
+        requested: RefCell<Vec<Range<usize>>>,
+    }
+
+    impl Source for RangeSource {
+        fn source_gltf(&self) -> io::Result<Vec<u8>> {
+            self.source.source_gltf()
+        }
+
+        fn source_external_data(&self, uri: &str) -> io::Result<Vec<u8>> {
+            self.source.source_external_data(uri)
+        }
+
+        fn source_external_ranges(&self, uri: &str, length: usize, ranges: &[Range<usize>]) -> io::Result<Vec<u8>> {
+            let all = self.source.source_external_data(uri)?;
+            let mut data = vec![0; length];
+            for range in ranges {
+                data[range.clone()].copy_from_slice(&all[range.clone()]);
+                self.requested.borrow_mut().push(range.clone());
+            }
+            Ok(data)
+        }
+    }
+
+    #[test]
+    fn partial_buffers() {
+        // two accessors close to each other at the start of a 2 MB buffer, an image near the end
+        let length = 2_000_000;
+        let gltf = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ "uri": "data.bin", "byteLength": {} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteLength": 12 }},
+                {{ "buffer": 0, "byteOffset": 100, "byteLength": 12 }},
+                {{ "buffer": 0, "byteOffset": 1500000, "byteLength": 4 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3" }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 1, "type": "VEC3" }}
+            ],
+            "images": [{{ "bufferView": 2, "mimeType": "image/png" }}]
+        }}"#, length);
+        let data: Vec<u8> = (0..length).map(|i| (i % 251) as u8 + 1).collect();
+        let source = RangeSource {
+            source: MemorySource::new(gltf.into_bytes()).with_external_data("data.bin", data.clone()),
+            requested: RefCell::new(vec![]),
+        };
+
+        let imp = import_source(&source).unwrap();
+        assert_eq!(*source.requested.borrow(), [0..112, 1_500_000..1_500_004]);
+        let buffer = &imp.buffers[0].0;
+        assert_eq!(buffer.len(), length);
+        assert_eq!(buffer[..112], data[..112]);
+        assert_eq!(buffer[1_500_000..1_500_004], data[1_500_000..1_500_004]);
+        assert!(buffer[112..1_500_000].iter().all(|&byte| byte == 0));
+
+        // the image isn't needed
+        source.requested.borrow_mut().clear();
+        let options = ImportOptions { geometry_only: true, ..ImportOptions::default() };
+        import_source_with_progress(&source, options, &mut |_| ()).unwrap();
+        assert_eq!(source.requested.borrow().len(), 1);
+        assert_eq!(source.requested.borrow()[0], 0..112);
+    }
+
+    #[test]
+    fn safe_mode_skips_failing_items() {
+        let source = MemorySource::new(GLTF.as_bytes().to_vec())
+            .with_external_data("data.bin", vec![1, 2, 3]);
+        let mut imp = import_source(&source).unwrap();
+        imp.options.safe_mode = true;
+        assert_eq!(imp.skip_on_error("item 0", Ok(0)), Some(0));
+        assert_eq!(imp.skip_on_error::<i32>("item 1", Err("corrupt".to_owned())), None);
+        assert_eq!(imp.skipped.get(), 1);
+    }
+}
@@ -0,0 +1,806 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use gltf::animation::{Interpolation, Property};
+use gltf::animation::util::ReadOutputs;
+use log::warn;
+use serde_json::Value;
+
+use crate::render::math::*;
+use crate::render::{read_f32_attribute, AnimatedFactors, Material, Node, Root};
+use crate::importdata::ImportData;
+
+/// Keyframes of one animated property. Outputs are stored as `Vector4`
+/// (translations and scales use `xyz`, rotations are quaternions in glTF order `xyzw`).
+pub struct Sampler {
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds (ascending)
+    pub inputs: Vec<f32>,
+    /// One value per keyframe, or (in-tangent, value, out-tangent) triplets for `CubicSpline`
+    pub outputs: Vec<Vector4>,
+}
+
+impl Sampler {
+    /// Number of output elements per keyframe
+    fn stride(&self) -> usize {
+        if self.interpolation == Interpolation::CubicSpline { 3 } else { 1 }
+    }
+
+    /// Value of keyframe `index`
+    fn value(&self, index: usize) -> Vector4 {
+        let stride = self.stride();
+        // the value is in the middle of a cubic spline triplet
+        self.outputs[index * stride + stride / 2]
+    }
+
+    /// Returns the keyframe before `time` and the position between it and the next one (0..1).
+    /// Times outside the keyframe range are clamped.
+    fn find_keyframe(&self, time: f32) -> (usize, f32) {
+        let last = self.inputs.len() - 1;
+        if time <= self.inputs[0] {
+            return (0, 0.0)
+        }
+        if time >= self.inputs[last] {
+            return (last, 0.0)
+        }
+        let next = self.inputs.iter().position(|&t| t > time).unwrap();
+        let prev = next - 1;
+        let delta = self.inputs[next] - self.inputs[prev];
+        (prev, (time - self.inputs[prev]) / delta)
+    }
+
+    /// Replaces non-finite keyframe times by the previous time and non-finite output components
+    /// by those of the previous keyframe (0 for the first), i.e. holds the last valid value.
+    /// Returns the number of replaced times and outputs.
+    pub fn sanitize(&mut self) -> usize {
+        let mut replaced = 0;
+        for i in 0..self.inputs.len() {
+            if !self.inputs[i].is_finite() {
+                self.inputs[i] = if i > 0 { self.inputs[i - 1] } else { 0.0 };
+                replaced += 1;
+            }
+        }
+        let stride = self.stride();
+        for i in 0..self.outputs.len() {
+            let previous = if i >= stride { self.outputs[i - stride] } else { Vector4::zero() };
+            let output = &mut self.outputs[i];
+            if !(output.x.is_finite() && output.y.is_finite() && output.z.is_finite() && output.w.is_finite()) {
+                for c in 0..4 {
+                    if !output[c].is_finite() {
+                        output[c] = previous[c];
+                    }
+                }
+                replaced += 1;
+            }
+        }
+        replaced
+    }
+
+    /// Samples a translation or scale at `time`
+    pub fn sample(&self, time: f32) -> Vector4 {
+        self.interpolate(time, |a, b, s| a.lerp(b, s))
+    }
+
+    /// Samples a rotation at `time` (spherical linear interpolation for `Linear`)
+    pub fn sample_rotation(&self, time: f32) -> Quaternion {
+        let rotation = self.interpolate(time, |a, b, s| {
+            let (a, mut b) = (to_quaternion(a), to_quaternion(b));
+            // take the shorter path
+            if a.dot(b) < 0.0 {
+                b = -b;
+            }
+            let q = a.slerp(b, s);
+            vec4(q.v.x, q.v.y, q.v.z, q.s)
+        });
+        to_quaternion(rotation).normalize()
+    }
+
+    fn interpolate(&self, time: f32, lerp: impl Fn(Vector4, Vector4, f32) -> Vector4) -> Vector4 {
+        let (index, s) = self.find_keyframe(time);
+        if s == 0.0 {
+            return self.value(index)
+        }
+        match self.interpolation {
+            Interpolation::Step => self.value(index),
+            Interpolation::CubicSpline => {
+                // Hermite spline, see Appendix C of the glTF 2.0 spec
+                let delta = self.inputs[index + 1] - self.inputs[index];
+                let v0 = self.outputs[index * 3 + 1];
+                let b0 = self.outputs[index * 3 + 2]; // out-tangent
+                let a1 = self.outputs[index * 3 + 3]; // in-tangent of the next keyframe
+                let v1 = self.outputs[index * 3 + 4];
+                let s2 = s * s;
+                let s3 = s2 * s;
+                v0 * (2.0 * s3 - 3.0 * s2 + 1.0) +
+                    b0 * (delta * (s3 - 2.0 * s2 + s)) +
+                    v1 * (-2.0 * s3 + 3.0 * s2) +
+                    a1 * (delta * (s3 - s2))
+            }
+            // CatmullRomSpline isn't part of the final glTF 2.0 spec
+            _ => lerp(self.value(index), self.value(index + 1), s),
+        }
+    }
+}
+
+fn to_quaternion(v: Vector4) -> Quaternion {
+    Quaternion::new(v.w, v.x, v.y, v.z) // NOTE: different element order!
+}
+
+pub struct Channel {
+    /// Index of the target node
+    pub node: usize,
+    pub property: Property,
+    pub sampler: Sampler,
+}
+
+impl Channel {
+    /// Returns `None` for channels that can't be applied (empty samplers) and for morph target
+    /// weights (see `WeightsChannel`)
+    fn from_gltf(g_channel: &gltf::animation::Channel<'_>, buffers: &[gltf::buffer::Data]) -> Option<Channel> {
+        let reader = g_channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inputs: Vec<f32> = reader.read_inputs()?.collect();
+        let outputs: Vec<Vector4> = match reader.read_outputs()? {
+            ReadOutputs::Translations(iter) | ReadOutputs::Scales(iter) =>
+                iter.map(|v| vec4(v[0], v[1], v[2], 0.0)).collect(),
+            ReadOutputs::Rotations(rotations) => rotations.into_f32().map(Vector4::from).collect(),
+            ReadOutputs::MorphTargetWeights(_) => return None,
+        };
+
+        let sampler = Sampler {
+            interpolation: g_channel.sampler().interpolation(),
+            inputs,
+            outputs,
+        };
+        if sampler.inputs.is_empty() || sampler.outputs.len() != sampler.inputs.len() * sampler.stride() {
+            warn!("Ignoring animation channel of animation {}: invalid number of keyframes",
+                g_channel.animation().index());
+            return None
+        }
+
+        Some(Channel {
+            node: g_channel.target().node().index(),
+            property: g_channel.target().property(),
+            sampler,
+        })
+    }
+
+    fn apply(&self, nodes: &mut [Node], time: f32) {
+        let node = &mut nodes[self.node];
+        match self.property {
+            Property::Translation => node.translation = self.sampler.sample(time).truncate(),
+            Property::Scale => node.scale = self.sampler.sample(time).truncate(),
+            Property::Rotation => node.rotation = self.sampler.sample_rotation(time),
+            Property::MorphTargetWeights => return,
+        }
+        node.transform_changed = true;
+    }
+}
+
+/// Animated morph target weights of a node's mesh
+pub struct WeightsChannel {
+    /// Index of the target node
+    pub node: usize,
+    /// One sampler per morph target, the weight is stored in `x`
+    pub samplers: Vec<Sampler>,
+}
+
+impl WeightsChannel {
+    fn from_gltf(g_channel: &gltf::animation::Channel<'_>, buffers: &[gltf::buffer::Data]) -> Option<WeightsChannel> {
+        let reader = g_channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inputs: Vec<f32> = reader.read_inputs()?.collect();
+        let outputs: Vec<f32> = match reader.read_outputs()? {
+            ReadOutputs::MorphTargetWeights(weights) => weights.into_f32().collect(),
+            _ => return None,
+        };
+        let interpolation = g_channel.sampler().interpolation();
+        let stride = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+        let samplers = match split_weights(&outputs, inputs.len() * stride) {
+            Some(outputs) => outputs.into_iter()
+                .map(|outputs| Sampler { interpolation, inputs: inputs.clone(), outputs })
+                .collect(),
+            None => {
+                warn!("Ignoring animation channel of animation {}: invalid number of keyframes",
+                    g_channel.animation().index());
+                return None
+            }
+        };
+        Some(WeightsChannel { node: g_channel.target().node().index(), samplers })
+    }
+
+    /// Uploads the morphed vertices of the node's mesh (its own copy, see `animates_weights`)
+    fn apply(&self, nodes: &[Node], time: f32) {
+        let weights: Vec<f32> = self.samplers.iter().map(|sampler| sampler.sample(time).x).collect();
+        if let Some(ref mesh) = nodes[self.node].mesh {
+            mesh.set_morph_weights(&weights);
+        }
+    }
+}
+
+/// Splits the outputs of a weights channel (the weights of all morph targets for each of the
+/// `elements` keyframe values and tangents) into one list per morph target.
+/// `None` if the number of outputs doesn't match.
+fn split_weights(outputs: &[f32], elements: usize) -> Option<Vec<Vec<Vector4>>> {
+    if elements == 0 {
+        return None
+    }
+    let targets = outputs.len() / elements;
+    if targets == 0 || targets * elements != outputs.len() {
+        return None
+    }
+    Some((0..targets)
+        .map(|target| (0..elements).map(|i| vec4(outputs[i * targets + target], 0.0, 0.0, 0.0)).collect())
+        .collect())
+}
+
+/// Whether an animation changes the morph target weights of the node
+pub fn animates_weights(doc: &gltf::Document, node_index: usize) -> bool {
+    doc.animations()
+        .flat_map(|animation| animation.channels())
+        .any(|channel| channel.target().node().index() == node_index &&
+            matches!(channel.target().property(), Property::MorphTargetWeights))
+}
+
+/// Property addressed by the JSON pointer of a KHR_animation_pointer channel
+/// (only a subset of the animatable properties is supported)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointerTarget {
+    NodeTranslation(usize),
+    NodeRotation(usize),
+    NodeScale(usize),
+    BaseColorFactor(usize),
+    MetallicFactor(usize),
+    RoughnessFactor(usize),
+    EmissiveFactor(usize),
+    EmissiveStrength(usize),
+}
+
+impl PointerTarget {
+    /// Parses e.g. `/materials/0/pbrMetallicRoughness/baseColorFactor`, `None` if unsupported
+    pub fn parse(pointer: &str) -> Option<PointerTarget> {
+        let parts: Vec<&str> = pointer.split('/').collect();
+        if parts.len() < 4 || !parts[0].is_empty() {
+            return None
+        }
+        let index: usize = parts[2].parse().ok()?;
+        let target = match (parts[1], &parts[3..]) {
+            ("nodes", ["translation"]) => PointerTarget::NodeTranslation(index),
+            ("nodes", ["rotation"]) => PointerTarget::NodeRotation(index),
+            ("nodes", ["scale"]) => PointerTarget::NodeScale(index),
+            ("materials", ["pbrMetallicRoughness", "baseColorFactor"]) => PointerTarget::BaseColorFactor(index),
+            ("materials", ["pbrMetallicRoughness", "metallicFactor"]) => PointerTarget::MetallicFactor(index),
+            ("materials", ["pbrMetallicRoughness", "roughnessFactor"]) => PointerTarget::RoughnessFactor(index),
+            ("materials", ["emissiveFactor"]) => PointerTarget::EmissiveFactor(index),
+            ("materials", ["extensions", "KHR_materials_emissive_strength", "emissiveStrength"]) =>
+                PointerTarget::EmissiveStrength(index),
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    /// Number of components of the animated value
+    fn components(self) -> usize {
+        match self {
+            PointerTarget::NodeRotation(_) | PointerTarget::BaseColorFactor(_) => 4,
+            PointerTarget::NodeTranslation(_) | PointerTarget::NodeScale(_) | PointerTarget::EmissiveFactor(_) => 3,
+            PointerTarget::MetallicFactor(_) | PointerTarget::RoughnessFactor(_) |
+                PointerTarget::EmissiveStrength(_) => 1,
+        }
+    }
+}
+
+/// KHR_animation_pointer channel (not exposed by the `gltf` crate, so it is read from the raw JSON)
+pub struct PointerChannel {
+    pub target: PointerTarget,
+    pub sampler: Sampler,
+}
+
+impl PointerChannel {
+    /// Returns `None` (with a warning) for invalid channels and unsupported pointers
+    fn from_json(channel: &Value, animation: &Value, animation_index: usize, imp: &ImportData) -> Option<PointerChannel> {
+        let pointer = channel["target"]["extensions"]["KHR_animation_pointer"]["pointer"].as_str().unwrap_or("");
+        let target = match PointerTarget::parse(pointer) {
+            Some(target) => target,
+            None => {
+                warn!("Ignoring animation channel of animation {}: unsupported KHR_animation_pointer target '{}'",
+                    animation_index, pointer);
+                return None
+            }
+        };
+        let sampler = &animation["samplers"][usize::try_from(channel["sampler"].as_u64()?).ok()?];
+        let read_accessor = |index: &Value| {
+            let accessor = imp.doc.accessors().nth(usize::try_from(index.as_u64()?).ok()?)?;
+            Some(read_f32_attribute(&accessor, &imp.buffers))
+        };
+        let (inputs, outputs) = match (read_accessor(&sampler["input"]), read_accessor(&sampler["output"])) {
+            (Some(inputs), Some(outputs)) => (inputs, outputs),
+            _ => {
+                warn!("Ignoring animation channel of animation {}: invalid sampler accessors", animation_index);
+                return None
+            }
+        };
+        let interpolation = match sampler["interpolation"].as_str() {
+            Some("STEP") => Interpolation::Step,
+            Some("CUBICSPLINE") => Interpolation::CubicSpline,
+            _ => Interpolation::Linear,
+        };
+        let components = target.components();
+        let sampler = Sampler {
+            interpolation,
+            inputs,
+            outputs: outputs.chunks(components)
+                .map(|c| vec4(c[0], *c.get(1).unwrap_or(&0.0), *c.get(2).unwrap_or(&0.0), *c.get(3).unwrap_or(&0.0)))
+                .collect(),
+        };
+        if sampler.inputs.is_empty() || sampler.outputs.len() != sampler.inputs.len() * sampler.stride() {
+            warn!("Ignoring animation channel of animation {}: invalid number of keyframes", animation_index);
+            return None
+        }
+        Some(PointerChannel { target, sampler })
+    }
+
+    fn apply(&self, nodes: &mut [Node], materials: &[Rc<Material>], time: f32) {
+        let material = |index: usize| materials.iter().find(|material| material.index == Some(index));
+        let animate_material = |index: usize, update: &dyn Fn(&mut AnimatedFactors)| {
+            if let Some(material) = material(index) {
+                let mut factors = material.animated.get();
+                update(&mut factors);
+                material.animated.set(factors);
+            }
+        };
+        let value = self.sampler.sample(time);
+        match self.target {
+            PointerTarget::NodeTranslation(node) => if let Some(node) = nodes.get_mut(node) {
+                node.translation = value.truncate();
+                node.transform_changed = true;
+            },
+            PointerTarget::NodeRotation(node) => if let Some(node) = nodes.get_mut(node) {
+                node.rotation = self.sampler.sample_rotation(time);
+                node.transform_changed = true;
+            },
+            PointerTarget::NodeScale(node) => if let Some(node) = nodes.get_mut(node) {
+                node.scale = value.truncate();
+                node.transform_changed = true;
+            },
+            PointerTarget::BaseColorFactor(m) => animate_material(m, &|f| f.base_color_factor = Some(value)),
+            PointerTarget::MetallicFactor(m) => animate_material(m, &|f| f.metallic_factor = Some(value.x)),
+            PointerTarget::RoughnessFactor(m) => animate_material(m, &|f| f.roughness_factor = Some(value.x)),
+            PointerTarget::EmissiveFactor(m) => animate_material(m, &|f| f.emissive_factor = Some(value.truncate())),
+            PointerTarget::EmissiveStrength(m) => animate_material(m, &|f| f.emissive_strength = Some(value.x)),
+        }
+    }
+}
+
+pub struct Animation {
+    pub index: usize, // glTF index
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    pub weights_channels: Vec<WeightsChannel>,
+    pub pointer_channels: Vec<PointerChannel>,
+    /// Time of the last keyframe in seconds
+    pub duration: f32,
+}
+
+impl Animation {
+    pub fn from_gltf(g_animation: &gltf::Animation<'_>, imp: &ImportData) -> Animation {
+        let mut channels: Vec<Channel> = g_animation.channels()
+            .filter_map(|g_channel| Channel::from_gltf(&g_channel, &imp.buffers))
+            .collect();
+        let mut weights_channels: Vec<WeightsChannel> = g_animation.channels()
+            .filter(|g_channel| matches!(g_channel.target().property(), Property::MorphTargetWeights))
+            .filter_map(|g_channel| WeightsChannel::from_gltf(&g_channel, &imp.buffers))
+            .collect();
+        // the pointer channels are removed from the document before it is passed to the `gltf` crate
+        let json = &imp.json["animations"][g_animation.index()];
+        let mut pointer_channels: Vec<PointerChannel> = json["channels"].as_array().into_iter().flatten()
+            .filter(|channel| is_pointer_channel(channel))
+            .filter_map(|channel| PointerChannel::from_json(channel, json, g_animation.index(), imp))
+            .collect();
+        if imp.options.sanitize {
+            let index = g_animation.index();
+            for channel in &mut channels {
+                let target = format!("{:?} of node {}", channel.property, channel.node);
+                warn_sanitized(channel.sampler.sanitize(), index, &target);
+            }
+            for channel in &mut weights_channels {
+                let replaced = channel.samplers.iter_mut().map(Sampler::sanitize).sum();
+                warn_sanitized(replaced, index, &format!("morph target weights of node {}", channel.node));
+            }
+            for channel in &mut pointer_channels {
+                warn_sanitized(channel.sampler.sanitize(), index, &format!("{:?}", channel.target));
+            }
+        }
+        let duration = channels.iter().map(|channel| &channel.sampler)
+            .chain(weights_channels.iter().flat_map(|channel| channel.samplers.iter()))
+            .chain(pointer_channels.iter().map(|channel| &channel.sampler))
+            .map(|sampler| *sampler.inputs.last().unwrap())
+            .fold(0.0, f32::max);
+
+        Animation {
+            index: g_animation.index(),
+            name: g_animation.name().map(|s| s.into()),
+            channels,
+            weights_channels,
+            pointer_channels,
+            duration,
+        }
+    }
+
+    /// Times of the keyframes of all channels, ascending and without duplicates
+    pub fn keyframe_times(&self) -> Vec<f32> {
+        let mut times: Vec<f32> = self.channels.iter().map(|channel| &channel.sampler)
+            .chain(self.weights_channels.iter().flat_map(|channel| channel.samplers.iter()))
+            .chain(self.pointer_channels.iter().map(|channel| &channel.sampler))
+            .flat_map(|sampler| sampler.inputs.iter().cloned())
+            .collect();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        times.dedup();
+        times
+    }
+
+    /// Sets the animated node and material properties to their values at `time` (in seconds)
+    /// and uploads the vertices with animated morph target weights.
+    /// The node transforms need to be updated afterwards (`Scene::update_transforms`).
+    pub fn animate(&self, nodes: &mut [Node], materials: &[Rc<Material>], time: f32) {
+        for channel in &self.channels {
+            channel.apply(nodes, time);
+        }
+        for channel in &self.weights_channels {
+            channel.apply(nodes, time);
+        }
+        for channel in &self.pointer_channels {
+            channel.apply(nodes, materials, time);
+        }
+    }
+}
+
+fn warn_sanitized(replaced: usize, animation_index: usize, target: &str) {
+    if replaced > 0 {
+        warn!("Replaced {} non-finite keyframe times or values of animation {} ({})",
+            replaced, animation_index, target);
+    }
+}
+
+/// Keyframes closer than this to the current time count as the current one when stepping
+const KEYFRAME_EPSILON: f32 = 1e-4;
+
+/// The first keyframe after `time` (or the last one before it if not `forward`) in the
+/// ascending `times`, wrapping around if `looping`. `None` if there is none.
+fn step_keyframe_time(times: &[f32], time: f32, forward: bool, looping: bool) -> Option<f32> {
+    let step = if forward {
+        times.iter().find(|&&t| t > time + KEYFRAME_EPSILON)
+    } else {
+        times.iter().rev().find(|&&t| t < time - KEYFRAME_EPSILON)
+    };
+    match (step, forward) {
+        (Some(&t), _) => Some(t),
+        (None, _) if !looping => None,
+        (None, true) => times.first().cloned(),
+        (None, false) => times.last().cloned(),
+    }
+}
+
+/// Index of the keyframe closest to `time` in the ascending `times`
+pub fn nearest_keyframe(times: &[f32], time: f32) -> Option<usize> {
+    if times.is_empty() {
+        return None
+    }
+    let after = match times.iter().position(|&t| t >= time) {
+        Some(after) => after,
+        None => return Some(times.len() - 1),
+    };
+    if after > 0 && time - times[after - 1] < times[after] - time {
+        Some(after - 1)
+    } else {
+        Some(after)
+    }
+}
+
+/// KHR_animation_pointer channels target a JSON pointer instead of a node
+pub fn is_pointer_channel(channel: &Value) -> bool {
+    channel["target"]["path"] == "pointer"
+}
+
+/// Playback state of one of the animations in `Root::animations`
+pub struct AnimationPlayer {
+    /// Index into `Root::animations`
+    pub animation: usize,
+    /// Current time in seconds
+    pub time: f32,
+    pub playing: bool,
+    /// If true, time wraps around at the end, otherwise the final pose is held
+    pub looping: bool,
+    /// Playback speed multiplier (negative values play backwards)
+    pub speed: f32,
+
+    // the animation needs to be re-applied after jumping while paused
+    dirty: bool,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            animation: 0,
+            time: 0.0,
+            playing: true,
+            looping: true,
+            speed: 1.0,
+            dirty: true,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    #[allow(dead_code)]
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    #[allow(dead_code)]
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn toggle_playing(&mut self) {
+        self.playing = !self.playing;
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Selects another animation (index into `Root::animations`) and jumps to `time`
+    pub fn seek(&mut self, animation: usize, time: f32) {
+        self.animation = animation;
+        self.time = time;
+        self.dirty = true;
+    }
+
+    pub fn jump_to_start(&mut self) {
+        self.time = 0.0;
+        self.dirty = true;
+    }
+
+    pub fn jump_to_end(&mut self, root: &Root) {
+        self.time = root.animations.get(self.animation).map_or(0.0, |animation| animation.duration);
+        self.dirty = true;
+    }
+
+    /// Pauses and moves to the next keyframe of any channel (or the previous one if not `forward`)
+    pub fn step_keyframe(&mut self, root: &Root, forward: bool) {
+        let times = match root.animations.get(self.animation) {
+            Some(animation) => animation.keyframe_times(),
+            None => return,
+        };
+        self.playing = false;
+        if let Some(time) = step_keyframe_time(&times, self.time, forward, self.looping) {
+            self.time = time;
+            self.dirty = true;
+        }
+    }
+
+    /// Pauses and moves the time by `delta` seconds (wrapping around or clamped like when playing)
+    pub fn step_time(&mut self, root: &Root, delta: f32) {
+        let duration = match root.animations.get(self.animation) {
+            Some(animation) => animation.duration,
+            None => return,
+        };
+        self.playing = false;
+        self.time += delta;
+        self.advance(0.0, duration);
+        self.dirty = true;
+    }
+
+    /// Advances the time (if playing) and applies the animation to the nodes.
+    /// Returns true if the node transforms need to be updated (`Scene::update_transforms`).
+    pub fn update(&mut self, root: &mut Root, delta_time: f32) -> bool {
+        let Root { animations, nodes, materials, .. } = root;
+        let animation = match animations.get(self.animation) {
+            Some(animation) => animation,
+            None => return false,
+        };
+        if !self.playing && !self.dirty {
+            return false
+        }
+        self.advance(delta_time, animation.duration);
+        animation.animate(nodes, materials, self.time);
+        self.dirty = false;
+        true
+    }
+
+    fn advance(&mut self, delta_time: f32, duration: f32) {
+        if self.playing {
+            self.time += delta_time * self.speed;
+        }
+        if self.looping && duration > 0.0 {
+            if self.time < 0.0 || self.time > duration {
+                self.time = self.time.rem_euclid(duration);
+            }
+        } else {
+            self.time = self.time.max(0.0).min(duration);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::Deg;
+
+    use crate::importdata::import_source;
+    use crate::memory_source::MemorySource;
+
+    fn scalar_sampler(interpolation: Interpolation, inputs: Vec<f32>, outputs: &[f32]) -> Sampler {
+        Sampler {
+            interpolation,
+            inputs,
+            outputs: outputs.iter().map(|&x| vec4(x, 0.0, 0.0, 0.0)).collect(),
+        }
+    }
+
+    #[test]
+    fn weights_per_target() {
+        // 2 targets, cubic spline: (in-tangents, values, out-tangents) of both per keyframe
+        let outputs = [0.0, 0.0, 1.0, 0.5, 0.0, 0.0,  0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let weights = split_weights(&outputs, 2 * 3).unwrap();
+        assert_eq!(weights.len(), 2);
+        let sampler = Sampler { interpolation: Interpolation::CubicSpline, inputs: vec![0.0, 1.0],
+            outputs: weights[1].clone() };
+        assert_eq!((sampler.sample(0.0).x, sampler.sample(1.0).x), (0.5, 1.0));
+        assert_eq!(weights[0][1].x, 1.0);
+
+        assert!(split_weights(&outputs[..5], 2).is_none());
+        assert!(split_weights(&[], 2).is_none());
+    }
+
+    #[test]
+    fn step_and_linear() {
+        let step = scalar_sampler(Interpolation::Step, vec![0.0, 1.0, 2.0], &[0.0, 1.0, 4.0]);
+        assert_eq!(step.sample(0.9).x, 0.0);
+        assert_eq!(step.sample(1.0).x, 1.0);
+        assert_eq!(step.sample(1.5).x, 1.0);
+        assert_eq!(step.sample(5.0).x, 4.0);
+
+        let linear = scalar_sampler(Interpolation::Linear, vec![0.0, 1.0, 2.0], &[0.0, 1.0, 4.0]);
+        assert_eq!(linear.sample(-1.0).x, 0.0);
+        assert_eq!(linear.sample(0.5).x, 0.5);
+        assert_eq!(linear.sample(1.5).x, 2.5);
+        assert_eq!(linear.sample(5.0).x, 4.0);
+    }
+
+    #[test]
+    fn non_finite_keyframes() {
+        let mut sampler = scalar_sampler(Interpolation::Linear, vec![0.0, f32::NAN, 2.0], &[1.0, f32::INFINITY, 4.0]);
+        assert_eq!(sampler.sanitize(), 2);
+        assert_eq!(sampler.inputs, vec![0.0, 0.0, 2.0]);
+        assert_eq!(sampler.sample(1.0).x, 2.5);
+        assert_eq!(sampler.sanitize(), 0);
+    }
+
+    #[test]
+    fn keyframe_steps() {
+        let times = [0.0, 0.5, 1.0];
+        assert_eq!(step_keyframe_time(&times, 0.0, true, false), Some(0.5));
+        assert_eq!(step_keyframe_time(&times, 0.7, false, false), Some(0.5));
+        assert_eq!(step_keyframe_time(&times, 1.0, true, false), None);
+        assert_eq!(step_keyframe_time(&times, 1.0, true, true), Some(0.0));
+        assert_eq!(step_keyframe_time(&times, 0.0, false, true), Some(1.0));
+        assert_eq!(nearest_keyframe(&times, 0.3), Some(1));
+        assert_eq!(nearest_keyframe(&times, 0.2), Some(0));
+        assert_eq!(nearest_keyframe(&times, 2.0), Some(2));
+        assert_eq!(nearest_keyframe(&[], 0.0), None);
+    }
+
+    #[test]
+    fn rotation_slerp() {
+        let rotations = [Quaternion::from_angle_y(Deg(0.0)), Quaternion::from_angle_y(Deg(90.0))];
+        let sampler = Sampler {
+            interpolation: Interpolation::Linear,
+            inputs: vec![0.0, 1.0],
+            outputs: rotations.iter().map(|q| vec4(q.v.x, q.v.y, q.v.z, q.s)).collect(),
+        };
+        let expected = Quaternion::from_angle_y(Deg(45.0));
+        assert!(sampler.sample_rotation(0.5).dot(expected) > 0.9999);
+
+        // the shorter path is taken if the second quaternion has the opposite sign
+        let sampler = Sampler {
+            outputs: vec![sampler.outputs[0], -sampler.outputs[1]],
+            ..sampler
+        };
+        assert!(sampler.sample_rotation(0.5).dot(expected).abs() > 0.9999);
+    }
+
+    #[test]
+    fn cubic_spline() {
+        // f(t) = t^3 with tangents f'(t) = 3t^2, i.e. the spline reproduces the cubic exactly
+        let keys = [0.0f32, 1.0, 3.0];
+        let outputs: Vec<f32> = keys.iter()
+            .flat_map(|&t| vec![3.0 * t * t, t * t * t, 3.0 * t * t])
+            .collect();
+        let sampler = scalar_sampler(Interpolation::CubicSpline, keys.to_vec(), &outputs);
+
+        assert_eq!(sampler.sample(1.0).x, 1.0);
+        assert_eq!(sampler.sample(3.0).x, 27.0);
+        for &t in &[0.5f32, 2.0, 0.25, 2.5] {
+            let value = sampler.sample(t).x;
+            assert!((value - t * t * t).abs() < 1e-4, "f({}) = {}", t, value);
+        }
+    }
+
+    #[test]
+    fn pointer_channels() {
+        assert_eq!(PointerTarget::parse("/materials/2/pbrMetallicRoughness/roughnessFactor"),
+            Some(PointerTarget::RoughnessFactor(2)));
+        assert_eq!(PointerTarget::parse("/nodes/0/rotation"), Some(PointerTarget::NodeRotation(0)));
+        assert_eq!(PointerTarget::parse("/nodes/x/rotation"), None);
+        assert_eq!(PointerTarget::parse("/cameras/0/perspective/yfov"), None);
+
+        // times [0, 1], scalars [0.2, 0.8], vec3s [(0, 0, 0), (2, 4, 6)]
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["KHR_animation_pointer"],
+            "nodes": [{}],
+            "buffers": [{ "byteLength": 40,
+                "uri": "data:application/octet-stream;base64,AAAAAAAAgD/NzEw+zcxMPwAAAAAAAAAAAAAAAAAAAEAAAIBAAADAQA==" }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 40 }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1] },
+                { "bufferView": 0, "byteOffset": 8, "componentType": 5126, "count": 2, "type": "SCALAR" },
+                { "bufferView": 0, "byteOffset": 16, "componentType": 5126, "count": 2, "type": "VEC3" }
+            ],
+            "animations": [{
+                "samplers": [{ "input": 0, "output": 1 }, { "input": 0, "output": 2 }],
+                "channels": [
+                    { "sampler": 0, "target": { "path": "pointer", "extensions": { "KHR_animation_pointer":
+                        { "pointer": "/materials/0/pbrMetallicRoughness/roughnessFactor" } } } },
+                    { "sampler": 1, "target": { "path": "pointer", "extensions": { "KHR_animation_pointer":
+                        { "pointer": "/nodes/0/translation" } } } },
+                    { "sampler": 0, "target": { "path": "pointer", "extensions": { "KHR_animation_pointer":
+                        { "pointer": "/cameras/0/perspective/yfov" } } } }
+                ]
+            }]
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut root = Root::from_gltf(&imp);
+        let animation = &root.animations[0];
+        assert!(animation.channels.is_empty());
+        let targets: Vec<_> = animation.pointer_channels.iter().map(|channel| channel.target).collect();
+        assert_eq!(targets, [PointerTarget::RoughnessFactor(0), PointerTarget::NodeTranslation(0)]);
+        assert_eq!(animation.duration, 1.0);
+        assert!((animation.pointer_channels[0].sampler.sample(0.5).x - 0.5).abs() < 1e-6);
+
+        let mut player = AnimationPlayer::default();
+        player.update(&mut root, 0.5);
+        assert_eq!(root.nodes[0].translation, vec3(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn player_looping_and_clamping() {
+        let mut player = AnimationPlayer::default();
+        player.set_speed(2.0);
+        player.advance(1.5, 2.0);
+        assert_eq!(player.time, 1.0); // wrapped
+        player.set_speed(1.0);
+        player.advance(1.0, 2.0);
+        assert_eq!(player.time, 2.0); // the end is reachable
+
+        player.set_speed(-1.0);
+        player.advance(2.5, 2.0);
+        assert_eq!(player.time, 1.5); // wrapped backwards
+
+        player.set_looping(false);
+        player.set_speed(1.0);
+        player.advance(10.0, 2.0);
+        assert_eq!(player.time, 2.0); // holds the final pose
+
+        player.toggle_playing();
+        player.jump_to_start();
+        player.advance(1.0, 2.0);
+        assert_eq!(player.time, 0.0);
+    }
+}
@@ -19,7 +19,10 @@ pub struct Camera {
     // perspective camera
     // TODO!: setters that update...
     pub fovy: Deg<f32>,
-    aspect_ratio: f32,
+    aspect_ratio: f32, // of the window
+    /// Aspect ratio given by the glTF camera. If set, the image is letterboxed/pillarboxed
+    /// to it (see `letterbox_scale`) instead of using the window's aspect ratio.
+    pub fixed_aspect_ratio: Option<f32>,
 
     // orthographic camera
     pub xmag: Option<f32>,
@@ -37,6 +40,7 @@ impl Default for Camera {
 
             fovy: Deg(ZOOM),
             aspect_ratio: 1.0,
+            fixed_aspect_ratio: None,
 
             xmag: None,
             ymag: None,
@@ -56,13 +60,13 @@ impl Camera {
             zfar: None,
             fovy: Deg(0.0),
             aspect_ratio: 1.0,
+            fixed_aspect_ratio: None,
             xmag: None,
             ymag: None,
         };
         match g_camera.projection() {
             Projection::Perspective(persp) => {
-                // TODO!!: ignoring aspect ratio for now as it would require window resizing...
-                let _aspect = persp.aspect_ratio();
+                camera.fixed_aspect_ratio = persp.aspect_ratio();
                 camera.fovy = Deg::from(Rad(persp.yfov()));
                 // cgmath asserts znear > 0, but the gltf spec allows it
                 camera.znear = persp.znear().max(0.0001); 
@@ -71,6 +75,7 @@ impl Camera {
             Projection::Orthographic(ortho) => {
                 camera.xmag = Some(ortho.xmag());
                 camera.ymag = Some(ortho.ymag());
+                camera.fixed_aspect_ratio = Some(ortho.xmag() / ortho.ymag());
                 camera.znear = ortho.znear();
                 camera.zfar = Some(ortho.zfar());
             }
@@ -84,15 +89,44 @@ impl Camera {
         self.update_projection_matrix();
     }
 
+    /// Scale of the image relative to the window to keep the fixed aspect ratio, e.g. (1, 0.5)
+    /// for a camera that is twice as wide (relative to its height) as the window
+    pub fn letterbox_scale(&self) -> (f32, f32) {
+        match self.fixed_aspect_ratio {
+            Some(fixed) if fixed > self.aspect_ratio => (1.0, self.aspect_ratio / fixed),
+            Some(fixed) => (fixed / self.aspect_ratio, 1.0),
+            None => (1.0, 1.0),
+        }
+    }
+
+    /// Switches between perspective and orthographic projection. The orthographic view volume
+    /// matches the perspective frustum at `focus_distance` (and vice versa), so that objects at
+    /// that distance keep their apparent size.
+    pub fn toggle_orthographic(&mut self, focus_distance: f32) {
+        if let Some(ymag) = self.ymag {
+            self.fovy = Deg::from(Rad(2.0 * (ymag / focus_distance).atan()));
+            self.xmag = None;
+            self.ymag = None;
+        } else {
+            self.ymag = Some(focus_distance * (Rad::from(self.fovy).0 / 2.0).tan()); // xmag: see below
+            if self.zfar.is_none() {
+                self.zfar = Camera::default().zfar;
+            }
+        }
+        self.update_projection_matrix();
+    }
+
     pub fn aspect_ratio(&self) -> f32 {
         self.aspect_ratio
     }
 
     pub fn update_projection_matrix(&mut self) {
-        if let Some(xmag) = self.xmag {
+        let aspect_ratio = self.fixed_aspect_ratio.unwrap_or(self.aspect_ratio);
+        if let Some(ymag) = self.ymag {
             // from https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#orthographic-projection
-            let r = xmag;
-            let t = self.ymag.unwrap();
+            let t = ymag;
+            let r = t * aspect_ratio;
+            self.xmag = Some(r);
             let f = self.zfar.unwrap();
             let n = self.znear;
             self.projection_matrix = Matrix4::new(
@@ -104,11 +138,11 @@ impl Camera {
         } else if let Some(zfar) = self.zfar {
             self.projection_matrix = perspective(
                 self.fovy,
-                self.aspect_ratio,
+                aspect_ratio,
                 self.znear, zfar)
         } else {
             // from https://github.com/KhronosGroup/glTF/tree/master/specification/2.0#infinite-perspective-projection
-            let a = self.aspect_ratio;
+            let a = aspect_ratio;
             let y = Rad::from(self.fovy).0;
             let n = self.znear;
 
@@ -119,6 +153,11 @@ impl Camera {
                 0.0,                   0.0,              -2.0*n, 0.0
             );
         }
+
+        let (scale_x, scale_y) = self.letterbox_scale();
+        if (scale_x, scale_y) != (1.0, 1.0) {
+            self.projection_matrix = Matrix4::from_nonuniform_scale(scale_x, scale_y, 1.0) * self.projection_matrix;
+        }
     }
 
     pub fn is_perspective(&self) -> bool {
@@ -137,3 +176,72 @@ impl Camera {
         format!("{} ({:?}, {})", self.index, self.name, type_)
     }
 }
+
+/// Smallest near/far ratio used by `fit_depth_range` (i.e. when the camera is close to or inside the bounds)
+const MIN_NEAR_FAR_RATIO: f32 = 1e-4;
+
+/// Near and far plane distances that tightly enclose the bounding sphere of `bounds`
+/// as seen from `eye`, for the best depth buffer precision without clipping.
+pub fn fit_depth_range(bounds: &Aabb3, eye: Point3) -> (f32, f32) {
+    let radius = (bounds.max - bounds.min).magnitude() / 2.0;
+    let center = bounds.min + (bounds.max - bounds.min) / 2.0;
+    let distance = (eye - center).magnitude();
+    let far = (distance + radius) * 1.01;
+    if !far.is_finite() || far <= 0.0 {
+        let camera = Camera::default();
+        return (camera.znear, camera.zfar.unwrap())
+    }
+    let near = ((distance - radius) * 0.99).max(far * MIN_NEAR_FAR_RATIO);
+    (near, far)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orthographic_toggle() {
+        let mut camera = Camera { fovy: Deg(90.0), ..Camera::default() };
+        camera.update_aspect_ratio(2.0);
+        camera.toggle_orthographic(3.0);
+        assert!(!camera.is_perspective());
+        assert!((camera.ymag.unwrap() - 3.0).abs() < 1e-5);
+        assert!((camera.xmag.unwrap() - 6.0).abs() < 1e-5);
+
+        camera.ymag = Some(1.5); // zoomed in
+        camera.toggle_orthographic(3.0);
+        assert!(camera.is_perspective());
+        assert!((camera.fovy.0 - 53.130_1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn letterbox() {
+        let mut camera = Camera { fixed_aspect_ratio: Some(2.0), ..Camera::default() };
+        camera.update_aspect_ratio(1.0);
+        assert_eq!(camera.letterbox_scale(), (1.0, 0.5));
+        camera.update_aspect_ratio(4.0);
+        assert_eq!(camera.letterbox_scale(), (0.5, 1.0));
+        // not distorted in the window
+        let p = camera.projection_matrix;
+        assert!((p[0][0] * 4.0 - p[1][1]).abs() < 1e-5);
+    }
+
+    #[test]
+    fn depth_range() {
+        let bounds = Aabb3 { min: Point3::new(-1.0, -1.0, -1.0), max: Point3::new(1.0, 1.0, 1.0) };
+        let radius = 3f32.sqrt();
+
+        let (near, far) = fit_depth_range(&bounds, Point3::new(0.0, 0.0, 10.0));
+        assert!(near < 10.0 - radius && near > 10.0 - radius - 0.1);
+        assert!(far > 10.0 + radius && far < 10.0 + radius + 0.2);
+
+        // inside the bounds (close inspection)
+        let (near, far) = fit_depth_range(&bounds, Point3::new(0.0, 0.0, 0.5));
+        assert!(far > 0.5 + radius);
+        assert_eq!(near, far * MIN_NEAR_FAR_RATIO);
+
+        // empty scene
+        let point = Aabb3 { min: Point3::new(0.0, 0.0, 0.0), max: Point3::new(0.0, 0.0, 0.0) };
+        assert_eq!(fit_depth_range(&point, Point3::new(0.0, 0.0, 0.0)), (0.01, 1000.0));
+    }
+}
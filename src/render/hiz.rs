@@ -0,0 +1,322 @@
+use std::os::raw::c_void;
+use std::ptr;
+
+use gltf::material::AlphaMode;
+use num_traits::clamp;
+
+use crate::controls::CameraParams;
+use crate::render::{Root, Scene};
+use crate::render::math::*;
+use crate::shader::Shader;
+
+/// Maximum width/height of the depth that is read back (the pre-pass is reduced to it on the GPU)
+const MAX_READBACK_SIZE: usize = 256;
+
+/// Hierarchical Z occlusion culling: renders the depth of the opaque primitives in a pre-pass,
+/// reduces it to the farthest depth per block on the GPU and reads it back as the base of a
+/// max-depth pyramid, against which the screen-space bounding boxes of primitives are tested.
+pub struct HiZ {
+    width: u32,
+    height: u32,
+    block_size: u32,
+
+    depth_framebuffer: u32,
+    depth_texture: u32,
+    reduced_framebuffer: u32,
+    reduced_texture: u32,
+
+    empty_vao: u32,
+
+    depth_shader: Shader,
+    u_mvp_matrix: i32,
+    reduce_shader: Shader,
+    u_block_size: i32,
+
+    /// From the last `render`
+    pub pyramid: Option<HiZPyramid>,
+}
+
+impl HiZ {
+    pub fn new() -> HiZ {
+        let mut depth_shader = Shader::from_source(
+            include_str!("../shaders/picking-vert.glsl"),
+            include_str!("../shaders/depth-frag.glsl"),
+            &[]);
+        let mut reduce_shader = Shader::from_source(
+            include_str!("../shaders/fullscreen-vert.glsl"),
+            include_str!("../shaders/hiz-reduce-frag.glsl"),
+            &[]);
+        let mut hi_z = unsafe {
+            HiZ {
+                width: 0,
+                height: 0,
+                block_size: 1,
+                depth_framebuffer: 0,
+                depth_texture: 0,
+                reduced_framebuffer: 0,
+                reduced_texture: 0,
+                empty_vao: 0,
+                u_mvp_matrix: depth_shader.uniform_location("u_MVPMatrix"),
+                depth_shader,
+                u_block_size: reduce_shader.uniform_location("u_BlockSize"),
+                reduce_shader,
+                pyramid: None,
+            }
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut hi_z.empty_vao);
+            hi_z.reduce_shader.use_program();
+            let u_depth = hi_z.reduce_shader.uniform_location("u_Depth");
+            hi_z.reduce_shader.set_int(u_depth, 0);
+        }
+        hi_z
+    }
+
+    fn reduced_size(&self) -> (usize, usize) {
+        let block_size = self.block_size as usize;
+        ((self.width as usize).div_ceil(block_size), (self.height as usize).div_ceil(block_size))
+    }
+
+    unsafe fn create_framebuffers(&mut self) {
+        gl::GenFramebuffers(1, &mut self.depth_framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.depth_framebuffer);
+        gl::GenTextures(1, &mut self.depth_texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::DEPTH_COMPONENT24 as i32, self.width as i32, self.height as i32,
+            0, gl::DEPTH_COMPONENT, gl::FLOAT, ptr::null());
+        set_texture_params();
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, self.depth_texture, 0);
+        gl::DrawBuffer(gl::NONE);
+        gl::ReadBuffer(gl::NONE);
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: Hi-Z depth framebuffer is not complete!");
+        }
+
+        let (reduced_width, reduced_height) = self.reduced_size();
+        gl::GenFramebuffers(1, &mut self.reduced_framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.reduced_framebuffer);
+        gl::GenTextures(1, &mut self.reduced_texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.reduced_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R32F as i32, reduced_width as i32, reduced_height as i32,
+            0, gl::RED, gl::FLOAT, ptr::null());
+        set_texture_params();
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.reduced_texture, 0);
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: Hi-Z framebuffer is not complete!");
+        }
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_framebuffers(&mut self) {
+        gl::DeleteFramebuffers(1, &self.depth_framebuffer);
+        gl::DeleteTextures(1, &self.depth_texture);
+        gl::DeleteFramebuffers(1, &self.reduced_framebuffer);
+        gl::DeleteTextures(1, &self.reduced_texture);
+    }
+
+    /// Recreates the framebuffers if the size changed
+    fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return
+        }
+        unsafe {
+            if self.width > 0 {
+                self.delete_framebuffers();
+            }
+            self.width = width;
+            self.height = height;
+            self.block_size = (width.max(height) as usize).div_ceil(MAX_READBACK_SIZE) as u32;
+            self.create_framebuffers();
+        }
+    }
+
+    /// Renders the depth of the opaque primitives of the given nodes at the size of the current
+    /// viewport and updates `pyramid`. Restores the previously bound framebuffer.
+    pub unsafe fn render(&mut self, scene: &Scene, root: &Root, nodes: &[usize], cam_params: &CameraParams) {
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let mut previous_framebuffer = 0;
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+        self.resize(viewport[2].max(1) as u32, viewport[3].max(1) as u32);
+
+        // depth pre-pass (blended and masked primitives don't hide what's behind them)
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.depth_framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+        gl::Disable(gl::BLEND);
+        self.depth_shader.use_program();
+        let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+        for &node_id in nodes {
+            let node = &root.nodes[node_id];
+            let mesh = match node.mesh {
+                Some(ref mesh) => mesh,
+                None => continue,
+            };
+            let transforms = match node.instances {
+                Some(ref instances) => instances.transforms.iter().map(|t| node.final_transform * t).collect(),
+                None => vec![node.final_transform],
+            };
+            let occluders = mesh.primitives.iter().filter(|p| scene.shows_primitive(p) &&
+                p.material().alpha_mode == AlphaMode::Opaque && !p.material().is_transmissive());
+            for primitive in occluders {
+                match scene.face_culling.culled_face(primitive.material().double_sided) {
+                    Some(face) => {
+                        gl::Enable(gl::CULL_FACE);
+                        gl::CullFace(face);
+                    }
+                    None => gl::Disable(gl::CULL_FACE),
+                }
+                for transform in &transforms {
+                    self.depth_shader.set_mat4(self.u_mvp_matrix, &(view_projection * transform));
+                    gl::FrontFace(scene.face_culling.front_face(transform));
+                    primitive.draw_geometry();
+                }
+            }
+        }
+        gl::CullFace(gl::BACK);
+        gl::FrontFace(gl::CCW);
+
+        // farthest depth per block
+        let (reduced_width, reduced_height) = self.reduced_size();
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.reduced_framebuffer);
+        gl::Viewport(0, 0, reduced_width as i32, reduced_height as i32);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::BindVertexArray(self.empty_vao);
+        self.reduce_shader.use_program();
+        self.reduce_shader.set_int(self.u_block_size, self.block_size as i32);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+
+        let mut depths = vec![0.0f32; reduced_width * reduced_height];
+        gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        gl::ReadPixels(0, 0, reduced_width as i32, reduced_height as i32, gl::RED, gl::FLOAT,
+            depths.as_mut_ptr() as *mut c_void);
+        self.pyramid = Some(HiZPyramid::new(reduced_width, reduced_height, depths));
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer as u32);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+    }
+}
+
+impl Drop for HiZ {
+    fn drop(&mut self) {
+        unsafe {
+            if self.width > 0 {
+                self.delete_framebuffers();
+            }
+            gl::DeleteVertexArrays(1, &self.empty_vao);
+        }
+    }
+}
+
+unsafe fn set_texture_params() {
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+}
+
+struct DepthLevel {
+    width: usize,
+    height: usize,
+    /// Window depth (0: near, 1: far), rows from the bottom
+    depths: Vec<f32>,
+}
+
+impl DepthLevel {
+    fn get(&self, x: usize, y: usize) -> f32 {
+        self.depths[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    /// Half the size (rounded up), each texel is the farthest of the 2x2 it covers
+    fn downsample(&self) -> DepthLevel {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let depths = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.get(2 * x, 2 * y).max(self.get(2 * x + 1, 2 * y))
+                .max(self.get(2 * x, 2 * y + 1)).max(self.get(2 * x + 1, 2 * y + 1)))
+            .collect();
+        DepthLevel { width, height, depths }
+    }
+}
+
+/// Max-depth mip chain of a depth buffer, down to 1x1
+pub struct HiZPyramid {
+    levels: Vec<DepthLevel>,
+}
+
+impl HiZPyramid {
+    pub fn new(width: usize, height: usize, depths: Vec<f32>) -> HiZPyramid {
+        assert_eq!(depths.len(), width * height);
+        let mut levels = vec![DepthLevel { width: width.max(1), height: height.max(1), depths }];
+        while let Some(level) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            let next = level.downsample();
+            levels.push(next);
+        }
+        HiZPyramid { levels }
+    }
+
+    /// Farthest depth in the rectangle between `min` and `max` (0..1 from the bottom left),
+    /// looked up in the level where it covers at most 2x2 texels
+    fn max_depth(&self, min: Vector2, max: Vector2) -> f32 {
+        let base = &self.levels[0];
+        let size = ((max.x - min.x) * base.width as f32).max((max.y - min.y) * base.height as f32);
+        let level = &self.levels[(size.max(1.0).log2().ceil() as usize).min(self.levels.len() - 1)];
+        let texel = |coord: f32, size: usize| ((coord * size as f32) as usize).min(size - 1);
+        let (x0, x1) = (texel(min.x, level.width), texel(max.x, level.width));
+        let (y0, y1) = (texel(min.y, level.height), texel(max.y, level.height));
+        (y0..=y1)
+            .flat_map(|y| (x0..=x1).map(move |x| (x, y)))
+            .map(|(x, y)| level.get(x, y))
+            .fold(0.0, f32::max)
+    }
+
+    /// Whether the box (world space) is completely behind the depth. Boxes that cross the
+    /// near plane or are outside the view count as visible.
+    pub fn occludes(&self, bounds: &Aabb3, view_projection: &Matrix4) -> bool {
+        let mut min = vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = -min;
+        for corner in &bounds.to_corners() {
+            let clip = view_projection * corner.to_homogeneous();
+            if clip.w <= 0.0 {
+                return false
+            }
+            let ndc = clip.truncate() / clip.w;
+            min = vec3(min.x.min(ndc.x), min.y.min(ndc.y), min.z.min(ndc.z));
+            max = vec3(max.x.max(ndc.x), max.y.max(ndc.y), max.z.max(ndc.z));
+        }
+        if max.x < -1.0 || min.x > 1.0 || max.y < -1.0 || min.y > 1.0 || min.z < -1.0 {
+            return false
+        }
+        let to_window = |ndc: f32| (clamp(ndc, -1.0, 1.0) + 1.0) / 2.0;
+        let nearest_depth = (min.z + 1.0) / 2.0;
+        nearest_depth > self.max_depth(Vector2::new(to_window(min.x), to_window(min.y)),
+            Vector2::new(to_window(max.x), to_window(max.y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pyramid() {
+        // a wall at depth 0.5 covering the left 3 of 5 columns, the rest is background
+        let depths = (0..15).map(|i| if i % 5 < 3 { 0.5 } else { 1.0 }).collect();
+        let pyramid = HiZPyramid::new(5, 3, depths);
+        let sizes: Vec<_> = pyramid.levels.iter().map(|level| (level.width, level.height)).collect();
+        assert_eq!(sizes, vec![(5, 3), (3, 2), (2, 1), (1, 1)]);
+        assert_eq!(pyramid.max_depth(Vector2::new(0.0, 0.0), Vector2::new(0.3, 0.3)), 0.5);
+        assert_eq!(pyramid.max_depth(Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0)), 1.0);
+
+        // unit cube in front of / behind the wall (orthographic view along -z)
+        let view_projection = cgmath::ortho(-1.0, 1.0, -1.0, 1.0, 0.0, 10.0);
+        let cube = |z: f32| Aabb3::new(Point3::new(-0.9, -0.5, z - 1.0), Point3::new(-0.4, 0.5, z));
+        assert!(pyramid.occludes(&cube(-8.0), &view_projection)); // depths 0.8..0.9
+        assert!(!pyramid.occludes(&cube(-2.0), &view_projection)); // depths 0.2..0.3
+    }
+}
@@ -0,0 +1,186 @@
+use std::convert::TryFrom;
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::warn;
+use serde_json::Value;
+
+use crate::render::math::*;
+use crate::render::{buffer_size, check_accessor, read_f32_attribute};
+use crate::importdata::ImportData;
+
+/// First of the four attribute locations of the per-instance matrix (one per column)
+const INSTANCE_MATRIX_LOCATION: u32 = 8;
+
+/// Whether the context has per-instance attributes, see `set_instanced_arrays_supported`
+static INSTANCED_ARRAYS_SUPPORTED: AtomicBool = AtomicBool::new(true);
+
+/// Selects how instanced meshes are drawn: with one instanced draw call, or (if `false`)
+/// with one draw call per instance that sets the instance matrix as a constant attribute.
+/// Set once after creating the context.
+pub fn set_instanced_arrays_supported(supported: bool) {
+    INSTANCED_ARRAYS_SUPPORTED.store(supported, Ordering::Relaxed)
+}
+
+pub fn instanced_arrays_supported() -> bool {
+    INSTANCED_ARRAYS_SUPPORTED.load(Ordering::Relaxed)
+}
+
+/// EXT_mesh_gpu_instancing: per-instance transforms of a node's mesh
+/// (relative to the node), uploaded as an instanced matrix attribute.
+pub struct Instances {
+    pub transforms: Vec<Matrix4>,
+    vbo: u32,
+}
+
+impl Instances {
+    /// Returns `None` if the node doesn't use the extension or its attributes are invalid,
+    /// an error if they read outside their buffers
+    pub fn from_gltf(imp: &ImportData, node_index: usize) -> Result<Option<Instances>, String> {
+        let attributes = match imp.extension("nodes", node_index, "EXT_mesh_gpu_instancing")
+            .and_then(|extension| extension.get("attributes"))
+        {
+            Some(attributes) => attributes,
+            None => return Ok(None),
+        };
+        let read = |name, components| read_instance_attribute(imp, attributes, name, components, node_index);
+        let values = (read("TRANSLATION", 3)?, read("ROTATION", 4)?, read("SCALE", 3)?);
+        let (translations, rotations, scales) = match values {
+            (Some(translations), Some(rotations), Some(scales)) => (translations, rotations, scales),
+            _ => return Ok(None),
+        };
+
+        let counts = [translations.len() / 3, rotations.len() / 4, scales.len() / 3];
+        let count = match counts.iter().cloned().filter(|&count| count > 0).min() {
+            Some(count) => count,
+            None => return Ok(None),
+        };
+        if counts.iter().any(|&c| c > 0 && c != count) {
+            warn!("EXT_mesh_gpu_instancing attributes of node {} have different counts ({:?}), using {}",
+                node_index, counts, count);
+        }
+
+        let transforms = instance_transforms(&translations, &rotations, &scales, count);
+        let mut vbo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, buffer_size::<Matrix4>(transforms.len()),
+                transforms.as_ptr() as *const c_void, gl::STATIC_DRAW);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        Ok(Some(Instances { transforms, vbo }))
+    }
+
+    pub fn count(&self) -> usize {
+        self.transforms.len()
+    }
+
+    /// Sets up the instanced matrix attribute in the currently bound vertex array
+    pub unsafe fn bind(&self) {
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        let stride = size_of::<Matrix4>() as i32;
+        for column in 0..4 {
+            let location = INSTANCE_MATRIX_LOCATION + column;
+            gl::EnableVertexAttribArray(location);
+            gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, stride,
+                (column as usize * size_of::<Vector4>()) as *const c_void);
+            gl::VertexAttribDivisor(location, 1);
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    /// Disables the instanced matrix attribute in the currently bound vertex array again
+    pub unsafe fn unbind(&self) {
+        for column in 0..4 {
+            gl::DisableVertexAttribArray(INSTANCE_MATRIX_LOCATION + column);
+        }
+        reset_instance_matrix();
+    }
+}
+
+impl Drop for Instances {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.vbo) }
+    }
+}
+
+/// Sets the instance matrix attribute to the identity for non-instanced draw calls
+/// (the attribute array is disabled for those, so the constant value is used)
+pub unsafe fn reset_instance_matrix() {
+    set_instance_matrix(&Matrix4::identity());
+}
+
+/// Sets the constant value of the instance matrix attribute (used while its array is disabled)
+pub unsafe fn set_instance_matrix(transform: &Matrix4) {
+    for column in 0..4 {
+        let value: &[f32; 4] = transform[column].as_ref();
+        gl::VertexAttrib4fv(INSTANCE_MATRIX_LOCATION + column as u32, value.as_ptr());
+    }
+}
+
+/// Reads an optional attribute (empty if missing), `None` if it is invalid, an error if it reads
+/// outside its buffer
+fn read_instance_attribute(imp: &ImportData, attributes: &Value, name: &str, components: usize,
+    node_index: usize) -> Result<Option<Vec<f32>>, String>
+{
+    let index = match attributes.get(name).and_then(Value::as_u64) {
+        Some(index) => usize::try_from(index).unwrap_or(usize::MAX),
+        None => return Ok(Some(vec![])),
+    };
+    let accessor = match imp.doc.accessors().nth(index) {
+        Some(accessor) => accessor,
+        None => {
+            warn!("Invalid {} accessor {} of EXT_mesh_gpu_instancing (node {})", name, index, node_index);
+            return Ok(None)
+        }
+    };
+    if accessor.dimensions().multiplicity() != components {
+        warn!("{} accessor of EXT_mesh_gpu_instancing (node {}) must have {} components",
+            name, node_index, components);
+        return Ok(None)
+    }
+    check_accessor(&accessor, &imp.buffers)?;
+    Ok(Some(read_f32_attribute(&accessor, &imp.buffers)))
+}
+
+/// Builds `count` TRS matrices, missing attributes default to the identity
+fn instance_transforms(translations: &[f32], rotations: &[f32], scales: &[f32], count: usize) -> Vec<Matrix4> {
+    (0..count)
+        .map(|i| {
+            let translation = match translations.get(i * 3..i * 3 + 3) {
+                Some(t) => vec3(t[0], t[1], t[2]),
+                None => Vector3::zero(),
+            };
+            let rotation = match rotations.get(i * 4..i * 4 + 4) {
+                Some(r) => Quaternion::new(r[3], r[0], r[1], r[2]).normalize(), // NOTE: different element order!
+                None => Quaternion::one(),
+            };
+            let scale = match scales.get(i * 3..i * 3 + 3) {
+                Some(s) => vec3(s[0], s[1], s[2]),
+                None => vec3(1.0, 1.0, 1.0),
+            };
+            Matrix4::from_translation(translation) *
+                Matrix4::from(rotation) *
+                Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms() {
+        let translations = [1.0, 2.0, 3.0, -1.0, 0.0, 0.0];
+        let rotations = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0]; // identity, 180° around z
+        let transforms = instance_transforms(&translations, &rotations, &[], 2);
+        assert_eq!(transforms.len(), 2);
+        assert_eq!(transforms[0], Matrix4::from_translation(vec3(1.0, 2.0, 3.0)));
+
+        let p = transforms[1].transform_point(Point3::new(1.0, 0.0, 0.0));
+        assert!((p - Point3::new(-2.0, 0.0, 0.0)).magnitude() < 1e-6);
+    }
+}
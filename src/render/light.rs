@@ -0,0 +1,138 @@
+use log::warn;
+use serde_json::Value;
+
+use crate::render::math::*;
+use crate::importdata::ImportData;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    /// Cone angles in radians
+    Spot { inner_cone_angle: f32, outer_cone_angle: f32 },
+}
+
+/// KHR_lights_punctual: a light attached to a node. The light's position and direction
+/// are given by the node's world transform (see `update_transform`).
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct Light {
+    pub index: usize, // index in the extension's `lights`
+    pub name: Option<String>,
+    pub kind: LightKind,
+    pub color: Vector3,
+    pub intensity: f32,
+    /// Distance at which the intensity reaches zero (`None`: infinite)
+    pub range: Option<f32>,
+
+    /// World space, updated with the node's transform
+    pub position: Vector3,
+    /// World space (normalized), lights point along the node's -Z axis
+    pub direction: Vector3,
+}
+
+impl Light {
+    /// Returns `None` if the node doesn't reference a light or the light is invalid
+    pub fn from_gltf(imp: &ImportData, node_index: usize) -> Option<Light> {
+        let index = imp.extension("nodes", node_index, "KHR_lights_punctual")?
+            .get("light")?
+            .as_u64()? as usize;
+        let light = match imp.json["extensions"]["KHR_lights_punctual"]["lights"].get(index) {
+            Some(light) => light,
+            None => {
+                warn!("Node {} references light {} which doesn't exist, ignoring it", node_index, index);
+                return None
+            }
+        };
+        let number = |value: &Value, default: f32| value.as_f64().map_or(default, |v| v as f32);
+
+        let kind = match light["type"].as_str() {
+            Some("directional") => LightKind::Directional,
+            Some("point") => LightKind::Point,
+            Some("spot") => LightKind::Spot {
+                inner_cone_angle: number(&light["spot"]["innerConeAngle"], 0.0),
+                outer_cone_angle: number(&light["spot"]["outerConeAngle"], std::f32::consts::FRAC_PI_4),
+            },
+            other => {
+                warn!("Light {} has an invalid type ({:?}), ignoring it", index, other);
+                return None
+            }
+        };
+        let color = match light["color"].as_array() {
+            Some(c) if c.len() == 3 => vec3(number(&c[0], 1.0), number(&c[1], 1.0), number(&c[2], 1.0)),
+            _ => vec3(1.0, 1.0, 1.0),
+        };
+
+        Some(Light {
+            index,
+            name: light["name"].as_str().map(|s| s.into()),
+            kind,
+            color,
+            intensity: number(&light["intensity"], 1.0),
+            range: light["range"].as_f64().map(|r| r as f32),
+
+            position: Vector3::zero(),
+            direction: vec3(0.0, 0.0, -1.0),
+        })
+    }
+
+    /// Derives the world space position and direction from the node's world transform
+    pub fn update_transform(&mut self, transform: &Matrix4) {
+        self.position = transform.transform_point(Point3::new(0.0, 0.0, 0.0)).to_vec();
+        let direction = transform.transform_vector(vec3(0.0, 0.0, -1.0));
+        if direction.magnitude2() > 0.0 {
+            self.direction = direction.normalize();
+        }
+    }
+
+    /// Short description for the node hierarchy (index, name and type)
+    pub fn description(&self) -> String {
+        let kind = match self.kind {
+            LightKind::Directional => "directional",
+            LightKind::Point => "point",
+            LightKind::Spot { .. } => "spot",
+        };
+        format!("{} ({:?}, {})", self.index, self.name, kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importdata::import_source;
+    use crate::memory_source::MemorySource;
+    use crate::render::Root;
+
+    #[test]
+    fn light_and_camera_on_one_node() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "nodes": [
+                { "children": [1], "translation": [0, 2, 0] },
+                { "camera": 0, "translation": [1, 0, 0], "rotation": [0, 0.7071068, 0, 0.7071068],
+                  "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+            ],
+            "cameras": [{ "type": "perspective", "perspective": { "yfov": 1, "znear": 0.1 } }],
+            "extensions": { "KHR_lights_punctual": { "lights": [
+                { "name": "spot", "type": "spot", "color": [1, 0.5, 0], "intensity": 10,
+                  "spot": { "outerConeAngle": 0.5 } }
+            ] } }
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut root = Root::from_gltf(&imp);
+        assert_eq!(root.camera_nodes, vec![1]);
+        assert_eq!(root.light_nodes, vec![1]);
+
+        let node = root.unsafe_get_node_mut(0);
+        node.update_transform(&mut root, &Matrix4::identity(), true);
+        let light = root.nodes[1].light.as_ref().unwrap();
+        assert_eq!(light.kind, LightKind::Spot { inner_cone_angle: 0.0, outer_cone_angle: 0.5 });
+        assert_eq!(light.color, vec3(1.0, 0.5, 0.0));
+        assert_eq!(light.intensity, 10.0);
+        // same world transform as the camera: translated by the parent, rotated 90° around Y
+        assert!((light.position - vec3(1.0, 2.0, 0.0)).magnitude() < 1e-6);
+        assert!((light.direction - vec3(-1.0, 0.0, 0.0)).magnitude() < 1e-6);
+        let camera_position = root.nodes[1].final_transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+        assert!((camera_position.to_vec() - light.position).magnitude() < 1e-6);
+    }
+}
@@ -0,0 +1,95 @@
+use std::mem::{size_of, size_of_val};
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::render::math::*;
+use crate::shader::Shader;
+
+/// Draws line segments in world space (e.g. for measurements and debug views)
+pub struct LineRenderer {
+    vao: u32,
+    vbo: u32,
+
+    shader: Shader,
+    u_mvp_matrix: i32,
+    u_color: i32,
+}
+
+impl LineRenderer {
+    pub fn new() -> LineRenderer {
+        let mut shader = Shader::from_source(
+            include_str!("../shaders/picking-vert.glsl"),
+            include_str!("../shaders/lines-frag.glsl"),
+            &[]);
+        let (u_mvp_matrix, u_color) = unsafe {
+            (shader.uniform_location("u_MVPMatrix"), shader.uniform_location("u_Color"))
+        };
+        let (mut vao, mut vbo) = (0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size_of::<Vector3>() as i32, ptr::null());
+            gl::BindVertexArray(0);
+        }
+        LineRenderer { vao, vbo, shader, u_mvp_matrix, u_color }
+    }
+
+    /// Draws a line for each pair of points. `depth_test: false` draws the lines
+    /// on top of everything.
+    pub unsafe fn draw(&mut self, points: &[Vector3], color: &Vector4, view_projection: &Matrix4, depth_test: bool) {
+        if points.len() < 2 {
+            return
+        }
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        gl::BufferData(gl::ARRAY_BUFFER, size_of_val(points) as isize,
+            points.as_ptr() as *const c_void, gl::STREAM_DRAW);
+
+        self.shader.use_program();
+        self.shader.set_mat4(self.u_mvp_matrix, view_projection);
+        self.shader.set_vector4(self.u_color, color);
+        if !depth_test {
+            gl::Disable(gl::DEPTH_TEST);
+        }
+        gl::DrawArrays(gl::LINES, 0, points.len() as i32);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindVertexArray(0);
+    }
+}
+
+impl Drop for LineRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+/// Line pairs of a square grid of `cells` x `cells` on the XZ plane, centered at the origin
+pub fn grid_lines(size: f32, cells: u32) -> Vec<Vector3> {
+    let half = size / 2.0;
+    (0..=cells)
+        .map(|i| -half + size * i as f32 / cells as f32)
+        .flat_map(|offset| vec![
+            vec3(offset, 0.0, -half), vec3(offset, 0.0, half),
+            vec3(-half, 0.0, offset), vec3(half, 0.0, offset),
+        ])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid() {
+        let lines = grid_lines(10.0, 10);
+        assert_eq!(lines.len(), 11 * 4);
+        assert_eq!(lines[0..2], [vec3(-5.0, 0.0, -5.0), vec3(-5.0, 0.0, 5.0)]);
+        assert_eq!(lines[lines.len() - 2..], [vec3(-5.0, 0.0, 5.0), vec3(5.0, 0.0, 5.0)]);
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+use num_traits::clamp;
+
+use crate::importdata::Progress;
+use crate::render::math::*;
+use crate::render::TextRenderer;
+
+/// Minimum time between two redraws, so that presenting the frames (vsync) doesn't slow down loading
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+const BAR_WIDTH: usize = 30;
+const TEXT_SIZE: f32 = 24.0;
+
+/// Spinner and progress bar shown while the model is loading
+pub struct LoadingScreen {
+    text: TextRenderer,
+    start: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl LoadingScreen {
+    pub fn new() -> LoadingScreen {
+        LoadingScreen { text: TextRenderer::new(), start: Instant::now(), last_draw: None }
+    }
+
+    /// Clears the current framebuffer and draws the progress centered in it, unless the last
+    /// draw was very recent. Returns whether it drew (i.e. the buffers should be swapped).
+    pub unsafe fn draw(&mut self, progress: &Progress) -> bool {
+        let now = Instant::now();
+        if let Some(last_draw) = self.last_draw {
+            if now - last_draw < REDRAW_INTERVAL {
+                return false
+            }
+        }
+        self.last_draw = Some(now);
+
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let text = progress_text(progress, now - self.start);
+        let lines = text.lines().count() as f32;
+        let columns = text.lines().map(str::len).max().unwrap_or(0) as f32;
+        let x = (viewport[2] as f32 - columns * TextRenderer::char_width(TEXT_SIZE)) / 2.0;
+        let y = (viewport[3] as f32 - lines * TEXT_SIZE) / 2.0;
+
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        self.text.draw_text(x.max(0.0), y.max(0.0), &text, TEXT_SIZE, &vec4(1.0, 1.0, 1.0, 1.0));
+        true
+    }
+}
+
+/// e.g. "/ Loading images (2/5)" and a bar with the overall percentage below.
+/// The spinner advances with `elapsed` (every 100 ms), not with the progress.
+fn progress_text(progress: &Progress, elapsed: Duration) -> String {
+    let spinner = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+    let fraction = clamp(progress.fraction(), 0.0, 1.0);
+    let filled = (fraction * BAR_WIDTH as f32).round() as usize;
+    format!("{} Loading {} ({}/{})\n[{}{}] {:3.0}%", spinner, progress.stage, progress.done, progress.total,
+        "#".repeat(filled), " ".repeat(BAR_WIDTH - filled), fraction * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text() {
+        let progress = Progress { stage: "nodes", done: 5, total: 10 };
+        assert_eq!(progress_text(&progress, Duration::from_millis(250)),
+            format!("- Loading nodes (5/10)\n[{}{}]  83%", "#".repeat(25), " ".repeat(5)));
+    }
+}
@@ -1,14 +1,17 @@
+use std::cell::Cell;
 use std::rc::Rc;
-use std::path::Path;
 
 use gltf;
+use log::debug;
+use serde_json::Value;
 
 use crate::render::math::*;
 use crate::render::{ Root };
-use crate::render::texture::Texture;
+use crate::render::texture::{ColorSpace, Texture};
 use crate::shader::*;
 use crate::importdata::ImportData;
 
+#[derive(Clone)]
 pub struct Material {
     pub index: Option<usize>, /// glTF index
     pub name: Option<String>,
@@ -27,12 +30,135 @@ pub struct Material {
     pub occlusion_strength: f32,
     pub emissive_factor: Vector3,
     pub emissive_texture: Option<Rc<Texture>>,
+    /// KHR_materials_emissive_strength
+    pub emissive_strength: f32,
 
     pub alpha_cutoff: f32,
     pub alpha_mode: gltf::material::AlphaMode,
 
     pub double_sided: bool,
 
+    // KHR_materials_transmission
+    pub transmission_factor: f32,
+    pub transmission_texture: Option<Rc<Texture>>,
+    /// Thin-walled transmission seen from both sides: the back faces are drawn (with flipped
+    /// normals) before the front faces, which then refract them. Defaults to `double_sided`.
+    pub transmission_back_faces: Cell<bool>,
+
+    // KHR_materials_sheen
+    pub sheen_color_factor: Vector3,
+    pub sheen_color_texture: Option<Rc<Texture>>,
+    pub sheen_roughness_factor: f32,
+    pub sheen_roughness_texture: Option<Rc<Texture>>,
+
+    /// KHR_materials_ior
+    pub ior: f32,
+
+    // KHR_materials_specular (scales and tints the dielectric reflectance,
+    // unrelated to the `specular_factor` of specular-glossiness materials)
+    pub dielectric_specular_factor: f32,
+    pub dielectric_specular_texture: Option<Rc<Texture>>,
+    pub dielectric_specular_color_factor: Vector3,
+    pub dielectric_specular_color_texture: Option<Rc<Texture>>,
+
+    // KHR_materials_pbrSpecularGlossiness (diffuse factor/texture are stored as base color)
+    pub specular_glossiness: bool,
+    pub specular_factor: Vector3,
+    pub glossiness_factor: f32,
+    pub specular_glossiness_texture: Option<Rc<Texture>>,
+
+    pub texture_transforms: TextureTransforms,
+
+    /// KHR_animation_pointer: current values of animated factors (shared by all primitives using the material)
+    pub animated: Cell<AnimatedFactors>,
+
+    extras: Value,
+}
+
+/// KHR_texture_transform of a texture slot: applied to the texture coordinates as
+/// translation * rotation * scale
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureTransform {
+    pub offset: Vector2,
+    /// Radians (counter-clockwise)
+    pub rotation: f32,
+    pub scale: Vector2,
+    /// Overrides the `texCoord` of the texture info
+    pub tex_coord: Option<u32>,
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        TextureTransform { offset: Vector2::zero(), rotation: 0.0, scale: Vector2::new(1.0, 1.0), tex_coord: None }
+    }
+}
+
+impl TextureTransform {
+    /// Parses the extension of a `textureInfo` object (identity if it doesn't have one)
+    fn from_texture_info(info: &Value) -> TextureTransform {
+        let ext = &info["extensions"]["KHR_texture_transform"];
+        let vec2 = |key: &str| match ext.get(key)?.as_array()?.as_slice() {
+            [x, y] => Some(Vector2::new(x.as_f64()? as f32, y.as_f64()? as f32)),
+            _ => None,
+        };
+        let default = TextureTransform::default();
+        TextureTransform {
+            offset: vec2("offset").unwrap_or(default.offset),
+            rotation: get_f32(ext, "rotation").unwrap_or(default.rotation),
+            scale: vec2("scale").unwrap_or(default.scale),
+            tex_coord: ext.get("texCoord").and_then(Value::as_u64).map(|tex_coord| tex_coord as u32),
+        }
+    }
+
+    /// The UV transform as matrix for the shader (see spec)
+    pub fn matrix(&self) -> Matrix3 {
+        let (sin, cos) = self.rotation.sin_cos();
+        let translation = Matrix3::new(
+            1.0, 0.0, 0.0,
+            0.0, 1.0, 0.0,
+            self.offset.x, self.offset.y, 1.0);
+        let rotation = Matrix3::new(
+            cos, -sin, 0.0,
+            sin, cos, 0.0,
+            0.0, 0.0, 1.0);
+        let scale = Matrix3::from_diagonal(vec3(self.scale.x, self.scale.y, 1.0));
+        translation * rotation * scale
+    }
+}
+
+/// Texture transforms of the core texture slots, which are independent of each other
+/// (e.g. only the normal map may be offset)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextureTransforms {
+    pub base_color: TextureTransform,
+    pub metallic_roughness: TextureTransform,
+    pub normal: TextureTransform,
+    pub occlusion: TextureTransform,
+    pub emissive: TextureTransform,
+}
+
+impl TextureTransforms {
+    /// Reads the transforms from the JSON of a material
+    fn from_json(material: &Value) -> TextureTransforms {
+        let pbr = &material["pbrMetallicRoughness"];
+        TextureTransforms {
+            base_color: TextureTransform::from_texture_info(&pbr["baseColorTexture"]),
+            metallic_roughness: TextureTransform::from_texture_info(&pbr["metallicRoughnessTexture"]),
+            normal: TextureTransform::from_texture_info(&material["normalTexture"]),
+            occlusion: TextureTransform::from_texture_info(&material["occlusionTexture"]),
+            emissive: TextureTransform::from_texture_info(&material["emissiveTexture"]),
+        }
+    }
+}
+
+/// Animated material factors, replacing the static ones if set
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnimatedFactors {
+    pub base_color_factor: Option<Vector4>,
+    pub metallic_factor: Option<f32>,
+    pub roughness_factor: Option<f32>,
+    pub emissive_factor: Option<Vector3>,
+    pub emissive_strength: Option<f32>,
 }
 
 impl Material {
@@ -40,7 +166,6 @@ impl Material {
         g_material: &gltf::material::Material<'_>,
         root: &mut Root,
         imp: &ImportData,
-        base_path: &Path
     ) -> Material {
         let pbr = g_material.pbr_metallic_roughness();
 
@@ -62,39 +187,239 @@ impl Material {
 
             emissive_factor: g_material.emissive_factor().into(),
             emissive_texture: None,
+            emissive_strength: 1.0,
 
             alpha_cutoff: g_material.alpha_cutoff(),
             alpha_mode: g_material.alpha_mode(),
 
             double_sided: g_material.double_sided(),
+
+            transmission_factor: 0.0,
+            transmission_texture: None,
+            transmission_back_faces: Cell::new(g_material.double_sided()),
+
+            sheen_color_factor: Vector3::zero(),
+            sheen_color_texture: None,
+            sheen_roughness_factor: 0.0,
+            sheen_roughness_texture: None,
+
+            ior: 1.5,
+
+            dielectric_specular_factor: 1.0,
+            dielectric_specular_texture: None,
+            dielectric_specular_color_factor: vec3(1.0, 1.0, 1.0),
+            dielectric_specular_color_texture: None,
+
+            specular_glossiness: false,
+            specular_factor: vec3(1.0, 1.0, 1.0),
+            glossiness_factor: 1.0,
+            specular_glossiness_texture: None,
+
+            texture_transforms: TextureTransforms::default(),
+
+            animated: Cell::new(AnimatedFactors::default()),
+
+            extras: g_material.extras().clone().unwrap_or(Value::Null),
         };
 
+        // the glTF default material instead
+        if imp.options.geometry_only {
+            material.index = None;
+            material.name = None;
+            material.base_color_factor = vec4(1.0, 1.0, 1.0, 1.0);
+            material.metallic_factor = 1.0;
+            material.roughness_factor = 1.0;
+            material.emissive_factor = Vector3::zero();
+            material.alpha_mode = gltf::material::AlphaMode::Opaque;
+            material.alpha_cutoff = 0.5;
+            material.double_sided = false;
+            material.transmission_back_faces.set(false);
+            material.extras = Value::Null;
+        }
+
+        if material.index.is_none() {
+            let defaults = &imp.options.default_material;
+            if let Some([r, g, b]) = defaults.base_color {
+                material.base_color_factor = vec4(r, g, b, 1.0);
+            }
+            material.metallic_factor = defaults.metallic.unwrap_or(material.metallic_factor);
+            material.roughness_factor = defaults.roughness.unwrap_or(material.roughness_factor);
+        }
+        if imp.options.geometry_only {
+            return material
+        }
+
         if let Some(color_info) = pbr.base_color_texture() {
-            material.base_color_texture = Some(
-                load_texture(&color_info.texture(), color_info.tex_coord(), root, imp, base_path));
+            material.base_color_texture =
+                load_texture(&color_info.texture(), color_info.tex_coord(), root, imp);
         }
         if let Some(mr_info) = pbr.metallic_roughness_texture() {
-            material.metallic_roughness_texture = Some(
-                load_texture(&mr_info.texture(), mr_info.tex_coord(), root, imp, base_path));
+            material.metallic_roughness_texture =
+                load_texture(&mr_info.texture(), mr_info.tex_coord(), root, imp);
         }
         if let Some(normal_texture) = g_material.normal_texture() {
-            material.normal_texture = Some(
-                load_texture(&normal_texture.texture(), normal_texture.tex_coord(), root, imp, base_path));
+            material.normal_texture =
+                load_texture(&normal_texture.texture(), normal_texture.tex_coord(), root, imp);
             material.normal_scale = Some(normal_texture.scale());
         }
         if let Some(occ_texture) = g_material.occlusion_texture() {
-            material.occlusion_texture = Some(
-                load_texture(&occ_texture.texture(), occ_texture.tex_coord(), root, imp, base_path));
+            material.occlusion_texture =
+                load_texture(&occ_texture.texture(), occ_texture.tex_coord(), root, imp);
             material.occlusion_strength = occ_texture.strength();
         }
         if let Some(em_info) = g_material.emissive_texture() {
-            material.emissive_texture = Some(
-                load_texture(&em_info.texture(), em_info.tex_coord(), root, imp, base_path));
+            material.emissive_texture =
+                load_texture(&em_info.texture(), em_info.tex_coord(), root, imp);
+        }
+
+        if let Some(index) = g_material.index() {
+            material.texture_transforms = TextureTransforms::from_json(&imp.json["materials"][index]);
+            if let Some(ext) = imp.extension("materials", index, "KHR_materials_transmission") {
+                material.transmission_factor = get_f32(ext, "transmissionFactor").unwrap_or(0.0);
+                material.transmission_texture = ext.get("transmissionTexture")
+                    .and_then(|info| load_extension_texture(info, root, imp));
+            }
+            if let Some(ext) = imp.extension("materials", index, "KHR_materials_emissive_strength") {
+                material.emissive_strength = get_f32(ext, "emissiveStrength").unwrap_or(1.0);
+            }
+            if let Some(ext) = imp.extension("materials", index, "KHR_materials_sheen") {
+                if let Some(color) = get_vec3(ext, "sheenColorFactor") {
+                    material.sheen_color_factor = color;
+                }
+                material.sheen_color_texture = ext.get("sheenColorTexture")
+                    .and_then(|info| load_extension_texture(info, root, imp));
+                material.sheen_roughness_factor = get_f32(ext, "sheenRoughnessFactor").unwrap_or(0.0);
+                material.sheen_roughness_texture = ext.get("sheenRoughnessTexture")
+                    .and_then(|info| load_extension_texture(info, root, imp));
+            }
+            if let Some(ext) = imp.extension("materials", index, "KHR_materials_ior") {
+                material.ior = get_f32(ext, "ior").unwrap_or(1.5);
+            }
+            if let Some(ext) = imp.extension("materials", index, "KHR_materials_specular") {
+                material.dielectric_specular_factor = get_f32(ext, "specularFactor").unwrap_or(1.0);
+                material.dielectric_specular_texture = ext.get("specularTexture")
+                    .and_then(|info| load_extension_texture(info, root, imp));
+                material.dielectric_specular_color_factor = get_vec3(ext, "specularColorFactor")
+                    .unwrap_or_else(|| vec3(1.0, 1.0, 1.0));
+                material.dielectric_specular_color_texture = ext.get("specularColorTexture")
+                    .and_then(|info| load_extension_texture(info, root, imp));
+            }
+            if let Some(ext) = imp.extension("materials", index, "KHR_materials_pbrSpecularGlossiness") {
+                // replaces the metallic-roughness parameters (which are a fallback then)
+                material.specular_glossiness = true;
+                material.base_color_factor = get_vec4(ext, "diffuseFactor").unwrap_or_else(|| vec4(1.0, 1.0, 1.0, 1.0));
+                material.base_color_texture = ext.get("diffuseTexture")
+                    .and_then(|info| load_extension_texture(info, root, imp));
+                material.texture_transforms.base_color = TextureTransform::from_texture_info(&ext["diffuseTexture"]);
+                material.specular_factor = get_vec3(ext, "specularFactor").unwrap_or_else(|| vec3(1.0, 1.0, 1.0));
+                material.glossiness_factor = get_f32(ext, "glossinessFactor").unwrap_or(1.0);
+                material.specular_glossiness_texture = ext.get("specularGlossinessTexture")
+                    .and_then(|info| load_extension_texture(info, root, imp));
+                material.metallic_factor = 0.0;
+                material.roughness_factor = 1.0 - material.glossiness_factor;
+                material.metallic_roughness_texture = None;
+            }
         }
 
         material
     }
 
+    /// Application-specific data of the glTF object (`Value::Null` if none)
+    #[allow(dead_code)]
+    pub fn extras(&self) -> &Value {
+        &self.extras
+    }
+
+    /// All textures used by the material
+    pub fn textures(&self) -> Vec<&Rc<Texture>> {
+        [&self.base_color_texture, &self.metallic_roughness_texture, &self.normal_texture,
+         &self.occlusion_texture, &self.emissive_texture, &self.transmission_texture,
+         &self.sheen_color_texture, &self.sheen_roughness_texture, &self.specular_glossiness_texture,
+         &self.dielectric_specular_texture, &self.dielectric_specular_color_texture]
+            .iter()
+            .filter_map(|texture| texture.as_ref())
+            .collect()
+    }
+
+    /// Textures with the unit they are bound to and the color space their slot expects
+    /// (sRGB for colors, linear for other data), see `color_space_conversions`
+    pub fn texture_slots(&self) -> Vec<(u32, &Rc<Texture>, ColorSpace)> {
+        use self::ColorSpace::*;
+        let specular_glossiness_slot = if self.specular_glossiness {
+            (3, &self.specular_glossiness_texture, Srgb)
+        } else {
+            (3, &self.metallic_roughness_texture, Linear)
+        };
+        [(0, &self.base_color_texture, Srgb), (1, &self.normal_texture, Linear), (2, &self.emissive_texture, Srgb),
+         specular_glossiness_slot, (4, &self.occlusion_texture, Linear), (6, &self.transmission_texture, Linear),
+         (8, &self.sheen_color_texture, Srgb), (9, &self.sheen_roughness_texture, Linear),
+         (11, &self.dielectric_specular_texture, Linear), (12, &self.dielectric_specular_color_texture, Srgb)]
+            .iter()
+            .filter_map(|&(unit, texture, color_space)| texture.as_ref().map(|texture| (unit, texture, color_space)))
+            .collect()
+    }
+
+    /// Names of the texture slots with the texture coordinate set each uses
+    /// (including `KHR_texture_transform` overrides)
+    pub fn texture_tex_coords(&self) -> Vec<(&'static str, u32)> {
+        let transforms = &self.texture_transforms;
+        let metallic_roughness = if self.specular_glossiness {
+            ("specular-glossiness", &self.specular_glossiness_texture, None)
+        } else {
+            ("metallic-roughness", &self.metallic_roughness_texture, transforms.metallic_roughness.tex_coord)
+        };
+        [("base color", &self.base_color_texture, transforms.base_color.tex_coord), metallic_roughness,
+         ("normal", &self.normal_texture, transforms.normal.tex_coord),
+         ("occlusion", &self.occlusion_texture, transforms.occlusion.tex_coord),
+         ("emissive", &self.emissive_texture, transforms.emissive.tex_coord),
+         ("transmission", &self.transmission_texture, None), ("sheen color", &self.sheen_color_texture, None),
+         ("sheen roughness", &self.sheen_roughness_texture, None), ("specular", &self.dielectric_specular_texture, None),
+         ("specular color", &self.dielectric_specular_color_texture, None)]
+            .iter()
+            .filter_map(|&(name, texture, tex_coord)| texture.as_ref()
+                .map(|texture| (name, tex_coord.unwrap_or(texture.tex_coord))))
+            .collect()
+    }
+
+    /// Bit masks (by texture unit) of the textures whose color space is overridden to differ
+    /// from the one of their slot: sRGB textures to decode and linear textures to encode
+    pub fn color_space_conversions(&self) -> (i32, i32) {
+        let (mut decode, mut encode) = (0, 0);
+        for (unit, texture, expected) in self.texture_slots() {
+            match texture.color_space.get() {
+                Some(ColorSpace::Srgb) if expected == ColorSpace::Linear => decode |= 1 << unit,
+                Some(ColorSpace::Linear) if expected == ColorSpace::Srgb => encode |= 1 << unit,
+                _ => (),
+            }
+        }
+        (decode, encode)
+    }
+
+    /// Whether the material needs to be rendered after the opaque objects (KHR_materials_transmission)
+    pub fn is_transmissive(&self) -> bool {
+        self.transmission_factor > 0.0 || self.transmission_texture.is_some()
+    }
+
+    /// Whether the material is drawn in the order-independent transparency pass (if enabled)
+    pub fn is_blended(&self) -> bool {
+        self.alpha_mode == gltf::material::AlphaMode::Blend && !self.is_transmissive()
+    }
+
+    /// Whether the back faces are drawn in a separate transmission pass (see `transmission_back_faces`)
+    pub fn has_transmission_back_faces(&self) -> bool {
+        self.is_transmissive() && self.transmission_back_faces.get()
+    }
+
+    /// Reflectance of the dielectric part at normal incidence (0.04 for the default IOR of 1.5)
+    pub fn dielectric_f0(&self) -> f32 {
+        ior_to_f0(self.ior)
+    }
+
+    /// spec (KHR_materials_sheen): a zero sheen color disables the sheen layer
+    pub fn has_sheen(&self) -> bool {
+        self.sheen_color_factor != Vector3::zero()
+    }
+
     pub fn shader_flags(&self) -> ShaderFlags {
         let mut flags = ShaderFlags::empty();
         if self.base_color_texture.is_some() {
@@ -112,6 +437,33 @@ impl Material {
         if self.occlusion_texture.is_some() {
             flags |= ShaderFlags::HAS_OCCLUSIONMAP;
         }
+        if self.is_transmissive() {
+            flags |= ShaderFlags::HAS_TRANSMISSION;
+        }
+        if self.transmission_texture.is_some() {
+            flags |= ShaderFlags::HAS_TRANSMISSIONMAP;
+        }
+        if self.specular_glossiness {
+            flags |= ShaderFlags::HAS_SPECULARGLOSSINESS;
+            if self.specular_glossiness_texture.is_some() {
+                flags |= ShaderFlags::HAS_SPECULARGLOSSINESSMAP;
+            }
+        }
+        if self.dielectric_specular_texture.is_some() {
+            flags |= ShaderFlags::HAS_DIELECTRICSPECULARMAP;
+        }
+        if self.dielectric_specular_color_texture.is_some() {
+            flags |= ShaderFlags::HAS_DIELECTRICSPECULARCOLORMAP;
+        }
+        if self.has_sheen() {
+            flags |= ShaderFlags::HAS_SHEEN;
+            if self.sheen_color_texture.is_some() {
+                flags |= ShaderFlags::HAS_SHEENCOLORMAP;
+            }
+            if self.sheen_roughness_texture.is_some() {
+                flags |= ShaderFlags::HAS_SHEENROUGHNESSMAP;
+            }
+        }
         flags
     }
 
@@ -121,14 +473,202 @@ fn load_texture(
     g_texture: &gltf::texture::Texture<'_>,
     tex_coord: u32,
     root: &mut Root,
-    imp: &ImportData,
-    base_path: &Path) -> Rc<Texture>
+    imp: &ImportData) -> Option<Rc<Texture>>
 {
-    if let Some(tex) = root.textures.iter().find(|tex| (***tex).index == g_texture.index()) {
-        return Rc::clone(tex)
+    // the set is part of the texture, so a texture used with another set (e.g. as normal map on
+    // TEXCOORD_1 and base color on TEXCOORD_0) gets another `Texture` for it (sharing the image)
+    if let Some(tex) = root.textures.iter().find(|tex| tex.has_index(g_texture.index()) && tex.tex_coord == tex_coord) {
+        return Some(Rc::clone(tex))
+    }
+    // different textures can reference the same image, e.g. exporters often write one texture
+    // per material, so it is only uploaded again if the sampler is different too
+    let (image, sampler) = (g_texture.source().index(), g_texture.sampler().index());
+    if let Some(tex) = root.textures.iter()
+        .find(|tex| tex.image == image && tex.sampler == sampler && tex.tex_coord == tex_coord)
+    {
+        debug!("Texture {} shares image {} with texture {}", g_texture.index(), image, tex.index);
+        tex.shared_indices.borrow_mut().push(g_texture.index());
+        return Some(Rc::clone(tex))
+    }
+    if let Some(tex) = root.textures.iter().find(|tex| tex.image == image && tex.sampler == sampler) {
+        debug!("Texture {} uses the uploaded image {} of texture {} with TEXCOORD_{}",
+            g_texture.index(), image, tex.index, tex_coord);
+        let texture = Rc::new(tex.with_tex_coord(g_texture, tex_coord));
+        root.textures.push(Rc::clone(&texture));
+        return Some(texture)
     }
 
-    let texture = Rc::new(Texture::from_gltf(g_texture, tex_coord, imp, base_path));
+    if imp.failed_textures.borrow().contains(&g_texture.index()) {
+        return None
+    }
+    let texture = Texture::from_gltf(g_texture, tex_coord, imp);
+    if texture.is_err() {
+        imp.failed_textures.borrow_mut().insert(g_texture.index());
+    }
+    let texture = Rc::new(imp.skip_on_error(&format!("texture {}", g_texture.index()), texture)?);
     root.textures.push(Rc::clone(&texture));
-    texture
+    Some(texture)
+}
+
+fn get_f32(json: &Value, key: &str) -> Option<f32> {
+    json.get(key).and_then(Value::as_f64).map(|v| v as f32)
+}
+
+fn get_vec3(json: &Value, key: &str) -> Option<Vector3> {
+    match json.get(key)?.as_array()?.as_slice() {
+        [x, y, z] => Some(vec3(x.as_f64()? as f32, y.as_f64()? as f32, z.as_f64()? as f32)),
+        _ => None,
+    }
+}
+
+fn get_vec4(json: &Value, key: &str) -> Option<Vector4> {
+    match json.get(key)?.as_array()?.as_slice() {
+        [x, y, z, w] => Some(vec4(x.as_f64()? as f32, y.as_f64()? as f32, z.as_f64()? as f32, w.as_f64()? as f32)),
+        _ => None,
+    }
+}
+
+/// Loads a texture referenced by a `textureInfo` object of an extension
+fn load_extension_texture(
+    info: &Value,
+    root: &mut Root,
+    imp: &ImportData) -> Option<Rc<Texture>>
+{
+    let index = info.get("index")?.as_u64()? as usize;
+    let tex_coord = info.get("texCoord").and_then(Value::as_u64).unwrap_or(0) as u32;
+    let g_texture = imp.doc.textures().nth(index)?;
+    load_texture(&g_texture, tex_coord, root, imp)
+}
+
+/// spec (KHR_materials_ior): `((ior - 1) / (ior + 1))^2`
+fn ior_to_f0(ior: f32) -> f32 {
+    ((ior - 1.0) / (ior + 1.0)).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importdata::{import_source, DefaultMaterial};
+    use crate::memory_source::MemorySource;
+
+    #[test]
+    fn default_and_specular_glossiness_materials() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "materials": [
+                { "name": "red", "pbrMetallicRoughness": { "baseColorFactor": [1, 0, 0, 1] } },
+                { "extensions": { "KHR_materials_pbrSpecularGlossiness": {
+                    "diffuseFactor": [0, 1, 0, 1], "specularFactor": [0.5, 0.5, 0.5], "glossinessFactor": 0.75
+                } } },
+                { "extensions": { "KHR_materials_specular": {
+                    "specularFactor": 0.5, "specularColorFactor": [1, 0.5, 0.25]
+                } } }
+            ],
+            "meshes": [{ "primitives": [
+                { "attributes": { "POSITION": 0 } },
+                { "attributes": { "POSITION": 0 }, "material": 0 },
+                { "attributes": { "POSITION": 0 }, "material": 1 },
+                { "attributes": { "POSITION": 0 }, "material": 2 }
+            ] }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3",
+                "min": [0, 0, 0], "max": [0, 0, 0] }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 12 }],
+            "buffers": [{ "byteLength": 12, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAA" }]
+        }"#;
+        let mut imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        imp.options.default_material = DefaultMaterial {
+            base_color: Some([0.5, 0.5, 0.5]),
+            metallic: Some(0.0),
+            roughness: None,
+        };
+        let mut root = Root::default();
+        let g_mesh = imp.doc.meshes().next().unwrap();
+        let materials: Vec<_> = g_mesh.primitives()
+            .map(|g_primitive| Material::from_gltf(&g_primitive.material(), &mut root, &imp))
+            .collect();
+
+        assert_eq!(materials[0].base_color_factor, vec4(0.5, 0.5, 0.5, 1.0));
+        assert_eq!((materials[0].metallic_factor, materials[0].roughness_factor), (0.0, 1.0));
+        // only used without a material
+        assert_eq!(materials[1].base_color_factor, vec4(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(materials[1].metallic_factor, 1.0);
+
+        let sg = &materials[2];
+        assert!(sg.specular_glossiness);
+        assert!(sg.shader_flags().contains(ShaderFlags::HAS_SPECULARGLOSSINESS));
+        assert_eq!(sg.base_color_factor, vec4(0.0, 1.0, 0.0, 1.0));
+        assert_eq!((sg.specular_factor, sg.glossiness_factor), (vec3(0.5, 0.5, 0.5), 0.75));
+
+        // full dielectric specular reflectance unless KHR_materials_specular is used
+        assert_eq!((materials[1].dielectric_specular_factor, materials[1].dielectric_specular_color_factor),
+            (1.0, vec3(1.0, 1.0, 1.0)));
+        let specular = &materials[3];
+        assert!(!specular.specular_glossiness);
+        assert_eq!((specular.dielectric_specular_factor, specular.dielectric_specular_color_factor),
+            (0.5, vec3(1.0, 0.5, 0.25)));
+    }
+
+    #[test]
+    fn transmission_back_faces() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "materials": [
+                { "doubleSided": true, "extensions": { "KHR_materials_transmission": { "transmissionFactor": 1 } } },
+                { "extensions": { "KHR_materials_transmission": { "transmissionFactor": 1 } } },
+                { "doubleSided": true }
+            ],
+            "meshes": [{ "primitives": [
+                { "attributes": { "POSITION": 0 }, "material": 0 },
+                { "attributes": { "POSITION": 0 }, "material": 1 },
+                { "attributes": { "POSITION": 0 }, "material": 2 }
+            ] }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3",
+                "min": [0, 0, 0], "max": [0, 0, 0] }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 12 }],
+            "buffers": [{ "byteLength": 12, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAA" }]
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut root = Root::default();
+        let materials: Vec<_> = imp.doc.meshes().next().unwrap().primitives()
+            .map(|g_primitive| Material::from_gltf(&g_primitive.material(), &mut root, &imp))
+            .collect();
+        let back_faces = || materials.iter().map(Material::has_transmission_back_faces).collect::<Vec<_>>();
+        assert_eq!(back_faces(), [true, false, false]);
+        materials[1].transmission_back_faces.set(true);
+        materials[2].transmission_back_faces.set(true);
+        // only for transmissive materials
+        assert_eq!(back_faces(), [true, true, false]);
+    }
+
+    #[test]
+    fn f0_from_ior() {
+        assert!((ior_to_f0(1.5) - 0.04).abs() < 1e-6);
+        assert_eq!(ior_to_f0(1.0), 0.0);
+        assert!((ior_to_f0(2.4) - 0.1696).abs() < 1e-4); // diamond
+    }
+
+    #[test]
+    fn per_slot_texture_transforms() {
+        let material = serde_json::json!({
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": 0, "extensions": { "KHR_texture_transform": {
+                    "scale": [2, 2]
+                } } },
+                "metallicRoughnessTexture": { "index": 0 }
+            },
+            "normalTexture": { "index": 0, "extensions": { "KHR_texture_transform": {
+                "offset": [0.5, 0], "rotation": 1.5707964, "texCoord": 1
+            } } }
+        });
+        let transforms = TextureTransforms::from_json(&material);
+        assert_eq!(transforms.metallic_roughness, TextureTransform::default());
+        assert_eq!(transforms.occlusion, TextureTransform::default());
+        assert_eq!(transforms.base_color.matrix() * vec3(0.25, 0.5, 1.0), vec3(0.5, 1.0, 1.0));
+
+        let normal = transforms.normal;
+        assert_eq!((normal.tex_coord, transforms.base_color.tex_coord), (Some(1), None));
+        // rotated, then offset
+        let uv = normal.matrix() * vec3(1.0, 0.0, 1.0);
+        assert!((uv - vec3(0.5, -1.0, 1.0)).magnitude() < 1e-6);
+    }
 }
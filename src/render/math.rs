@@ -7,6 +7,7 @@ pub use cgmath::{vec3, vec4};
 use num_traits::clamp;
 
 use collision;
+use collision::{Aabb, Union};
 
 pub type Vector2 = cgmath::Vector2<f32>;
 pub type Vector3 = cgmath::Vector3<f32>;
@@ -14,11 +15,24 @@ pub type Vector4 = cgmath::Vector4<f32>;
 
 pub type Point3 = cgmath::Point3<f32>;
 
+pub type Matrix3 = cgmath::Matrix3<f32>;
 pub type Matrix4 = cgmath::Matrix4<f32>;
 pub type Quaternion = cgmath::Quaternion<f32>;
 
 pub type Aabb3 = collision::Aabb3<f32>;
 
+/// Union of two bounding boxes where `Aabb3::zero()` counts as empty
+/// (otherwise the union would always include the origin)
+pub fn union_bounds(a: &Aabb3, b: &Aabb3) -> Aabb3 {
+    if *a == Aabb3::zero() {
+        *b
+    } else if *b == Aabb3::zero() {
+        *a
+    } else {
+        a.union(b)
+    }
+}
+
 // A point's spherical coordinates, inspired by ThreeJS version
 pub struct Spherical {
     pub radius: f32,
@@ -59,13 +73,15 @@ impl Spherical {
     }
 }
 
-use std::num::ParseFloatError;
-pub fn parse_vec3(s: &str) -> Result<Vector3, ParseFloatError> {
+pub fn parse_vec3(s: &str) -> Result<Vector3, String> {
     let coords: Vec<&str> = s.split(',').collect();
-    assert!(coords.len() == 3, "Failed to parse Vector3 ({})", s);
-    let x = coords[0].parse::<f32>()?;
-    let y = coords[1].parse::<f32>()?;
-    let z = coords[2].parse::<f32>()?;
+    if coords.len() != 3 {
+        return Err(format!("expected 3 comma-separated numbers, got '{}'", s))
+    }
+    let parse = |coord: &str| coord.trim().parse::<f32>().map_err(|err| format!("{} ('{}')", err, coord));
+    let x = parse(coords[0])?;
+    let y = parse(coords[1])?;
+    let z = parse(coords[2])?;
 
     Ok(vec3(x, y, z))
 }
@@ -1,52 +1,103 @@
 // use std::rc::Rc;
-use std::path::Path;
 
-use collision::{Aabb, Aabb3, Union};
+use collision::{Aabb, Aabb3};
 
 use gltf;
+use serde_json::Value;
 
 use crate::render::math::*;
-use crate::render::{Primitive, Root};
+use crate::render::{FaceCulling, Instances, Primitive, Root};
 use crate::importdata::ImportData;
 
 pub struct Mesh {
     pub index: usize, // glTF index
     pub primitives: Vec<Primitive>,
-    // TODO: weights
-    // pub weights: Vec<Rc<?>>
+    /// Morph target weights applied to the vertices at load time (see `morph_weights`).
+    /// Nodes that override the mesh's default weights get their own copy of the mesh.
+    pub weights: Vec<f32>,
+    /// Whether an animation changes the weights (the mesh isn't shared with other nodes then)
+    pub animated_weights: bool,
     pub name: Option<String>,
 
     pub bounds: Aabb3<f32>,
+
+    extras: Value,
 }
 
 impl Mesh {
     pub fn from_gltf(
         g_mesh: &gltf::Mesh<'_>,
+        weights: Vec<f32>,
+        animated_weights: bool,
         root: &mut Root,
         imp: &ImportData,
-        base_path: &Path,
     ) -> Mesh {
         let primitives: Vec<Primitive> = g_mesh.primitives()
             .enumerate()
-            .map(|(i, g_prim)| {
-                Primitive::from_gltf(&g_prim, i, g_mesh.index(), root, imp, base_path)
+            .filter_map(|(i, g_prim)| {
+                let primitive = Primitive::from_gltf(&g_prim, i, g_mesh.index(), &weights, animated_weights, root, imp);
+                imp.skip_on_error(&format!("primitive {} of mesh {}", i, g_mesh.index()), primitive)
             })
             .collect();
 
         let bounds = primitives.iter()
-            .fold(Aabb3::zero(), |bounds, prim| prim.bounds.union(&bounds));
+            .fold(Aabb3::zero(), |bounds, prim| union_bounds(&bounds, &prim.bounds));
 
         Mesh {
             index: g_mesh.index(),
             primitives,
+            weights,
+            animated_weights,
             name: g_mesh.name().map(|s| s.into()),
             bounds,
+            extras: g_mesh.extras().clone().unwrap_or(Value::Null),
         }
     }
 
-    pub fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3) {
+    /// Application-specific data of the glTF object (`Value::Null` if none)
+    #[allow(dead_code)]
+    pub fn extras(&self) -> &Value {
+        &self.extras
+    }
+
+    /// Number of morph targets (the most of any primitive, they should all have the same)
+    pub fn morph_target_count(g_mesh: &gltf::Mesh<'_>) -> usize {
+        g_mesh.primitives().map(|g_prim| g_prim.morph_targets().len()).max().unwrap_or(0)
+    }
+
+    /// Applies animated morph target weights to the primitives (see `Primitive::set_morph_weights`)
+    pub fn set_morph_weights(&self, weights: &[f32]) {
         for primitive in &self.primitives {
-            unsafe { primitive.draw(model_matrix, mvp_matrix, camera_position) }
+            primitive.set_morph_weights(weights);
+        }
+    }
+
+    /// Draws all primitives for which `filter` returns true (once per instance if given)
+    pub fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3,
+        instances: Option<&Instances>, culling: &FaceCulling, filter: &dyn Fn(&Primitive) -> bool)
+    {
+        for primitive in self.primitives.iter().filter(|p| filter(p)) {
+            unsafe { primitive.draw(model_matrix, mvp_matrix, camera_position, instances, culling) }
         }
     }
 }
+
+/// Morph target weights of a node's mesh: the node's weights if it has them, otherwise the
+/// mesh's default weights, otherwise all zero. Padded with zeros or truncated to `target_count`.
+pub fn morph_weights(node_weights: Option<&[f32]>, mesh_weights: Option<&[f32]>, target_count: usize) -> Vec<f32> {
+    let weights = node_weights.or(mesh_weights).unwrap_or(&[]);
+    (0..target_count).map(|i| weights.get(i).cloned().unwrap_or(0.0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_fallback() {
+        assert_eq!(morph_weights(Some(&[0.5]), Some(&[1.0, 1.0]), 2), vec![0.5, 0.0]);
+        assert_eq!(morph_weights(None, Some(&[0.25, 0.75, 1.0]), 2), vec![0.25, 0.75]);
+        assert_eq!(morph_weights(None, None, 3), vec![0.0; 3]);
+        assert!(morph_weights(Some(&[1.0]), None, 0).is_empty());
+    }
+}
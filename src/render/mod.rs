@@ -11,11 +11,57 @@ mod mesh;
 pub use self::mesh::*;
 mod primitive;
 pub use self::primitive::*;
+mod normals;
+pub use self::normals::*;
+mod tangents;
+pub use self::tangents::*;
+mod vertexao;
+pub use self::vertexao::*;
+mod simplify;
+pub use self::simplify::*;
+mod instancing;
+pub use self::instancing::*;
 
 mod material;
 pub use self::material::*;
 mod texture;
 pub use self::texture::*;
 
+mod animation;
+pub use self::animation::*;
+
 mod camera;
 pub use self::camera::*;
+mod light;
+pub use self::light::*;
+
+mod picking;
+pub use self::picking::*;
+mod transmission;
+pub use self::transmission::*;
+mod postprocess;
+pub use self::postprocess::*;
+mod supersampling;
+pub use self::supersampling::*;
+mod ssao;
+pub use self::ssao::*;
+mod overdraw;
+pub use self::overdraw::*;
+mod hiz;
+pub use self::hiz::*;
+mod outline;
+pub use self::outline::*;
+mod oit;
+pub use self::oit::*;
+mod scalebar;
+pub use self::scalebar::*;
+mod lines;
+pub use self::lines::*;
+mod text;
+pub use self::text::*;
+mod loading;
+pub use self::loading::*;
+mod vertexvectors;
+pub use self::vertexvectors::*;
+mod uvchecker;
+pub use self::uvchecker::*;
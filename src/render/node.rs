@@ -1,31 +1,53 @@
 use std::rc::Rc;
-use std::path::Path;
 
 use gltf;
 
-use collision::{Aabb, Union};
+use collision::Aabb;
+use log::{debug, info, warn};
+use serde_json::Value;
 
 use crate::controls::CameraParams;
 use crate::render::math::*;
-use crate::render::mesh::Mesh;
+use crate::render::mesh::{morph_weights, Mesh};
+use crate::render::{animates_weights, Instances};
+use crate::render::primitive::{FaceCulling, Primitive};
 use crate::render::Root;
 use crate::render::camera::Camera;
+use crate::render::Light;
 use crate::importdata::ImportData;
 
 pub struct Node {
     pub index: usize, // glTF index
     pub children: Vec<usize>,
     pub mesh: Option<Rc<Mesh>>,
+    /// EXT_mesh_gpu_instancing: the mesh is drawn once per instance transform
+    pub instances: Option<Instances>,
     pub rotation: Quaternion,
     pub scale: Vector3,
     pub translation: Vector3,
-    // TODO: weights
-    // weights_id: usize,
     pub camera: Option<Camera>,
+    /// KHR_lights_punctual
+    pub light: Option<Light>,
     pub name: Option<String>,
 
+    /// If false, the node and its children aren't rendered (and don't count for the scene bounds).
+    /// Initialized from the extras, see `hidden_by_extras`.
+    pub visible: bool,
+
+    /// MSFT_lod: indices of the nodes that replace this one at coarser levels of detail
+    pub lods: Vec<usize>,
+    /// MSFT_screencoverage (from extras): minimum screen coverage of each level
+    pub lod_coverage: Vec<f32>,
+    /// Selected level of detail (0: the node itself), see `Scene::update_lods`
+    pub lod_level: usize,
+
     pub final_transform: Matrix4, // including parent transforms
+    /// Set after changing `translation`, `rotation` or `scale`, so that the next
+    /// `Scene::update_transforms` recomputes `final_transform` of the node and its subtree
+    pub transform_changed: bool,
     pub bounds: Aabb3,
+
+    extras: Value,
 }
 
 
@@ -34,20 +56,40 @@ impl Node {
         g_node: &gltf::Node<'_>,
         root: &mut Root,
         imp: &ImportData,
-        base_path: &Path
     ) -> Node {
         let (trans, rot, scale) = g_node.transform().decomposed();
         let r = rot;
         let rotation = Quaternion::new(r[3], r[0], r[1], r[2]); // NOTE: different element order!
+        let (translation, rotation, scale) =
+            sanitize_trs(trans.into(), rotation, scale.into(), g_node.index());
 
+        // if the instances fail to load, the node is kept without its mesh (e.g. for its children).
+        // `None`: failed
+        let instances = match g_node.mesh() {
+            Some(_) => imp.skip_on_error(&format!("the mesh of node {}", g_node.index()),
+                Instances::from_gltf(imp, g_node.index())),
+            None => Some(None),
+        };
         let mut mesh = None;
-        if let Some(g_mesh) = g_node.mesh() {
-            if let Some(existing_mesh) = root.meshes.iter().find(|mesh| (***mesh).index == g_mesh.index()) {
+        if let Some(g_mesh) = g_node.mesh().filter(|_| instances.is_some()) {
+            // the morph targets are applied when loading, so different (or animated) weights need a
+            // separate copy
+            let target_count = Mesh::morph_target_count(&g_mesh);
+            let weights = morph_weights(g_node.weights(), g_mesh.weights(), target_count);
+            let animated_weights = target_count > 0 && animates_weights(&imp.doc, g_node.index());
+            if let Some(existing_mesh) = root.meshes.iter()
+                .filter(|mesh| !animated_weights && !mesh.animated_weights)
+                .find(|mesh| (***mesh).index == g_mesh.index() && mesh.weights == weights)
+            {
                 mesh = Some(Rc::clone(existing_mesh));
             }
 
             if mesh.is_none() { // not using else due to borrow-checking madness
-                mesh = Some(Rc::new(Mesh::from_gltf(&g_mesh, root, imp, base_path)));
+                if root.meshes.iter().any(|mesh| mesh.index == g_mesh.index()) {
+                    debug!("Node {} {} the morph target weights of mesh {}, loading a copy: {:?}", g_node.index(),
+                        if animated_weights { "animates" } else { "overrides" }, g_mesh.index(), weights);
+                }
+                mesh = Some(Rc::new(Mesh::from_gltf(&g_mesh, weights, animated_weights, root, imp)));
                 root.meshes.push(mesh.clone().unwrap());
             }
         }
@@ -55,34 +97,88 @@ impl Node {
                 .map(|g_node| g_node.index())
                 .collect();
 
+        let extras = g_node.extras().clone().unwrap_or(Value::Null);
+        let lods = imp.extension("nodes", g_node.index(), "MSFT_lod")
+            .and_then(|ext| ext.get("ids"))
+            .and_then(Value::as_array)
+            .map(|ids| ids.iter().filter_map(Value::as_u64).map(|id| id as usize).collect())
+            .unwrap_or_default();
+        let visible = !hidden_by_extras(&extras);
+        if !visible {
+            debug!("Node {} ({:?}) is hidden by its extras", g_node.index(), g_node.name());
+        }
+        let lod_coverage = extras.get("MSFT_screencoverage")
+            .and_then(Value::as_array)
+            .map(|coverage| coverage.iter().filter_map(Value::as_f64).map(|c| c as f32).collect())
+            .unwrap_or_default();
+
         Node {
             index: g_node.index(),
             children,
+            instances: instances.flatten(),
             mesh,
             rotation,
-            scale: scale.into(),
-            translation: trans.into(),
+            scale,
+            translation,
             camera: g_node.camera().as_ref().map(Camera::from_gltf),
+            light: Light::from_gltf(imp, g_node.index()),
             name: g_node.name().map(|s| s.into()),
 
+            visible,
+
+            lods,
+            lod_coverage,
+            lod_level: 0,
+
             final_transform: Matrix4::identity(),
+            transform_changed: true,
 
             bounds: Aabb3::zero(),
+
+            extras,
         }
     }
 
-    pub fn update_transform(&mut self, root: &mut Root, parent_transform: &Matrix4) {
-        self.final_transform = *parent_transform;
+    /// Application-specific data of the glTF object (`Value::Null` if none)
+    #[allow(dead_code)]
+    pub fn extras(&self) -> &Value {
+        &self.extras
+    }
 
-        // TODO: cache local tranform when adding animations?
-        self.final_transform = self.final_transform *
-            Matrix4::from_translation(self.translation) *
-            Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z) *
-            Matrix4::from(self.rotation);
+    /// Recomputes `final_transform` of the nodes in the subtree whose local transform (see
+    /// `transform_changed`) or parent transform changed, the others keep theirs.
+    pub fn update_transform(&mut self, root: &mut Root, parent_transform: &Matrix4, parent_changed: bool) {
+        let changed = parent_changed || self.transform_changed;
+        if changed {
+            // spec: T * R * S (a `matrix` is decomposed into TRS when loading)
+            let transform = parent_transform *
+                Matrix4::from_translation(self.translation) *
+                Matrix4::from(self.rotation) *
+                Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+
+            if is_finite(&transform) {
+                self.final_transform = transform;
+            } else {
+                warn!("Node {} ({:?}) has a non-finite transform, using parent transform instead",
+                    self.index, self.name);
+                self.final_transform = *parent_transform;
+            }
+            // cameras use `final_transform` directly (see `CameraControls::set_camera`)
+            if let Some(ref mut light) = self.light {
+                light.update_transform(&self.final_transform);
+            }
+            self.transform_changed = false;
+        }
 
         for node_id in &self.children {
             let node = root.unsafe_get_node_mut(*node_id);
-            node.update_transform(root, &self.final_transform);
+            node.update_transform(root, &self.final_transform, changed);
+        }
+
+        // the coarser levels of detail replace this node, i.e. they have the same parent
+        for node_id in &self.lods {
+            let node = root.unsafe_get_node_mut(*node_id);
+            node.update_transform(root, parent_transform, parent_changed);
         }
     }
 
@@ -90,26 +186,215 @@ impl Node {
     pub fn update_bounds(&mut self, root: &mut Root) {
         self.bounds = Aabb3::zero();
         if let Some(ref mesh) = self.mesh {
-            self.bounds = mesh.bounds
-                .transform(&self.final_transform);
+            self.bounds = match self.instances {
+                Some(ref instances) => instances.transforms.iter()
+                    .map(|transform| mesh.bounds.transform(&(self.final_transform * transform)))
+                    .fold(None, |bounds: Option<Aabb3>, b| Some(match bounds {
+                        Some(bounds) => union_bounds(&bounds, &b),
+                        None => b,
+                    }))
+                    .unwrap_or_else(Aabb3::zero),
+                None => mesh.bounds.transform(&self.final_transform),
+            };
         }
 
         for node_id in &self.children {
             let node = root.unsafe_get_node_mut(*node_id);
             node.update_bounds(root);
-            self.bounds = self.bounds.union(&node.bounds);
+            if node.visible {
+                self.bounds = union_bounds(&self.bounds, &node.bounds);
+            }
+        }
+
+        for node_id in &self.lods {
+            root.unsafe_get_node_mut(*node_id).update_bounds(root);
+        }
+    }
+
+    /// The node that is rendered in place of this one at the selected level of detail
+    /// (`None`: culled because the screen coverage is below the minimum of the last level)
+    pub fn lod_node(&self) -> Option<usize> {
+        match self.lod_level {
+            0 => Some(self.index),
+            level => self.lods.get(level - 1).cloned(),
         }
     }
 
-    pub fn draw(&mut self, root: &mut Root, cam_params: &CameraParams) {
+    /// Selects the level of detail from the screen coverage of the bounds (fraction of the viewport height).
+    /// Without MSFT_screencoverage, each level switches to the next one at half the coverage
+    /// of the previous one, starting at `threshold`.
+    pub fn select_lod(&mut self, cam_params: &CameraParams, threshold: f32) {
+        if self.lods.is_empty() {
+            return
+        }
+        let radius = self.bounds.dim().magnitude() / 2.0;
+        let distance = (self.bounds.center().to_vec() - cam_params.position).magnitude();
+        let coverage = if distance > radius {
+            // projection_matrix[1][1] = 1 / tan(fovy / 2)
+            radius * cam_params.projection_matrix[1][1] / distance
+        } else {
+            1.0
+        };
+        let thresholds: Vec<f32> = if self.lod_coverage.is_empty() {
+            (0..self.lods.len()).map(|i| threshold / 2f32.powi(i as i32)).collect()
+        } else {
+            self.lod_coverage.clone()
+        };
+        self.lod_level = lod_level(coverage, &thresholds, self.lods.len() + 1);
+    }
+
+    /// Draws the primitives of the node's own mesh for which `filter` returns true
+    /// (ignoring `visible` and children, see `Scene::visible_nodes`)
+    pub fn draw_mesh(&self, cam_params: &CameraParams, culling: &FaceCulling, filter: &dyn Fn(&Primitive) -> bool) {
         if let Some(ref mesh) = self.mesh {
             let mvp_matrix = cam_params.projection_matrix * cam_params.view_matrix * self.final_transform;
 
-            (*mesh).draw(&self.final_transform, &mvp_matrix, &cam_params.position);
+            (*mesh).draw(&self.final_transform, &mvp_matrix, &cam_params.position, self.instances.as_ref(),
+                culling, filter);
         }
-        for node_id in &self.children {
-            let node = root.unsafe_get_node_mut(*node_id);
-            node.draw(root, cam_params);
+    }
+}
+
+/// Replaces NaN/infinite components, normalizes the rotation and warns about
+/// degenerate scales (as produced by some sloppy exporters).
+fn sanitize_trs(translation: Vector3, rotation: Quaternion, scale: Vector3, node_index: usize)
+    -> (Vector3, Quaternion, Vector3)
+{
+    let mut translation = translation;
+    if !is_finite_vec3(&translation) {
+        warn!("Node {} has a non-finite translation ({:?}), resetting to zero", node_index, translation);
+        translation = Vector3::zero();
+    }
+
+    let mut scale = scale;
+    if !is_finite_vec3(&scale) {
+        warn!("Node {} has a non-finite scale ({:?}), resetting to one", node_index, scale);
+        scale = vec3(1.0, 1.0, 1.0);
+    }
+    let determinant = scale.x * scale.y * scale.z;
+    if determinant.abs() < f32::EPSILON {
+        warn!("Node {} has a zero scale ({:?}), its mesh will not be visible", node_index, scale);
+    } else if determinant < 0.0 {
+        info!("Node {} has a negative-determinant scale ({:?}), rendering it with flipped winding order",
+            node_index, scale);
+    }
+
+    let magnitude = rotation.magnitude();
+    let rotation = if !magnitude.is_finite() || magnitude < f32::EPSILON {
+        warn!("Node {} has an invalid rotation ({:?}), resetting to identity", node_index, rotation);
+        Quaternion::one()
+    } else {
+        if (magnitude - 1.0).abs() > 1e-4 {
+            warn!("Node {} has a non-normalized rotation (length: {}), normalizing", node_index, magnitude);
+        }
+        rotation / magnitude
+    };
+
+    (translation, rotation, scale)
+}
+
+/// Helper/guide nodes can be shipped hidden with `"extras": { "hidden": true }`
+/// (or `"visible": false`)
+fn hidden_by_extras(extras: &Value) -> bool {
+    extras.get("hidden").and_then(Value::as_bool) == Some(true)
+        || extras.get("visible").and_then(Value::as_bool) == Some(false)
+}
+
+/// First of `levels` levels whose minimum coverage (`thresholds`) is reached (no minimum if missing),
+/// or `levels` if the coverage is below all of them
+fn lod_level(coverage: f32, thresholds: &[f32], levels: usize) -> usize {
+    (0..levels)
+        .find(|&level| match thresholds.get(level) {
+            Some(&min) => coverage >= min,
+            None => true,
+        })
+        .unwrap_or(levels)
+}
+
+fn is_finite_vec3(v: &Vector3) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+fn is_finite(m: &Matrix4) -> bool {
+    let m: &[f32; 16] = m.as_ref();
+    m.iter().all(|x| x.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importdata::import_source;
+    use crate::memory_source::MemorySource;
+
+    #[test]
+    fn matrix_transform() {
+        // column-major: rotated 90 degrees around z, scaled by (2, 3, 4), translated by (5, 6, 7)
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "matrix": [0, 2, 0, 0,  -3, 0, 0, 0,  0, 0, 4, 0,  5, 6, 7, 1] }]
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut root = Root::from_gltf(&imp);
+        let node = root.unsafe_get_node_mut(0);
+        node.update_transform(&mut root, &Matrix4::identity(), true);
+        let expected = Matrix4::new(
+            0.0, 2.0, 0.0, 0.0,
+            -3.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 4.0, 0.0,
+            5.0, 6.0, 7.0, 1.0);
+        for (column, expected_column) in (0..4).map(|i| (node.final_transform[i], expected[i])) {
+            assert!((column - expected_column).magnitude() < 1e-5, "{:?}", node.final_transform);
         }
+        let p = node.final_transform.transform_point(Point3::new(1.0, 1.0, 1.0));
+        assert!((p - Point3::new(2.0, 8.0, 11.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn changed_transforms() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "children": [1], "translation": [1, 0, 0] }, { "translation": [0, 1, 0] }]
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut root = Root::from_gltf(&imp);
+        let parent = root.unsafe_get_node_mut(0);
+        parent.update_transform(&mut root, &Matrix4::identity(), false);
+        let child_position = |root: &Root| root.nodes[1].final_transform.transform_point(Point3::origin());
+        assert_eq!(child_position(&root), Point3::new(1.0, 1.0, 0.0));
+
+        // unchanged nodes keep their transform
+        parent.translation = vec3(2.0, 0.0, 0.0);
+        parent.update_transform(&mut root, &Matrix4::identity(), false);
+        assert_eq!(child_position(&root), Point3::new(1.0, 1.0, 0.0));
+
+        // a changed node moves its subtree
+        parent.transform_changed = true;
+        parent.update_transform(&mut root, &Matrix4::identity(), false);
+        assert_eq!(child_position(&root), Point3::new(2.0, 1.0, 0.0));
+        assert!(!root.nodes[0].transform_changed && !root.nodes[1].transform_changed);
+    }
+
+    #[test]
+    fn lod_levels() {
+        // MSFT_screencoverage for two levels of detail plus the minimum of the last one
+        let thresholds = [0.5, 0.2, 0.01];
+        assert_eq!(lod_level(0.8, &thresholds, 3), 0);
+        assert_eq!(lod_level(0.5, &thresholds, 3), 0);
+        assert_eq!(lod_level(0.3, &thresholds, 3), 1);
+        assert_eq!(lod_level(0.1, &thresholds, 3), 2);
+        assert_eq!(lod_level(0.001, &thresholds, 3), 3); // culled
+
+        // without a minimum for the last level
+        assert_eq!(lod_level(0.001, &thresholds[..2], 3), 2);
+    }
+
+    #[test]
+    fn hidden_extras() {
+        assert!(hidden_by_extras(&serde_json::json!({ "hidden": true })));
+        assert!(hidden_by_extras(&serde_json::json!({ "visible": false })));
+        assert!(!hidden_by_extras(&serde_json::json!({ "hidden": false, "note": "guide" })));
+        assert!(!hidden_by_extras(&Value::Null));
     }
 }
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use crate::render::math::*;
+use crate::render::Vertex;
+
+/// Generates normals for a triangle list without NORMAL attribute.
+/// Faces whose normals differ by at most `smooth_angle` degrees share a smoothed
+/// normal at common positions, otherwise the vertices are split to keep a hard edge
+/// (0: flat shading). Returns the new vertices and indices.
+pub fn generate_normals(vertices: &[Vertex], indices: Option<&[u32]>, smooth_angle: f32) -> (Vec<Vertex>, Vec<u32>) {
+    let sequential_indices: Vec<u32>;
+    let indices = match indices {
+        Some(indices) => indices,
+        None => {
+            sequential_indices = (0..vertices.len() as u32).collect();
+            &sequential_indices
+        }
+    };
+    let triangles: Vec<&[u32]> = indices.chunks(3).filter(|t| t.len() == 3).collect();
+
+    // area-weighted (unnormalized) face normals
+    let face_normals: Vec<Vector3> = triangles.iter()
+        .map(|t| {
+            let position = |i: usize| vertices[t[i] as usize].position;
+            let (p0, p1, p2) = (position(0), position(1), position(2));
+            (p1 - p0).cross(p2 - p0)
+        })
+        .collect();
+
+    // faces adjacent to each position (vertices are often split at UV seams, so
+    // adjacency is determined by position rather than index)
+    let mut faces_at_position: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (face, t) in triangles.iter().enumerate() {
+        for &index in t.iter() {
+            faces_at_position.entry(position_key(&vertices[index as usize].position))
+                .or_default()
+                .push(face);
+        }
+    }
+
+    // small tolerance so that coplanar faces are smoothed with an angle of 0
+    let min_cos = smooth_angle.to_radians().cos() - 1e-5;
+    let mut new_vertices = vec![];
+    let mut new_indices = Vec::with_capacity(triangles.len() * 3);
+    // (original index, normal) -> new index
+    let mut vertex_map: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    for (face, t) in triangles.iter().enumerate() {
+        let face_normal = normalize_or_zero(face_normals[face]);
+        for &index in t.iter() {
+            let vertex = &vertices[index as usize];
+            let mut normal = Vector3::zero();
+            for &other in &faces_at_position[&position_key(&vertex.position)] {
+                if other == face || normalize_or_zero(face_normals[other]).dot(face_normal) >= min_cos {
+                    normal += face_normals[other];
+                }
+            }
+            let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { vec3(0.0, 0.0, 1.0) };
+
+            let new_index = *vertex_map.entry((index, position_key(&normal))).or_insert_with(|| {
+                new_vertices.push(Vertex { normal, ..vertex.clone() });
+                new_vertices.len() as u32 - 1
+            });
+            new_indices.push(new_index);
+        }
+    }
+    (new_vertices, new_indices)
+}
+
+/// Bitwise key for hashing exactly equal positions (or normals)
+pub fn position_key(v: &Vector3) -> [u32; 3] {
+    [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()]
+}
+
+fn normalize_or_zero(v: Vector3) -> Vector3 {
+    if v.magnitude2() > 0.0 { v.normalize() } else { v }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles folded by 90 degrees along the edge (0,0,0)-(1,0,0)
+    fn folded_quad() -> Vec<Vertex> {
+        [vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0),
+         vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0)].iter()
+            .map(|&position| Vertex { position, ..Vertex::default() })
+            .collect()
+    }
+
+    #[test]
+    fn hard_edges() {
+        let (vertices, indices) = generate_normals(&folded_quad(), None, 30.0);
+        assert_eq!(indices.len(), 6);
+        assert_eq!(vertices.len(), 6);
+        for i in 0..3 {
+            assert_eq!(vertices[indices[i] as usize].normal, vec3(0.0, 0.0, 1.0));
+            assert_eq!(vertices[indices[i + 3] as usize].normal, vec3(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn smooth_edges() {
+        let (vertices, indices) = generate_normals(&folded_quad(), None, 100.0);
+        let shared = vec3(0.0, 1.0, 1.0).normalize();
+        // the vertices on the common edge are smoothed, the others keep their face normal
+        assert!((vertices[indices[0] as usize].normal - shared).magnitude() < 1e-6);
+        assert!((vertices[indices[1] as usize].normal - shared).magnitude() < 1e-6);
+        assert_eq!(vertices[indices[2] as usize].normal, vec3(0.0, 0.0, 1.0));
+
+        // indexed: the edge vertices are shared by both triangles
+        let positions = [vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)];
+        let vertices: Vec<Vertex> = positions.iter()
+            .map(|&position| Vertex { position, ..Vertex::default() })
+            .collect();
+        let (smooth, _) = generate_normals(&vertices, Some(&[0, 1, 2, 1, 0, 3]), 100.0);
+        assert_eq!(smooth.len(), 4);
+        let (flat, _) = generate_normals(&vertices, Some(&[0, 1, 2, 1, 0, 3]), 0.0);
+        assert_eq!(flat.len(), 6); // split along the hard edge
+    }
+}
@@ -0,0 +1,166 @@
+use std::ptr;
+
+use crate::shader::Shader;
+
+/// Weighted blended order-independent transparency (McGuire and Bavoil 2013): primitives with
+/// alpha blending are accumulated in floating point targets, weighted by their coverage and
+/// depth, and then composited over the opaque scene. Unlike blending in draw order, this
+/// avoids sorting artifacts between overlapping transparent surfaces (the order is only
+/// approximated by the depth weights).
+pub struct WeightedBlendedOit {
+    width: i32,
+    height: i32,
+
+    framebuffer: u32,
+    /// Sum of the weighted premultiplied colors (RGB) and product of the transmittances (A)
+    accum_texture: u32,
+    /// Sum of the weighted alphas
+    weight_texture: u32,
+    /// Copy of the opaque scene's depth, so that the transparent surfaces are occluded
+    depth_renderbuffer: u32,
+
+    empty_vao: u32,
+    composite_shader: Shader,
+
+    previous_framebuffer: u32,
+    viewport: [i32; 4],
+}
+
+impl WeightedBlendedOit {
+    pub fn new() -> WeightedBlendedOit {
+        let mut oit = WeightedBlendedOit {
+            width: 0,
+            height: 0,
+            framebuffer: 0,
+            accum_texture: 0,
+            weight_texture: 0,
+            depth_renderbuffer: 0,
+            empty_vao: 0,
+            composite_shader: Shader::from_source(include_str!("../shaders/fullscreen-vert.glsl"),
+                include_str!("../shaders/oit-composite-frag.glsl"), &[]),
+            previous_framebuffer: 0,
+            viewport: [0; 4],
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut oit.empty_vao);
+
+            oit.composite_shader.use_program();
+            let u_accum = oit.composite_shader.uniform_location("u_Accum");
+            oit.composite_shader.set_int(u_accum, 0);
+            let u_weight = oit.composite_shader.uniform_location("u_Weight");
+            oit.composite_shader.set_int(u_weight, 1);
+        }
+        oit
+    }
+
+    unsafe fn create_framebuffer(&mut self) {
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        self.accum_texture = create_target(gl::RGBA16F, gl::RGBA, self.width, self.height);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.accum_texture, 0);
+        self.weight_texture = create_target(gl::R16F, gl::RED, self.width, self.height);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT1, gl::TEXTURE_2D, self.weight_texture, 0);
+        let attachments = [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1];
+        gl::DrawBuffers(2, attachments.as_ptr());
+
+        // same format as the other framebuffers (and usually the window), required for blitting
+        gl::GenRenderbuffers(1, &mut self.depth_renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_renderbuffer);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, self.width, self.height);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER,
+            self.depth_renderbuffer);
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: OIT framebuffer is not complete!");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_framebuffer(&mut self) {
+        if self.framebuffer == 0 {
+            return
+        }
+        gl::DeleteFramebuffers(1, &self.framebuffer);
+        gl::DeleteTextures(1, &self.accum_texture);
+        gl::DeleteTextures(1, &self.weight_texture);
+        gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+        self.framebuffer = 0;
+    }
+
+    /// Binds the accumulation targets (sized like the current viewport) with the depth of the
+    /// opaque scene rendered so far, and sets up the blending. The primitives are then drawn
+    /// with `Root::set_weighted_blended` enabled.
+    pub unsafe fn begin(&mut self) {
+        let mut previous_framebuffer = 0;
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+        self.previous_framebuffer = previous_framebuffer as u32;
+        gl::GetIntegerv(gl::VIEWPORT, self.viewport.as_mut_ptr());
+        let [x, y, width, height] = self.viewport;
+        if width != self.width || height != self.height || self.framebuffer == 0 {
+            self.delete_framebuffer();
+            self.width = width;
+            self.height = height;
+            self.create_framebuffer();
+        }
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.previous_framebuffer);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.framebuffer);
+        gl::BlitFramebuffer(x, y, x + width, y + height, 0, 0, width, height, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl::Viewport(0, 0, width, height);
+        gl::ClearBufferfv(gl::COLOR, 0, [0.0, 0.0, 0.0, 1.0].as_ptr());
+        gl::ClearBufferfv(gl::COLOR, 1, [0.0; 4].as_ptr());
+
+        gl::DepthMask(gl::FALSE);
+        gl::Enable(gl::BLEND);
+        // additive for the weighted sums, multiplicative (1 - alpha) for the transmittance
+        gl::BlendFuncSeparate(gl::ONE, gl::ONE, gl::ZERO, gl::ONE_MINUS_SRC_ALPHA);
+    }
+
+    /// Composites the accumulated transparent surfaces over the previously bound framebuffer
+    pub unsafe fn end(&mut self) {
+        gl::DepthMask(gl::TRUE);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_framebuffer);
+        let [x, y, width, height] = self.viewport;
+        gl::Viewport(x, y, width, height);
+
+        gl::Disable(gl::DEPTH_TEST);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::BindVertexArray(self.empty_vao);
+        self.composite_shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.accum_texture);
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, self.weight_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindVertexArray(0);
+        gl::Disable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+impl Drop for WeightedBlendedOit {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_framebuffer();
+            gl::DeleteVertexArrays(1, &self.empty_vao);
+        }
+    }
+}
+
+unsafe fn create_target(internal_format: gl::types::GLenum, format: gl::types::GLenum, width: i32, height: i32)
+    -> u32
+{
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, width, height, 0, format, gl::FLOAT, ptr::null());
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    texture
+}
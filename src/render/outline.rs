@@ -0,0 +1,219 @@
+use std::ptr;
+
+use crate::controls::CameraParams;
+use crate::render::{Root, Scene};
+use crate::render::math::*;
+use crate::shader::Shader;
+
+#[derive(Clone, Copy, Debug)]
+pub struct OutlineOptions {
+    pub enabled: bool,
+    /// Linear RGB
+    pub color: Vector3,
+    /// Width of the edges in pixels
+    pub thickness: f32,
+    /// Only outline the picked node (including its children) instead of the whole scene
+    pub selected_only: bool,
+}
+
+impl Default for OutlineOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: vec3(1.0, 0.5, 0.0),
+            thickness: 2.0,
+            selected_only: false,
+        }
+    }
+}
+
+/// Outline view: renders the view space normals and depth of the given nodes in a pre-pass
+/// and draws the discontinuities found by a Sobel filter (silhouettes, creases) over the
+/// previously bound framebuffer. The edge is evaluated at four subpixel positions, so that
+/// it is antialiased even without multisampling.
+pub struct Outline {
+    width: u32,
+    height: u32,
+
+    framebuffer: u32,
+    normal_depth_texture: u32,
+    depth_renderbuffer: u32,
+
+    empty_vao: u32,
+
+    normal_depth_shader: Shader,
+    u_mvp_matrix: i32,
+    u_model_view_matrix: i32,
+    u_normal_matrix: i32,
+    edge_shader: Shader,
+    u_color: i32,
+    u_offset: i32,
+}
+
+impl Outline {
+    pub fn new(width: u32, height: u32) -> Outline {
+        let mut normal_depth_shader = Shader::from_source(
+            include_str!("../shaders/outline-vert.glsl"),
+            include_str!("../shaders/outline-normal-depth-frag.glsl"),
+            &[]);
+        let mut edge_shader = Shader::from_source(
+            include_str!("../shaders/fullscreen-vert.glsl"),
+            include_str!("../shaders/outline-frag.glsl"),
+            &[]);
+        let mut outline = unsafe {
+            Outline {
+                width, height,
+                framebuffer: 0,
+                normal_depth_texture: 0,
+                depth_renderbuffer: 0,
+                empty_vao: 0,
+                u_mvp_matrix: normal_depth_shader.uniform_location("u_MVPMatrix"),
+                u_model_view_matrix: normal_depth_shader.uniform_location("u_ModelViewMatrix"),
+                u_normal_matrix: normal_depth_shader.uniform_location("u_NormalMatrix"),
+                normal_depth_shader,
+                u_color: edge_shader.uniform_location("u_Color"),
+                u_offset: edge_shader.uniform_location("u_Offset"),
+                edge_shader,
+            }
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut outline.empty_vao);
+            outline.create_framebuffer();
+
+            outline.edge_shader.use_program();
+            let u_normal_depth = outline.edge_shader.uniform_location("u_NormalDepth");
+            outline.edge_shader.set_int(u_normal_depth, 0);
+        }
+        outline
+    }
+
+    unsafe fn create_framebuffer(&mut self) {
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        // normal in rgb, linear depth in alpha (0: background)
+        gl::GenTextures(1, &mut self.normal_depth_texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.normal_depth_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA16F as i32, self.width as i32, self.height as i32,
+            0, gl::RGBA, gl::FLOAT, ptr::null());
+        // nearest: interpolating with the background would make up depths in between
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D,
+            self.normal_depth_texture, 0);
+
+        gl::GenRenderbuffers(1, &mut self.depth_renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_renderbuffer);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, self.width as i32, self.height as i32);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER,
+            self.depth_renderbuffer);
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: Outline framebuffer is not complete!");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_framebuffer(&mut self) {
+        gl::DeleteFramebuffers(1, &self.framebuffer);
+        gl::DeleteTextures(1, &self.normal_depth_texture);
+        gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+    }
+
+    /// Recreates the framebuffer if the size changed
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return
+        }
+        self.width = width;
+        self.height = height;
+        unsafe {
+            self.delete_framebuffer();
+            self.create_framebuffer();
+        }
+    }
+
+    /// Outlines the given nodes of `scene` (respecting its face culling) and blends the edges
+    /// into the previously bound framebuffer. `thickness` overrides `options.thickness`, e.g.
+    /// scaled for supersampling.
+    pub unsafe fn render(&mut self, scene: &Scene, root: &Root, nodes: &[usize], cam_params: &CameraParams,
+        options: &OutlineOptions, thickness: f32)
+    {
+        let mut previous_framebuffer = 0;
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+
+        let mut clear_color = [0.0; 4];
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+
+        // normal and depth pre-pass
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        gl::ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+        gl::Disable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+        self.normal_depth_shader.use_program();
+        for &node_id in nodes {
+            let node = &root.nodes[node_id];
+            let mesh = match node.mesh {
+                Some(ref mesh) => mesh,
+                None => continue,
+            };
+            let transforms = match node.instances {
+                Some(ref instances) => instances.transforms.iter().map(|t| node.final_transform * t).collect(),
+                None => vec![node.final_transform],
+            };
+            for transform in &transforms {
+                let model_view = cam_params.view_matrix * transform;
+                let normal_matrix = model_view.invert().unwrap_or_else(Matrix4::identity).transpose();
+                self.normal_depth_shader.set_mat4(self.u_mvp_matrix, &(cam_params.projection_matrix * model_view));
+                self.normal_depth_shader.set_mat4(self.u_model_view_matrix, &model_view);
+                self.normal_depth_shader.set_mat4(self.u_normal_matrix, &normal_matrix);
+                gl::FrontFace(scene.face_culling.front_face(transform));
+                for primitive in mesh.primitives.iter().filter(|p| scene.shows_primitive(p)) {
+                    match scene.face_culling.culled_face(primitive.material().double_sided) {
+                        Some(face) => {
+                            gl::Enable(gl::CULL_FACE);
+                            gl::CullFace(face);
+                        }
+                        None => gl::Disable(gl::CULL_FACE),
+                    }
+                    primitive.draw_geometry();
+                }
+            }
+        }
+        gl::CullFace(gl::BACK);
+        gl::FrontFace(gl::CCW);
+
+        // edges
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer as u32);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::BindVertexArray(self.empty_vao);
+        self.edge_shader.use_program();
+        self.edge_shader.set_vector3(self.u_color, &options.color);
+        // the Sobel kernel detects an edge one sample offset to both sides
+        let offset = thickness.max(1.0) / 2.0;
+        self.edge_shader.set_vec2(self.u_offset, offset / self.width as f32, offset / self.height as f32);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.normal_depth_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::BindVertexArray(0);
+        gl::Disable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+impl Drop for Outline {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_framebuffer();
+            gl::DeleteVertexArrays(1, &self.empty_vao);
+        }
+    }
+}
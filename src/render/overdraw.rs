@@ -0,0 +1,164 @@
+use std::ptr;
+
+use crate::controls::CameraParams;
+use crate::render::{Root, Scene};
+use crate::shader::Shader;
+
+/// Number of overlapping fragments shown white, one step of the color ramp per fragment
+/// (black: none, blue, cyan, green, yellow, red)
+pub const OVERDRAW_MAX_COUNT: u32 = 6;
+
+/// Overdraw view: counts the fragments of all visible primitives per pixel with additive
+/// blending and without depth test, and shows the count as a heatmap.
+pub struct Overdraw {
+    width: u32,
+    height: u32,
+
+    framebuffer: u32,
+    count_texture: u32,
+
+    empty_vao: u32,
+
+    count_shader: Shader,
+    u_mvp_matrix: i32,
+    ramp_shader: Shader,
+}
+
+impl Overdraw {
+    pub fn new(width: u32, height: u32) -> Overdraw {
+        let mut count_shader = Shader::from_source(
+            include_str!("../shaders/picking-vert.glsl"),
+            include_str!("../shaders/overdraw-frag.glsl"),
+            &[]);
+        let mut overdraw = Overdraw {
+            width, height,
+            framebuffer: 0,
+            count_texture: 0,
+            empty_vao: 0,
+            u_mvp_matrix: unsafe { count_shader.uniform_location("u_MVPMatrix") },
+            count_shader,
+            ramp_shader: Shader::from_source(include_str!("../shaders/fullscreen-vert.glsl"),
+                include_str!("../shaders/overdraw-ramp-frag.glsl"), &[]),
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut overdraw.empty_vao);
+            overdraw.create_framebuffer();
+
+            overdraw.ramp_shader.use_program();
+            let u_count = overdraw.ramp_shader.uniform_location("u_Count");
+            overdraw.ramp_shader.set_int(u_count, 0);
+            let u_max_count = overdraw.ramp_shader.uniform_location("u_MaxCount");
+            overdraw.ramp_shader.set_float(u_max_count, OVERDRAW_MAX_COUNT as f32);
+        }
+        overdraw
+    }
+
+    unsafe fn create_framebuffer(&mut self) {
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        // float, so that the count doesn't saturate
+        gl::GenTextures(1, &mut self.count_texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.count_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R16F as i32, self.width as i32, self.height as i32,
+            0, gl::RED, gl::FLOAT, ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.count_texture, 0);
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: Overdraw framebuffer is not complete!");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_framebuffer(&mut self) {
+        gl::DeleteFramebuffers(1, &self.framebuffer);
+        gl::DeleteTextures(1, &self.count_texture);
+    }
+
+    /// Recreates the framebuffer if the size changed
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return
+        }
+        self.width = width;
+        self.height = height;
+        unsafe {
+            self.delete_framebuffer();
+            self.create_framebuffer();
+        }
+    }
+
+    /// Counts the fragments of the visible nodes of all `scenes` (respecting their face culling)
+    /// and draws the heatmap into the previously bound framebuffer.
+    pub unsafe fn render(&mut self, scenes: &[(&Scene, &Root)], cam_params: &CameraParams) {
+        let mut previous_framebuffer = 0;
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+
+        let mut clear_color = [0.0; 4];
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+        self.count_shader.use_program();
+        let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+        for &(scene, root) in scenes {
+            for node_id in scene.visible_nodes(root) {
+                let node = &root.nodes[node_id];
+                let mesh = match node.mesh {
+                    Some(ref mesh) => mesh,
+                    None => continue,
+                };
+                let transforms = match node.instances {
+                    Some(ref instances) => instances.transforms.iter().map(|t| node.final_transform * t).collect(),
+                    None => vec![node.final_transform],
+                };
+                for transform in &transforms {
+                    self.count_shader.set_mat4(self.u_mvp_matrix, &(view_projection * transform));
+                    gl::FrontFace(scene.face_culling.front_face(transform));
+                    for primitive in mesh.primitives.iter().filter(|p| scene.shows_primitive(p)) {
+                        match scene.face_culling.culled_face(primitive.material().double_sided) {
+                            Some(face) => {
+                                gl::Enable(gl::CULL_FACE);
+                                gl::CullFace(face);
+                            }
+                            None => gl::Disable(gl::CULL_FACE),
+                        }
+                        primitive.draw_geometry();
+                    }
+                }
+            }
+        }
+        gl::Disable(gl::BLEND);
+        gl::CullFace(gl::BACK);
+        gl::FrontFace(gl::CCW);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer as u32);
+        gl::BindVertexArray(self.empty_vao);
+        self.ramp_shader.use_program();
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.count_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+impl Drop for Overdraw {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_framebuffer();
+            gl::DeleteVertexArrays(1, &self.empty_vao);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::controls::CameraParams;
+use crate::render::{front_face, Root, Scene};
+use crate::render::math::*;
+use crate::shader::Shader;
+use crate::utils::gl_check_error;
+
+/// Result of picking: the node and the position of the primitive in the `primitives` of the node's
+/// mesh (see `Primitive::index` for its glTF index)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pick {
+    pub node: usize,
+    pub primitive: usize,
+    /// World space position of the picked surface point
+    pub position: Point3,
+    /// Feature or batch id of the picked triangle, if the primitive has them (see `Primitive::has_feature_ids`)
+    pub feature_id: Option<u32>,
+}
+
+/// Renders primitive ids into an integer framebuffer to determine what's under the cursor
+pub struct Picker {
+    framebuffer: u32,
+    id_texture: u32,
+    feature_id_texture: u32,
+    depth_renderbuffer: u32,
+    width: u32,
+    height: u32,
+
+    shader: Shader,
+    u_mvp_matrix: i32,
+    u_pick_id: i32,
+}
+
+impl Picker {
+    pub fn new(width: u32, height: u32) -> Picker {
+        let mut shader = Shader::from_source(
+            include_str!("../shaders/picking-vert.glsl"),
+            include_str!("../shaders/picking-frag.glsl"),
+            &[]);
+        let (u_mvp_matrix, u_pick_id) = unsafe {
+            (shader.uniform_location("u_MVPMatrix"), shader.uniform_location("u_PickId"))
+        };
+
+        let mut picker = Picker {
+            framebuffer: 0,
+            id_texture: 0,
+            feature_id_texture: 0,
+            depth_renderbuffer: 0,
+            width, height,
+            shader,
+            u_mvp_matrix,
+            u_pick_id,
+        };
+        unsafe { picker.create_framebuffer() };
+        picker
+    }
+
+    unsafe fn create_framebuffer(&mut self) {
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        self.id_texture = self.create_id_texture();
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.id_texture, 0);
+        self.feature_id_texture = self.create_id_texture();
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT1, gl::TEXTURE_2D, self.feature_id_texture, 0);
+        let attachments = [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1];
+        gl::DrawBuffers(2, attachments.as_ptr());
+
+        gl::GenRenderbuffers(1, &mut self.depth_renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_renderbuffer);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, self.width as i32, self.height as i32);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, self.depth_renderbuffer);
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: Picking framebuffer is not complete!");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn create_id_texture(&self) -> u32 {
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R32UI as i32, self.width as i32, self.height as i32,
+            0, gl::RED_INTEGER, gl::UNSIGNED_INT, ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        texture
+    }
+
+    unsafe fn delete_framebuffer(&mut self) {
+        gl::DeleteFramebuffers(1, &self.framebuffer);
+        gl::DeleteTextures(1, &self.id_texture);
+        gl::DeleteTextures(1, &self.feature_id_texture);
+        gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+    }
+
+    /// Recreates the framebuffer if the size changed
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return
+        }
+        self.width = width;
+        self.height = height;
+        unsafe {
+            self.delete_framebuffer();
+            self.create_framebuffer();
+        }
+    }
+
+    /// Returns the primitive at the given window position (in physical pixels, origin top left).
+    /// NOTE: leaves the default framebuffer bound.
+    pub fn pick(&mut self, scene: &Scene, root: &Root, cam_params: &CameraParams, x: u32, y: u32) -> Option<Pick> {
+        if x >= self.width || y >= self.height {
+            return None
+        }
+
+        let mut picks = vec![];
+        let mut id = 0u32;
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.width as i32, self.height as i32);
+            gl::ClearBufferuiv(gl::COLOR, 0, &id);
+            gl::ClearBufferuiv(gl::COLOR, 1, &id);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+            gl::Disable(gl::BLEND);
+
+            self.shader.use_program();
+            let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+            for node_id in scene.visible_nodes(root) {
+                let node = &root.nodes[node_id];
+                let mesh = match node.mesh {
+                    Some(ref mesh) => mesh,
+                    None => continue,
+                };
+                self.shader.set_mat4(self.u_mvp_matrix, &(view_projection * node.final_transform));
+                gl::FrontFace(front_face(&node.final_transform));
+                for (i, primitive) in mesh.primitives.iter().enumerate() {
+                    if !scene.shows_primitive(primitive) {
+                        continue
+                    }
+                    if primitive.material().double_sided {
+                        gl::Disable(gl::CULL_FACE);
+                    } else {
+                        gl::Enable(gl::CULL_FACE);
+                    }
+                    id += 1;
+                    picks.push((node_id, i));
+                    self.shader.set_uint(self.u_pick_id, id);
+                    primitive.draw_geometry();
+                }
+            }
+
+            let mut picked_id = 0u32;
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl::ReadPixels(x as i32, (self.height - y - 1) as i32, 1, 1, gl::RED_INTEGER, gl::UNSIGNED_INT,
+                &mut picked_id as *mut u32 as *mut c_void);
+
+            let mut feature_id = 0u32;
+            gl::ReadBuffer(gl::COLOR_ATTACHMENT1);
+            gl::ReadPixels(x as i32, (self.height - y - 1) as i32, 1, 1, gl::RED_INTEGER, gl::UNSIGNED_INT,
+                &mut feature_id as *mut u32 as *mut c_void);
+
+            let mut depth = 0f32;
+            gl::ReadPixels(x as i32, (self.height - y - 1) as i32, 1, 1, gl::DEPTH_COMPONENT, gl::FLOAT,
+                &mut depth as *mut f32 as *mut c_void);
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl_check_error!();
+
+            if picked_id == 0 {
+                return None
+            }
+            let (node, primitive) = picks.get(picked_id as usize - 1).cloned()?;
+            let position = self.unproject(&view_projection, x, y, depth);
+            let feature_id = root.nodes[node].mesh.as_ref()
+                .filter(|mesh| mesh.primitives[primitive].has_feature_ids())
+                .map(|_| feature_id);
+            Some(Pick { node, primitive, position, feature_id })
+        }
+    }
+
+    /// Reconstructs the world space position from window coordinates and depth
+    fn unproject(&self, view_projection: &Matrix4, x: u32, y: u32, depth: f32) -> Point3 {
+        let ndc = Vector4::new(
+            (x as f32 + 0.5) / self.width as f32 * 2.0 - 1.0,
+            1.0 - (y as f32 + 0.5) / self.height as f32 * 2.0,
+            depth * 2.0 - 1.0,
+            1.0);
+        let inverse = view_projection.invert().unwrap_or_else(Matrix4::identity);
+        let position = inverse * ndc;
+        Point3::from_vec(position.truncate() / position.w)
+    }
+}
+
+impl Drop for Picker {
+    fn drop(&mut self) {
+        unsafe { self.delete_framebuffer() }
+    }
+}
@@ -0,0 +1,413 @@
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::render::SsaoOptions;
+use crate::render::math::*;
+use crate::shader::Shader;
+
+/// Number of (progressively halved) textures in the bloom mip chain
+const BLOOM_MIP_COUNT: usize = 6;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BloomOptions {
+    pub enabled: bool,
+    /// Pixels brighter than this (max. color component) contribute to the bloom
+    pub threshold: f32,
+    pub intensity: f32,
+}
+
+impl Default for BloomOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1.0,
+            intensity: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExposureOptions {
+    /// Manual exposure in stops, i.e. the scene color is scaled by 2^stops
+    /// (applied on top of the auto exposure)
+    pub stops: f32,
+    /// Adapt the exposure to the average scene luminance
+    pub auto: bool,
+    /// Rate of the auto exposure adaptation (1/seconds)
+    pub adaptation_speed: f32,
+}
+
+impl Default for ExposureOptions {
+    fn default() -> Self {
+        Self {
+            stops: 0.0,
+            auto: false,
+            adaptation_speed: 2.0,
+        }
+    }
+}
+
+/// Color space of the display, the final pass converts the (linear sRGB) scene colors to its primaries
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputColorSpace {
+    #[default]
+    Srgb,
+    /// Wide gamut displays, e.g. of recent Macs (same white point and transfer curve as sRGB)
+    DisplayP3,
+}
+
+impl OutputColorSpace {
+    /// Converts linear sRGB colors to linear colors with the primaries of the color space
+    pub fn primaries_transform(self) -> Matrix3 {
+        match self {
+            OutputColorSpace::Srgb => Matrix3::identity(),
+            // column-major, i.e. the transpose of the usual notation
+            OutputColorSpace::DisplayP3 => Matrix3::new(
+                0.822_462, 0.033_194, 0.017_083,
+                0.177_538, 0.966_806, 0.072_397,
+                0.0, 0.0, 0.910_520),
+        }
+    }
+}
+
+/// Operator that maps the HDR scene colors to the displayable range (after bloom and exposure)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMapping {
+    /// Colors are clamped, like when rendering without post-processing
+    #[default]
+    None,
+    /// `c / (1 + c)` per channel, keeps the hue but desaturates highlights
+    Reinhard,
+    /// Filmic curve (Narkowicz' fit of the ACES reference rendering transform)
+    Aces,
+}
+
+impl ToneMapping {
+    /// Value of the `u_ToneMapping` uniform of the composite shader
+    fn shader_index(self) -> i32 {
+        match self {
+            ToneMapping::None => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+        }
+    }
+}
+
+/// Options of the screen-space effects
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PostProcessOptions {
+    pub bloom: BloomOptions,
+    pub ssao: SsaoOptions,
+    pub exposure: ExposureOptions,
+    pub output_color_space: OutputColorSpace,
+    pub tone_mapping: ToneMapping,
+}
+
+impl PostProcessOptions {
+    /// Whether the scene needs to be rendered into the HDR framebuffer at all
+    /// (SSAO is applied while rendering the scene)
+    pub fn enabled(&self) -> bool {
+        self.bloom.enabled || self.exposure.stops != 0.0 || self.exposure.auto ||
+            self.output_color_space != OutputColorSpace::Srgb || self.tone_mapping != ToneMapping::None
+    }
+}
+
+/// Average luminance that the auto exposure maps to middle gray
+const AUTO_EXPOSURE_KEY: f32 = 0.18;
+/// Limits of the auto exposure factor (+-6 stops)
+const AUTO_EXPOSURE_RANGE: (f32, f32) = (1.0 / 64.0, 64.0);
+
+/// Renders the scene into a HDR (floating point) framebuffer and applies the
+/// post-processing effects when compositing the result into the target framebuffer.
+///
+/// Usage: `begin()`, draw the scene, `end()`.
+pub struct PostProcessing {
+    width: u32,
+    height: u32,
+
+    framebuffer: u32,
+    color_texture: u32,
+    depth_renderbuffer: u32,
+    // framebuffer that was bound in `begin()`
+    target_framebuffer: u32,
+
+    bloom_framebuffer: u32,
+    bloom_textures: Vec<(u32, u32, u32)>, // (id, width, height)
+
+    empty_vao: u32,
+
+    /// Current auto exposure factor (adapts over time, see `adapt_exposure`)
+    auto_exposure: f32,
+
+    downsample_shader: Shader,
+    upsample_shader: Shader,
+    composite_shader: Shader,
+}
+
+impl PostProcessing {
+    pub fn new(width: u32, height: u32) -> PostProcessing {
+        let vertex_shader = include_str!("../shaders/fullscreen-vert.glsl");
+        let mut post = PostProcessing {
+            width, height,
+            framebuffer: 0,
+            color_texture: 0,
+            depth_renderbuffer: 0,
+            target_framebuffer: 0,
+            bloom_framebuffer: 0,
+            bloom_textures: vec![],
+            empty_vao: 0,
+            auto_exposure: 1.0,
+            downsample_shader: Shader::from_source(vertex_shader,
+                include_str!("../shaders/bloom-downsample-frag.glsl"), &[]),
+            upsample_shader: Shader::from_source(vertex_shader,
+                include_str!("../shaders/bloom-upsample-frag.glsl"), &[]),
+            composite_shader: Shader::from_source(vertex_shader,
+                include_str!("../shaders/composite-frag.glsl"), &[]),
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut post.empty_vao);
+            post.create_framebuffers();
+
+            post.composite_shader.use_program();
+            let (u_color, u_bloom) = (
+                post.composite_shader.uniform_location("u_Color"),
+                post.composite_shader.uniform_location("u_Bloom"));
+            post.composite_shader.set_int(u_color, 0);
+            post.composite_shader.set_int(u_bloom, 1);
+            for shader in &mut [&mut post.downsample_shader, &mut post.upsample_shader] {
+                shader.use_program();
+                let u_texture = shader.uniform_location("u_Texture");
+                shader.set_int(u_texture, 0);
+            }
+        }
+        post
+    }
+
+    unsafe fn create_framebuffers(&mut self) {
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        self.color_texture = create_hdr_texture(self.width, self.height);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.color_texture, 0);
+
+        gl::GenRenderbuffers(1, &mut self.depth_renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_renderbuffer);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, self.width as i32, self.height as i32);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, self.depth_renderbuffer);
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: HDR framebuffer is not complete!");
+        }
+
+        // the bloom textures are attached as needed
+        gl::GenFramebuffers(1, &mut self.bloom_framebuffer);
+        let (mut width, mut height) = (self.width, self.height);
+        for _ in 0..BLOOM_MIP_COUNT {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            self.bloom_textures.push((create_hdr_texture(width, height), width, height));
+        }
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_framebuffers(&mut self) {
+        gl::DeleteFramebuffers(1, &self.framebuffer);
+        gl::DeleteTextures(1, &self.color_texture);
+        gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+        gl::DeleteFramebuffers(1, &self.bloom_framebuffer);
+        for (id, _, _) in self.bloom_textures.drain(..) {
+            gl::DeleteTextures(1, &id);
+        }
+    }
+
+    /// Recreates the framebuffers if the size changed
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return
+        }
+        self.width = width;
+        self.height = height;
+        unsafe {
+            self.delete_framebuffers();
+            self.create_framebuffers();
+        }
+    }
+
+    /// Redirects rendering into the HDR framebuffer (and clears it)
+    pub unsafe fn begin(&mut self) {
+        let mut target = 0;
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut target);
+        self.target_framebuffer = target as u32;
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    }
+
+    /// Applies the effects and composites the result into the framebuffer that
+    /// was bound when calling `begin()`. `delta_time` (seconds) is the time since the
+    /// last frame, for the auto exposure adaptation.
+    pub unsafe fn end(&mut self, options: &PostProcessOptions, delta_time: f32) {
+        let mut exposure = 2f32.powf(options.exposure.stops);
+        if options.exposure.auto {
+            let luminance = self.average_luminance();
+            self.auto_exposure = adapt_exposure(self.auto_exposure, luminance, delta_time,
+                options.exposure.adaptation_speed);
+            exposure *= self.auto_exposure;
+        } else {
+            self.auto_exposure = 1.0;
+        }
+
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        gl::BindVertexArray(self.empty_vao);
+
+        if options.bloom.enabled {
+            self.render_bloom(&options.bloom);
+        }
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.target_framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        self.composite_shader.use_program();
+        let intensity = if options.bloom.enabled { options.bloom.intensity } else { 0.0 };
+        let u_bloom_intensity = self.composite_shader.uniform_location("u_BloomIntensity");
+        self.composite_shader.set_float(u_bloom_intensity, intensity);
+        let u_exposure = self.composite_shader.uniform_location("u_Exposure");
+        self.composite_shader.set_float(u_exposure, exposure);
+        let u_output_primaries = self.composite_shader.uniform_location("u_OutputPrimaries");
+        self.composite_shader.set_mat3(u_output_primaries, &options.output_color_space.primaries_transform());
+        let u_tone_mapping = self.composite_shader.uniform_location("u_ToneMapping");
+        self.composite_shader.set_int(u_tone_mapping, options.tone_mapping.shader_index());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.color_texture);
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, self.bloom_textures[0].0);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    /// Average luminance of the scene, from the smallest mip level of the HDR color texture
+    unsafe fn average_luminance(&self) -> f32 {
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.color_texture);
+        gl::GenerateMipmap(gl::TEXTURE_2D);
+        let level = 32 - self.width.max(self.height).leading_zeros() as i32 - 1; // log2, 1x1
+        let mut pixel = [0f32; 4];
+        gl::GetTexImage(gl::TEXTURE_2D, level, gl::RGBA, gl::FLOAT, pixel.as_mut_ptr() as *mut c_void);
+        0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2]
+    }
+
+    /// Thresholds and downsamples the scene through the mip chain, then
+    /// upsamples back to the first mip (which then contains the bloom)
+    unsafe fn render_bloom(&mut self, options: &BloomOptions) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.bloom_framebuffer);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        self.downsample_shader.use_program();
+        let u_texel_size = self.downsample_shader.uniform_location("u_TexelSize");
+        let u_threshold = self.downsample_shader.uniform_location("u_Threshold");
+        let (mut source, mut source_width, mut source_height) = (self.color_texture, self.width, self.height);
+        for (i, &(id, width, height)) in self.bloom_textures.iter().enumerate() {
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, id, 0);
+            gl::Viewport(0, 0, width as i32, height as i32);
+            self.downsample_shader.set_vec2(u_texel_size, 1.0 / source_width as f32, 1.0 / source_height as f32);
+            self.downsample_shader.set_float(u_threshold, if i == 0 { options.threshold } else { -1.0 });
+            gl::BindTexture(gl::TEXTURE_2D, source);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            source = id;
+            source_width = width;
+            source_height = height;
+        }
+
+        self.upsample_shader.use_program();
+        let u_texel_size = self.upsample_shader.uniform_location("u_TexelSize");
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+        for pair in self.bloom_textures.windows(2).rev() {
+            let (target, width, height) = pair[0];
+            let (source, source_width, source_height) = pair[1];
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, target, 0);
+            gl::Viewport(0, 0, width as i32, height as i32);
+            self.upsample_shader.set_vec2(u_texel_size, 1.0 / source_width as f32, 1.0 / source_height as f32);
+            gl::BindTexture(gl::TEXTURE_2D, source);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+        gl::Disable(gl::BLEND);
+    }
+}
+
+impl Drop for PostProcessing {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_framebuffers();
+            gl::DeleteVertexArrays(1, &self.empty_vao);
+        }
+    }
+}
+
+/// Moves the exposure factor towards the one that maps `average_luminance` to middle gray,
+/// exponentially with the given speed (1/seconds)
+fn adapt_exposure(exposure: f32, average_luminance: f32, delta_time: f32, speed: f32) -> f32 {
+    let (min, max) = AUTO_EXPOSURE_RANGE;
+    let target = if average_luminance > 0.0 {
+        (AUTO_EXPOSURE_KEY / average_luminance).max(min).min(max)
+    } else {
+        max
+    };
+    exposure + (target - exposure) * (1.0 - (-delta_time * speed).exp())
+}
+
+unsafe fn create_hdr_texture(width: u32, height: u32) -> u32 {
+    let mut id = 0;
+    gl::GenTextures(1, &mut id);
+    gl::BindTexture(gl::TEXTURE_2D, id);
+    gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA16F as i32, width as i32, height as i32,
+        0, gl::RGBA, gl::FLOAT, ptr::null());
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposure_adaptation() {
+        // a dark scene is brightened gradually
+        let exposure = adapt_exposure(1.0, 0.045, 0.1, 2.0);
+        assert!(exposure > 1.0 && exposure < 4.0);
+        let mut exposure = 1.0;
+        for _ in 0..100 {
+            exposure = adapt_exposure(exposure, 0.045, 0.1, 2.0);
+        }
+        assert!((exposure - 4.0).abs() < 1e-3);
+
+        // black scenes are limited to +6 stops
+        assert!((adapt_exposure(1.0, 0.0, 100.0, 2.0) - 64.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn output_primaries() {
+        let to_p3 = OutputColorSpace::DisplayP3.primaries_transform();
+        // the white point is shared, and sRGB colors are less saturated in P3
+        assert!((to_p3 * vec3(1.0, 1.0, 1.0) - vec3(1.0, 1.0, 1.0)).magnitude() < 1e-4);
+        let red = to_p3 * vec3(1.0, 0.0, 0.0);
+        assert!(red.x < 1.0 && red.y > 0.0 && red.z > 0.0);
+        assert_eq!(OutputColorSpace::Srgb.primaries_transform(), Matrix3::identity());
+    }
+
+    #[test]
+    fn tone_mapping_needs_hdr_framebuffer() {
+        let mut options = PostProcessOptions::default();
+        assert!(!options.enabled());
+        options.tone_mapping = ToneMapping::Aces;
+        assert!(options.enabled());
+    }
+}
@@ -1,20 +1,28 @@
-use std::mem::size_of;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem::{self, size_of};
+use std::ops::Range;
 use std::os::raw::c_void;
-use std::path::Path;
 use std::ptr;
 use std::rc::Rc;
 
 use gl;
 use gl::types::GLenum;
 use gltf;
+use gltf::accessor::DataType;
+use gltf::mesh::Semantic;
+use collision::Aabb;
 use log::{warn, debug};
+use serde_json::Value;
 
 use crate::render::math::*;
-use crate::render::{Material, Root};
+use crate::render::{bake_vertex_occlusion, generate_normals, generate_tangents, simplify_triangles};
+use crate::render::{Instances, Material, Root};
+use crate::render::{instanced_arrays_supported, reset_instance_matrix, set_instance_matrix};
 use crate::shader::*;
-use crate::importdata::ImportData;
+use crate::importdata::{ImportData, ImportOptions};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Vertex {
     pub position: Vector3,
     pub normal: Vector3,
@@ -24,6 +32,10 @@ pub struct Vertex {
     pub color_0: Vector4,
     pub joints_0: [u16; 4],
     pub weights_0: Vector4,
+    /// Baked ambient occlusion (1: unoccluded), see `bake_vertex_occlusion`
+    pub occlusion: f32,
+    /// Feature or batch id of a custom attribute, see `ImportOptions::feature_id_attribute`
+    pub feature_id: u32,
 }
 
 impl Default for Vertex {
@@ -37,10 +49,23 @@ impl Default for Vertex {
             color_0: Vector4::zero(),
             joints_0: [0; 4],
             weights_0: Vector4::zero(),
+            occlusion: 1.0,
+            feature_id: 0,
         }
     }
 }
 
+/// Custom attributes read as feature ids if `ImportOptions::feature_id_attribute` isn't set
+/// (EXT_mesh_features and 3D Tiles batch tables)
+pub const DEFAULT_FEATURE_ID_ATTRIBUTES: [&str; 2] = ["_FEATURE_ID_0", "_BATCHID"];
+
+/// Identifies a primitive by glTF mesh index and its index within the mesh (`Primitive::index`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PrimitiveId {
+    pub mesh: usize,
+    pub primitive: usize,
+}
+
 #[derive(Clone, Debug)]
 pub struct Texture {
     pub id: u32,
@@ -49,46 +74,217 @@ pub struct Texture {
 }
 
 pub struct Primitive {
+    /// Index within the glTF mesh (the position in `Mesh::primitives` differs if earlier
+    /// primitives were skipped in safe mode)
+    pub index: usize,
     pub bounds: Aabb3,
 
     vao: u32,
-    vbo: u32,
+    vertex_buffer: Rc<VertexBuffer>,
+    /// Index of the first of the `num_vertices` vertices of the primitive in `vertex_buffer`
+    first_vertex: usize,
     num_vertices: u32,
 
     ebo: Option<u32>,
     num_indices: u32,
 
     mode: GLenum,
+    /// `(first, count)` of the indices (or vertices) per draw call, split for huge primitives
+    draw_ranges: Vec<(usize, i32)>,
+    /// Decimated preview (`--simplify`), drawn instead while `show_simplified` is set
+    simplified: Option<SimplifiedIndices>,
+    show_simplified: Cell<bool>,
+
+    // flags determined by the vertex attributes, combined with the material's flags to select the shader
+    vertex_shader_flags: ShaderFlags,
+    /// Whether `Vertex::feature_id` was read from a custom attribute
+    has_feature_ids: bool,
+
+    // RefCell since meshes are shared, but materials can be replaced at runtime
+    material: RefCell<Rc<Material>>,
+
+    pbr_shader: RefCell<Rc<PbrShader>>,
+
+    /// Material without a selected variant
+    base_material: Rc<Material>,
+    /// KHR_materials_variants: variant index -> material
+    variant_materials: HashMap<usize, Rc<Material>>,
+    /// Material set with `set_material`, takes precedence over the variants
+    material_override: RefCell<Option<Rc<Material>>>,
+
+    /// Unmorphed vertices and morph targets if the weights are animated, see `set_morph_weights`
+    morph_targets: Option<MorphTargets>,
+}
+
+/// Displacements of one morph target (empty for attributes the target doesn't displace)
+struct MorphTarget {
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    /// XYZ only, the handedness isn't morphed
+    tangents: Vec<Vector3>,
+}
+
+impl MorphTarget {
+    fn read_all(g_primitive: &gltf::Primitive<'_>, buffers: &[gltf::buffer::Data]) -> Vec<MorphTarget> {
+        let read = |accessor: Option<gltf::Accessor<'_>>| accessor
+            .map(|accessor| read_f32_attribute(&accessor, buffers).chunks(3).map(|d| vec3(d[0], d[1], d[2])).collect())
+            .unwrap_or_default();
+        g_primitive.morph_targets()
+            .map(|target| MorphTarget {
+                positions: read(target.positions()),
+                normals: read(target.normals()),
+                tangents: read(target.tangents()),
+            })
+            .collect()
+    }
+}
+
+/// CPU copy of a primitive with animated morph target weights, to upload the morphed vertices
+/// again when the weights change
+struct MorphTargets {
+    base_vertices: Vec<Vertex>,
+    targets: Vec<MorphTarget>,
+    /// Vertices displaced by any target, only these are uploaded
+    displaced: Range<usize>,
+    /// Currently applied weights
+    weights: RefCell<Vec<f32>>,
+}
+
+/// Index buffer of a simplified triangle list with its own vertex array, see `Primitive::simplify`
+struct SimplifiedIndices {
+    vao: u32,
+    ebo: u32,
+    num_indices: u32,
+    draw_ranges: Vec<(usize, i32)>,
+}
+
+/// Uploaded vertices of a primitive, or of the attribute buffer views of several primitives:
+/// primitives whose accessors read from the same views (e.g. one mesh split by material, or the
+/// meshes packed into a few views by gltfpack) share it, each using its range of the vertices,
+/// see `Root::vertex_buffers`. The buffer is deleted when the last primitive using it is dropped.
+#[derive(Debug)]
+pub struct VertexBuffer {
+    vbo: u32,
+    /// Capacity in vertices
+    num_vertices: usize,
+    /// Flags determined by the vertex attributes
+    shader_flags: ShaderFlags,
+    has_feature_ids: bool,
+    /// Vertex ranges uploaded with `upload` and their bounds
+    uploaded: RefCell<Vec<(Range<usize>, Aabb3)>>,
+}
+
+impl VertexBuffer {
+    /// Allocates a buffer for `num_vertices` vertices.
+    /// `usage`: `gl::STATIC_DRAW`, or `gl::DYNAMIC_DRAW` for vertices that are updated while animating
+    pub fn new(num_vertices: usize, shader_flags: ShaderFlags, has_feature_ids: bool, usage: GLenum) -> VertexBuffer {
+        // indices are 32 bit, so larger vertex buffers can't be addressed anyway
+        if num_vertices > u32::MAX as usize {
+            panic!("Primitive has too many vertices ({}, at most {} are supported)", num_vertices, u32::MAX);
+        }
+        let mut vbo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, buffer_size::<Vertex>(num_vertices), ptr::null(), usage);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        VertexBuffer { vbo, num_vertices, shader_flags, has_feature_ids, uploaded: RefCell::new(vec![]) }
+    }
+
+    /// Uploads `vertices` (with the given bounds) starting at vertex `first`
+    pub fn upload(&self, first: usize, vertices: &[Vertex], bounds: Aabb3) {
+        assert!(first + vertices.len() <= self.num_vertices, "vertices out of the range of the vertex buffer");
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferSubData(gl::ARRAY_BUFFER, buffer_size::<Vertex>(first), buffer_size::<Vertex>(vertices.len()),
+                vertices.as_ptr() as *const c_void);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        self.uploaded.borrow_mut().push((first..first + vertices.len(), bounds));
+    }
 
-    material: Rc<Material>,
+    /// Bounds of the vertices in `range` if exactly this range was uploaded already
+    fn uploaded_bounds(&self, range: &Range<usize>) -> Option<Aabb3> {
+        self.uploaded.borrow().iter()
+            .find(|(uploaded, _)| uploaded == range)
+            .map(|&(_, bounds)| bounds)
+    }
+}
 
-    pbr_shader: Rc<PbrShader>,
+impl Drop for VertexBuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.vbo) }
+    }
+}
 
-    // TODO!: mode, targets
+/// Vertices of a primitive as read by `Primitive::load_vertex_data`
+struct VertexData {
+    vertices: Vec<Vertex>,
+    indices: Option<Vec<u32>>,
+    /// Flags determined by the vertex attributes
+    shader_flags: ShaderFlags,
+    bounds: Aabb3,
+    has_feature_ids: bool,
+    morph_targets: Option<MorphTargets>,
 }
 
 impl Primitive {
+    /// `vertices`: the range of the primitive's vertices in `vertex_buffer`
     pub fn new(
+        vertex_buffer: Rc<VertexBuffer>,
+        vertices: Range<usize>,
         bounds: Aabb3,
-        vertices: &[Vertex],
         indices: Option<Vec<u32>>,
         mode: GLenum,
         material: Rc<Material>,
         shader: Rc<PbrShader>,
     ) -> Primitive {
+        let num_vertices = vertices.len();
         let num_indices = indices.as_ref().map(|i| i.len()).unwrap_or(0);
+        if num_indices > u32::MAX as usize {
+            panic!("Primitive has too many indices ({}, at most {} are supported)", num_indices, u32::MAX);
+        }
+        let (count, max_count, elements) = match indices {
+            Some(_) => (num_indices, unsafe { gl_limit(gl::MAX_ELEMENTS_INDICES) }, "indices"),
+            None => (num_vertices, unsafe { gl_limit(gl::MAX_ELEMENTS_VERTICES) }, "vertices"),
+        };
+        if indices.is_some() && num_vertices > unsafe { gl_limit(gl::MAX_ELEMENTS_VERTICES) } {
+            debug!("Primitive has {} vertices, more than the recommended GL_MAX_ELEMENTS_VERTICES", num_vertices);
+        }
+        let draw_ranges = draw_ranges(count, mode, max_count);
+        if draw_ranges.len() == 1 && count > max_count {
+            // strips, fans and loops can't be split without duplicating vertices
+            if count > i32::MAX as usize {
+                panic!("Primitive with mode {} has too many {} for a single draw call ({})", mode, elements, count);
+            }
+            warn!("Primitive with mode {} exceeds the recommended maximum of {} {} per draw call ({})",
+                mode, max_count, elements, count);
+        }
         let mut prim = Primitive {
+            index: 0,
             bounds,
-            num_vertices: vertices.len() as u32,
+            first_vertex: vertices.start,
+            num_vertices: num_vertices as u32,
             num_indices: num_indices as u32,
-            vao: 0, vbo: 0, ebo: None,
+            vao: 0, ebo: None,
             mode,
-            material,
-            pbr_shader: shader,
+            draw_ranges,
+            simplified: None,
+            show_simplified: Cell::new(false),
+            vertex_shader_flags: vertex_buffer.shader_flags,
+            has_feature_ids: vertex_buffer.has_feature_ids,
+            vertex_buffer,
+            material: RefCell::new(Rc::clone(&material)),
+            pbr_shader: RefCell::new(shader),
+            base_material: material,
+            variant_materials: HashMap::new(),
+            material_override: RefCell::new(None),
+            morph_targets: None,
         };
 
-        // now that we have all the required data, set the vertex buffers and its attribute pointers.
-        unsafe { prim.setup_primitive(vertices, indices) }
+        // now that we have all the required data, set the index buffer and the attribute pointers.
+        unsafe { prim.setup_primitive(indices) }
         prim
     }
 
@@ -96,33 +292,301 @@ impl Primitive {
         g_primitive: &gltf::Primitive<'_>,
         primitive_index: usize,
         mesh_index: usize,
+        morph_weights: &[f32],
+        animated_weights: bool,
         root: &mut Root,
-        imp: &ImportData,
-        base_path: &Path) -> Primitive
+        imp: &ImportData) -> Result<Primitive, String>
     {
-        let buffers = &imp.buffers;
-        let reader = g_primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-        let positions = {
-            let iter = reader
-                .read_positions()
-                .unwrap_or_else(||
-                    panic!("primitives must have the POSITION attribute (mesh: {}, primitive: {})",
-                        mesh_index, primitive_index)
-                );
-            iter.collect::<Vec<_>>()
+        // everything that can fail is checked before creating GL objects or changing `root`
+        let num_vertices = check_accessors(g_primitive, &imp.buffers)?;
+        let mode = g_primitive.mode().as_gl_enum();
+        let json = &imp.json["meshes"][mesh_index]["primitives"][primitive_index];
+        let has_morph_targets = g_primitive.morph_targets().len() > 0;
+        let morphed = has_morph_targets && morph_weights.iter().any(|&weight| weight != 0.0);
+        let animated_weights = animated_weights && has_morph_targets;
+        let tangent_tex_coord = tangent_tex_coord(g_primitive, mode, &imp.options);
+        // morphed vertices depend on the weights of the node, generated tangents on the indices
+        let shared = if morphed || animated_weights || tangent_tex_coord.is_some() {
+            None
+        } else {
+            shared_vertices(g_primitive, mode, &imp.options)
+        };
+        let cached = shared.as_ref().and_then(|(key, range, _)| {
+            let vertex_buffer = root.vertex_buffers.get(key)?;
+            Some((Rc::clone(vertex_buffer), vertex_buffer.uploaded_bounds(range)?))
+        });
+        let (vertex_buffer, vertices, bounds, indices, morph_targets) = match cached {
+            Some((vertex_buffer, bounds)) => {
+                debug!("Primitive {} of mesh {} shares the vertices of an earlier primitive",
+                    primitive_index, mesh_index);
+                let (_, range, _) = shared.unwrap();
+                let indices = read_indices(g_primitive, &imp.buffers);
+                check_indices(indices.as_deref(), num_vertices)?;
+                (vertex_buffer, range, bounds, indices, None)
+            }
+            None => {
+                let weights = if morphed || animated_weights { morph_weights } else { &[] };
+                let data = Self::load_vertex_data(g_primitive, primitive_index, mesh_index,
+                    weights, animated_weights, tangent_tex_coord, imp)?;
+                let (vertex_buffer, range) = match shared {
+                    // the vertices correspond to the elements of the views (no generated normals etc.)
+                    Some((key, range, capacity)) if range.len() == data.vertices.len() => {
+                        let vertex_buffer = root.vertex_buffers.entry(key)
+                            .or_insert_with(|| Rc::new(VertexBuffer::new(capacity, data.shader_flags,
+                                data.has_feature_ids, gl::STATIC_DRAW)));
+                        if range.start > 0 || range.len() < capacity {
+                            debug!("Primitive {} of mesh {} uses vertices {:?} of a shared vertex buffer",
+                                primitive_index, mesh_index, range);
+                        }
+                        (Rc::clone(vertex_buffer), range)
+                    }
+                    _ => {
+                        let usage = if data.morph_targets.is_some() { gl::DYNAMIC_DRAW } else { gl::STATIC_DRAW };
+                        let vertex_buffer = VertexBuffer::new(data.vertices.len(), data.shader_flags,
+                            data.has_feature_ids, usage);
+                        (Rc::new(vertex_buffer), 0..data.vertices.len())
+                    }
+                };
+                vertex_buffer.upload(range.start, &data.vertices, data.bounds);
+                (vertex_buffer, range, data.bounds, data.indices, data.morph_targets)
+            }
+        };
+
+        let material = load_material(&g_primitive.material(), root, imp);
+        let shader = root.get_shader(primitive_shader_flags(mode, vertex_buffer.shader_flags, material.shader_flags()));
+
+        let mut primitive = Primitive::new(vertex_buffer, vertices, bounds, indices, mode, material, shader);
+        primitive.index = primitive_index;
+        primitive.morph_targets = morph_targets;
+        if let Some(ratio) = imp.options.simplify {
+            unsafe { primitive.simplify(ratio) }
+            if let Some(triangles) = primitive.simplified_triangle_count() {
+                debug!("Simplified primitive {} of mesh {} from {} to {} triangles",
+                    primitive_index, mesh_index, primitive.triangle_count(), triangles);
+            }
+        }
+
+        let variants_extension = &json["extensions"]["KHR_materials_variants"];
+        for (material_index, variants) in variant_mappings(variants_extension).into_iter()
+            .filter(|_| !imp.options.geometry_only)
+        {
+            let g_material = match imp.doc.materials().nth(material_index) {
+                Some(g_material) => g_material,
+                None => {
+                    warn!("Invalid material {} in KHR_materials_variants of primitive {} of mesh {}",
+                        material_index, primitive_index, mesh_index);
+                    continue
+                }
+            };
+            let material = load_material(&g_material, root, imp);
+            for variant in variants {
+                primitive.variant_materials.insert(variant, Rc::clone(&material));
+            }
+        }
+        Ok(primitive)
+    }
+
+    /// Reads the vertices, applies the morph targets with non-zero `morph_weights` and
+    /// generates missing normals etc. With `animated_weights`, the unmorphed vertices and the
+    /// morph targets are kept for `set_morph_weights`. Missing tangents are generated for the
+    /// texture coordinate set `tangent_tex_coord`, see `tangent_tex_coord`. The accessors must
+    /// have been checked with `check_accessors`.
+    fn load_vertex_data(
+        g_primitive: &gltf::Primitive<'_>,
+        primitive_index: usize,
+        mesh_index: usize,
+        morph_weights: &[f32],
+        animated_weights: bool,
+        tangent_tex_coord: Option<u32>,
+        imp: &ImportData) -> Result<VertexData, String>
+    {
+        let (mut vertices, mut indices, mut vertex_shader_flags) =
+            Self::load_vertices(g_primitive, &imp.buffers, primitive_index, mesh_index);
+        check_indices(indices.as_deref(), vertices.len())?;
+        let mode = g_primitive.mode().as_gl_enum();
+        let has_feature_ids = read_feature_ids(g_primitive, &imp.buffers, imp.options.feature_id_attribute.as_deref(),
+            &mut vertices, primitive_index, mesh_index);
+
+        let mut sanitized = false;
+        if imp.options.sanitize {
+            let (positions, normals) = sanitize_vertices(&mut vertices);
+            if positions > 0 || normals > 0 {
+                warn!("Replaced non-finite values in {} positions and {} normals of primitive {} of mesh {}",
+                    positions, normals, primitive_index, mesh_index);
+                sanitized = true;
+            }
+        }
+
+        let mut animated_weights = animated_weights;
+        if animated_weights && !vertex_shader_flags.contains(ShaderFlags::HAS_NORMALS) && mode == gl::TRIANGLES {
+            // the generated normals don't follow the morph targets (and the vertices are split)
+            warn!("Primitive {} of mesh {} has animated morph target weights, but no normals: \
+                   using the initial weights", primitive_index, mesh_index);
+            animated_weights = false;
+        }
+        let morphed = morph_weights.iter().any(|&weight| weight != 0.0);
+        let targets = if morphed || animated_weights {
+            MorphTarget::read_all(g_primitive, &imp.buffers)
+        } else {
+            vec![]
         };
+        if morphed {
+            debug!("Applying morph target weights {:?} to primitive {} of mesh {}",
+                morph_weights, primitive_index, mesh_index);
+            if !animated_weights {
+                apply_morph_targets(&mut vertices, 0, &targets, morph_weights);
+            }
+        }
+
+        if !vertex_shader_flags.contains(ShaderFlags::HAS_NORMALS) {
+            if mode == gl::TRIANGLES {
+                debug!("Generating NORMALs for primitive {} of mesh {}", primitive_index, mesh_index);
+                let (new_vertices, new_indices) =
+                    generate_normals(&vertices, indices.as_deref(), imp.options.smooth_angle);
+                vertices = new_vertices;
+                indices = Some(new_indices);
+                vertex_shader_flags |= ShaderFlags::HAS_NORMALS;
+            } else {
+                debug!("Found no NORMALs for primitive {} of mesh {} \
+                       (normal generation is only implemented for triangle lists)", primitive_index, mesh_index);
+            }
+        }
+
+        if let Some(tex_coord) = tangent_tex_coord {
+            if vertex_shader_flags.contains(ShaderFlags::HAS_NORMALS) {
+                debug!("Generating TANGENTs for primitive {} of mesh {}", primitive_index, mesh_index);
+                generate_tangents(&mut vertices, indices.as_deref(), tex_coord);
+                vertex_shader_flags |= ShaderFlags::HAS_TANGENTS;
+            }
+        }
+
+        if let Some(samples) = imp.options.vertex_ao_samples {
+            if mode == gl::TRIANGLES && vertex_shader_flags.contains(ShaderFlags::HAS_NORMALS) {
+                debug!("Baking vertex AO for primitive {} of mesh {}", primitive_index, mesh_index);
+                bake_vertex_occlusion(&mut vertices, indices.as_deref(), samples);
+                vertex_shader_flags |= ShaderFlags::HAS_VERTEX_AO;
+            }
+        }
+
+        let is_quantized = match g_primitive.get(&Semantic::Positions) {
+            Some(accessor) => accessor.data_type() != DataType::F32,
+            None => false,
+        };
+        let morph_targets = if animated_weights {
+            let base_vertices = vertices.clone();
+            apply_morph_targets(&mut vertices, 0, &targets, morph_weights);
+            Some(MorphTargets {
+                base_vertices,
+                displaced: displaced_range(&targets),
+                targets,
+                weights: RefCell::new(morph_weights.to_vec()),
+            })
+        } else {
+            None
+        };
+
+        let bounds = if let Some(ref morph_targets) = morph_targets {
+            morph_bounds(&morph_targets.base_vertices, &morph_targets.targets)
+        } else if is_quantized || morphed || sanitized {
+            // the accessor's min/max aren't dequantized (or morphed or sanitized) yet
+            vertex_bounds(&vertices)
+        } else {
+            let bounds = g_primitive.bounding_box();
+            Aabb3 {
+                min: bounds.min.into(),
+                max: bounds.max.into()
+            }
+        };
+
+        Ok(VertexData { vertices, indices, shader_flags: vertex_shader_flags, bounds, has_feature_ids, morph_targets })
+    }
 
-        let bounds = g_primitive.bounding_box();
-        let bounds = Aabb3 {
-            min: bounds.min.into(),
-            max: bounds.max.into()
+    /// Applies animated morph target weights: uploads the vertices displaced by the morph targets
+    /// again if the weights changed. Does nothing for primitives without animated weights.
+    pub fn set_morph_weights(&self, weights: &[f32]) {
+        let morph_targets = match self.morph_targets {
+            Some(ref morph_targets) => morph_targets,
+            None => return,
         };
+        if morph_targets.weights.borrow().as_slice() == weights {
+            return
+        }
+        morph_targets.weights.replace(weights.to_vec());
+        let displaced = morph_targets.displaced.clone();
+        let mut vertices = morph_targets.base_vertices[displaced.clone()].to_vec();
+        apply_morph_targets(&mut vertices, displaced.start, &morph_targets.targets, weights);
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer.vbo);
+            gl::BufferSubData(gl::ARRAY_BUFFER, buffer_size::<Vertex>(self.first_vertex + displaced.start),
+                buffer_size::<Vertex>(vertices.len()), vertices.as_ptr() as *const c_void);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    /// Whether the vertices have feature ids (see `ImportOptions::feature_id_attribute`)
+    pub fn has_feature_ids(&self) -> bool {
+        self.has_feature_ids
+    }
+
+    pub fn material(&self) -> Rc<Material> {
+        Rc::clone(&self.material.borrow())
+    }
+
+    /// Flags of the current shader variant (from the vertex attributes and the material)
+    pub fn shader_flags(&self) -> ShaderFlags {
+        self.pbr_shader.borrow().flags
+    }
+
+    /// Replaces the material (also when switching variants later) and selects the matching
+    /// shader variant. Takes effect on the next `draw`.
+    pub fn set_material(&self, material: Rc<Material>, root: &mut Root) {
+        *self.material_override.borrow_mut() = Some(Rc::clone(&material));
+        self.apply_material(material, root);
+    }
+
+    fn apply_material(&self, material: Rc<Material>, root: &mut Root) {
+        let shader = root.get_shader(primitive_shader_flags(self.mode, self.vertex_shader_flags,
+            material.shader_flags()));
+        *self.pbr_shader.borrow_mut() = shader;
+        *self.material.borrow_mut() = material;
+    }
+
+    /// Selects the material of a KHR_materials_variants variant,
+    /// `None` (or a variant without mapping) restores the base material.
+    /// Does nothing if the material was replaced with `set_material`.
+    pub fn set_variant(&self, variant: Option<usize>, root: &mut Root) {
+        if self.material_override.borrow().is_some() {
+            return
+        }
+        let material = variant
+            .and_then(|variant| self.variant_materials.get(&variant))
+            .unwrap_or(&self.base_material);
+        self.apply_material(Rc::clone(material), root);
+    }
+
+    /// Reads vertex attributes and indices of a primitive (doesn't touch OpenGL).
+    /// The accessors must have been checked with `check_accessors`.
+    fn load_vertices(
+        g_primitive: &gltf::Primitive<'_>,
+        buffers: &[gltf::buffer::Data],
+        primitive_index: usize,
+        mesh_index: usize) -> (Vec<Vertex>, Option<Vec<u32>>, ShaderFlags)
+    {
+        let reader = g_primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        // NOTE: positions, normals, tangents and texture coordinates are read with `read_f32_attribute`
+        // since they may be quantized (KHR_mesh_quantization), which the reader doesn't support
+        let positions = g_primitive.get(&Semantic::Positions)
+            .map(|accessor| read_f32_attribute(&accessor, buffers))
+            .unwrap_or_else(||
+                panic!("primitives must have the POSITION attribute (mesh: {}, primitive: {})",
+                    mesh_index, primitive_index)
+            );
 
         let mut vertices: Vec<Vertex> = positions
-            .into_iter()
+            .chunks(3)
             .map(|position| {
                 Vertex {
-                    position: Vector3::from(position),
+                    position: vec3(position[0], position[1], position[2]),
                     ..Vertex::default()
                 }
             }).collect();
@@ -130,32 +594,28 @@ impl Primitive {
         let mut shader_flags = ShaderFlags::empty();
 
         // normals
-        if let Some(normals) = reader.read_normals() {
-            for (i, normal) in normals.enumerate() {
-                vertices[i].normal = Vector3::from(normal);
+        if let Some(accessor) = g_primitive.get(&Semantic::Normals) {
+            let normals = read_f32_attribute(&accessor, buffers);
+            for (i, normal) in normals.chunks(3).enumerate() {
+                vertices[i].normal = vec3(normal[0], normal[1], normal[2]);
             }
             shader_flags |= ShaderFlags::HAS_NORMALS;
         }
-        else {
-            debug!("Found no NORMALs for primitive {} of mesh {} \
-                   (flat normal calculation not implemented yet)", primitive_index, mesh_index);
-        }
 
         // tangents
-        if let Some(tangents) = reader.read_tangents() {
-            for (i, tangent) in tangents.enumerate() {
-                vertices[i].tangent = Vector4::from(tangent);
+        if let Some(accessor) = g_primitive.get(&Semantic::Tangents) {
+            let tangents = read_f32_attribute(&accessor, buffers);
+            for (i, tangent) in tangents.chunks(4).enumerate() {
+                let mut tangent = vec4(tangent[0], tangent[1], tangent[2], tangent[3]);
+                tangent.w = tangent_handedness(tangent.w);
+                vertices[i].tangent = tangent;
             }
             shader_flags |= ShaderFlags::HAS_TANGENTS;
         }
-        else {
-            debug!("Found no TANGENTS for primitive {} of mesh {} \
-                   (tangent calculation not implemented yet)", primitive_index, mesh_index);
-        }
 
         // texture coordinates
         let mut tex_coord_set = 0;
-        while let Some(tex_coords) = reader.read_tex_coords(tex_coord_set) {
+        while let Some(accessor) = g_primitive.get(&Semantic::TexCoords(tex_coord_set)) {
             if tex_coord_set > 1 {
                 warn!("Ignoring texture coordinate set {}, \
                         only supporting 2 sets at the moment. (mesh: {}, primitive: {})",
@@ -163,10 +623,12 @@ impl Primitive {
                 tex_coord_set += 1;
                 continue;
             }
-            for (i, tex_coord) in tex_coords.into_f32().enumerate() {
+            let tex_coords = read_f32_attribute(&accessor, buffers);
+            for (i, tex_coord) in tex_coords.chunks(2).enumerate() {
+                let tex_coord = Vector2::new(tex_coord[0], tex_coord[1]);
                 match tex_coord_set {
-                    0 => vertices[i].tex_coord_0 = Vector2::from(tex_coord),
-                    1 => vertices[i].tex_coord_1 = Vector2::from(tex_coord),
+                    0 => vertices[i].tex_coord_0 = tex_coord,
+                    1 => vertices[i].tex_coord_1 = tex_coord,
                     _ => unreachable!()
                 }
             }
@@ -207,111 +669,199 @@ impl Primitive {
                 mesh_index, primitive_index);
         }
 
-        let indices = reader
-            .read_indices()
-            .map(|read_indices| {
-                read_indices.into_u32().collect::<Vec<_>>()
-            });
+        (vertices, read_indices(g_primitive, buffers), shader_flags)
 
-        // TODO: spec:
-        // Implementation note: When the 'mode' property is set to a non-triangular type
-        //(such as POINTS or LINES) some additional considerations must be taken while
-        //considering the proper rendering technique:
-        //   For LINES with NORMAL and TANGENT properties can render with standard lighting including normal maps.
-        //   For all POINTS or LINES with no TANGENT property, render with standard lighting but ignore any normal maps on the material.
-        //   For POINTS or LINES with no NORMAL property, don't calculate lighting and instead output the COLOR value for each pixel drawn.
-        let mode = g_primitive.mode().as_gl_enum();
+    }
+
+    /// render the mesh
+    pub unsafe fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3,
+        instances: Option<&Instances>, culling: &FaceCulling)
+    {
+        // TODO!: determine if shader+material already active to reduce work...
+        let material = self.material.borrow();
+        let pbr_shader = self.pbr_shader.borrow();
 
-        let g_material = g_primitive.material();
+        // blending set up by the caller is kept (x-ray mode and order-independent transparency,
+        // see `Scene::draw_xray` and `WeightedBlendedOit`)
+        let caller_blending = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+        // with a clipping plane, the back faces of the cut are visible (see `Root::set_clip_plane`)
+        let double_sided = material.double_sided || gl::IsEnabled(gl::CLIP_DISTANCE0) == gl::TRUE;
+        match culling.culled_face(double_sided) {
+            Some(face) => {
+                gl::Enable(gl::CULL_FACE);
+                gl::CullFace(face);
+            }
+            None => gl::Disable(gl::CULL_FACE),
+        }
+        gl::FrontFace(culling.front_face(model_matrix));
 
-        let mut material = None;
-        if let Some(mat) = root.materials.iter().find(|m| (***m).index == g_material.index()) {
-            material = Rc::clone(mat).into()
+        if self.mode == gl::POINTS {
+            gl::PointSize(10.0);
         }
 
-        if material.is_none() { // no else due to borrow checker madness
-            let mat = Rc::new(Material::from_gltf(&g_material, root, imp, base_path));
-            root.materials.push(Rc::clone(&mat));
-            material = Some(mat);
-        };
-        let material = material.unwrap();
-        shader_flags |= material.shader_flags();
+        self.configure_shader(model_matrix, mvp_matrix, camera_position, caller_blending);
 
-        let mut new_shader = false; // borrow checker workaround
-        let shader =
-            if let Some(shader) = root.shaders.get(&shader_flags) {
-                Rc::clone(shader)
+        match instances {
+            Some(instances) if instanced_arrays_supported() => self.draw_instanced(instances),
+            Some(instances) => {
+                // same result with one draw call per instance
+                for transform in &instances.transforms {
+                    set_instance_matrix(transform);
+                    self.draw_geometry();
+                }
+                reset_instance_matrix();
             }
-            else {
-                new_shader = true;
-                PbrShader::new(shader_flags).into()
-
-            };
-        if new_shader {
-            root.shaders.insert(shader_flags, Rc::clone(&shader));
+            None => self.draw_geometry(),
         }
+        gl::ActiveTexture(gl::TEXTURE0);
 
-        Primitive::new(bounds, &vertices, indices, mode, material, shader)
-    }
+        gl::FrontFace(gl::CCW);
+        gl::CullFace(gl::BACK);
 
-    /// render the mesh
-    pub unsafe fn draw(&self, model_matrix: &Matrix4, mvp_matrix: &Matrix4, camera_position: &Vector3) {
-        // TODO!: determine if shader+material already active to reduce work...
+        if material.alpha_mode != gltf::material::AlphaMode::Opaque {
+            let shader = &pbr_shader.shader;
 
-        if self.material.double_sided {
-            gl::Disable(gl::CULL_FACE);
-        } else {
-            gl::Enable(gl::CULL_FACE);
+            if !caller_blending {
+                gl::Disable(gl::BLEND);
+            }
+            shader.set_float(pbr_shader.uniforms.u_AlphaBlend, 0.0);
+            if material.alpha_mode == gltf::material::AlphaMode::Mask {
+                shader.set_float(pbr_shader.uniforms.u_AlphaCutoff, 0.0);
+            }
         }
+    }
 
-        if self.mode == gl::POINTS {
-            gl::PointSize(10.0);
+    /// Issues the draw call only, i.e. the caller is responsible for setting up the shader
+    pub unsafe fn draw_geometry(&self) {
+        let (vao, indexed, draw_ranges) = self.shown_geometry();
+        gl::BindVertexArray(vao);
+        for &(first, count) in draw_ranges {
+            if indexed {
+                gl::DrawElements(self.mode, count, gl::UNSIGNED_INT, (first * size_of::<u32>()) as *const c_void);
+            }
+            else {
+                gl::DrawArrays(self.mode, first as i32, count)
+            }
         }
+        gl::BindVertexArray(0);
+    }
 
-        self.configure_shader(model_matrix, mvp_matrix, camera_position);
+    unsafe fn draw_instanced(&self, instances: &Instances) {
+        let (vao, indexed, draw_ranges) = self.shown_geometry();
+        gl::BindVertexArray(vao);
+        instances.bind();
+        let instance_count = instances.count() as i32;
+        for &(first, count) in draw_ranges {
+            if indexed {
+                gl::DrawElementsInstanced(self.mode, count, gl::UNSIGNED_INT,
+                    (first * size_of::<u32>()) as *const c_void, instance_count);
+            }
+            else {
+                gl::DrawArraysInstanced(self.mode, first as i32, count, instance_count)
+            }
+        }
+        instances.unbind();
+        gl::BindVertexArray(0);
+    }
 
-        // draw mesh
-        gl::BindVertexArray(self.vao);
-        if self.ebo.is_some() {
-            gl::DrawElements(self.mode, self.num_indices as i32, gl::UNSIGNED_INT, ptr::null());
+    /// Vertex array, whether it is indexed and the draw ranges of the full or simplified geometry
+    fn shown_geometry(&self) -> (u32, bool, &[(usize, i32)]) {
+        match self.simplified {
+            Some(ref simplified) if self.show_simplified.get() => (simplified.vao, true, &simplified.draw_ranges),
+            _ => (self.vao, self.ebo.is_some(), &self.draw_ranges),
         }
-        else {
-            gl::DrawArrays(self.mode, 0, self.num_vertices as i32)
+    }
+
+    /// Adds a decimated version of a triangle list with about `ratio` of its triangles
+    /// (see `simplify_triangles`), which shares the vertex buffer and is shown instead of the
+    /// full mesh until `set_show_simplified(false)`. Other modes are left alone.
+    pub unsafe fn simplify(&mut self, ratio: f32) {
+        if self.mode != gl::TRIANGLES {
+            return
         }
+        let vertices = self.read_vertices();
+        let indices = self.read_indices().unwrap_or_else(|| (0..self.num_vertices).collect());
+        let target_triangles = ((indices.len() / 3) as f64 * f64::from(ratio)).round() as usize;
+        let simplified = simplify_triangles(&vertices, &indices, target_triangles);
+        let ebo = create_index_buffer(&simplified);
+        self.simplified = Some(SimplifiedIndices {
+            vao: create_vertex_array(self.vertex_buffer.vbo, self.first_vertex, Some(ebo)),
+            ebo,
+            num_indices: simplified.len() as u32,
+            draw_ranges: draw_ranges(simplified.len(), gl::TRIANGLES, gl_limit(gl::MAX_ELEMENTS_INDICES)),
+        });
+        self.show_simplified.set(true);
+    }
 
-        gl::BindVertexArray(0);
-        gl::ActiveTexture(gl::TEXTURE0);
+    /// Switches between the simplified and the full geometry (no effect if not simplified)
+    pub fn set_show_simplified(&self, enabled: bool) {
+        self.show_simplified.set(enabled);
+    }
+
+    /// Triangles of the simplified geometry, `None` if not simplified
+    pub fn simplified_triangle_count(&self) -> Option<u32> {
+        self.simplified.as_ref().map(|simplified| simplified.num_indices / 3)
+    }
 
-        if self.material.alpha_mode != gltf::material::AlphaMode::Opaque {
-            let shader = &self.pbr_shader.shader;
+    /// Reads the (possibly generated) vertices back from the GPU, e.g. for debug views
+    pub unsafe fn read_vertices(&self) -> Vec<Vertex> {
+        let mut vertices = vec![Vertex::default(); self.num_vertices as usize];
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer.vbo);
+        gl::GetBufferSubData(gl::ARRAY_BUFFER, buffer_size::<Vertex>(self.first_vertex),
+            buffer_size::<Vertex>(vertices.len()), vertices.as_mut_ptr() as *mut c_void);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        vertices
+    }
 
-            gl::Disable(gl::BLEND);
-            shader.set_float(self.pbr_shader.uniforms.u_AlphaBlend, 0.0);
-            if self.material.alpha_mode == gltf::material::AlphaMode::Mask {
-                shader.set_float(self.pbr_shader.uniforms.u_AlphaCutoff, 0.0);
-            }
+    /// Reads the indices back from the GPU (`None` for non-indexed primitives)
+    pub unsafe fn read_indices(&self) -> Option<Vec<u32>> {
+        let ebo = self.ebo?;
+        let mut indices = vec![0u32; self.num_indices as usize];
+        // not via ELEMENT_ARRAY_BUFFER, that binding is part of the current vertex array's state
+        gl::BindBuffer(gl::COPY_READ_BUFFER, ebo);
+        gl::GetBufferSubData(gl::COPY_READ_BUFFER, 0, buffer_size::<u32>(indices.len()),
+            indices.as_mut_ptr() as *mut c_void);
+        gl::BindBuffer(gl::COPY_READ_BUFFER, 0);
+        Some(indices)
+    }
+
+    pub fn mode(&self) -> GLenum {
+        self.mode
+    }
+
+    pub fn triangle_count(&self) -> u32 {
+        let count = if self.ebo.is_some() { self.num_indices } else { self.num_vertices };
+        match self.mode {
+            gl::TRIANGLES => count / 3,
+            gl::TRIANGLE_STRIP | gl::TRIANGLE_FAN => count.saturating_sub(2),
+            _ => 0,
         }
     }
 
     unsafe fn configure_shader(&self, model_matrix: &Matrix4,
-        mvp_matrix: &Matrix4, camera_position: &Vector3)
+        mvp_matrix: &Matrix4, camera_position: &Vector3, caller_blending: bool)
     {
         // let pbr_shader = &Rc::get_mut(&mut self.pbr_shader).unwrap();
-        let mat = &self.material;
-        let shader = &self.pbr_shader.shader;
-        let uniforms = &self.pbr_shader.uniforms;
-        self.pbr_shader.shader.use_program();
+        let mat = self.material.borrow();
+        let pbr_shader = self.pbr_shader.borrow();
+        let shader = &pbr_shader.shader;
+        let uniforms = &pbr_shader.uniforms;
+        shader.use_program();
 
         // camera params
         shader.set_mat4(uniforms.u_ModelMatrix, model_matrix);
         shader.set_mat4(uniforms.u_MVPMatrix, mvp_matrix);
         shader.set_vector3(uniforms.u_Camera, camera_position);
 
+        shader.set_bool(uniforms.u_DoubleSided, mat.double_sided);
+
         // alpha blending
         if mat.alpha_mode != gltf::material::AlphaMode::Opaque {
             // BLEND + MASK
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            if !caller_blending {
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            }
             shader.set_float(uniforms.u_AlphaBlend, 1.0);
 
             if mat.alpha_mode == gltf::material::AlphaMode::Mask {
@@ -319,95 +869,1138 @@ impl Primitive {
             }
         }
 
+        let animated = mat.animated.get();
+
+        let (decode, encode) = mat.color_space_conversions();
+        shader.set_int(uniforms.u_ColorSpaceDecode, decode);
+        shader.set_int(uniforms.u_ColorSpaceEncode, encode);
+
         // NOTE: for sampler numbers, see also PbrShader constructor
-        shader.set_vector4(uniforms.u_BaseColorFactor, &mat.base_color_factor);
+        shader.set_vector4(uniforms.u_BaseColorFactor, &animated.base_color_factor.unwrap_or(mat.base_color_factor));
         if let Some(ref base_color_texture) = mat.base_color_texture {
             gl::ActiveTexture(gl::TEXTURE0);
             gl::BindTexture(gl::TEXTURE_2D, base_color_texture.id);
-            shader.set_int(uniforms.u_BaseColorTexCoord, base_color_texture.tex_coord as i32);
+            let transform = &mat.texture_transforms.base_color;
+            shader.set_int(uniforms.u_BaseColorTexCoord, transform.tex_coord.unwrap_or(base_color_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_BaseColorUVTransform, &transform.matrix());
         }
         if let Some(ref normal_texture) = mat.normal_texture {
             gl::ActiveTexture(gl::TEXTURE1);
             gl::BindTexture(gl::TEXTURE_2D, normal_texture.id);
-            shader.set_int(uniforms.u_NormalTexCoord, normal_texture.tex_coord as i32);
+            let transform = &mat.texture_transforms.normal;
+            shader.set_int(uniforms.u_NormalTexCoord, transform.tex_coord.unwrap_or(normal_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_NormalUVTransform, &transform.matrix());
             shader.set_float(uniforms.u_NormalScale, mat.normal_scale.unwrap_or(1.0));
         }
         if let Some(ref emissive_texture) = mat.emissive_texture {
             gl::ActiveTexture(gl::TEXTURE2);
             gl::BindTexture(gl::TEXTURE_2D, emissive_texture.id);
-            shader.set_int(uniforms.u_EmissiveTexCoord, emissive_texture.tex_coord as i32);
-            shader.set_vector3(uniforms.u_EmissiveFactor, &mat.emissive_factor);
+            let transform = &mat.texture_transforms.emissive;
+            shader.set_int(uniforms.u_EmissiveTexCoord, transform.tex_coord.unwrap_or(emissive_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_EmissiveUVTransform, &transform.matrix());
         }
+        shader.set_vector3(uniforms.u_EmissiveFactor, &(animated.emissive_factor.unwrap_or(mat.emissive_factor) *
+            animated.emissive_strength.unwrap_or(mat.emissive_strength)));
 
         if let Some(ref mr_texture) = mat.metallic_roughness_texture {
             gl::ActiveTexture(gl::TEXTURE3);
             gl::BindTexture(gl::TEXTURE_2D, mr_texture.id);
-            shader.set_int(uniforms.u_MetallicRoughnessTexCoord, mr_texture.tex_coord as i32);
+            let transform = &mat.texture_transforms.metallic_roughness;
+            shader.set_int(uniforms.u_MetallicRoughnessTexCoord, transform.tex_coord.unwrap_or(mr_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_MetallicRoughnessUVTransform, &transform.matrix());
         }
         shader.set_vec2(uniforms.u_MetallicRoughnessValues,
-            mat.metallic_factor, mat.roughness_factor);
+            animated.metallic_factor.unwrap_or(mat.metallic_factor),
+            animated.roughness_factor.unwrap_or(mat.roughness_factor));
+        shader.set_float(uniforms.u_DielectricF0, mat.dielectric_f0());
+        shader.set_float(uniforms.u_DielectricSpecularFactor, mat.dielectric_specular_factor);
+        shader.set_vector3(uniforms.u_DielectricSpecularColorFactor, &mat.dielectric_specular_color_factor);
+        if let Some(ref specular_texture) = mat.dielectric_specular_texture {
+            gl::ActiveTexture(gl::TEXTURE11);
+            gl::BindTexture(gl::TEXTURE_2D, specular_texture.id);
+            shader.set_int(uniforms.u_DielectricSpecularTexCoord, specular_texture.tex_coord as i32);
+        }
+        if let Some(ref specular_color_texture) = mat.dielectric_specular_color_texture {
+            gl::ActiveTexture(gl::TEXTURE12);
+            gl::BindTexture(gl::TEXTURE_2D, specular_color_texture.id);
+            shader.set_int(uniforms.u_DielectricSpecularColorTexCoord, specular_color_texture.tex_coord as i32);
+        }
+
+        if mat.specular_glossiness {
+            shader.set_vector3(uniforms.u_SpecularFactor, &mat.specular_factor);
+            shader.set_float(uniforms.u_GlossinessFactor, mat.glossiness_factor);
+            if let Some(ref sg_texture) = mat.specular_glossiness_texture {
+                gl::ActiveTexture(gl::TEXTURE3);
+                gl::BindTexture(gl::TEXTURE_2D, sg_texture.id);
+                shader.set_int(uniforms.u_SpecularGlossinessTexCoord, sg_texture.tex_coord as i32);
+            }
+        }
 
         if let Some(ref occlusion_texture) = mat.occlusion_texture {
             gl::ActiveTexture(gl::TEXTURE4);
             gl::BindTexture(gl::TEXTURE_2D, occlusion_texture.id);
-            shader.set_int(uniforms.u_OcclusionTexCoord, occlusion_texture.tex_coord as i32);
+            let transform = &mat.texture_transforms.occlusion;
+            shader.set_int(uniforms.u_OcclusionTexCoord, transform.tex_coord.unwrap_or(occlusion_texture.tex_coord) as i32);
+            shader.set_mat3(uniforms.u_OcclusionUVTransform, &transform.matrix());
             shader.set_float(uniforms.u_OcclusionStrength, mat.occlusion_strength);
         }
+
+        if mat.is_transmissive() {
+            shader.set_float(uniforms.u_TransmissionFactor, mat.transmission_factor);
+            if let Some(ref transmission_texture) = mat.transmission_texture {
+                gl::ActiveTexture(gl::TEXTURE6);
+                gl::BindTexture(gl::TEXTURE_2D, transmission_texture.id);
+                shader.set_int(uniforms.u_TransmissionTexCoord, transmission_texture.tex_coord as i32);
+            }
+        }
+
+        if mat.has_sheen() {
+            shader.set_vector3(uniforms.u_SheenColorFactor, &mat.sheen_color_factor);
+            shader.set_float(uniforms.u_SheenRoughnessFactor, mat.sheen_roughness_factor);
+            if let Some(ref sheen_color_texture) = mat.sheen_color_texture {
+                gl::ActiveTexture(gl::TEXTURE8);
+                gl::BindTexture(gl::TEXTURE_2D, sheen_color_texture.id);
+                shader.set_int(uniforms.u_SheenColorTexCoord, sheen_color_texture.tex_coord as i32);
+            }
+            if let Some(ref sheen_roughness_texture) = mat.sheen_roughness_texture {
+                gl::ActiveTexture(gl::TEXTURE9);
+                gl::BindTexture(gl::TEXTURE_2D, sheen_roughness_texture.id);
+                shader.set_int(uniforms.u_SheenRoughnessTexCoord, sheen_roughness_texture.tex_coord as i32);
+            }
+        }
     }
 
-    unsafe fn setup_primitive(&mut self, vertices: &[Vertex], indices: Option<Vec<u32>>) {
-        // create buffers/arrays
-        gl::GenVertexArrays(1, &mut self.vao);
-        gl::GenBuffers(1, &mut self.vbo);
-        if indices.is_some() {
-            let mut ebo = 0;
-            gl::GenBuffers(1, &mut ebo);
-            self.ebo = Some(ebo);
-        }
-
-        gl::BindVertexArray(self.vao);
-        // load data into vertex buffers
-        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-        let size = (vertices.len() * size_of::<Vertex>()) as isize;
-        let data = &vertices[0] as *const Vertex as *const c_void;
-        gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-
-        if let Some(ebo) = self.ebo {
-            let indices = indices.unwrap();
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-            let size = (indices.len() * size_of::<u32>()) as isize;
-            let data = &indices[0] as *const u32 as *const c_void;
-            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-        }
-
-        // set the vertex attribute pointers
-        let size = size_of::<Vertex>() as i32;
-        // POSITION
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, position) as *const c_void);
-        // NORMAL
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, normal) as *const c_void);
-        // TANGENT
-        gl::EnableVertexAttribArray(2);
-        gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tangent) as *const c_void);
-        // TEXCOORD_0
-        gl::EnableVertexAttribArray(3);
-        gl::VertexAttribPointer(3, 2, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tex_coord_0) as *const c_void);
-        // TEXCOORD_1
-        gl::EnableVertexAttribArray(4);
-        gl::VertexAttribPointer(4, 2, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tex_coord_1) as *const c_void);
-        // COLOR_0
-        gl::EnableVertexAttribArray(5);
-        gl::VertexAttribPointer(5, 4, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, color_0) as *const c_void);
-        // JOINTS_0
-        gl::EnableVertexAttribArray(6);
-        // TODO: normalization?
-        gl::VertexAttribPointer(6, 4, gl::UNSIGNED_SHORT, gl::FALSE, size, offset_of!(Vertex, joints_0) as *const c_void);
-        // WEIGHTS_0
-        gl::EnableVertexAttribArray(7);
-        gl::VertexAttribPointer(7, 4, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, weights_0) as *const c_void);
+    unsafe fn setup_primitive(&mut self, indices: Option<Vec<u32>>) {
+        self.ebo = indices.map(|indices| create_index_buffer(&indices));
+        self.vao = create_vertex_array(self.vertex_buffer.vbo, self.first_vertex, self.ebo);
+    }
+}
 
-        gl::BindVertexArray(0);
+impl Drop for Primitive {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            if let Some(ebo) = self.ebo {
+                gl::DeleteBuffers(1, &ebo);
+            }
+            if let Some(ref simplified) = self.simplified {
+                gl::DeleteVertexArrays(1, &simplified.vao);
+                gl::DeleteBuffers(1, &simplified.ebo);
+            }
+        }
+    }
+}
+
+/// Uploads indices to a new buffer (without touching the current vertex array's binding)
+unsafe fn create_index_buffer(indices: &[u32]) -> u32 {
+    let mut ebo = 0;
+    gl::GenBuffers(1, &mut ebo);
+    gl::BindBuffer(gl::COPY_WRITE_BUFFER, ebo);
+    let size = buffer_size::<u32>(indices.len());
+    gl::BufferData(gl::COPY_WRITE_BUFFER, size, indices.as_ptr() as *const c_void, gl::STATIC_DRAW);
+    gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+    ebo
+}
+
+/// Creates a vertex array with the attribute pointers into the (already uploaded, possibly
+/// shared) vertices starting at `first_vertex` and the given index buffer
+unsafe fn create_vertex_array(vbo: u32, first_vertex: usize, ebo: Option<u32>) -> u32 {
+    let mut vao = 0;
+    gl::GenVertexArrays(1, &mut vao);
+    gl::BindVertexArray(vao);
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    if let Some(ebo) = ebo {
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+    }
+
+    // set the vertex attribute pointers
+    let size = size_of::<Vertex>() as i32;
+    let offset = |field: usize| (first_vertex * size_of::<Vertex>() + field) as *const c_void;
+    // POSITION
+    gl::EnableVertexAttribArray(0);
+    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, position)));
+    // NORMAL
+    gl::EnableVertexAttribArray(1);
+    gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, normal)));
+    // TANGENT
+    gl::EnableVertexAttribArray(2);
+    gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, tangent)));
+    // TEXCOORD_0
+    gl::EnableVertexAttribArray(3);
+    gl::VertexAttribPointer(3, 2, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, tex_coord_0)));
+    // TEXCOORD_1
+    gl::EnableVertexAttribArray(4);
+    gl::VertexAttribPointer(4, 2, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, tex_coord_1)));
+    // COLOR_0
+    gl::EnableVertexAttribArray(5);
+    gl::VertexAttribPointer(5, 4, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, color_0)));
+    // JOINTS_0
+    gl::EnableVertexAttribArray(6);
+    // TODO: normalization?
+    gl::VertexAttribPointer(6, 4, gl::UNSIGNED_SHORT, gl::FALSE, size, offset(mem::offset_of!(Vertex, joints_0)));
+    // WEIGHTS_0
+    gl::EnableVertexAttribArray(7);
+    gl::VertexAttribPointer(7, 4, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, weights_0)));
+    // baked ambient occlusion (after the instance matrix, see `INSTANCE_MATRIX_LOCATION`)
+    gl::EnableVertexAttribArray(12);
+    gl::VertexAttribPointer(12, 1, gl::FLOAT, gl::FALSE, size, offset(mem::offset_of!(Vertex, occlusion)));
+    // feature id (only used for picking)
+    gl::EnableVertexAttribArray(13);
+    gl::VertexAttribIPointer(13, 1, gl::UNSIGNED_INT, size, offset(mem::offset_of!(Vertex, feature_id)));
+
+    gl::BindVertexArray(0);
+    vao
+}
+
+/// Size in bytes of a buffer with `count` elements of type `T`, panics if it doesn't fit a `GLsizeiptr`
+pub fn buffer_size<T>(count: usize) -> isize {
+    count.checked_mul(size_of::<T>())
+        .filter(|&size| size <= isize::MAX as usize)
+        .unwrap_or_else(|| panic!("Buffer with {} elements of {} bytes is too large", count, size_of::<T>()))
+        as isize
+}
+
+/// Combines the flags of the vertex attributes and the material following the implementation
+/// note of the spec for points and lines: without a TANGENT attribute the normal map is ignored,
+/// without a NORMAL attribute they aren't lit but output the base color
+fn primitive_shader_flags(mode: GLenum, vertex_flags: ShaderFlags, material_flags: ShaderFlags) -> ShaderFlags {
+    let mut flags = vertex_flags | material_flags;
+    if let gl::POINTS | gl::LINES | gl::LINE_LOOP | gl::LINE_STRIP = mode {
+        if !vertex_flags.contains(ShaderFlags::HAS_TANGENTS) {
+            flags.remove(ShaderFlags::HAS_NORMALMAP);
+        }
+        if !vertex_flags.contains(ShaderFlags::HAS_NORMALS) {
+            flags.insert(ShaderFlags::UNLIT);
+        }
+    }
+    flags
+}
+
+/// GL_MAX_ELEMENTS_INDICES or GL_MAX_ELEMENTS_VERTICES, clamped to what fits a `GLsizei`
+unsafe fn gl_limit(name: GLenum) -> usize {
+    let mut value = 0;
+    gl::GetIntegerv(name, &mut value);
+    // some drivers report 0 or negative values
+    if value <= 0 { i32::MAX as usize } else { value as usize }
+}
+
+/// Splits `count` indices (or vertices) into draw calls of at most `max_count` elements
+/// at primitive boundaries. Strips, fans and loops are kept in one range.
+fn draw_ranges(count: usize, mode: GLenum, max_count: usize) -> Vec<(usize, i32)> {
+    let primitive_size = match mode {
+        gl::POINTS => 1,
+        gl::LINES => 2,
+        gl::TRIANGLES => 3,
+        _ => return vec![(0, count.min(i32::MAX as usize) as i32)],
+    };
+    let step = (max_count.min(i32::MAX as usize) / primitive_size * primitive_size).max(primitive_size);
+    if count <= step {
+        return vec![(0, count as i32)]
+    }
+    (0..count).step_by(step)
+        .map(|first| (first, (count - first).min(step) as i32))
+        .collect()
+}
+
+/// Checks that the accessors of the primitive (including its morph targets) are within their
+/// buffers and that the attributes have matching element counts and types, so that reading them
+/// can't fail. Returns the number of vertices.
+fn check_accessors(g_primitive: &gltf::Primitive<'_>, buffers: &[gltf::buffer::Data]) -> Result<usize, String> {
+    let num_vertices = g_primitive.get(&Semantic::Positions)
+        .ok_or("the POSITION attribute is missing")?
+        .count();
+    if num_vertices == 0 {
+        return Err("POSITION has no elements".to_owned())
+    }
+    let targets = g_primitive.morph_targets()
+        .flat_map(|target| vec![target.positions(), target.normals(), target.tangents()])
+        .flatten()
+        .map(|accessor| (accessor, Some(3)));
+    let attributes = g_primitive.attributes()
+        .map(|(semantic, accessor)| {
+            let components = match semantic {
+                Semantic::Positions | Semantic::Normals => Some(3),
+                Semantic::Tangents => Some(4),
+                Semantic::TexCoords(_) => Some(2),
+                _ => None,
+            };
+            (accessor, components)
+        });
+    for (accessor, components) in attributes.chain(targets) {
+        check_accessor(&accessor, buffers)?;
+        if accessor.count() != num_vertices {
+            return Err(format!("Accessor {} has {} elements, but POSITION has {}",
+                accessor.index(), accessor.count(), num_vertices))
+        }
+        if let Some(components) = components.filter(|&c| c != accessor.dimensions().multiplicity()) {
+            return Err(format!("Accessor {} must have {} components", accessor.index(), components))
+        }
+    }
+    if let Some(accessor) = g_primitive.indices() {
+        check_accessor(&accessor, buffers)?;
+    }
+    Ok(num_vertices)
+}
+
+/// Error if an index refers to a vertex that doesn't exist
+fn check_indices(indices: Option<&[u32]>, num_vertices: usize) -> Result<(), String> {
+    match indices.and_then(|indices| indices.iter().max()) {
+        Some(&max) if max as usize >= num_vertices =>
+            Err(format!("Index {} is out of range ({} vertices)", max, num_vertices)),
+        _ => Ok(()),
+    }
+}
+
+fn read_indices(g_primitive: &gltf::Primitive<'_>, buffers: &[gltf::buffer::Data]) -> Option<Vec<u32>> {
+    let reader = g_primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    reader
+        .read_indices()
+        .map(|read_indices| {
+            read_indices.into_u32().collect::<Vec<_>>()
+        })
+}
+
+/// Key of `Root::vertex_buffers` (the buffer views of the attributes and the layout of their
+/// elements), the range of the primitive's vertices in the views and the number of vertices that
+/// fit into the views. `None` if the vertices can't be shared since they are modified depending
+/// on the indices (generated normals, baked occlusion) or don't correspond to the elements of
+/// the views (sparse accessors, attributes starting at different elements).
+fn shared_vertices(g_primitive: &gltf::Primitive<'_>, mode: GLenum, options: &ImportOptions)
+    -> Option<(String, Range<usize>, usize)>
+{
+    if mode == gl::TRIANGLES && (g_primitive.get(&Semantic::Normals).is_none() || options.vertex_ao_samples.is_some()) {
+        return None
+    }
+    let mut attributes = vec![];
+    let mut vertices = None;
+    let mut capacity = usize::MAX;
+    for (semantic, accessor) in g_primitive.attributes() {
+        if accessor.sparse().is_some() {
+            return None
+        }
+        let view = accessor.view();
+        let stride = view.stride().unwrap_or_else(|| accessor.size());
+        // offset within the (interleaved) elements of the view
+        let (first, offset) = (accessor.offset() / stride, accessor.offset() % stride);
+        let range = first..first + accessor.count();
+        if *vertices.get_or_insert_with(|| range.clone()) != range {
+            return None
+        }
+        capacity = capacity.min(view.length().checked_sub(offset + accessor.size())? / stride + 1);
+        let normalized = if accessor.normalized() { " normalized" } else { "" };
+        attributes.push(format!("{:?}: view {} + {} (stride {}, {:?} {:?}{})", semantic, view.index(), offset,
+            stride, accessor.dimensions(), accessor.data_type(), normalized));
+    }
+    let vertices = vertices.filter(|vertices| vertices.end <= capacity)?;
+    // the order of the attributes isn't defined
+    attributes.sort();
+    Some((attributes.join(", "), vertices, capacity))
+}
+
+/// The texture coordinate set of the normal map if the tangents have to be generated, i.e. for
+/// normal mapped triangle lists without TANGENT attribute (otherwise the fragment shader derives
+/// the tangent space per pixel)
+fn tangent_tex_coord(g_primitive: &gltf::Primitive<'_>, mode: GLenum, options: &ImportOptions) -> Option<u32> {
+    if mode != gl::TRIANGLES || options.geometry_only || g_primitive.get(&Semantic::Tangents).is_some() {
+        return None
+    }
+    let tex_coord = g_primitive.material().normal_texture()?.tex_coord();
+    // only the first two sets are read
+    if tex_coord > 1 || g_primitive.get(&Semantic::TexCoords(tex_coord)).is_none() {
+        return None
+    }
+    Some(tex_coord)
+}
+
+/// Materials are shared by all primitives that use them
+/// (with `ImportOptions::geometry_only`, all primitives share the default material)
+fn load_material(g_material: &gltf::Material<'_>, root: &mut Root, imp: &ImportData) -> Rc<Material> {
+    let index = if imp.options.geometry_only { None } else { g_material.index() };
+    if let Some(material) = root.materials.iter().find(|m| m.index == index) {
+        return Rc::clone(material)
+    }
+    let material = Rc::new(Material::from_gltf(g_material, root, imp));
+    root.materials.push(Rc::clone(&material));
+    material
+}
+
+/// `(material index, variant indices)` of the `mappings` of a primitive's KHR_materials_variants extension
+fn variant_mappings(extension: &Value) -> Vec<(usize, Vec<usize>)> {
+    let mappings = match extension["mappings"].as_array() {
+        Some(mappings) => mappings,
+        None => return vec![],
+    };
+    mappings.iter()
+        .filter_map(|mapping| {
+            let material = mapping["material"].as_u64()? as usize;
+            let variants = mapping["variants"].as_array()?.iter()
+                .filter_map(|variant| variant.as_u64().map(|variant| variant as usize))
+                .collect();
+            Some((material, variants))
+        })
+        .collect()
+}
+
+/// Which faces are culled, independent of the material (for diagnosing inside-out geometry)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CullMode {
+    /// Back faces, unless the material is double-sided
+    Auto,
+    Off,
+    Back,
+    Front,
+}
+
+impl CullMode {
+    /// For cycling through the modes
+    pub fn next(self) -> CullMode {
+        use self::CullMode::*;
+        match self {
+            Auto => Off,
+            Off => Back,
+            Back => Front,
+            Front => Auto,
+        }
+    }
+}
+
+/// Overrides of the face culling and front face winding, see `Scene::face_culling`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaceCulling {
+    pub cull_mode: CullMode,
+    /// `gl::CCW` or `gl::CW`, `None`: determined by the model matrix (see `front_face`)
+    pub front_face: Option<GLenum>,
+}
+
+impl Default for FaceCulling {
+    fn default() -> Self {
+        FaceCulling { cull_mode: CullMode::Auto, front_face: None }
+    }
+}
+
+impl FaceCulling {
+    /// The face to cull (`None`: culling disabled). `Auto` culls back faces unless the
+    /// primitive is `double_sided`.
+    pub fn culled_face(&self, double_sided: bool) -> Option<GLenum> {
+        match self.cull_mode {
+            CullMode::Auto if double_sided => None,
+            CullMode::Auto | CullMode::Back => Some(gl::BACK),
+            CullMode::Off => None,
+            CullMode::Front => Some(gl::FRONT),
+        }
+    }
+
+    pub fn front_face(&self, model_matrix: &Matrix4) -> GLenum {
+        self.front_face.unwrap_or_else(|| front_face(model_matrix))
+    }
+
+    /// e.g. "culling: back faces, front faces: counter-clockwise (forced)"
+    pub fn description(&self) -> String {
+        let culling = match self.cull_mode {
+            CullMode::Auto => "back faces unless double-sided",
+            CullMode::Off => "off (forced)",
+            CullMode::Back => "back faces (forced)",
+            CullMode::Front => "front faces (forced)",
+        };
+        let winding = match self.front_face {
+            None => "counter-clockwise (clockwise if mirrored)",
+            Some(gl::CW) => "clockwise (forced)",
+            Some(_) => "counter-clockwise (forced)",
+        };
+        format!("culling: {}, front faces: {}", culling, winding)
+    }
+}
+
+/// Winding order of front faces for a model matrix: mirroring transforms
+/// (negative determinant) turn counter-clockwise triangles clockwise
+pub fn front_face(model_matrix: &Matrix4) -> GLenum {
+    if model_matrix.determinant() < 0.0 { gl::CW } else { gl::CCW }
+}
+
+/// Returns the bytes of the buffer that the elements of the accessor occupy, or an error if
+/// they aren't within the buffer
+pub fn check_accessor(accessor: &gltf::Accessor<'_>, buffers: &[gltf::buffer::Data]) -> Result<Range<usize>, String> {
+    let view = accessor.view();
+    let stride = view.stride().unwrap_or_else(|| accessor.size());
+    let range = accessor_byte_range(view.offset(), accessor.offset(), stride, accessor.count(), accessor.size())
+        .ok_or_else(|| format!("Accessor {} exceeds the address space ({} elements of {} bytes at offset {} + {})",
+            accessor.index(), accessor.count(), stride, view.offset(), accessor.offset()))?;
+    let length = buffers.get(view.buffer().index()).map_or(0, |data| data.len());
+    if range.end > length {
+        return Err(format!("Accessor {} reads bytes {}..{} of buffer {}, which only has {} bytes",
+            accessor.index(), range.start, range.end, view.buffer().index(), length))
+    }
+    Ok(range)
+}
+
+/// Reads the components of a (non-sparse) vertex attribute as floats, converting integer
+/// components as allowed by KHR_mesh_quantization. Normalized integers are mapped to
+/// [0, 1] (unsigned) or [-1, 1] (signed) as defined by the spec, others are converted as is
+/// (the dequantization is part of the node transform then).
+pub fn read_f32_attribute(accessor: &gltf::Accessor<'_>, buffers: &[gltf::buffer::Data]) -> Vec<f32> {
+    // all offsets below are within this range, so they can't overflow either
+    let start = check_accessor(accessor, buffers).unwrap_or_else(|err| panic!("{}", err)).start;
+    let view = accessor.view();
+    let data = &buffers[view.buffer().index()].0;
+    let data_type = accessor.data_type();
+    let component_size = data_type.size();
+    let components = accessor.dimensions().multiplicity();
+    let stride = view.stride().unwrap_or_else(|| accessor.size());
+    let normalized = accessor.normalized();
+
+    let mut values = Vec::with_capacity(accessor.count() * components);
+    for i in 0..accessor.count() {
+        for c in 0..components {
+            let offset = start + i * stride + c * component_size;
+            let bytes = &data[offset..offset + component_size];
+            let value = match data_type {
+                DataType::I8 => {
+                    let value = f32::from(bytes[0] as i8);
+                    if normalized { (value / 127.0).max(-1.0) } else { value }
+                }
+                DataType::U8 => {
+                    let value = f32::from(bytes[0]);
+                    if normalized { value / 255.0 } else { value }
+                }
+                DataType::I16 => {
+                    let value = f32::from(i16::from_le_bytes([bytes[0], bytes[1]]));
+                    if normalized { (value / 32767.0).max(-1.0) } else { value }
+                }
+                DataType::U16 => {
+                    let value = f32::from(u16::from_le_bytes([bytes[0], bytes[1]]));
+                    if normalized { value / 65535.0 } else { value }
+                }
+                DataType::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32,
+                DataType::F32 => f32::from_bits(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            };
+            values.push(value);
+        }
+    }
+    values
+}
+
+/// Adds the displacements of the morph targets (POSITION, NORMAL and TANGENT, as far as present)
+/// scaled by their weights to the vertices, which start at index `first` of the primitive's
+/// vertices. Normals and tangents are renormalized.
+fn apply_morph_targets(vertices: &mut [Vertex], first: usize, targets: &[MorphTarget], weights: &[f32]) {
+    for (target, &weight) in targets.iter().zip(weights).filter(|&(_, &weight)| weight != 0.0) {
+        for (vertex, d) in vertices.iter_mut().zip(target.positions.iter().skip(first)) {
+            vertex.position += d * weight;
+        }
+        for (vertex, d) in vertices.iter_mut().zip(target.normals.iter().skip(first)) {
+            vertex.normal += d * weight;
+        }
+        for (vertex, d) in vertices.iter_mut().zip(target.tangents.iter().skip(first)) {
+            vertex.tangent += d.extend(0.0) * weight;
+        }
+    }
+    for vertex in vertices {
+        if vertex.normal.magnitude2() > 0.0 {
+            vertex.normal = vertex.normal.normalize();
+        }
+        let tangent = vertex.tangent.truncate();
+        if tangent.magnitude2() > 0.0 {
+            vertex.tangent = tangent.normalize().extend(vertex.tangent.w);
+        }
+    }
+}
+
+/// Bytes of `count` elements of `element_size` bytes that are `stride` bytes apart, starting at
+/// the offset of the accessor in its buffer view plus the view's offset (glTF allows 64-bit
+/// offsets in principle). `None` if the arithmetic overflows `usize`.
+pub fn accessor_byte_range(view_offset: usize, accessor_offset: usize, stride: usize, count: usize,
+    element_size: usize) -> Option<Range<usize>>
+{
+    let start = view_offset.checked_add(accessor_offset)?;
+    if count == 0 {
+        return Some(start..start)
+    }
+    let end = (count - 1).checked_mul(stride)?
+        .checked_add(element_size)?
+        .checked_add(start)?;
+    Some(start..end)
+}
+
+/// Vertices displaced by any of the morph targets (empty if none is)
+fn displaced_range(targets: &[MorphTarget]) -> Range<usize> {
+    let (mut first, mut end) = (usize::MAX, 0);
+    for attribute in targets.iter().flat_map(|target| vec![&target.positions, &target.normals, &target.tangents]) {
+        if let Some(i) = attribute.iter().position(|d| !d.is_zero()) {
+            first = first.min(i);
+        }
+        if let Some(i) = attribute.iter().rposition(|d| !d.is_zero()) {
+            end = end.max(i + 1);
+        }
+    }
+    if first < end { first..end } else { 0..0 }
+}
+
+/// Bounds of the vertices with animated morph targets, containing each target fully applied
+/// (weights are usually between 0 and 1)
+fn morph_bounds(base_vertices: &[Vertex], targets: &[MorphTarget]) -> Aabb3 {
+    let mut bounds = vertex_bounds(base_vertices);
+    for target in targets {
+        for (vertex, d) in base_vertices.iter().zip(&target.positions) {
+            bounds = bounds.grow(Point3::from_vec(vertex.position + d));
+        }
+    }
+    bounds
+}
+
+fn vertex_bounds(vertices: &[Vertex]) -> Aabb3 {
+    vertices.iter()
+        .fold(None, |bounds: Option<Aabb3>, vertex| {
+            let point = Point3::from_vec(vertex.position);
+            Some(match bounds {
+                Some(bounds) => bounds.grow(point),
+                None => Aabb3::new(point, point),
+            })
+        })
+        .unwrap_or_else(Aabb3::zero)
+}
+
+/// Reads the feature ids of the custom attribute `name` (or the first of
+/// `DEFAULT_FEATURE_ID_ATTRIBUTES`) into the vertices. Returns whether the primitive has it.
+fn read_feature_ids(g_primitive: &gltf::Primitive<'_>, buffers: &[gltf::buffer::Data], name: Option<&str>,
+    vertices: &mut [Vertex], primitive_index: usize, mesh_index: usize) -> bool
+{
+    let names = match name {
+        Some(name) => vec![name],
+        None => DEFAULT_FEATURE_ID_ATTRIBUTES.to_vec(),
+    };
+    // the `gltf` crate strips the underscore of custom attributes
+    let found = names.iter()
+        .filter(|name| name.starts_with('_'))
+        .find_map(|name| g_primitive.get(&Semantic::Extras(name[1..].to_owned())).map(|accessor| (name, accessor)));
+    let (name, accessor) = match found {
+        Some(found) => found,
+        None => return false,
+    };
+    if accessor.dimensions().multiplicity() != 1 {
+        warn!("Ignoring feature ids {} of primitive {} of mesh {}: must be scalars", name, primitive_index, mesh_index);
+        return false
+    }
+    // NOTE: ids above 2^24 lose precision, `read_f32_attribute` converts to float
+    for (vertex, id) in vertices.iter_mut().zip(read_f32_attribute(&accessor, buffers)) {
+        vertex.feature_id = id as u32;
+    }
+    true
+}
+
+/// Zeros non-finite position components and replaces normals with non-finite components by +Z
+/// (zeroed ones couldn't be normalized in the shader). Returns the number of replaced positions
+/// and normals.
+pub fn sanitize_vertices(vertices: &mut [Vertex]) -> (usize, usize) {
+    let (mut positions, mut normals) = (0, 0);
+    for vertex in vertices {
+        if !is_finite(vertex.position) {
+            for i in 0..3 {
+                if !vertex.position[i].is_finite() {
+                    vertex.position[i] = 0.0;
+                }
+            }
+            positions += 1;
+        }
+        if !is_finite(vertex.normal) {
+            vertex.normal = Vector3::unit_z();
+            normals += 1;
+        }
+    }
+    (positions, normals)
+}
+
+fn is_finite(v: Vector3) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+/// glTF stores the handedness of the tangent basis in `tangent.w`, which must be 1.0 or -1.0
+/// (`bitangent = cross(normal, tangent.xyz) * tangent.w`, see vertex shader).
+/// Some exporters write 0 or unnormalized values, which would collapse or scale the bitangent.
+fn tangent_handedness(w: f32) -> f32 {
+    if w < 0.0 { -1.0 } else { 1.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a document with a single primitive from the given attributes
+    /// (semantic, accessor type, data), embedding the buffer as a data URI.
+    fn import_primitive(attributes: &[(&str, &str, &[f32])], indices: Option<&[u32]>)
+        -> (gltf::Document, Vec<gltf::buffer::Data>)
+    {
+        import_primitive_with_material(attributes, indices, None)
+    }
+
+    /// Like `import_primitive`, with the given material (JSON), which may use texture 0
+    fn import_primitive_with_material(attributes: &[(&str, &str, &[f32])], indices: Option<&[u32]>,
+        material: Option<&str>) -> (gltf::Document, Vec<gltf::buffer::Data>)
+    {
+        let mut data: Vec<u8> = vec![];
+        let mut accessors = vec![];
+        let mut views = vec![];
+        let mut json_attributes = vec![];
+        for (semantic, type_, values) in attributes {
+            let components = match *type_ { "VEC2" => 2, "VEC3" => 3, "VEC4" => 4, _ => 1 };
+            let bounds = if *semantic == "POSITION" {
+                let axis = |i: usize| values.iter().skip(i).step_by(3).cloned();
+                let min: Vec<_> = (0..3).map(|i| axis(i).fold(f32::MAX, f32::min)).collect();
+                let max: Vec<_> = (0..3).map(|i| axis(i).fold(f32::MIN, f32::max)).collect();
+                format!(r#", "min": {:?}, "max": {:?}"#, min, max)
+            } else {
+                String::new()
+            };
+            views.push(format!(r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}"#,
+                data.len(), values.len() * 4));
+            accessors.push(format!(
+                r#"{{ "bufferView": {}, "componentType": 5126, "count": {}, "type": "{}"{} }}"#,
+                views.len() - 1, values.len() / components, type_, bounds));
+            json_attributes.push(format!(r#""{}": {}"#, semantic, accessors.len() - 1));
+            data.extend(values.iter().flat_map(|v| v.to_bits().to_le_bytes().to_vec()));
+        }
+        let mut json_indices = String::new();
+        if let Some(indices) = indices {
+            views.push(format!(r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}"#,
+                data.len(), indices.len() * 4));
+            accessors.push(format!(
+                r#"{{ "bufferView": {}, "componentType": 5125, "count": {}, "type": "SCALAR" }}"#,
+                views.len() - 1, indices.len()));
+            json_indices = format!(r#", "indices": {}"#, accessors.len() - 1);
+            data.extend(indices.iter().flat_map(|i| i.to_le_bytes().to_vec()));
+        }
+        let (json_materials, json_material) = match material {
+            Some(material) => (format!(r#""materials": [{}], "textures": [{{ "source": 0 }}],
+                "images": [{{ "uri": "texture.png" }}],"#, material), r#", "material": 0"#),
+            None => (String::new(), ""),
+        };
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}],
+            "bufferViews": [{}],
+            "accessors": [{}],{}
+            "meshes": [{{ "primitives": [{{ "attributes": {{ {} }}{}{} }}] }}]
+        }}"#, data.len(), base64::encode(&data), views.join(", "), accessors.join(", "), json_materials,
+            json_attributes.join(", "), json_indices, json_material);
+
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        (gltf.document, vec![gltf::buffer::Data(data)])
+    }
+
+    fn load_first_primitive(doc: &gltf::Document, buffers: &[gltf::buffer::Data])
+        -> (Vec<Vertex>, Option<Vec<u32>>, ShaderFlags)
+    {
+        let g_mesh = doc.meshes().next().unwrap();
+        let g_primitive = g_mesh.primitives().next().unwrap();
+        Primitive::load_vertices(&g_primitive, buffers, 0, 0)
+    }
+
+    #[test]
+    fn animated_morph_targets() {
+        let vertex = |x: f32| Vertex { position: vec3(x, 0.0, 0.0), normal: vec3(0.0, 0.0, 1.0), ..Vertex::default() };
+        let base_vertices = vec![vertex(0.0), vertex(1.0), vertex(2.0), vertex(3.0)];
+        let up = vec3(0.0, 1.0, 0.0);
+        let targets = vec![
+            MorphTarget { positions: vec![Vector3::zero(), up, Vector3::zero(), Vector3::zero()],
+                normals: vec![], tangents: vec![] },
+            MorphTarget { positions: vec![], normals: vec![Vector3::zero(), Vector3::zero(), up, Vector3::zero()],
+                tangents: vec![] },
+        ];
+        let displaced = displaced_range(&targets);
+        assert_eq!(displaced, 1..3);
+
+        // only the displaced vertices are morphed (and uploaded)
+        let mut vertices = base_vertices[displaced.clone()].to_vec();
+        apply_morph_targets(&mut vertices, displaced.start, &targets, &[0.5, 1.0]);
+        assert_eq!(vertices[0].position, vec3(1.0, 0.5, 0.0));
+        assert_eq!(vertices[1].position, vec3(2.0, 0.0, 0.0));
+        assert!((vertices[1].normal - vec3(0.0, 1.0, 1.0).normalize()).magnitude() < 1e-6);
+
+        assert_eq!(morph_bounds(&base_vertices, &targets).max, Point3::new(3.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn tangent_handedness_on_mirrored_uvs() {
+        // Two triangles sharing the edge x = 1, the right one with mirrored UVs (u decreasing
+        // along +x), as produced by mirror modifiers. The vertices are split at the seam, the
+        // tangents of the mirrored half point along -x with a sloppy handedness (-0.5, -2.0).
+        let positions = [0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  1.0, 1.0, 0.0,
+                         1.0, 0.0, 0.0,  2.0, 0.0, 0.0,  1.0, 1.0, 0.0];
+        let normals = [0.0, 0.0, 1.0].repeat(6);
+        let tex_coords = [0.0, 0.0,  1.0, 0.0,  1.0, 1.0,
+                          1.0, 0.0,  0.0, 0.0,  1.0, 1.0];
+        let tangents = [1.0, 0.0, 0.0, 1.0,  1.0, 0.0, 0.0, 0.0,  1.0, 0.0, 0.0, 2.0,
+                        -1.0, 0.0, 0.0, -0.5,  -1.0, 0.0, 0.0, -1.0,  -1.0, 0.0, 0.0, -2.0];
+        let (doc, buffers) = import_primitive(&[
+            ("POSITION", "VEC3", &positions),
+            ("NORMAL", "VEC3", &normals),
+            ("TEXCOORD_0", "VEC2", &tex_coords),
+            ("TANGENT", "VEC4", &tangents)
+        ], Some(&[0, 1, 2, 3, 4, 5]));
+
+        let (vertices, _, flags) = load_first_primitive(&doc, &buffers);
+        assert!(flags.contains(ShaderFlags::HAS_TANGENTS));
+        let handedness: Vec<_> = vertices.iter().map(|v| v.tangent.w).collect();
+        assert_eq!(handedness, [1.0, 1.0, 1.0, -1.0, -1.0, -1.0]);
+
+        // same formula as the vertex shader: the bitangent points along +v on both halves
+        let bitangent = |v: &Vertex| v.normal.cross(v.tangent.truncate()) * v.tangent.w;
+        for vertex in &vertices {
+            assert_eq!(bitangent(vertex), vec3(0.0, 1.0, 0.0));
+        }
+        assert_eq!(vertices[4].tangent.truncate(), vec3(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn generated_tangents() {
+        // quad rotated by 90 degrees around +z (u along +y), with exported tangents
+        let positions = [0.0, 0.0, 0.0,  0.0, 1.0, 0.0,  -1.0, 1.0, 0.0,  -1.0, 0.0, 0.0];
+        let normals = [0.0, 0.0, 1.0,  0.0, 0.0, 1.0,  0.0, 0.0, 1.0,  0.0, 0.0, 1.0];
+        let tex_coords = [0.0, 0.0,  1.0, 0.0,  1.0, 1.0,  0.0, 1.0];
+        let tangents = [0.0, 1.0, 0.0, 1.0,  0.0, 1.0, 0.0, 1.0,  0.0, 1.0, 0.0, 1.0,  0.0, 1.0, 0.0, 1.0];
+        let indices = [0, 1, 2, 0, 2, 3];
+        let mut attributes = vec![
+            ("POSITION", "VEC3", &positions[..]),
+            ("NORMAL", "VEC3", &normals[..]),
+            ("TEXCOORD_0", "VEC2", &tex_coords[..]),
+        ];
+        let (doc, buffers) = import_primitive(&attributes, Some(&indices));
+        let (mut generated, indices, _) = load_first_primitive(&doc, &buffers);
+        generate_tangents(&mut generated, indices.as_deref(), 0);
+
+        attributes.push(("TANGENT", "VEC4", &tangents[..]));
+        let (doc, buffers) = import_primitive(&attributes, indices.as_deref());
+        let (exported, _, _) = load_first_primitive(&doc, &buffers);
+        for (generated, exported) in generated.iter().zip(&exported) {
+            assert!((generated.tangent - exported.tangent).magnitude() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn tangents_for_normal_map_on_second_set() {
+        // u runs along +x in TEXCOORD_0, but along +y in TEXCOORD_1, which the normal map uses
+        let normals = [0.0, 0.0, 1.0].repeat(3);
+        let (doc, buffers) = import_primitive_with_material(&[
+            ("POSITION", "VEC3", &[0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0]),
+            ("NORMAL", "VEC3", &normals),
+            ("TEXCOORD_0", "VEC2", &[0.0, 0.0,  1.0, 0.0,  0.0, 1.0]),
+            ("TEXCOORD_1", "VEC2", &[0.0, 0.0,  0.0, 1.0,  1.0, 0.0])
+        ], Some(&[0, 1, 2]), Some(r#"{ "normalTexture": { "index": 0, "texCoord": 1 } }"#));
+        let g_mesh = doc.meshes().next().unwrap();
+        let g_primitive = g_mesh.primitives().next().unwrap();
+        let tex_coord = tangent_tex_coord(&g_primitive, gl::TRIANGLES, &ImportOptions::default());
+        assert_eq!(tex_coord, Some(1));
+
+        let (mut vertices, indices, _) = load_first_primitive(&doc, &buffers);
+        generate_tangents(&mut vertices, indices.as_deref(), tex_coord.unwrap());
+        for vertex in &vertices {
+            assert!((vertex.tangent - vec4(0.0, 1.0, 0.0, -1.0)).magnitude() < 1e-6, "{:?}", vertex.tangent);
+        }
+
+        // without a TEXCOORD_1 attribute the tangent space is derived per pixel
+        let (doc, _) = import_primitive_with_material(&[
+            ("POSITION", "VEC3", &[0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0]),
+            ("NORMAL", "VEC3", &normals),
+            ("TEXCOORD_0", "VEC2", &[0.0, 0.0,  1.0, 0.0,  0.0, 1.0])
+        ], None, Some(r#"{ "normalTexture": { "index": 0, "texCoord": 1 } }"#));
+        let g_mesh = doc.meshes().next().unwrap();
+        let g_primitive = g_mesh.primitives().next().unwrap();
+        assert_eq!(tangent_tex_coord(&g_primitive, gl::TRIANGLES, &ImportOptions::default()), None);
+    }
+
+    #[test]
+    fn feature_ids() {
+        let (doc, buffers) = import_primitive(&[
+            ("POSITION", "VEC3", &[0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0]),
+            ("_BATCHID", "SCALAR", &[7.0, 7.0, 42.0])
+        ], None);
+        let g_mesh = doc.meshes().next().unwrap();
+        let g_primitive = g_mesh.primitives().next().unwrap();
+        let (mut vertices, _, _) = load_first_primitive(&doc, &buffers);
+        assert!(read_feature_ids(&g_primitive, &buffers, None, &mut vertices, 0, 0));
+        let ids: Vec<_> = vertices.iter().map(|v| v.feature_id).collect();
+        assert_eq!(ids, [7, 7, 42]);
+        assert!(!read_feature_ids(&g_primitive, &buffers, Some("_FEATURE_ID_1"), &mut vertices, 0, 0));
+    }
+
+    #[test]
+    fn non_finite_vertices() {
+        let mut vertices = vec![
+            Vertex { position: vec3(1.0, f32::NAN, 2.0), normal: vec3(0.0, 1.0, 0.0), ..Vertex::default() },
+            Vertex { position: vec3(1.0, 2.0, 3.0), normal: vec3(f32::INFINITY, 0.0, 0.0), ..Vertex::default() },
+            Vertex { position: vec3(f32::NEG_INFINITY, 0.0, 0.0), normal: vec3(0.0, 0.0, -1.0), ..Vertex::default() },
+        ];
+        assert_eq!(sanitize_vertices(&mut vertices), (2, 1));
+        assert_eq!(vertices[0].position, vec3(1.0, 0.0, 2.0));
+        assert_eq!(vertices[1].normal, vec3(0.0, 0.0, 1.0));
+        assert_eq!(vertices[2].position, vec3(0.0, 0.0, 0.0));
+        assert_eq!(sanitize_vertices(&mut vertices), (0, 0));
+    }
+
+    #[test]
+    fn accessor_byte_ranges() {
+        // 3 interleaved VEC3 positions (stride 24)
+        assert_eq!(accessor_byte_range(100, 12, 24, 3, 12), Some(112..172));
+        assert_eq!(accessor_byte_range(100, 12, 24, 0, 12), Some(112..112));
+        if cfg!(target_pointer_width = "64") {
+            // beyond the 32-bit range of the offsets
+            let view_offset = u32::MAX as usize;
+            assert_eq!(accessor_byte_range(view_offset, 16, 16, 2, 16),
+                Some(view_offset + 16..view_offset + 48));
+        }
+        assert_eq!(accessor_byte_range(usize::MAX - 8, 16, 16, 1, 16), None);
+        assert_eq!(accessor_byte_range(0, 0, usize::MAX / 2, 3, 4), None);
+    }
+
+    #[test]
+    fn variants() {
+        let extension: Value = serde_json::from_str(r#"{ "mappings": [
+            { "material": 2, "variants": [0, 3] },
+            { "material": 1, "variants": [1], "name": "blue" },
+            { "variants": [2] }
+        ] }"#).unwrap();
+        assert_eq!(variant_mappings(&extension), [(2, vec![0, 3]), (1, vec![1])]);
+        assert_eq!(variant_mappings(&Value::Null), []);
+    }
+
+    #[test]
+    fn mirrored_front_face() {
+        let mirror = Matrix4::from_translation(vec3(1.0, 0.0, 0.0)) * Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0);
+        assert_eq!(front_face(&Matrix4::identity()), gl::CCW);
+        assert_eq!(front_face(&mirror), gl::CW);
+        assert_eq!(front_face(&(mirror * mirror)), gl::CCW);
+
+        // counter-clockwise triangle facing +z: after mirroring, the geometric normal of the
+        // original winding points inwards, i.e. the winding is reversed
+        let (p0, p1, p2) = (Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0));
+        let normal = vec3(0.0, 0.0, 1.0);
+        let (q0, q1, q2) = (mirror.transform_point(p0), mirror.transform_point(p1), mirror.transform_point(p2));
+        let geometric_normal = (q1 - q0).cross(q2 - q0);
+        let shading_normal = mirror.transform_vector(normal);
+        assert!(geometric_normal.dot(shading_normal) < 0.0);
+    }
+
+    #[test]
+    fn forced_face_culling() {
+        let auto = FaceCulling::default();
+        assert_eq!((auto.culled_face(false), auto.culled_face(true)), (Some(gl::BACK), None));
+        let front = FaceCulling { cull_mode: CullMode::Front, front_face: Some(gl::CW) };
+        assert_eq!(front.culled_face(true), Some(gl::FRONT));
+        let mirror = Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0);
+        assert_eq!((auto.front_face(&mirror), front.front_face(&Matrix4::identity())), (gl::CW, gl::CW));
+        assert_eq!(FaceCulling { cull_mode: CullMode::Off, front_face: None }.culled_face(false), None);
+    }
+
+    #[test]
+    fn quantized_attributes() {
+        // KHR_mesh_quantization: unnormalized u16 positions (with byteStride 8 for alignment)
+        // and normalized i8 normals (padded to 4 bytes)
+        let mut data: Vec<u8> = vec![];
+        for position in &[[0u16, 0, 0], [1000, 0, 0], [0, 500, 65535]] {
+            data.extend(position.iter().flat_map(|c| c.to_le_bytes().to_vec()));
+            data.extend(&[0, 0]);
+        }
+        for normal in &[[0i8, 0, 127], [0, -127, 0], [-128, 0, 0]] {
+            data.extend(normal.iter().map(|&c| c as u8));
+            data.push(0);
+        }
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "extensionsUsed": ["KHR_mesh_quantization"],
+            "extensionsRequired": ["KHR_mesh_quantization"],
+            "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 24, "byteStride": 8 }},
+                {{ "buffer": 0, "byteOffset": 24, "byteLength": 12, "byteStride": 4 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5123, "count": 3, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [1000, 500, 65535] }},
+                {{ "bufferView": 1, "componentType": 5120, "normalized": true, "count": 3, "type": "VEC3" }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }} }}] }}]
+        }}"#, data.len(), base64::encode(&data));
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let buffers = vec![gltf::buffer::Data(data)];
+
+        let (vertices, _, flags) = load_first_primitive(&gltf.document, &buffers);
+        assert!(flags.contains(ShaderFlags::HAS_NORMALS));
+        let positions: Vec<_> = vertices.iter().map(|v| v.position).collect();
+        assert_eq!(positions, [vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0), vec3(0.0, 500.0, 65535.0)]);
+        let normals: Vec<_> = vertices.iter().map(|v| v.normal).collect();
+        assert_eq!(normals, [vec3(0.0, 0.0, 1.0), vec3(0.0, -1.0, 0.0), vec3(-1.0, 0.0, 0.0)]);
+
+        let bounds = vertex_bounds(&vertices);
+        assert_eq!((bounds.min, bounds.max), (Point3::new(0.0, 0.0, 0.0), Point3::new(1000.0, 500.0, 65535.0)));
+    }
+
+    #[test]
+    fn tex_coords() {
+        // without texture coordinates they stay zero
+        let positions = [0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0];
+        let (doc, buffers) = import_primitive(&[("POSITION", "VEC3", &positions)], None);
+        let (vertices, _, flags) = load_first_primitive(&doc, &buffers);
+        assert!(!flags.contains(ShaderFlags::HAS_UV));
+        assert!(vertices.iter().all(|v| v.tex_coord_0 == Vector2::zero() && v.tex_coord_1 == Vector2::zero()));
+
+        // normalized u16 TEXCOORD_0 and u8 TEXCOORD_1 (KHR_mesh_quantization, padded to 4 bytes)
+        let mut data: Vec<u8> = positions.iter().flat_map(|v| v.to_bits().to_le_bytes().to_vec()).collect();
+        for uv in &[[0u16, 0], [65535, 0], [0, 32768]] {
+            data.extend(uv.iter().flat_map(|c| c.to_le_bytes().to_vec()));
+        }
+        for uv in &[[0u8, 255], [51, 0], [255, 255]] {
+            data.extend(uv);
+            data.extend(&[0, 0]);
+        }
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "extensionsUsed": ["KHR_mesh_quantization"],
+            "extensionsRequired": ["KHR_mesh_quantization"],
+            "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 12 }},
+                {{ "buffer": 0, "byteOffset": 48, "byteLength": 12, "byteStride": 4 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5123, "normalized": true, "count": 3, "type": "VEC2" }},
+                {{ "bufferView": 2, "componentType": 5121, "normalized": true, "count": 3, "type": "VEC2" }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2 }} }}] }}]
+        }}"#, data.len(), base64::encode(&data));
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let buffers = vec![gltf::buffer::Data(data)];
+
+        let (vertices, _, flags) = load_first_primitive(&gltf.document, &buffers);
+        assert!(flags.contains(ShaderFlags::HAS_UV));
+        let uv0: Vec<_> = vertices.iter().map(|v| v.tex_coord_0).collect();
+        assert_eq!(uv0[..2], [Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0)]);
+        assert!((uv0[2].y - 0.5).abs() < 1e-4);
+        let uv1: Vec<_> = vertices.iter().map(|v| v.tex_coord_1).collect();
+        assert_eq!(uv1, [Vector2::new(0.0, 1.0), Vector2::new(0.2, 0.0), Vector2::new(1.0, 1.0)]);
+    }
+
+    #[test]
+    fn cube_without_normals() {
+        let positions = [
+            0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  1.0, 1.0, 0.0,  0.0, 1.0, 0.0,
+            0.0, 0.0, 1.0,  1.0, 0.0, 1.0,  1.0, 1.0, 1.0,  0.0, 1.0, 1.0,
+        ];
+        let indices = [
+            0, 2, 1,  0, 3, 2,  4, 5, 6,  4, 6, 7, // -z, +z
+            0, 1, 5,  0, 5, 4,  3, 6, 2,  3, 7, 6, // -y, +y
+            0, 4, 7,  0, 7, 3,  1, 2, 6,  1, 6, 5, // -x, +x
+        ];
+        let (doc, buffers) = import_primitive(&[("POSITION", "VEC3", &positions)], Some(&indices));
+        let (vertices, indices, flags) = load_first_primitive(&doc, &buffers);
+        assert!(!flags.contains(ShaderFlags::HAS_NORMALS));
+
+        // flat shading (the default smooth angle) splits every corner into one vertex per face
+        let (vertices, indices) = generate_normals(&vertices, indices.as_deref(), 0.0);
+        assert_eq!((vertices.len(), indices.len()), (24, 36));
+        let center = vec3(0.5, 0.5, 0.5);
+        for vertex in &vertices {
+            assert!((vertex.normal.magnitude() - 1.0).abs() < 1e-6);
+            // pointing outwards along an axis
+            let offset = vertex.position - center;
+            assert!((vertex.normal.dot(offset) - 0.5).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn non_indexed() {
+        // a quad as triangle soup, drawn with glDrawArrays
+        let positions = [0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  1.0, 1.0, 0.0,
+                         0.0, 0.0, 0.0,  1.0, 1.0, 0.0,  0.0, 1.0, 0.0];
+        let (doc, buffers) = import_primitive(&[("POSITION", "VEC3", &positions)], None);
+        assert!(doc.meshes().next().unwrap().primitives().next().unwrap().indices().is_none());
+        let (vertices, indices, _) = load_first_primitive(&doc, &buffers);
+        assert_eq!(indices, None);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(vertices[4].position, vec3(1.0, 1.0, 0.0));
+        assert_eq!(draw_ranges(vertices.len(), gl::TRIANGLES, 4), [(0, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn point_and_line_shader_flags() {
+        let material = ShaderFlags::HAS_BASECOLORMAP | ShaderFlags::HAS_NORMALMAP;
+        let normals = ShaderFlags::HAS_NORMALS;
+        assert_eq!(primitive_shader_flags(gl::TRIANGLES, ShaderFlags::empty(), material), material);
+        assert_eq!(primitive_shader_flags(gl::LINES, normals | ShaderFlags::HAS_TANGENTS, material),
+            normals | ShaderFlags::HAS_TANGENTS | material);
+        assert_eq!(primitive_shader_flags(gl::LINE_STRIP, normals, material), normals | ShaderFlags::HAS_BASECOLORMAP);
+        assert_eq!(primitive_shader_flags(gl::POINTS, ShaderFlags::empty(), material),
+            ShaderFlags::HAS_BASECOLORMAP | ShaderFlags::UNLIT);
+    }
+
+    #[test]
+    fn split_draw_ranges() {
+        assert_eq!(draw_ranges(9, gl::TRIANGLES, 100), [(0, 9)]);
+        // at triangle boundaries
+        assert_eq!(draw_ranges(9, gl::TRIANGLES, 4), [(0, 3), (3, 3), (6, 3)]);
+        assert_eq!(draw_ranges(10, gl::LINES, 7), [(0, 6), (6, 4)]);
+        assert_eq!(draw_ranges(10, gl::TRIANGLE_STRIP, 4), [(0, 10)]);
+        assert_eq!(draw_ranges(0, gl::TRIANGLES, 4), [(0, 0)]);
+    }
+
+    #[test]
+    fn shared_vertex_buffers() {
+        // two triangles in the same views, interleaved positions and normals for the first one
+        let gltf = gltf::Gltf::from_slice(br#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 228, "uri": "data.bin" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 72 },
+                { "buffer": 0, "byteOffset": 72, "byteLength": 72 },
+                { "buffer": 0, "byteOffset": 144, "byteLength": 72, "byteStride": 24 },
+                { "buffer": 0, "byteOffset": 216, "byteLength": 12 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "byteOffset": 12, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 2, "byteOffset": 12, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 3, "componentType": 5125, "count": 3, "type": "SCALAR" }
+            ],
+            "meshes": [{ "primitives": [
+                { "attributes": { "POSITION": 0, "NORMAL": 1 }, "indices": 7 },
+                { "attributes": { "NORMAL": 3, "POSITION": 2 }, "indices": 7 },
+                { "attributes": { "POSITION": 0, "NORMAL": 1 } },
+                { "attributes": { "POSITION": 0, "NORMAL": 4 } },
+                { "attributes": { "POSITION": 5, "NORMAL": 6 } },
+                { "attributes": { "POSITION": 0 } },
+                { "attributes": { "POSITION": 0 }, "mode": 1 }
+            ] }]
+        }"#).unwrap();
+        let g_mesh = gltf.document.meshes().next().unwrap();
+        let primitives: Vec<_> = g_mesh.primitives().collect();
+        let options = ImportOptions::default();
+        let shared = |index: usize, options: &ImportOptions| {
+            let g_primitive = &primitives[index];
+            shared_vertices(g_primitive, g_primitive.mode().as_gl_enum(), options)
+        };
+        let (key, vertices, capacity) = shared(0, &options).unwrap();
+        assert_eq!((vertices, capacity), (0..3, 6));
+        // different accessors into the same views, and the same accessors with other indices
+        assert_eq!(shared(1, &options), Some((key.clone(), 3..6, 6)));
+        assert_eq!(shared(2, &options), Some((key.clone(), 0..3, 6)));
+        // the normals start at the second element of their view
+        assert_eq!(shared(3, &options), None);
+        let (interleaved, vertices, capacity) = shared(4, &options).unwrap();
+        assert_ne!(interleaved, key);
+        assert_eq!((vertices, capacity), (0..3, 3));
+
+        // generated normals and baked occlusion depend on the triangles
+        assert_eq!(shared(5, &options), None);
+        assert!(shared(6, &options).is_some());
+        let baked = ImportOptions { vertex_ao_samples: Some(16), ..options };
+        assert_eq!(shared(0, &baked), None);
     }
 }
@@ -1,11 +1,16 @@
 use std::rc::Rc;
 use std::collections::HashMap;
-use std::path::Path;
+
+use log::{info, warn};
 
 use crate::shader::*;
-use crate::render::{Mesh, Node, Material};
-use crate::render::texture::Texture;
-use crate::importdata::ImportData;
+use crate::render::math::*;
+use crate::render::{Animation, Mesh, Node, Material, PrimitiveId, VertexBuffer};
+use crate::render::texture::{ColorSpace, Texture};
+use crate::importdata::{ImportData, Progress};
+
+/// Estimated texture memory above which a warning is logged (bytes)
+const TEXTURE_MEMORY_WARNING_SIZE: usize = 1024 * 1024 * 1024;
 
 #[derive(Default)]
 pub struct Root {
@@ -14,22 +19,57 @@ pub struct Root {
     pub textures: Vec<Rc<Texture>>,
     pub materials: Vec<Rc<Material>>,
     pub shaders: HashMap<ShaderFlags, Rc<PbrShader>>,
+    /// Vertex buffers by the buffer views of the attributes, see `VertexBuffer`
+    pub vertex_buffers: HashMap<String, Rc<VertexBuffer>>,
+    pub animations: Vec<Animation>,
 
     pub camera_nodes: Vec<usize>, // indices of camera nodes
+    pub light_nodes: Vec<usize>, // indices of light nodes
     // TODO!: joint_nodes, mesh_nodes?
 }
 
 impl Root {
-    pub fn from_gltf(imp: &ImportData, base_path: &Path) -> Self {
+    #[allow(dead_code)]
+    pub fn from_gltf(imp: &ImportData) -> Self {
+        Self::from_gltf_with_progress(imp, &mut |_| ())
+    }
+
+    /// Like `from_gltf`, calling `on_progress` before each node (meshes and textures
+    /// are loaded along with the first node that uses them)
+    pub fn from_gltf_with_progress(imp: &ImportData, on_progress: &mut dyn FnMut(Progress)) -> Self {
         let mut root = Root::default();
+        let node_count = imp.doc.nodes().len();
         let nodes = imp.doc.nodes()
-            .map(|g_node| Node::from_gltf(&g_node, &mut root, imp, base_path))
+            .map(|g_node| {
+                on_progress(Progress { stage: "nodes", done: g_node.index(), total: node_count });
+                Node::from_gltf(&g_node, &mut root, imp)
+            })
             .collect();
         root.nodes = nodes;
         root.camera_nodes = root.nodes.iter()
             .filter(|node| node.camera.is_some())
             .map(|node| node.index)
             .collect();
+        root.light_nodes = root.nodes.iter()
+            .filter(|node| node.light.is_some())
+            .map(|node| node.index)
+            .collect();
+        root.animations = imp.doc.animations()
+            .map(|g_animation| Animation::from_gltf(&g_animation, imp))
+            .collect();
+
+        if imp.options.simplify.is_some() {
+            let (full, simplified) = root.triangle_counts();
+            info!("Simplified the meshes from {} to {} triangles", full, simplified);
+        }
+
+        let texture_memory: usize = root.textures.iter().map(|texture| texture.memory_size).sum();
+        if texture_memory > TEXTURE_MEMORY_WARNING_SIZE {
+            warn!("Textures need about {} MB of GPU memory, consider using --max-texture-size",
+                texture_memory / (1024 * 1024));
+        } else {
+            info!("Textures need about {} MB of GPU memory", texture_memory / (1024 * 1024));
+        }
         root
     }
 
@@ -42,8 +82,604 @@ impl Root {
         }
     }
 
+    /// Triangles of all meshes at full resolution and with the simplified primitives
+    /// (see `ImportOptions::simplify`)
+    pub fn triangle_counts(&self) -> (u64, u64) {
+        let primitives = || self.meshes.iter().flat_map(|mesh| mesh.primitives.iter());
+        let full = primitives().map(|p| u64::from(p.triangle_count())).sum();
+        let simplified = primitives()
+            .map(|p| u64::from(p.simplified_triangle_count().unwrap_or_else(|| p.triangle_count())))
+            .sum();
+        (full, simplified)
+    }
+
+    /// Shows the simplified primitives instead of the full-resolution ones (if there are any)
+    pub fn set_simplified(&self, enabled: bool) {
+        for primitive in self.meshes.iter().flat_map(|mesh| mesh.primitives.iter()) {
+            primitive.set_show_simplified(enabled);
+        }
+    }
+
+    /// Returns the shader variant for the given flags, compiling it on first use.
+    pub fn get_shader(&mut self, flags: ShaderFlags) -> Rc<PbrShader> {
+        let shader = self.shaders.entry(flags)
+            .or_insert_with(|| Rc::new(PbrShader::new(flags)));
+        Rc::clone(shader)
+    }
+
+    /// Sets how much the screen-space ambient occlusion darkens the ambient light (0: disabled)
+    pub fn set_ssao_strength(&self, strength: f32) {
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_float(pbr_shader.uniforms.u_SSAOStrength, strength);
+            }
+        }
+    }
+
+    /// Sets the far plane distance for the logarithmic depth buffer (`None`: disabled)
+    pub fn set_log_depth(&self, zfar: Option<f32>) {
+        // see pbr-frag.glsl
+        let coefficient = zfar.map_or(0.0, |zfar| 2.0 / (zfar + 1.0).log2());
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_float(pbr_shader.uniforms.u_LogDepthFC, coefficient);
+            }
+        }
+    }
+
+    /// Replaces the vertex normals (and normal maps) by geometric face normals
+    pub fn set_flat_shading(&self, enabled: bool) {
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_bool(pbr_shader.uniforms.u_FlatShading, enabled);
+            }
+        }
+    }
+
+    /// Overrides the color space of the texture with the glTF index `index` for all its uses
+    /// (`None`: derived from the usage again). Returns false if there is no such texture.
+    #[allow(dead_code)]
+    pub fn set_texture_color_space(&self, index: usize, color_space: Option<ColorSpace>) -> bool {
+        // there is one texture per texture coordinate set it is used with
+        let mut found = false;
+        for texture in self.textures.iter().filter(|texture| texture.has_index(index)) {
+            texture.color_space.set(color_space);
+            found = true;
+        }
+        found
+    }
+
+    /// Replaces the material of a primitive at runtime (e.g. for material editors), also for
+    /// the following variant switches. The matching shader variant is selected and the change
+    /// is visible on the next frame. Returns false if there is no such primitive.
+    #[allow(dead_code)]
+    pub fn set_material(&mut self, primitive_id: PrimitiveId, material: Rc<Material>) -> bool {
+        // including the copies with other morph target weights
+        let meshes: Vec<_> = self.meshes.iter()
+            .filter(|mesh| mesh.index == primitive_id.mesh)
+            .map(Rc::clone)
+            .collect();
+        let mut found = false;
+        for primitive in meshes.iter().flat_map(|mesh| &mesh.primitives).filter(|p| p.index == primitive_id.primitive) {
+            primitive.set_material(Rc::clone(&material), self);
+            found = true;
+        }
+        found
+    }
+
+    /// Enables or disables drawing the back faces of transmissive materials (see
+    /// `Material::transmission_back_faces`), of the material with the glTF index `index` or of
+    /// all materials (`None`). Returns the number of affected transmissive materials.
+    pub fn set_transmission_back_faces(&self, index: Option<usize>, enabled: bool) -> usize {
+        let materials = self.materials.iter()
+            .filter(|material| material.is_transmissive() && (index.is_none() || material.index == index));
+        let mut count = 0;
+        for material in materials {
+            material.transmission_back_faces.set(enabled);
+            count += 1;
+        }
+        count
+    }
+
+    /// Opacity of all surfaces in x-ray mode (0: disabled, see `Scene::draw_xray`)
+    pub fn set_xray_opacity(&self, opacity: f32) {
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_float(pbr_shader.uniforms.u_XRayOpacity, opacity);
+            }
+        }
+    }
+
+    /// Outputs colors weighted for order-independent transparency (see `WeightedBlendedOit`)
+    pub fn set_weighted_blended(&self, enabled: bool) {
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_bool(pbr_shader.uniforms.u_WeightedBlended, enabled);
+            }
+        }
+    }
+
+    /// Shows the linear view space depth between `znear` and `zfar` as grayscale instead of the
+    /// shaded result, reconstructed from the depth buffer value with the inverse projection
+    pub fn set_depth_view(&self, enabled: bool, projection_matrix: &Matrix4, znear: f32, zfar: f32) {
+        let inverse_projection = projection_matrix.invert().unwrap_or_else(Matrix4::identity);
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                let (shader, uniforms) = (&pbr_shader.shader, &pbr_shader.uniforms);
+                shader.use_program();
+                shader.set_bool(uniforms.u_DepthView, enabled);
+                shader.set_vec2(uniforms.u_DepthRange, znear, zfar);
+                shader.set_mat4(uniforms.u_InverseProjection, &inverse_projection);
+            }
+        }
+    }
+
+    /// Colors the surfaces by the texel density of their base color texture relative to
+    /// `target` texels per world unit (see the PBR shader), `None` shades normally
+    pub fn set_texel_density_view(&self, target: Option<f32>) {
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_float(pbr_shader.uniforms.u_TexelDensity, target.unwrap_or(0.0));
+            }
+        }
+    }
+
+    /// Replaces the base color by the UV checker (which must be bound, see `UvCheckerTexture::bind`),
+    /// mapped by the texture coordinate set `tex_coord` or (`None`) the one of the base color texture
+    pub fn set_uv_checker(&self, enabled: bool, tex_coord: Option<u32>) {
+        let tex_coord = tex_coord.map_or(-1, |tex_coord| tex_coord as i32);
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_bool(pbr_shader.uniforms.u_UVChecker, enabled);
+                pbr_shader.shader.set_int(pbr_shader.uniforms.u_UVCheckerTexCoord, tex_coord);
+            }
+        }
+    }
+
+    /// Sets the clipping plane (`None`: disabled). GL_CLIP_DISTANCE0 needs to be enabled as well.
+    pub fn set_clip_plane(&self, clip_plane: Option<&ClipPlane>) {
+        let equation = clip_plane.map_or(Vector4::unit_w(), ClipPlane::equation);
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_vector4(pbr_shader.uniforms.u_ClipPlane, &equation);
+                pbr_shader.shader.set_bool(pbr_shader.uniforms.u_Clipping, clip_plane.is_some());
+            }
+        }
+    }
+
+    pub fn set_debug_view(&self, debug_view: &DebugView) {
+        let texture = debug_view.texture.map_or(0, |texture| texture as i32);
+        let channel = debug_view.channel.map_or(-1, |channel| channel as i32);
+        for pbr_shader in self.shaders.values() {
+            unsafe {
+                pbr_shader.shader.use_program();
+                pbr_shader.shader.set_int(pbr_shader.uniforms.u_DebugTexture, texture);
+                pbr_shader.shader.set_int(pbr_shader.uniforms.u_DebugChannel, channel);
+            }
+        }
+    }
+
+    pub fn set_lights(&self, lights: &LightOptions) {
+        for pbr_shader in self.shaders.values() {
+            pbr_shader.set_lights(lights);
+        }
+    }
+
+    /// Returns the index of the first node with the given name
+    pub fn find_node_by_name(&self, name: &str) -> Option<usize> {
+        self.nodes.iter()
+            .find(|node| node.name.as_deref() == Some(name))
+            .map(|node| node.index)
+    }
+
+    /// Indices of the node and all its descendants
+    pub fn subtree(&self, node_index: usize) -> Vec<usize> {
+        let mut stack = vec![node_index];
+        let mut nodes = vec![];
+        while let Some(node_id) = stack.pop() {
+            nodes.push(node_id);
+            stack.extend(&self.nodes[node_id].children);
+        }
+        nodes
+    }
+
     /// Note: index refers to the vec of camera node indices!
     pub fn get_camera_node(&self, index: usize) -> &Node {
         &self.nodes[self.camera_nodes[index]]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importdata::import_source;
+    use crate::memory_source::MemorySource;
+    use crate::render::Vertex;
+
+    /// Just enough of an OpenGL driver to load a scene without a context:
+    /// hands out object names and keeps track of the ones that weren't deleted
+    mod fake_gl {
+        use std::cell::{Cell, RefCell};
+        use std::collections::{HashMap, HashSet};
+        use std::os::raw::{c_char, c_void};
+        use std::ptr;
+
+        thread_local! {
+            static NEXT_NAME: Cell<u32> = const { Cell::new(1) };
+            static LIVE: RefCell<HashMap<&'static str, HashSet<u32>>> = RefCell::new(HashMap::new());
+            static POSITION_OFFSETS: RefCell<Vec<usize>> = const { RefCell::new(vec![]) };
+        }
+
+        fn create(kind: &'static str) -> u32 {
+            let name = NEXT_NAME.with(|next| next.replace(next.get() + 1));
+            LIVE.with(|live| live.borrow_mut().entry(kind).or_default().insert(name));
+            name
+        }
+
+        fn delete(kind: &'static str, name: u32) {
+            // deleting 0 is silently ignored, like deleting a name twice
+            LIVE.with(|live| live.borrow_mut().entry(kind).or_default().remove(&name));
+        }
+
+        unsafe fn create_n(kind: &'static str, n: i32, names: *mut u32) {
+            for i in 0..n as usize {
+                *names.add(i) = create(kind);
+            }
+        }
+
+        unsafe fn delete_n(kind: &'static str, n: i32, names: *const u32) {
+            for i in 0..n as usize {
+                delete(kind, *names.add(i));
+            }
+        }
+
+        /// Number of live objects of a kind ("buffers", "vertex arrays", "textures", "programs", "shaders")
+        pub fn live(kind: &'static str) -> usize {
+            LIVE.with(|live| live.borrow().get(kind).map_or(0, HashSet::len))
+        }
+
+        /// Byte offsets of the POSITION attribute (location 0) in the vertex arrays created so far
+        pub fn position_offsets() -> Vec<usize> {
+            POSITION_OFFSETS.with(|offsets| offsets.borrow().clone())
+        }
+
+        extern "system" fn vertex_attrib_pointer(index: u32, _: i32, _: u32, _: u8, _: i32, pointer: *const c_void) {
+            if index == 0 {
+                POSITION_OFFSETS.with(|offsets| offsets.borrow_mut().push(pointer as usize));
+            }
+        }
+
+        extern "system" fn gen_buffers(n: i32, names: *mut u32) { unsafe { create_n("buffers", n, names) } }
+        extern "system" fn delete_buffers(n: i32, names: *const u32) { unsafe { delete_n("buffers", n, names) } }
+        extern "system" fn gen_vertex_arrays(n: i32, names: *mut u32) {
+            unsafe { create_n("vertex arrays", n, names) }
+        }
+        extern "system" fn delete_vertex_arrays(n: i32, names: *const u32) {
+            unsafe { delete_n("vertex arrays", n, names) }
+        }
+        extern "system" fn gen_textures(n: i32, names: *mut u32) { unsafe { create_n("textures", n, names) } }
+        extern "system" fn delete_textures(n: i32, names: *const u32) { unsafe { delete_n("textures", n, names) } }
+        extern "system" fn create_program() -> u32 { create("programs") }
+        extern "system" fn delete_program(name: u32) { delete("programs", name) }
+        extern "system" fn create_shader(_: u32) -> u32 { create("shaders") }
+        extern "system" fn delete_shader(name: u32) { delete("shaders", name) }
+
+        // compilation and linking always succeed, limits are unknown (0) and uniforms inactive
+        extern "system" fn get_iv(_: u32, _: u32, value: *mut i32) { unsafe { *value = 1 } }
+        extern "system" fn get_info_log(_: u32, _: i32, length: *mut i32, _: *mut c_char) {
+            if !length.is_null() {
+                unsafe { *length = 0 }
+            }
+        }
+        extern "system" fn get_integerv(_: u32, value: *mut i32) { unsafe { *value = 0 } }
+        extern "system" fn get_uniform_location(_: u32, _: *const c_char) -> i32 { -1 }
+
+        macro_rules! no_ops {
+            ($($name:ident($($arg:ty),*);)*) => {
+                $(extern "system" fn $name($(_: $arg),*) {})*
+                fn no_op(symbol: &str) -> *const c_void {
+                    match symbol {
+                        $(stringify!($name) => $name as *const c_void,)*
+                        _ => ptr::null(),
+                    }
+                }
+            }
+        }
+
+        #[allow(non_snake_case)]
+        mod no_op_functions {
+            use super::*;
+            no_ops! {
+                glActiveTexture(u32);
+                glAttachShader(u32, u32);
+                glBindBuffer(u32, u32);
+                glBindTexture(u32, u32);
+                glBindVertexArray(u32);
+                glBufferData(u32, isize, *const c_void, u32);
+                glBufferSubData(u32, isize, isize, *const c_void);
+                glCompileShader(u32);
+                glDisableVertexAttribArray(u32);
+                glEnableVertexAttribArray(u32);
+                glGenerateMipmap(u32);
+                glLinkProgram(u32);
+                glPixelStorei(u32, i32);
+                glShaderSource(u32, i32, *const *const c_char, *const i32);
+                glTexImage2D(u32, i32, i32, i32, i32, i32, u32, u32, *const c_void);
+                glTexParameterf(u32, u32, f32);
+                glTexParameteri(u32, u32, i32);
+                glTexSubImage2D(u32, i32, i32, i32, i32, i32, u32, u32, *const c_void);
+                glUniform1f(i32, f32);
+                glUniform1i(i32, i32);
+                glUniform1ui(i32, u32);
+                glUniform2f(i32, f32, f32);
+                glUniform3f(i32, f32, f32, f32);
+                glUniform3fv(i32, i32, *const f32);
+                glUniform4fv(i32, i32, *const f32);
+                glUniformMatrix3fv(i32, i32, u8, *const f32);
+                glUniformMatrix4fv(i32, i32, u8, *const f32);
+                glUseProgram(u32);
+                glVertexAttrib4fv(u32, *const f32);
+                glVertexAttribDivisor(u32, u32);
+                glVertexAttribIPointer(u32, i32, u32, i32, *const c_void);
+            }
+            pub fn address(symbol: &str) -> *const c_void { no_op(symbol) }
+        }
+
+        fn address(symbol: &str) -> *const c_void {
+            match symbol {
+                "glGenBuffers" => gen_buffers as *const c_void,
+                "glDeleteBuffers" => delete_buffers as *const c_void,
+                "glGenVertexArrays" => gen_vertex_arrays as *const c_void,
+                "glDeleteVertexArrays" => delete_vertex_arrays as *const c_void,
+                "glGenTextures" => gen_textures as *const c_void,
+                "glDeleteTextures" => delete_textures as *const c_void,
+                "glCreateProgram" => create_program as *const c_void,
+                "glDeleteProgram" => delete_program as *const c_void,
+                "glCreateShader" => create_shader as *const c_void,
+                "glDeleteShader" => delete_shader as *const c_void,
+                "glGetShaderiv" | "glGetProgramiv" => get_iv as *const c_void,
+                "glGetShaderInfoLog" | "glGetProgramInfoLog" => get_info_log as *const c_void,
+                "glGetIntegerv" => get_integerv as *const c_void,
+                "glGetUniformLocation" => get_uniform_location as *const c_void,
+                "glVertexAttribPointer" => vertex_attrib_pointer as *const c_void,
+                _ => no_op_functions::address(symbol),
+            }
+        }
+
+        pub fn load() {
+            gl::load_with(address);
+        }
+    }
+
+    /// 1x1 pixel image
+    const PNG: &str =
+        "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8BQDwAEhQGAhKmMIQAAAABJRU5ErkJggg==";
+
+    /// One triangle drawn twice (sharing the vertex buffer), once with a base color texture
+    fn two_primitives() -> ImportData {
+        let mut data: Vec<u8> = [0.0f32, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0].iter()
+            .chain([0.0f32, 0.0, 1.0].repeat(3).iter())
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        data.extend([0u32, 1, 2].iter().flat_map(|i| i.to_le_bytes().to_vec()));
+        let gltf = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0 }}],
+            "meshes": [{{ "primitives": [
+                {{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 2, "material": 0 }},
+                {{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 2 }}
+            ] }}],
+            "materials": [{{ "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }} }}],
+            "textures": [{{ "source": 0 }}],
+            "images": [{{ "uri": "data:image/png;base64,{}" }}],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5125, "count": 3, "type": "SCALAR" }}
+            ],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 72, "byteLength": 12 }}
+            ],
+            "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}]
+        }}"#, PNG,
+            data.len(), base64::encode(&data));
+        import_source(&MemorySource::new(gltf.into_bytes())).unwrap()
+    }
+
+    #[test]
+    fn reloading_frees_gl_objects() {
+        let imp = two_primitives();
+
+        fake_gl::load();
+        let kinds = ["buffers", "vertex arrays", "textures", "programs", "shaders"];
+        let live = || kinds.iter().map(|kind| fake_gl::live(kind)).collect::<Vec<_>>();
+        let mut root = Root::from_gltf(&imp);
+        let loaded = live();
+        // one shared vertex buffer, an index buffer and a vertex array per primitive
+        assert_eq!(loaded[..3], [3, 2, 1]);
+        assert!(loaded[3] > 0);
+
+        // like the viewer, the new scene is loaded before the old one is dropped
+        for _ in 0..3 {
+            root = Root::from_gltf(&imp);
+            assert_eq!(live(), loaded);
+        }
+        drop(root);
+        assert_eq!(live(), [0; 5]);
+    }
+
+    #[test]
+    fn replacing_materials() {
+        fake_gl::load();
+        let mut root = Root::from_gltf(&two_primitives());
+        let mesh = Rc::clone(&root.meshes[0]);
+        let textured = mesh.primitives[0].material();
+        let untextured = mesh.primitives[1].material();
+        assert!(!mesh.primitives[1].shader_flags().contains(ShaderFlags::HAS_BASECOLORMAP));
+
+        assert!(root.set_material(PrimitiveId { mesh: 0, primitive: 1 }, Rc::clone(&textured)));
+        assert!(Rc::ptr_eq(&mesh.primitives[1].material(), &textured));
+        assert!(mesh.primitives[1].shader_flags().contains(ShaderFlags::HAS_BASECOLORMAP));
+
+        assert!(root.set_material(PrimitiveId { mesh: 0, primitive: 0 }, untextured));
+        assert!(!mesh.primitives[0].shader_flags().contains(ShaderFlags::HAS_BASECOLORMAP));
+
+        // switching back to the base materials keeps the replacements
+        for primitive in &mesh.primitives {
+            primitive.set_variant(None, &mut root);
+        }
+        assert!(Rc::ptr_eq(&mesh.primitives[1].material(), &textured));
+        assert!(!mesh.primitives[0].shader_flags().contains(ShaderFlags::HAS_BASECOLORMAP));
+
+        assert!(!root.set_material(PrimitiveId { mesh: 0, primitive: 2 }, Rc::clone(&textured)));
+        assert!(!root.set_material(PrimitiveId { mesh: 1, primitive: 0 }, textured));
+    }
+
+    #[test]
+    fn safe_mode_skips_corrupt_primitives_and_textures() {
+        let mut data: Vec<u8> = [0.0f32, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0].iter()
+            .chain([0.0f32, 0.0, 1.0].repeat(3).iter())
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        data.extend([0u32, 1, 5,  0, 1, 2].iter().flat_map(|i| i.to_le_bytes().to_vec()));
+        let gltf = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0 }}],
+            "meshes": [{{ "primitives": [
+                {{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 2 }},
+                {{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 3, "material": 0 }},
+                {{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 3, "material": 1 }}
+            ] }}],
+            "materials": [
+                {{ "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }} }},
+                {{ "emissiveTexture": {{ "index": 0 }}, "emissiveFactor": [1, 1, 1] }}
+            ],
+            "textures": [{{ "source": 0 }}],
+            "images": [{{ "uri": "data:image/png;base64,{}" }}],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5125, "count": 3, "type": "SCALAR" }},
+                {{ "bufferView": 2, "byteOffset": 12, "componentType": 5125, "count": 3, "type": "SCALAR" }}
+            ],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 72, "byteLength": 24 }}
+            ],
+            "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}]
+        }}"#, PNG, data.len(), base64::encode(&data));
+        let mut imp = import_source(&MemorySource::new(gltf.into_bytes())).unwrap();
+        imp.options.safe_mode = true;
+        // the pixels don't match the size of the image anymore
+        imp.images[0].pixels.clear();
+
+        fake_gl::load();
+        let mut root = Root::from_gltf(&imp);
+        // the first primitive (index 5 of 3 vertices) and the texture, once for both materials
+        assert_eq!(imp.skipped.get(), 2);
+        let mesh = Rc::clone(&root.meshes[0]);
+        let indices: Vec<_> = mesh.primitives.iter().map(|primitive| primitive.index).collect();
+        assert_eq!(indices, [1, 2]);
+        assert!(mesh.primitives[0].material().base_color_texture.is_none());
+        assert!(mesh.primitives[1].material().emissive_texture.is_none());
+        assert_eq!(fake_gl::live("textures"), 0);
+        // one shared vertex buffer and an index buffer per loaded primitive
+        assert_eq!(fake_gl::live("buffers"), 3);
+
+        // the primitives keep their glTF indices
+        let material = mesh.primitives[0].material();
+        assert!(root.set_material(PrimitiveId { mesh: 0, primitive: 2 }, Rc::clone(&material)));
+        assert!(Rc::ptr_eq(&mesh.primitives[1].material(), &material));
+        assert!(!root.set_material(PrimitiveId { mesh: 0, primitive: 0 }, material));
+    }
+
+    #[test]
+    fn texture_with_two_sets_is_uploaded_once() {
+        let gltf = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "materials": [{{
+                "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }},
+                "normalTexture": {{ "index": 0, "texCoord": 1 }}
+            }}],
+            "textures": [{{ "source": 0 }}],
+            "images": [{{ "uri": "data:image/png;base64,{}" }}]
+        }}"#, PNG);
+        let imp = import_source(&MemorySource::new(gltf.into_bytes())).unwrap();
+
+        fake_gl::load();
+        let mut root = Root::default();
+        let material = Material::from_gltf(&imp.doc.materials().next().unwrap(), &mut root, &imp);
+        let base_color = material.base_color_texture.as_ref().unwrap();
+        let normal = material.normal_texture.as_ref().unwrap();
+        assert_eq!((base_color.tex_coord, normal.tex_coord), (0, 1));
+        assert_eq!(base_color.id, normal.id);
+        assert_eq!(root.textures.len(), 2);
+        assert_eq!(fake_gl::live("textures"), 1);
+
+        drop(material);
+        drop(root);
+        assert_eq!(fake_gl::live("textures"), 0);
+    }
+
+    #[test]
+    fn primitives_share_the_vertex_buffer_of_their_views() {
+        // two triangles with their own accessors into the same views (like gltfpack writes them)
+        let mut data: Vec<u8> = [0.0f32, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0,
+                                 2.0, 0.0, 0.0,  3.0, 0.0, 0.0,  2.0, 1.0, 0.0].iter()
+            .chain([0.0f32, 0.0, 1.0].repeat(6).iter())
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        data.extend([0u32, 1, 2].iter().flat_map(|i| i.to_le_bytes().to_vec()));
+        let gltf = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0 }}],
+            "meshes": [{{ "primitives": [
+                {{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 4 }},
+                {{ "attributes": {{ "POSITION": 2, "NORMAL": 3 }}, "indices": 4 }}
+            ] }}],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3",
+                   "min": [2, 0, 0], "max": [3, 1, 0] }},
+                {{ "bufferView": 1, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                {{ "bufferView": 2, "componentType": 5125, "count": 3, "type": "SCALAR" }}
+            ],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 72 }},
+                {{ "buffer": 0, "byteOffset": 72, "byteLength": 72 }},
+                {{ "buffer": 0, "byteOffset": 144, "byteLength": 12 }}
+            ],
+            "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}]
+        }}"#, data.len(), base64::encode(&data));
+        let imp = import_source(&MemorySource::new(gltf.into_bytes())).unwrap();
+
+        fake_gl::load();
+        let root = Root::from_gltf(&imp);
+        // one vertex buffer and an index buffer per primitive
+        assert_eq!(root.vertex_buffers.len(), 1);
+        assert_eq!(fake_gl::live("buffers"), 3);
+        // the second primitive starts at its fourth vertex
+        let position = std::mem::offset_of!(Vertex, position);
+        assert_eq!(fake_gl::position_offsets(), [position, position + 3 * std::mem::size_of::<Vertex>()]);
+        let primitives = &root.meshes[0].primitives;
+        assert_eq!(primitives[1].bounds.min, Point3::new(2.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::render::math::*;
+use crate::render::{LineRenderer, TextRenderer};
+
+/// Maximum length of the bar relative to the viewport width
+const MAX_SCREEN_FRACTION: f32 = 0.25;
+const MARGIN: f32 = 20.0;
+const TICK_HEIGHT: f32 = 6.0;
+const TEXT_SIZE: f32 = 16.0;
+
+/// Map-like scale bar in the bottom left corner, labeled with a round length that fits the
+/// current zoom, and the dimensions of the model's bounding box above it.
+pub struct ScaleBar {
+    lines: LineRenderer,
+    text: TextRenderer,
+}
+
+impl ScaleBar {
+    pub fn new() -> ScaleBar {
+        ScaleBar { lines: LineRenderer::new(), text: TextRenderer::new() }
+    }
+
+    /// `meters_per_pixel`: world size of a pixel at the depth of the camera target.
+    /// `scale` multiplies all pixel sizes (e.g. for supersampling).
+    pub unsafe fn draw(&mut self, meters_per_pixel: f32, model_size: Vector3, scale: f32) {
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let (width, height) = (viewport[2] as f32, viewport[3] as f32);
+        let color = vec4(1.0, 1.0, 1.0, 1.0);
+
+        let text_size = TEXT_SIZE * scale;
+        let (x, y) = (MARGIN * scale, height - MARGIN * scale);
+        let size_label = format!("Model size: {} x {} x {}", format_length(model_size.x),
+            format_length(model_size.y), format_length(model_size.z));
+        self.text.draw_text(x, y - 3.0 * text_size, &size_label, text_size, &color);
+
+        if !(meters_per_pixel > 0.0 && meters_per_pixel.is_finite()) {
+            return
+        }
+        let length = scale_bar_length(meters_per_pixel * width * MAX_SCREEN_FRACTION);
+        let bar_width = length / meters_per_pixel;
+        let tick = TICK_HEIGHT * scale;
+        let points = [
+            vec3(x, y, 0.0), vec3(x + bar_width, y, 0.0),
+            vec3(x, y, 0.0), vec3(x, y - tick, 0.0),
+            vec3(x + bar_width, y, 0.0), vec3(x + bar_width, y - tick, 0.0),
+        ];
+        // window pixels from the top left, like the text
+        let pixels = cgmath::ortho(0.0, width, height, 0.0, -1.0, 1.0);
+        self.lines.draw(&points, &color, &pixels, false);
+        self.text.draw_text(x, y - tick - 1.5 * text_size, &format_length(length), text_size, &color);
+    }
+}
+
+/// The longest "round" length (1, 2 or 5 times a power of ten) not longer than `max_length`
+pub fn scale_bar_length(max_length: f32) -> f32 {
+    let power = 10f32.powf(max_length.log10().floor());
+    [5.0, 2.0, 1.0].iter()
+        .map(|factor| factor * power)
+        .find(|&length| length <= max_length)
+        .unwrap_or(power)
+}
+
+/// Length in meters with a unit that avoids long fractions, e.g. "50 cm" or "1.25 km"
+pub fn format_length(meters: f32) -> String {
+    let (value, unit) = match meters.abs() {
+        m if m >= 1000.0 => (meters / 1000.0, "km"),
+        m if m >= 1.0 => (meters, "m"),
+        m if m >= 0.01 => (meters * 100.0, "cm"),
+        _ => (meters * 1000.0, "mm"),
+    };
+    // rounded to three decimals, without trailing zeros
+    format!("{} {}", (value * 1000.0).round() / 1000.0, unit)
+}
+
+/// Suggested `--scale` for models whose largest dimension (in file units) is implausible
+/// for meters, i.e. that were probably authored in millimeters (e.g. CAD exports)
+pub fn unit_scale_hint(largest_dimension: f32) -> Option<f32> {
+    if largest_dimension > 1000.0 {
+        Some(0.001)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths() {
+        assert_eq!(scale_bar_length(7.3), 5.0);
+        assert!((scale_bar_length(0.25) - 0.2).abs() < 1e-6);
+        assert_eq!(scale_bar_length(1.0), 1.0);
+        assert_eq!(format_length(scale_bar_length(0.004)), "2 mm");
+        assert_eq!(format_length(0.5), "50 cm");
+        assert_eq!(format_length(1250.0), "1.25 km");
+        assert_eq!(format_length(1.8), "1.8 m");
+        assert_eq!(unit_scale_hint(2400.0), Some(0.001));
+        assert_eq!(unit_scale_hint(2.4), None);
+    }
+}
@@ -1,15 +1,74 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
 use gltf;
+use serde_json::Value;
 
-use collision::{Aabb, Union};
+use cgmath::Deg;
+use collision::Aabb;
 
 use crate::controls::CameraParams;
-use crate::render::{Root};
+use crate::render::{CullMode, FaceCulling, HiZ, Material, Primitive, Root, TransmissionTexture,
+    WeightedBlendedOit};
+use crate::render::texture::Texture;
 use crate::render::math::*;
 
+/// Up axis of the scene's content. glTF is always Y-up, but some converted
+/// assets are Z-up and appear rotated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    /// Rotation that makes content with this up axis appear upright (Y-up)
+    pub fn rotation(self) -> Matrix4 {
+        match self {
+            UpAxis::Y => Matrix4::identity(),
+            UpAxis::Z => Matrix4::from_angle_x(Deg(-90.0)),
+        }
+    }
+}
+
 pub struct Scene {
     pub name: Option<String>,
     pub nodes: Vec<usize>,
     pub bounds: Aabb3,
+    /// Applied on top of the node transforms, see `set_root_transform`
+    pub root_transform: Matrix4,
+    /// Translation removed by `recenter`, i.e. original position = rendered position + offset
+    pub origin_offset: Vector3,
+
+    /// If set, only this node (and optionally its children) is rendered
+    pub isolated_node: Option<usize>,
+    pub isolate_children: bool,
+    /// Selected material variant (see `set_variant`)
+    pub variant: Option<usize>,
+    /// If set, only primitives with this material are rendered
+    pub solo_material: Option<Rc<Material>>,
+    /// Overrides the culling and winding of all primitives (for debugging)
+    pub face_culling: FaceCulling,
+    /// Draws the alpha blended primitives with weighted blended order-independent transparency
+    /// instead of in draw order (see `WeightedBlendedOit`)
+    pub order_independent_transparency: bool,
+    /// Skips opaque primitives hidden behind others (see `HiZ`)
+    pub occlusion_culling: bool,
+    /// Number of primitives skipped by the occlusion culling in the last frame
+    pub occluded_primitives: usize,
+
+    // created on demand if there are transmissive materials
+    transmission_texture: Option<TransmissionTexture>,
+    // created on demand if order-independent transparency is enabled
+    oit: Option<WeightedBlendedOit>,
+    // created on demand if occlusion culling is enabled
+    hi_z: Option<HiZ>,
+    /// `root_transform` of the last `update_transforms` (`None`: not updated yet)
+    applied_root_transform: Option<Matrix4>,
+
+    // kept for inspection of data the renderer doesn't use (see `document()`)
+    document: Option<gltf::Document>,
+    json: Value,
 }
 
 impl Default for Scene {
@@ -17,7 +76,23 @@ impl Default for Scene {
         Self {
             name: None,
             nodes: vec![],
-            bounds: Aabb3::zero()
+            bounds: Aabb3::zero(),
+            root_transform: Matrix4::identity(),
+            origin_offset: Vector3::zero(),
+            isolated_node: None,
+            isolate_children: true,
+            variant: None,
+            solo_material: None,
+            face_culling: FaceCulling::default(),
+            order_independent_transparency: false,
+            occlusion_culling: false,
+            occluded_primitives: 0,
+            transmission_texture: None,
+            oit: None,
+            hi_z: None,
+            applied_root_transform: None,
+            document: None,
+            json: Value::Null,
         }
     }
 }
@@ -32,25 +107,446 @@ impl Scene {
             .map(|g_node| g_node.index())
             .collect();
 
-        // propagate transforms
-        let root_transform = Matrix4::identity();
-        for node_id in &scene.nodes {
+        scene.update_transforms(root);
+        scene
+    }
+
+    /// Propagates the root transform through the node hierarchy and updates the bounds
+    /// (e.g. after animating the nodes). Only the subtrees of changed nodes are recomputed,
+    /// unless the root transform changed.
+    pub fn update_transforms(&mut self, root: &mut Root) {
+        let root_changed = self.applied_root_transform != Some(self.root_transform);
+        self.applied_root_transform = Some(self.root_transform);
+        self.bounds = Aabb3::zero();
+        for node_id in &self.nodes {
             let node = root.unsafe_get_node_mut(*node_id);
-            node.update_transform(root, &root_transform);
+            node.update_transform(root, &self.root_transform, root_changed);
             node.update_bounds(root);
-            scene.bounds = scene.bounds.union(&node.bounds);
+            if node.visible {
+                self.bounds = union_bounds(&self.bounds, &node.bounds);
+            }
         }
+    }
 
-        scene
+    /// Transforms the whole scene without modifying the nodes (e.g. `UpAxis::rotation`).
+    /// Replaces any previous `recenter`.
+    pub fn set_root_transform(&mut self, root: &mut Root, transform: Matrix4) {
+        self.root_transform = transform;
+        self.origin_offset = Vector3::zero();
+        self.update_transforms(root);
+    }
+
+    /// Moves the center of the bounds to the origin, which avoids float precision
+    /// issues (jitter, z-fighting) for scenes with huge coordinates (e.g. geospatial data).
+    /// The applied translation is kept in `origin_offset`.
+    pub fn recenter(&mut self, root: &mut Root) {
+        let center = self.bounds.center().to_vec();
+        self.root_transform = Matrix4::from_translation(-center) * self.root_transform;
+        self.origin_offset += center;
+        self.update_transforms(root);
+    }
+
+    /// Keeps the document (and its raw JSON) the scene was loaded from
+    pub fn set_document(&mut self, document: gltf::Document, json: Value) {
+        self.document = Some(document);
+        self.json = json;
+    }
+
+    /// The parsed glTF document, e.g. for reading extras or node names.
+    /// `None` if the scene wasn't loaded from a file.
+    #[allow(dead_code)]
+    pub fn document(&self) -> Option<&gltf::Document> {
+        self.document.as_ref()
+    }
+
+    /// Raw JSON of the document, for data not exposed by `gltf::Document`
+    /// (e.g. `json["asset"]["generator"]`, extensions).
+    pub fn json(&self) -> &Value {
+        &self.json
+    }
+
+    /// The node hierarchy as an indented tree, one node per line with its mesh
+    /// (primitive count and materials), camera, light and local TRS
+    pub fn hierarchy(&self, root: &Root) -> String {
+        let mut tree = format!("Scene \"{}\"\n", self.name.as_deref().unwrap_or("unnamed"));
+        let mut stack: Vec<(usize, usize)> = self.nodes.iter().rev().map(|&node_id| (node_id, 1)).collect();
+        while let Some((node_id, depth)) = stack.pop() {
+            let node = &root.nodes[node_id];
+            tree += &"  ".repeat(depth);
+            tree += &format!("{} \"{}\"", node.index, node.name.as_deref().unwrap_or("unnamed"));
+            if let Some(ref mesh) = node.mesh {
+                let materials: Vec<String> = mesh.primitives.iter()
+                    .map(|p| match p.material().name {
+                        Some(ref name) => format!("\"{}\"", name),
+                        None if p.material().index.is_none() => "default".to_owned(),
+                        None => format!("#{}", p.material().index.unwrap()),
+                    })
+                    .collect();
+                tree += &format!(" [mesh {} \"{}\": {} primitive(s), materials {}]",
+                    mesh.index, mesh.name.as_deref().unwrap_or("unnamed"),
+                    mesh.primitives.len(), materials.join(", "));
+            }
+            if let Some(ref camera) = node.camera {
+                tree += &format!(" [camera {}]", camera.description());
+            }
+            if let Some(ref light) = node.light {
+                tree += &format!(" [light {}]", light.description());
+            }
+            if !node.visible {
+                tree += " [hidden]";
+            }
+            let (t, r, s) = (node.translation, node.rotation, node.scale);
+            tree += &format!(" T({}, {}, {}) R({}, {}, {}, {}) S({}, {}, {})\n",
+                t.x, t.y, t.z, r.v.x, r.v.y, r.v.z, r.s, s.x, s.y, s.z);
+            stack.extend(node.children.iter().rev().map(|&child_id| (child_id, depth + 1)));
+        }
+        tree
     }
 
     // TODO: flatten draw call hierarchy (global Vec<Primitive>?)
     pub fn draw(&mut self, root: &mut Root, cam_params: &CameraParams) {
         // TODO!: for correct alpha blending, sort by material alpha mode and
         // render opaque objects first.
-        for node_id in &self.nodes {
-            let node = root.unsafe_get_node_mut(*node_id);
-            node.draw(root, cam_params);
+        let visible_nodes = self.visible_nodes(root);
+        let oit = self.order_independent_transparency;
+        let hi_z = if self.occlusion_culling {
+            let mut hi_z = self.hi_z.take().unwrap_or_else(HiZ::new);
+            unsafe { hi_z.render(self, root, &visible_nodes, cam_params) };
+            Some(hi_z)
+        } else {
+            None
+        };
+        let pyramid = hi_z.as_ref().and_then(|hi_z| hi_z.pyramid.as_ref());
+        let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+        let occluded = Cell::new(0);
+        for node_id in &visible_nodes {
+            let node = &root.nodes[*node_id];
+            // instanced primitives are always drawn, their bounds are only known per instance
+            let not_occluded = |p: &Primitive| match pyramid {
+                Some(pyramid) if node.instances.is_none() &&
+                    pyramid.occludes(&p.bounds.transform(&node.final_transform), &view_projection) =>
+                {
+                    occluded.set(occluded.get() + 1);
+                    false
+                }
+                _ => true,
+            };
+            node.draw_mesh(cam_params, &self.face_culling, &|p| self.shows_primitive(p) &&
+                !p.material().is_transmissive() && !(oit && p.material().is_blended()) && not_occluded(p));
+        }
+        self.occluded_primitives = occluded.get();
+        if hi_z.is_some() {
+            self.hi_z = hi_z;
+        }
+
+        self.draw_transmission(root, cam_params, &visible_nodes);
+
+        // the blended primitives are composited after all others, so they also cover transmissive ones
+        let has_blended = oit && visible_nodes.iter()
+            .filter_map(|node_id| root.nodes[*node_id].mesh.as_ref())
+            .flat_map(|mesh| mesh.primitives.iter())
+            .any(|p| self.shows_primitive(p) && p.material().is_blended());
+        if has_blended {
+            let mut weighted_blended = self.oit.take().unwrap_or_else(WeightedBlendedOit::new);
+            unsafe { weighted_blended.begin() };
+            root.set_weighted_blended(true);
+            for node_id in &visible_nodes {
+                root.nodes[*node_id].draw_mesh(cam_params, &self.face_culling,
+                    &|p| self.shows_primitive(p) && p.material().is_blended());
+            }
+            root.set_weighted_blended(false);
+            unsafe { weighted_blended.end() };
+            self.oit = Some(weighted_blended);
+        }
+    }
+
+    /// KHR_materials_transmission: transmissive primitives sample a copy of the opaque scene
+    fn draw_transmission(&mut self, root: &mut Root, cam_params: &CameraParams, visible_nodes: &[usize]) {
+        let has_transmission = visible_nodes.iter()
+            .filter_map(|node_id| root.nodes[*node_id].mesh.as_ref())
+            .flat_map(|mesh| mesh.primitives.iter())
+            .any(|p| self.shows_primitive(p) && p.material().is_transmissive());
+        if !has_transmission {
+            return
+        }
+        // taken out while drawing, the filters borrow `self`
+        let mut transmission_texture = self.transmission_texture.take().unwrap_or_else(TransmissionTexture::new);
+        unsafe { transmission_texture.copy_framebuffer() };
+
+        // thin-walled surfaces seen from both sides: the far (back) faces first, then the framebuffer
+        // is copied again so that the near faces refract them (only if the culling isn't forced)
+        let shows_back_faces = |p: &Primitive| self.shows_primitive(p) && p.material().has_transmission_back_faces();
+        let has_back_faces = self.face_culling.cull_mode == CullMode::Auto && visible_nodes.iter()
+            .filter_map(|node_id| root.nodes[*node_id].mesh.as_ref())
+            .flat_map(|mesh| mesh.primitives.iter())
+            .any(shows_back_faces);
+        if has_back_faces {
+            let back_faces = FaceCulling { cull_mode: CullMode::Front, ..self.face_culling };
+            for node_id in visible_nodes {
+                root.nodes[*node_id].draw_mesh(cam_params, &back_faces, &shows_back_faces);
+            }
+            unsafe { transmission_texture.copy_framebuffer() };
+
+            let front_faces = FaceCulling { cull_mode: CullMode::Back, ..self.face_culling };
+            for node_id in visible_nodes {
+                root.nodes[*node_id].draw_mesh(cam_params, &front_faces, &shows_back_faces);
+            }
+        }
+        for node_id in visible_nodes {
+            root.nodes[*node_id].draw_mesh(cam_params, &self.face_culling, &|p| self.shows_primitive(p) &&
+                p.material().is_transmissive() && !(has_back_faces && p.material().has_transmission_back_faces()));
+        }
+        self.transmission_texture = Some(transmission_texture);
+    }
+
+    /// X-ray mode: draws all primitives blended without depth test and back face culling,
+    /// so that occluded parts shine through (the opacity is set with `Root::set_xray_opacity`)
+    pub fn draw_xray(&mut self, root: &mut Root, cam_params: &CameraParams) {
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        // forced culling modes are kept
+        let cull_mode = match self.face_culling.cull_mode {
+            CullMode::Auto => CullMode::Off,
+            cull_mode => cull_mode,
+        };
+        let face_culling = FaceCulling { cull_mode, ..self.face_culling };
+        for node_id in self.visible_nodes(root) {
+            root.nodes[node_id].draw_mesh(cam_params, &face_culling, &|p| self.shows_primitive(p));
         }
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    /// Indices of all nodes that are rendered (respecting visibility and isolation), depth-first
+    pub fn visible_nodes(&self, root: &Root) -> Vec<usize> {
+        let mut stack: Vec<usize> = match self.isolated_node {
+            Some(node_id) if !self.isolate_children => return vec![node_id],
+            Some(node_id) => vec![node_id],
+            None => self.nodes.iter().rev().cloned().collect(),
+        };
+        let mut visible = vec![];
+        while let Some(node_id) = stack.pop() {
+            let node = &root.nodes[node_id];
+            if !node.visible {
+                continue
+            }
+            // MSFT_lod: a coarser level replaces the node including its children
+            let node = match node.lod_node() {
+                Some(lod_node_id) => &root.nodes[lod_node_id],
+                None => continue,
+            };
+            visible.push(node.index);
+            stack.extend(node.children.iter().rev());
+        }
+        visible
+    }
+
+    /// Whether any visible node has a mesh (files with only cameras, lights or empty nodes don't)
+    pub fn has_geometry(&self, root: &Root) -> bool {
+        self.visible_nodes(root).iter().any(|&node_id| root.nodes[node_id].mesh.is_some())
+    }
+
+    /// Selects the level of detail of all nodes with MSFT_lod levels (see `Node::select_lod`).
+    /// Returns the number of primitives rendered at a lower level of detail.
+    pub fn update_lods(&mut self, root: &mut Root, cam_params: &CameraParams, threshold: f32) -> usize {
+        let mut lower_lod_primitives = 0;
+        let mut stack = self.nodes.clone();
+        while let Some(node_id) = stack.pop() {
+            let node = &mut root.nodes[node_id];
+            node.select_lod(cam_params, threshold);
+            let lod_node_id = match node.lod_node() {
+                Some(lod_node_id) => lod_node_id,
+                None => continue,
+            };
+            let lod_node = &root.nodes[lod_node_id];
+            if lod_node_id != node_id {
+                lower_lod_primitives += lod_node.mesh.as_ref().map_or(0, |mesh| mesh.primitives.len());
+            }
+            stack.extend(&lod_node.children);
+        }
+        lower_lod_primitives
+    }
+
+    /// Uploads finer mip levels of streamed textures (see `ImportOptions::stream_textures`)
+    /// until about `budget` bytes are uploaded. Textures of visible primitives near
+    /// the camera come first. Returns the number of textures that are still incomplete.
+    pub fn stream_textures(&self, root: &Root, camera_position: Point3, budget: usize) -> usize {
+        let mut textures: Vec<(f32, &Rc<Texture>)> = root.textures.iter()
+            .filter(|texture| texture.is_streaming())
+            .map(|texture| (f32::INFINITY, texture))
+            .collect();
+        if textures.is_empty() {
+            return 0
+        }
+        for node_id in self.visible_nodes(root) {
+            let node = &root.nodes[node_id];
+            let mesh = match node.mesh {
+                Some(ref mesh) => mesh,
+                None => continue,
+            };
+            let bounds = &node.bounds;
+            let radius = (bounds.max - bounds.min).magnitude() / 2.0;
+            let distance = ((bounds.center() - camera_position).magnitude() - radius).max(0.0);
+            for primitive in &mesh.primitives {
+                for texture in primitive.material().textures() {
+                    if let Some(entry) = textures.iter_mut().find(|(_, t)| Rc::ptr_eq(t, texture)) {
+                        entry.0 = entry.0.min(distance);
+                    }
+                }
+            }
+        }
+        textures.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut uploaded = 0;
+        for (_, texture) in &textures {
+            while uploaded < budget && texture.is_streaming() {
+                uploaded += texture.stream_next_level();
+            }
+        }
+        textures.iter().filter(|(_, texture)| texture.is_streaming()).count()
+    }
+
+    /// Names of the material variants (KHR_materials_variants), unnamed ones as their index
+    pub fn variants(&self) -> Vec<String> {
+        let variants = match self.json["extensions"]["KHR_materials_variants"]["variants"].as_array() {
+            Some(variants) => variants,
+            None => return vec![],
+        };
+        variants.iter().enumerate()
+            .map(|(i, variant)| variant["name"].as_str().map_or_else(|| i.to_string(), |name| name.to_owned()))
+            .collect()
+    }
+
+    /// Index of the variant with the given name or index
+    pub fn find_variant(&self, name_or_index: &str) -> Option<usize> {
+        let variants = self.variants();
+        variants.iter().position(|name| name == name_or_index)
+            .or_else(|| name_or_index.parse().ok().filter(|&index| index < variants.len()))
+    }
+
+    /// Switches all primitives to the materials of a variant (see `variants`),
+    /// `None` restores the base materials
+    pub fn set_variant(&mut self, root: &mut Root, variant: Option<usize>) {
+        self.variant = variant;
+        for mesh in root.meshes.clone() {
+            for primitive in &mesh.primitives {
+                primitive.set_variant(variant, root);
+            }
+        }
+    }
+
+    /// Shows or hides a node including its children
+    pub fn set_visible(&mut self, root: &mut Root, node_index: usize, visible: bool) {
+        root.nodes[node_index].visible = visible;
+        self.update_transforms(root);
+    }
+
+    /// Shows or hides the first node with the given name, returns false if there is none
+    pub fn set_node_visible_by_name(&mut self, root: &mut Root, name: &str, visible: bool) -> bool {
+        match root.find_node_by_name(name) {
+            Some(node_index) => {
+                self.set_visible(root, node_index, visible);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Only render the given node (and optionally its children). Pass `None` to render all nodes again.
+    pub fn isolate(&mut self, node_index: Option<usize>, include_children: bool) {
+        self.isolated_node = node_index;
+        self.isolate_children = include_children;
+    }
+
+    /// Whether the primitive is rendered with the current `solo_material` (if any)
+    pub fn shows_primitive(&self, primitive: &Primitive) -> bool {
+        match self.solo_material {
+            Some(ref material) => Rc::ptr_eq(material, &primitive.material()),
+            None => true,
+        }
+    }
+
+    /// Bounds of the rendered part of the scene (respecting isolation)
+    pub fn visible_bounds(&self, root: &Root) -> Aabb3 {
+        match self.isolated_node {
+            Some(node_id) if self.isolate_children => root.nodes[node_id].bounds,
+            Some(node_id) => {
+                let node = &root.nodes[node_id];
+                node.mesh.as_ref()
+                    .map(|mesh| mesh.bounds.transform(&node.final_transform))
+                    .unwrap_or_else(Aabb3::zero)
+            }
+            None => self.bounds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importdata::import_source;
+    use crate::memory_source::MemorySource;
+
+    #[test]
+    fn hierarchy() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "name": "main", "nodes": [0] }],
+            "nodes": [
+                { "name": "root", "children": [1, 2], "translation": [1, 2, 3] },
+                { "name": "cam", "camera": 0, "scale": [2, 2, 2] },
+                { "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+            ],
+            "cameras": [{ "type": "perspective", "perspective": { "yfov": 1, "znear": 0.1, "zfar": 100 } }],
+            "extensions": { "KHR_lights_punctual": { "lights": [{ "type": "point" }] } }
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut root = Root::from_gltf(&imp);
+        let mut scene = Scene::from_gltf(&imp.doc.scenes().next().unwrap(), &mut root);
+        scene.set_document(imp.doc.clone(), imp.json.clone());
+        assert_eq!(scene.hierarchy(&root),
+            "Scene \"main\"\n  \
+             0 \"root\" T(1, 2, 3) R(0, 0, 0, 1) S(1, 1, 1)\n    \
+             1 \"cam\" [camera 0 (None, perspective)] T(0, 0, 0) R(0, 0, 0, 1) S(2, 2, 2)\n    \
+             2 \"unnamed\" [light 0 (None, point)] T(0, 0, 0) R(0, 0, 0, 1) S(1, 1, 1)\n");
+    }
+
+    #[test]
+    fn structure_only() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "children": [1] }, { "camera": 0 }],
+            "cameras": [{ "type": "perspective", "perspective": { "yfov": 1, "znear": 0.1 } }]
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut root = Root::from_gltf(&imp);
+        let scene = Scene::from_gltf(&imp.doc.scenes().next().unwrap(), &mut root);
+        assert_eq!(scene.visible_nodes(&root), [0, 1]);
+        assert!(!scene.has_geometry(&root));
+        assert_eq!(scene.bounds, Aabb3::zero());
+        assert!(!Scene::default().has_geometry(&root));
+    }
+
+    #[test]
+    fn variants() {
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["KHR_materials_variants"],
+            "extensions": { "KHR_materials_variants": { "variants": [{ "name": "red" }, {}] } },
+            "scenes": [{ "nodes": [] }]
+        }"#;
+        let imp = import_source(&MemorySource::new(gltf.as_bytes().to_vec())).unwrap();
+        let mut scene = Scene::default();
+        scene.set_document(imp.doc.clone(), imp.json.clone());
+        assert_eq!(scene.variants(), ["red", "1"]);
+        assert_eq!(scene.find_variant("red"), Some(0));
+        assert_eq!(scene.find_variant("1"), Some(1));
+        assert_eq!(scene.find_variant("2"), None);
     }
 }
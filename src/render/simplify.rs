@@ -0,0 +1,254 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::render::math::*;
+use crate::render::{position_key, Vertex};
+
+/// Weight of the planes that keep open borders in place, relative to the face planes
+const BORDER_WEIGHT: f64 = 10.0;
+
+/// Decimates a triangle list to about `target_triangles` by repeatedly collapsing the edge
+/// with the smallest quadric error (Garland & Heckbert) into one of its endpoints.
+/// Vertices only merge and never move, so the result indexes the original `vertices`
+/// (i.e. the vertex buffer can be shared with the full-resolution mesh). Vertices with the
+/// same position (split at UV seams) collapse together. Collapses that would flip a
+/// triangle are skipped, so the target isn't always reached.
+pub fn simplify_triangles(vertices: &[Vertex], indices: &[u32], target_triangles: usize) -> Vec<u32> {
+    if indices.len() / 3 <= target_triangles {
+        return indices.to_vec()
+    }
+
+    // the collapses work on welded positions, `first_vertex` maps them back to a vertex
+    let mut positions: Vec<Vector3> = vec![];
+    let mut first_vertex: Vec<u32> = vec![];
+    let mut position_indices: HashMap<[u32; 3], usize> = HashMap::new();
+    let welded: Vec<usize> = vertices.iter().enumerate()
+        .map(|(i, vertex)| *position_indices.entry(position_key(&vertex.position)).or_insert_with(|| {
+            positions.push(vertex.position);
+            first_vertex.push(i as u32);
+            positions.len() - 1
+        }))
+        .collect();
+
+    // original corners and current welded corners per triangle
+    let triangles: Vec<[u32; 3]> = indices.chunks(3)
+        .filter(|t| t.len() == 3)
+        .map(|t| [t[0], t[1], t[2]])
+        .filter(|t| {
+            let w = |i: usize| welded[t[i] as usize];
+            w(0) != w(1) && w(1) != w(2) && w(2) != w(0)
+        })
+        .collect();
+    let mut corners: Vec<[usize; 3]> = triangles.iter()
+        .map(|t| [welded[t[0] as usize], welded[t[1] as usize], welded[t[2] as usize]])
+        .collect();
+    let mut alive = vec![true; triangles.len()];
+    let mut triangle_count = triangles.len();
+
+    let mut position_triangles: Vec<Vec<usize>> = vec![vec![]; positions.len()];
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    // welded edge -> number of adjacent triangles and the last of them
+    let mut edges: HashMap<(usize, usize), (u32, usize)> = HashMap::new();
+    for (t, c) in corners.iter().enumerate() {
+        let normal = face_normal(&positions, c);
+        let area = normal.magnitude() as f64 / 2.0;
+        let plane = if area > 0.0 { Some(Quadric::from_plane(normal.normalize(), positions[c[0]], area)) } else { None };
+        for i in 0..3 {
+            position_triangles[c[i]].push(t);
+            if let Some(plane) = plane {
+                quadrics[c[i]].add(&plane);
+            }
+            let (a, b) = (c[i], c[(i + 1) % 3]);
+            let edge = edges.entry((a.min(b), a.max(b))).or_insert((0, t));
+            *edge = (edge.0 + 1, t);
+        }
+    }
+    // planes through border edges, perpendicular to their triangle
+    for (&(a, b), &(count, t)) in &edges {
+        let normal = face_normal(&positions, &corners[t]);
+        let edge = positions[b] - positions[a];
+        let border_normal = edge.cross(normal);
+        if count == 1 && border_normal.magnitude2() > 0.0 {
+            let weight = BORDER_WEIGHT * edge.magnitude2() as f64;
+            let plane = Quadric::from_plane(border_normal.normalize(), positions[a], weight);
+            quadrics[a].add(&plane);
+            quadrics[b].add(&plane);
+        }
+    }
+
+    let mut versions = vec![0u32; positions.len()];
+    let mut heap = BinaryHeap::new();
+    let best_collapse = |a: usize, b: usize, quadrics: &[Quadric], versions: &[u32]| {
+        let mut quadric = quadrics[a];
+        quadric.add(&quadrics[b]);
+        let (error_a, error_b) = (quadric.error(positions[a]), quadric.error(positions[b]));
+        let (from, to, error) = if error_a <= error_b { (b, a, error_a) } else { (a, b, error_b) };
+        Collapse { error, from, to, versions: (versions[from], versions[to]) }
+    };
+    for &(a, b) in edges.keys() {
+        heap.push(best_collapse(a, b, &quadrics, &versions));
+    }
+
+    while triangle_count > target_triangles {
+        let Collapse { from, to, versions: (from_version, to_version), .. } = match heap.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        // outdated by an earlier collapse (removed vertices have an odd version)
+        if versions[from] != from_version || versions[to] != to_version || from_version % 2 == 1 {
+            continue
+        }
+        let flips = position_triangles[from].iter()
+            .filter(|&&t| alive[t] && !corners[t].contains(&to))
+            .any(|&t| {
+                let mut moved = corners[t];
+                for corner in moved.iter_mut().filter(|corner| **corner == from) {
+                    *corner = to;
+                }
+                face_normal(&positions, &moved).dot(face_normal(&positions, &corners[t])) <= 0.0
+            });
+        if flips {
+            continue
+        }
+
+        for t in std::mem::take(&mut position_triangles[from]) {
+            if !alive[t] {
+                continue
+            }
+            if corners[t].contains(&to) {
+                alive[t] = false;
+                triangle_count -= 1;
+            } else {
+                for corner in corners[t].iter_mut().filter(|corner| **corner == from) {
+                    *corner = to;
+                }
+                position_triangles[to].push(t);
+            }
+        }
+        let from_quadric = quadrics[from];
+        quadrics[to].add(&from_quadric);
+        versions[from] += 1;
+        versions[to] += 2;
+
+        position_triangles[to].retain(|&t| alive[t]);
+        let mut neighbours: Vec<usize> = position_triangles[to].iter()
+            .flat_map(|&t| corners[t].to_vec())
+            .filter(|&p| p != to)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for neighbour in neighbours {
+            heap.push(best_collapse(to, neighbour, &quadrics, &versions));
+        }
+    }
+
+    // corners that weren't collapsed keep their vertex (e.g. their side of a UV seam)
+    triangles.iter().zip(&corners).zip(&alive)
+        .filter(|(_, &alive)| alive)
+        .flat_map(|((t, c), _)| (0..3).map(move |i| (t[i], c[i])))
+        .map(|(index, position)| if welded[index as usize] == position { index } else { first_vertex[position] })
+        .collect()
+}
+
+/// Unnormalized (area-weighted) face normal
+fn face_normal(positions: &[Vector3], corners: &[usize; 3]) -> Vector3 {
+    let (p0, p1, p2) = (positions[corners[0]], positions[corners[1]], positions[corners[2]]);
+    (p1 - p0).cross(p2 - p0)
+}
+
+/// Sum of squared distances to a set of planes, as a symmetric 4x4 matrix (upper triangle)
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// Plane through `point` with the unit `normal`, scaled by `weight`
+    fn from_plane(normal: Vector3, point: Vector3, weight: f64) -> Quadric {
+        let (a, b, c) = (f64::from(normal.x), f64::from(normal.y), f64::from(normal.z));
+        let d = -f64::from(normal.dot(point));
+        let mut q = [a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d];
+        for value in q.iter_mut() {
+            *value *= weight;
+        }
+        Quadric(q)
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (value, other) in self.0.iter_mut().zip(&other.0) {
+            *value += other;
+        }
+    }
+
+    fn error(&self, p: Vector3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (f64::from(p.x), f64::from(p.y), f64::from(p.z));
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// Candidate collapse of the edge `from` - `to` into `to`, ordered by smallest error first
+/// (for the max-heap). `versions` of both positions when it was computed.
+struct Collapse {
+    error: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.error.partial_cmp(&self.error).unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `n` x `n` quads in the unit square
+    fn grid(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = (0..=n).flat_map(|y| (0..=n).map(move |x| (x, y)))
+            .map(|(x, y)| Vertex { position: vec3(x as f32, y as f32, 0.0) / n as f32, ..Vertex::default() })
+            .collect();
+        let index = |x: u32, y: u32| y * (n + 1) + x;
+        let indices = (0..n).flat_map(|y| (0..n).map(move |x| (x, y)))
+            .flat_map(|(x, y)| vec![index(x, y), index(x + 1, y), index(x + 1, y + 1),
+                index(x, y), index(x + 1, y + 1), index(x, y + 1)])
+            .collect();
+        (vertices, indices)
+    }
+
+    #[test]
+    fn planar_grid() {
+        let (vertices, indices) = grid(10);
+        let simplified = simplify_triangles(&vertices, &indices, 20);
+        let triangles = simplified.len() / 3;
+        assert!(triangles > 0 && triangles <= 20, "{} triangles", triangles);
+        // the flat square with its straight borders survives without flipped or missing area
+        let area: f32 = simplified.chunks(3)
+            .map(|t| {
+                let p = |i: usize| vertices[t[i] as usize].position;
+                (p(1) - p(0)).cross(p(2) - p(0)).z / 2.0
+            })
+            .sum();
+        assert!((area - 1.0).abs() < 1e-5, "area {}", area);
+
+        assert_eq!(simplify_triangles(&vertices, &indices, 200), indices);
+    }
+}
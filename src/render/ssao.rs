@@ -0,0 +1,262 @@
+use std::ffi::CString;
+use std::ptr;
+
+use crate::controls::CameraParams;
+use crate::render::{front_face, Root, Scene};
+use crate::render::math::*;
+use crate::shader::{Shader, SSAO_TEXTURE_UNIT};
+
+const KERNEL_SIZE: usize = 16; // must match the SSAO shader
+
+#[derive(Clone, Copy, Debug)]
+pub struct SsaoOptions {
+    pub enabled: bool,
+    /// Sampling radius relative to the size (bounding box diagonal) of the scene
+    pub radius: f32,
+    /// Depth bias relative to the radius (avoids self-occlusion "acne")
+    pub bias: f32,
+    /// How much the ambient light is darkened (0..1)
+    pub strength: f32,
+}
+
+impl Default for SsaoOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.02,
+            bias: 0.05,
+            strength: 1.0,
+        }
+    }
+}
+
+/// Screen-space ambient occlusion: renders the depth of the scene in a pre-pass
+/// and computes the (blurred) occlusion from it, which the PBR shader then
+/// samples to modulate the ambient light.
+pub struct Ssao {
+    width: u32,
+    height: u32,
+
+    depth_framebuffer: u32,
+    depth_texture: u32,
+    ao_framebuffer: u32,
+    ao_texture: u32,
+    blur_framebuffer: u32,
+    blur_texture: u32,
+
+    empty_vao: u32,
+
+    depth_shader: Shader,
+    u_mvp_matrix: i32,
+    ssao_shader: Shader,
+    blur_shader: Shader,
+}
+
+impl Ssao {
+    pub fn new(width: u32, height: u32) -> Ssao {
+        let fullscreen_vertex_shader = include_str!("../shaders/fullscreen-vert.glsl");
+        let mut depth_shader = Shader::from_source(
+            include_str!("../shaders/picking-vert.glsl"),
+            include_str!("../shaders/depth-frag.glsl"),
+            &[]);
+        let mut ssao = Ssao {
+            width, height,
+            depth_framebuffer: 0,
+            depth_texture: 0,
+            ao_framebuffer: 0,
+            ao_texture: 0,
+            blur_framebuffer: 0,
+            blur_texture: 0,
+            empty_vao: 0,
+            u_mvp_matrix: unsafe { depth_shader.uniform_location("u_MVPMatrix") },
+            depth_shader,
+            ssao_shader: Shader::from_source(fullscreen_vertex_shader,
+                include_str!("../shaders/ssao-frag.glsl"), &[]),
+            blur_shader: Shader::from_source(fullscreen_vertex_shader,
+                include_str!("../shaders/ssao-blur-frag.glsl"), &[]),
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut ssao.empty_vao);
+            ssao.create_framebuffers();
+
+            ssao.ssao_shader.use_program();
+            let u_depth = ssao.ssao_shader.uniform_location("u_Depth");
+            ssao.ssao_shader.set_int(u_depth, 0);
+            let kernel = ssao_kernel();
+            let name = CString::new("u_Kernel").unwrap();
+            let u_kernel = gl::GetUniformLocation(ssao.ssao_shader.id, name.as_ptr());
+            gl::Uniform3fv(u_kernel, KERNEL_SIZE as i32, kernel.as_ptr() as *const f32);
+
+            ssao.blur_shader.use_program();
+            let u_texture = ssao.blur_shader.uniform_location("u_Texture");
+            ssao.blur_shader.set_int(u_texture, 0);
+        }
+        ssao
+    }
+
+    unsafe fn create_framebuffers(&mut self) {
+        gl::GenFramebuffers(1, &mut self.depth_framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.depth_framebuffer);
+        gl::GenTextures(1, &mut self.depth_texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::DEPTH_COMPONENT24 as i32, self.width as i32, self.height as i32,
+            0, gl::DEPTH_COMPONENT, gl::FLOAT, ptr::null());
+        set_texture_params(gl::NEAREST);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, self.depth_texture, 0);
+        gl::DrawBuffer(gl::NONE);
+        gl::ReadBuffer(gl::NONE);
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: SSAO depth framebuffer is not complete!");
+        }
+
+        let (ao_framebuffer, ao_texture) = create_ao_framebuffer(self.width, self.height);
+        self.ao_framebuffer = ao_framebuffer;
+        self.ao_texture = ao_texture;
+        let (blur_framebuffer, blur_texture) = create_ao_framebuffer(self.width, self.height);
+        self.blur_framebuffer = blur_framebuffer;
+        self.blur_texture = blur_texture;
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_framebuffers(&mut self) {
+        gl::DeleteFramebuffers(1, &self.depth_framebuffer);
+        gl::DeleteTextures(1, &self.depth_texture);
+        gl::DeleteFramebuffers(1, &self.ao_framebuffer);
+        gl::DeleteTextures(1, &self.ao_texture);
+        gl::DeleteFramebuffers(1, &self.blur_framebuffer);
+        gl::DeleteTextures(1, &self.blur_texture);
+    }
+
+    /// Recreates the framebuffers if the size changed
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return
+        }
+        self.width = width;
+        self.height = height;
+        unsafe {
+            self.delete_framebuffers();
+            self.create_framebuffers();
+        }
+    }
+
+    /// Computes the ambient occlusion of the visible scene and binds it to `SSAO_TEXTURE_UNIT`.
+    /// Restores the previously bound framebuffer.
+    pub unsafe fn render(&mut self, scene: &Scene, root: &Root, cam_params: &CameraParams, options: &SsaoOptions) {
+        let mut previous_framebuffer = 0;
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+
+        // depth pre-pass
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.depth_framebuffer);
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+        gl::Disable(gl::BLEND);
+        self.depth_shader.use_program();
+        let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+        for node_id in scene.visible_nodes(root) {
+            let node = &root.nodes[node_id];
+            let mesh = match node.mesh {
+                Some(ref mesh) => mesh,
+                None => continue,
+            };
+            self.depth_shader.set_mat4(self.u_mvp_matrix, &(view_projection * node.final_transform));
+            gl::FrontFace(front_face(&node.final_transform));
+            for primitive in mesh.primitives.iter().filter(|p| scene.shows_primitive(p)) {
+                if primitive.material().double_sided {
+                    gl::Disable(gl::CULL_FACE);
+                } else {
+                    gl::Enable(gl::CULL_FACE);
+                }
+                primitive.draw_geometry();
+            }
+        }
+
+        gl::Disable(gl::DEPTH_TEST);
+        gl::BindVertexArray(self.empty_vao);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        // occlusion
+        let scene_size = (scene.bounds.max - scene.bounds.min).magnitude();
+        let radius = options.radius * scene_size;
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.ao_framebuffer);
+        self.ssao_shader.use_program();
+        let projection = cam_params.projection_matrix;
+        let inverse_projection = projection.invert().unwrap_or_else(Matrix4::identity);
+        let uniforms = [
+            self.ssao_shader.uniform_location("u_Projection"),
+            self.ssao_shader.uniform_location("u_InverseProjection"),
+            self.ssao_shader.uniform_location("u_Radius"),
+            self.ssao_shader.uniform_location("u_Bias"),
+        ];
+        self.ssao_shader.set_mat4(uniforms[0], &projection);
+        self.ssao_shader.set_mat4(uniforms[1], &inverse_projection);
+        self.ssao_shader.set_float(uniforms[2], radius);
+        self.ssao_shader.set_float(uniforms[3], options.bias * radius);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        // blur
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.blur_framebuffer);
+        self.blur_shader.use_program();
+        gl::BindTexture(gl::TEXTURE_2D, self.ao_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer as u32);
+
+        gl::ActiveTexture(gl::TEXTURE0 + SSAO_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_2D, self.blur_texture);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+impl Drop for Ssao {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_framebuffers();
+            gl::DeleteVertexArrays(1, &self.empty_vao);
+        }
+    }
+}
+
+unsafe fn set_texture_params(filter: u32) {
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+}
+
+/// Creates a framebuffer with a single-channel color texture
+unsafe fn create_ao_framebuffer(width: u32, height: u32) -> (u32, u32) {
+    let (mut framebuffer, mut texture) = (0, 0);
+    gl::GenFramebuffers(1, &mut framebuffer);
+    gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, width as i32, height as i32,
+        0, gl::RED, gl::UNSIGNED_BYTE, ptr::null());
+    set_texture_params(gl::LINEAR);
+    gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+    if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+        panic!("ERROR::FRAMEBUFFER:: SSAO framebuffer is not complete!");
+    }
+    (framebuffer, texture)
+}
+
+/// Sample offsets in the unit hemisphere around +z, denser towards the center
+/// (deterministic golden angle spiral instead of random samples)
+fn ssao_kernel() -> Vec<Vector3> {
+    const GOLDEN_ANGLE: f32 = 2.399_963;
+    (0..KERNEL_SIZE)
+        .map(|i| {
+            let t = (i as f32 + 0.5) / KERNEL_SIZE as f32;
+            let z = (1.0 - t).sqrt();
+            let r = (1.0 - z * z).sqrt();
+            let phi = i as f32 * GOLDEN_ANGLE;
+            let scale = 0.1 + 0.9 * t * t;
+            Vector3::new(r * phi.cos(), r * phi.sin(), z) * scale
+        })
+        .collect()
+}
@@ -0,0 +1,157 @@
+use std::ptr;
+
+use crate::shader::Shader;
+
+/// Renders into a framebuffer at a multiple of the target resolution and downsamples
+/// the result into the target framebuffer (supersampling anti-aliasing).
+///
+/// Usage: `begin()`, draw the scene (at `size()`), `end()`.
+pub struct Supersampling {
+    /// Of the target framebuffer
+    target_width: u32,
+    target_height: u32,
+    scale: f32,
+
+    framebuffer: u32,
+    color_texture: u32,
+    depth_renderbuffer: u32,
+    // framebuffer that was bound in `begin()`
+    target_framebuffer: u32,
+
+    empty_vao: u32,
+    downsample_shader: Shader,
+}
+
+impl Supersampling {
+    pub fn new(target_width: u32, target_height: u32, scale: f32) -> Supersampling {
+        let mut supersampling = Supersampling {
+            target_width, target_height, scale,
+            framebuffer: 0,
+            color_texture: 0,
+            depth_renderbuffer: 0,
+            target_framebuffer: 0,
+            empty_vao: 0,
+            downsample_shader: Shader::from_source(include_str!("../shaders/fullscreen-vert.glsl"),
+                include_str!("../shaders/downsample-frag.glsl"), &[]),
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut supersampling.empty_vao);
+            supersampling.create_framebuffer();
+
+            supersampling.downsample_shader.use_program();
+            let u_texture = supersampling.downsample_shader.uniform_location("u_Texture");
+            supersampling.downsample_shader.set_int(u_texture, 0);
+        }
+        supersampling
+    }
+
+    /// Size of the supersampled framebuffer (`scale` times the target size)
+    pub fn size(&self) -> (u32, u32) {
+        scaled_size(self.target_width, self.target_height, self.scale)
+    }
+
+    unsafe fn create_framebuffer(&mut self) {
+        let (width, height) = self.size();
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        gl::GenTextures(1, &mut self.color_texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.color_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, width as i32, height as i32,
+            0, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.color_texture, 0);
+
+        gl::GenRenderbuffers(1, &mut self.depth_renderbuffer);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_renderbuffer);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, self.depth_renderbuffer);
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("ERROR::FRAMEBUFFER:: Supersampling framebuffer ({}x{}) is not complete!", width, height);
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete_framebuffer(&mut self) {
+        gl::DeleteFramebuffers(1, &self.framebuffer);
+        gl::DeleteTextures(1, &self.color_texture);
+        gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+    }
+
+    /// Recreates the framebuffer if the target size or the scale changed
+    pub fn resize(&mut self, target_width: u32, target_height: u32, scale: f32) {
+        if (target_width, target_height, scale) == (self.target_width, self.target_height, self.scale) {
+            return
+        }
+        self.target_width = target_width;
+        self.target_height = target_height;
+        self.scale = scale;
+        unsafe {
+            self.delete_framebuffer();
+            self.create_framebuffer();
+        }
+    }
+
+    /// Redirects rendering into the supersampled framebuffer
+    pub unsafe fn begin(&mut self) {
+        let mut target = 0;
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut target);
+        self.target_framebuffer = target as u32;
+
+        let (width, height) = self.size();
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl::Viewport(0, 0, width as i32, height as i32);
+    }
+
+    /// Downsamples the result into the framebuffer that was bound when calling `begin()`
+    pub unsafe fn end(&mut self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.target_framebuffer);
+        gl::Viewport(0, 0, self.target_width as i32, self.target_height as i32);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        gl::BindVertexArray(self.empty_vao);
+
+        self.downsample_shader.use_program();
+        let u_target_size = self.downsample_shader.uniform_location("u_TargetSize");
+        self.downsample_shader.set_vec2(u_target_size, self.target_width as f32, self.target_height as f32);
+        let u_samples = self.downsample_shader.uniform_location("u_Samples");
+        self.downsample_shader.set_int(u_samples, self.scale.ceil() as i32);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.color_texture);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::BindVertexArray(0);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+impl Drop for Supersampling {
+    fn drop(&mut self) {
+        unsafe {
+            self.delete_framebuffer();
+            gl::DeleteVertexArrays(1, &self.empty_vao);
+        }
+    }
+}
+
+/// `width` x `height` multiplied by `scale`, at least 1x1
+pub fn scaled_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let scale = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+    (scale(width), scale(height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_sizes() {
+        assert_eq!(scaled_size(800, 600, 2.0), (1600, 1200));
+        assert_eq!(scaled_size(801, 601, 1.5), (1202, 902));
+        assert_eq!(scaled_size(1, 1, 0.25), (1, 1));
+    }
+}
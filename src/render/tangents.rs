@@ -0,0 +1,89 @@
+use crate::render::math::*;
+use crate::render::Vertex;
+
+/// Generates tangents for a triangle list without TANGENT attribute (requires normals).
+/// The tangent follows the texture coordinates of the set `tex_coord` (0 or 1), averaged over
+/// the adjacent triangles like the per-pixel fallback of the fragment shader (not MikkTSpace).
+/// The handedness follows the glTF convention `bitangent = cross(normal, tangent.xyz) * tangent.w`:
+/// `w` is -1 where the texture coordinates are mirrored, so that the bitangent points along +v.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: Option<&[u32]>, tex_coord: u32) {
+    let sequential_indices: Vec<u32>;
+    let indices = match indices {
+        Some(indices) => indices,
+        None => {
+            sequential_indices = (0..vertices.len() as u32).collect();
+            &sequential_indices
+        }
+    };
+    let uv = |vertex: &Vertex| if tex_coord == 0 { vertex.tex_coord_0 } else { vertex.tex_coord_1 };
+
+    // derivatives of the position along u and v, weighted by the triangle size
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+    for t in indices.chunks(3).filter(|t| t.len() == 3) {
+        let (v0, v1, v2) = (&vertices[t[0] as usize], &vertices[t[1] as usize], &vertices[t[2] as usize]);
+        let (e1, e2) = (v1.position - v0.position, v2.position - v0.position);
+        let (d1, d2) = (uv(v1) - uv(v0), uv(v2) - uv(v0));
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det == 0.0 || !det.is_finite() {
+            continue
+        }
+        let tangent = (e1 * d2.y - e2 * d1.y) * det.signum();
+        let bitangent = (e2 * d1.x - e1 * d2.x) * det.signum();
+        for &index in t {
+            tangents[index as usize] += tangent;
+            bitangents[index as usize] += bitangent;
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = vertex.normal;
+        let mut tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() == 0.0 {
+            // no usable texture coordinates: any direction perpendicular to the normal
+            let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+            tangent = axis - normal * normal.dot(axis);
+        }
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+        vertex.tangent = tangent.normalize().extend(handedness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_tex_coords() {
+        // two quads next to each other (as separate triangles), the right one with mirrored UVs
+        let quad = |x: f32, mirrored: bool| {
+            let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+            corners.iter()
+                .map(|&(u, v)| Vertex {
+                    position: vec3(x + u, v, 0.0),
+                    normal: vec3(0.0, 0.0, 1.0),
+                    tex_coord_1: Vector2::new(if mirrored { 1.0 - u } else { u }, v),
+                    ..Vertex::default()
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut vertices = quad(0.0, false);
+        vertices.extend(quad(1.0, true));
+        generate_tangents(&mut vertices, None, 1);
+
+        for (i, vertex) in vertices.iter().enumerate() {
+            let expected = if i < 6 { vec4(1.0, 0.0, 0.0, 1.0) } else { vec4(-1.0, 0.0, 0.0, -1.0) };
+            assert_eq!(vertex.tangent, expected);
+            // the bitangent points along +v in both halves
+            let bitangent = vertex.normal.cross(vertex.tangent.truncate()) * vertex.tangent.w;
+            assert_eq!(bitangent, vec3(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn without_tex_coords() {
+        let mut vertices = vec![Vertex { normal: vec3(1.0, 0.0, 0.0), ..Vertex::default() }; 3];
+        generate_tangents(&mut vertices, None, 0);
+        assert!(vertices.iter().all(|v| v.tangent == vec4(0.0, 1.0, 0.0, 1.0)));
+    }
+}
@@ -0,0 +1,173 @@
+use std::mem::{size_of, size_of_val};
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::render::math::*;
+use crate::shader::Shader;
+
+/// Printable ASCII (32-126) in a grid of `ATLAS_COLUMNS` x `ATLAS_ROWS` cells of
+/// `GLYPH_WIDTH` x `GLYPH_HEIGHT` pixels (coverage as grayscale), rasterized from DejaVu Sans Mono
+const FONT_ATLAS: &[u8] = include_bytes!("../data/font.png");
+const ATLAS_COLUMNS: usize = 16;
+const ATLAS_ROWS: usize = 6;
+const GLYPH_WIDTH: f32 = 16.0;
+const GLYPH_HEIGHT: f32 = 32.0;
+const FIRST_CHAR: char = ' ';
+const LAST_CHAR: char = '~';
+
+/// Draws monospaced text on top of the rendered image (e.g. for HUDs and debug labels),
+/// independent of the model shaders
+pub struct TextRenderer {
+    vao: u32,
+    vbo: u32,
+    texture: u32,
+
+    shader: Shader,
+    u_color: i32,
+}
+
+impl TextRenderer {
+    pub fn new() -> TextRenderer {
+        let mut shader = Shader::from_source(
+            include_str!("../shaders/text-vert.glsl"),
+            include_str!("../shaders/text-frag.glsl"),
+            &[]);
+        let (u_color, u_font_sampler) = unsafe {
+            (shader.uniform_location("u_Color"), shader.uniform_location("u_FontSampler"))
+        };
+        let atlas = image::load_from_memory(FONT_ATLAS).unwrap().to_luma();
+        let (mut vao, mut vbo, mut texture) = (0, 0, 0);
+        unsafe {
+            shader.use_program();
+            shader.set_int(u_font_sampler, 0);
+
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RED as i32, atlas.width() as i32, atlas.height() as i32,
+                0, gl::RED, gl::UNSIGNED_BYTE, atlas.into_raw().as_ptr() as *const c_void);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            let stride = size_of::<[f32; 4]>() as i32;
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, size_of::<[f32; 2]>() as *const c_void);
+            gl::BindVertexArray(0);
+        }
+        TextRenderer { vao, vbo, texture, shader, u_color }
+    }
+
+    /// Draws `text` with its top left corner at `x`, `y` (window pixels from the top left).
+    /// `size` is the line height in pixels, `\n` starts a new line and characters
+    /// outside of printable ASCII are shown as `?`.
+    pub unsafe fn draw_text(&mut self, x: f32, y: f32, text: &str, size: f32, color: &Vector4) {
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let vertices = text_vertices(x, y, text, size, (viewport[2] as f32, viewport[3] as f32));
+        if vertices.is_empty() {
+            return
+        }
+
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        gl::BufferData(gl::ARRAY_BUFFER, size_of_val(&vertices[..]) as isize,
+            vertices.as_ptr() as *const c_void, gl::STREAM_DRAW);
+
+        self.shader.use_program();
+        self.shader.set_vector4(self.u_color, color);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture);
+
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::CULL_FACE);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::DrawArrays(gl::TRIANGLES, 0, vertices.len() as i32);
+        gl::Disable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+        gl::BindVertexArray(0);
+    }
+
+    /// Width of a character in pixels for the given line height (the font is monospaced)
+    pub fn char_width(size: f32) -> f32 {
+        size * GLYPH_WIDTH / GLYPH_HEIGHT
+    }
+}
+
+impl Drop for TextRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+/// Two triangles per visible character as `[x, y, u, v]` (position in normalized device coordinates)
+fn text_vertices(x: f32, y: f32, text: &str, size: f32, viewport_size: (f32, f32)) -> Vec<[f32; 4]> {
+    let (width, height) = viewport_size;
+    let char_width = TextRenderer::char_width(size);
+    let mut vertices = Vec::with_capacity(text.len() * 6);
+    for (line_index, line) in text.lines().enumerate() {
+        let top = y + line_index as f32 * size;
+        for (column, c) in line.chars().enumerate() {
+            if c == ' ' {
+                continue
+            }
+            let c = if c < FIRST_CHAR || c > LAST_CHAR { '?' } else { c };
+            let index = c as usize - FIRST_CHAR as usize;
+            let u0 = (index % ATLAS_COLUMNS) as f32 / ATLAS_COLUMNS as f32;
+            let v0 = (index / ATLAS_COLUMNS) as f32 / ATLAS_ROWS as f32;
+            let (u1, v1) = (u0 + 1.0 / ATLAS_COLUMNS as f32, v0 + 1.0 / ATLAS_ROWS as f32);
+
+            let left = x + column as f32 * char_width;
+            let (x0, x1) = (left / width * 2.0 - 1.0, (left + char_width) / width * 2.0 - 1.0);
+            let (y0, y1) = (1.0 - top / height * 2.0, 1.0 - (top + size) / height * 2.0);
+            vertices.extend_from_slice(&[
+                [x0, y0, u0, v0], [x0, y1, u0, v1], [x1, y1, u1, v1],
+                [x0, y0, u0, v0], [x1, y1, u1, v1], [x1, y0, u1, v0],
+            ]);
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn atlas_layout() {
+        let atlas = image::load_from_memory(FONT_ATLAS).unwrap();
+        assert_eq!(atlas.dimensions(), (ATLAS_COLUMNS as u32 * 16, ATLAS_ROWS as u32 * 32));
+        assert!(ATLAS_COLUMNS * ATLAS_ROWS > LAST_CHAR as usize - FIRST_CHAR as usize);
+    }
+
+    #[test]
+    fn vertices() {
+        let vertices = text_vertices(0.0, 0.0, "A b\n\u{e9}", 32.0, (64.0, 64.0));
+        assert_eq!(vertices.len(), 3 * 6); // the space is skipped
+        // 'A' covers the top left quarter of the viewport and the second cell of the third atlas row
+        assert_eq!(vertices[0], [-1.0, 1.0, 1.0 / 16.0, 2.0 / 6.0]);
+        assert_eq!(vertices[2], [-0.5, 0.0, 2.0 / 16.0, 3.0 / 6.0]);
+        // 'b' in the third column
+        assert_eq!(vertices[6][0], 0.0);
+        // '?' instead of 'é' on the second line
+        let question_mark = '?' as usize - 32;
+        assert_eq!(vertices[12], [-1.0, 0.0, (question_mark % 16) as f32 / 16.0, (question_mark / 16) as f32 / 6.0]);
+    }
+}
@@ -1,32 +1,100 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::os::raw::c_void;
-use std::path::Path;
-use std::{fs, io};
+use std::ptr;
+use std::rc::Rc;
 
-use base64;
 use gl;
 use gltf;
 use gltf::json::texture::MinFilter;
-use gltf::image::Source;
+use gltf::image::Format;
 
-use image;
-use image::ImageFormat::{JPEG, PNG};
+use image::{DynamicImage, GenericImageView, ImageBuffer};
 use image::DynamicImage::*;
-use image::GenericImageView;
 use image::FilterType;
+use log::info;
 
 use crate::importdata::ImportData;
 
 pub struct Texture {
     pub index: usize, // glTF index
     pub name: Option<String>,
+    /// glTF indices of further textures with the same image and sampler, which share this one
+    pub shared_indices: RefCell<Vec<usize>>,
+    pub image: usize, // glTF index
+    pub sampler: Option<usize>, // glTF index, `None`: default sampler
 
     pub id: u32, // OpenGL id
+    /// Shared with the textures for other sets, see `with_tex_coord`
+    object: Rc<TextureObject>,
     pub tex_coord: u32, // the tex coord set to use
+
+    /// Estimated GPU memory in bytes (including mip maps)
+    pub memory_size: usize,
+
+    /// Finer mip levels that still have to be uploaded when streaming
+    pending_mips: RefCell<Option<PendingMips>>,
+
+    /// Overrides the color space expected by the texture slots it is used in
+    /// (e.g. for normal maps that were saved as sRGB), `None`: derived from the usage
+    pub color_space: Cell<Option<ColorSpace>>,
+}
+
+/// OpenGL texture, deleted when the last `Texture` using it is dropped
+struct TextureObject(u32);
+
+impl Drop for TextureObject {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.0) }
+    }
+}
+
+/// Encoding of the color values of a texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn other(self) -> ColorSpace {
+        match self {
+            ColorSpace::Srgb => ColorSpace::Linear,
+            ColorSpace::Linear => ColorSpace::Srgb,
+        }
+    }
+}
+
+/// Largest mip level (width or height) that is uploaded right away when streaming textures
+const STREAMING_INITIAL_SIZE: u32 = 64;
+
+struct PendingMips {
+    /// Level 0
+    image: DynamicImage,
+    format: gl::types::GLenum,
+    /// Finest level uploaded so far (`TEXTURE_BASE_LEVEL`)
+    base_level: u32,
 }
 
 impl Texture {
-    pub fn from_gltf(g_texture: &gltf::Texture<'_>, tex_coord: u32, imp: &ImportData, base_path: &Path) -> Texture {
-        let buffers = &imp.buffers;
+    /// Uploads the image of `g_texture`, fails if its pixel data is missing or doesn't match its size
+    pub fn from_gltf(g_texture: &gltf::Texture<'_>, tex_coord: u32, imp: &ImportData) -> Result<Texture, String> {
+        // textures with the same image and sampler are shared by `Material::from_gltf`
+        let g_img = g_texture.source();
+        let img = imp.images.get(g_img.index())
+            .ok_or_else(|| format!("image {} wasn't loaded", g_img.index()))?;
+        let (format, channels) = match img.format {
+            Format::R8 => (gl::RED, 1),
+            Format::R8G8 => (gl::RG, 2),
+            Format::R8G8B8 => (gl::RGB, 3),
+            Format::R8G8B8A8 => (gl::RGBA, 4),
+        };
+        let expected_size = img.width as usize * img.height as usize * channels;
+        if expected_size == 0 || img.pixels.len() != expected_size {
+            return Err(format!("image {} has {} bytes, which doesn't match its size ({}x{}, {} channels)",
+                g_img.index(), img.pixels.len(), img.width, img.height, channels))
+        }
+
         let mut texture_id = 0;
         unsafe {
             gl::GenTextures(1, &mut texture_id);
@@ -35,74 +103,6 @@ impl Texture {
         let (needs_power_of_two, generate_mip_maps) =
             unsafe { Self::set_sampler_params(&g_texture.sampler()) };
 
-        // TODO!: share images via Rc? detect if occurs?
-        // TODO!!: better I/O abstraction...
-        let g_img = g_texture.source();
-        let img = match g_img.source() {
-            Source::View { view, mime_type } => {
-                let parent_buffer_data = &buffers[view.buffer().index()].0;
-                let begin = view.offset();
-                let end = begin + view.length();
-                let data = &parent_buffer_data[begin..end];
-                match mime_type {
-                    "image/jpeg" => image::load_from_memory_with_format(data, JPEG),
-                    "image/png" => image::load_from_memory_with_format(data, PNG),
-                    _ => panic!(format!("unsupported image type (image: {}, mime_type: {})",
-                        g_img.index(), mime_type)),
-                }
-            },
-            Source::Uri { uri, mime_type } => {
-                if uri.starts_with("data:") {
-                    let encoded = uri.split(',').nth(1).unwrap();
-                    let data = base64::decode(&encoded).unwrap();
-                    let mime_type = if let Some(ty) = mime_type {
-                        ty
-                    } else {
-                        uri.split(',')
-                            .nth(0).unwrap()
-                            .split(':')
-                            .nth(1).unwrap()
-                            .split(';')
-                            .nth(0).unwrap()
-                    };
-
-                    match mime_type {
-                        "image/jpeg" => image::load_from_memory_with_format(&data, JPEG),
-                        "image/png" => image::load_from_memory_with_format(&data, PNG),
-                        _ => panic!(format!("unsupported image type (image: {}, mime_type: {})",
-                            g_img.index(), mime_type)),
-                    }
-                }
-                else if let Some(mime_type) = mime_type {
-                    let path = base_path.parent().unwrap_or_else(|| Path::new("./")).join(uri);
-                    let file = fs::File::open(path).unwrap();
-                    let reader = io::BufReader::new(file);
-                    match mime_type {
-                        "image/jpeg" => image::load(reader, JPEG),
-                        "image/png" => image::load(reader, PNG),
-                        _ => panic!(format!("unsupported image type (image: {}, mime_type: {})",
-                            g_img.index(), mime_type)),
-                    }
-                }
-                else {
-                    let path = base_path.parent().unwrap_or_else(||Path::new("./")).join(uri);
-                    image::open(path)
-                }
-            }
-        };
-
-        // TODO: handle I/O problems
-        let dyn_img = img.expect("Image loading failed.");
-
-        let format = match dyn_img {
-            ImageLuma8(_) => gl::RED,
-            ImageLumaA8(_) => gl::RG,
-            ImageRgb8(_) => gl::RGB,
-            ImageRgba8(_) => gl::RGBA,
-            ImageBgr8(_) => gl::BGR,
-            ImageBgra8(_) => gl::BGRA,
-        };
-
         // **Non-Power-Of-Two Texture Implementation Note**: glTF does not guarantee that a texture's
         // dimensions are a power-of-two.  At runtime, if a texture's width or height is not a
         // power-of-two, the texture needs to be resized so its dimensions are powers-of-two if the
@@ -110,32 +110,121 @@ impl Texture {
         // * Has a wrapping mode (either `wrapS` or `wrapT`) equal to `REPEAT` or `MIRRORED_REPEAT`, or
         // * Has a minification filter (`minFilter`) that uses mipmapping (`NEAREST_MIPMAP_NEAREST`, \\
         //   `NEAREST_MIPMAP_LINEAR`, `LINEAR_MIPMAP_NEAREST`, or `LINEAR_MIPMAP_LINEAR`).
-        let (width, height) = dyn_img.dimensions();
-        let (data, width, height) =
-            if needs_power_of_two && (!width.is_power_of_two() || !height.is_power_of_two()) {
-                let nwidth = width.next_power_of_two();
-                let nheight = height.next_power_of_two();
-                let resized = dyn_img.resize(nwidth, nheight, FilterType::Lanczos3);
-                (resized.raw_pixels(), resized.width(), resized.height())
-            }
-            else {
-                (dyn_img.raw_pixels(), dyn_img.width(), dyn_img.height())
-            };
+        let (width, height) = texture_size(img.width, img.height, needs_power_of_two, imp.options.max_texture_size);
+        if width < img.width || height < img.height {
+            info!("Downscaling image {} from {}x{} to {}x{}", g_img.index(), img.width, img.height, width, height);
+        }
+        let data = if (width, height) != (img.width, img.height) {
+            // `resize_exact`, since the aspect ratio changes when scaling to powers of two
+            let resized = dynamic_image(img).resize_exact(width, height, FilterType::Lanczos3);
+            Cow::Owned(resized.raw_pixels())
+        }
+        else {
+            Cow::Borrowed(&img.pixels[..])
+        };
+        let mut memory_size = (width * height) as usize * img.pixels.len() / (img.width * img.height) as usize;
+        if generate_mip_maps {
+            memory_size = memory_size * 4 / 3;
+        }
 
-        unsafe {
-            gl::TexImage2D(gl::TEXTURE_2D, 0, format as i32, width as i32, height as i32,
-                0, format, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const c_void);
+        let mut pending_mips = None;
+        let levels = mip_level_count(width, height);
+        if imp.options.stream_textures && generate_mip_maps && levels > 1 {
+            let image = match data {
+                Cow::Owned(pixels) => dynamic_image_from_raw(img.format, width, height, pixels),
+                Cow::Borrowed(_) => dynamic_image(img),
+            };
+            let base_level = (0..levels)
+                .find(|&level| (width >> level).max(height >> level) <= STREAMING_INITIAL_SIZE)
+                .unwrap_or(levels - 1);
+            unsafe {
+                // allocate all levels, then upload the coarse ones
+                for level in 0..levels {
+                    let (level_width, level_height) = mip_level_size(width, height, level);
+                    gl::TexImage2D(gl::TEXTURE_2D, level as i32, format as i32, level_width as i32,
+                        level_height as i32, 0, format, gl::UNSIGNED_BYTE, ptr::null());
+                }
+                for level in base_level..levels {
+                    upload_mip_level(&image, format, level);
+                }
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, levels as i32 - 1);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, base_level as i32);
+            }
+            pending_mips = Some(PendingMips { image, format, base_level });
+        } else {
+            unsafe {
+                gl::TexImage2D(gl::TEXTURE_2D, 0, format as i32, width as i32, height as i32,
+                    0, format, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const c_void);
 
-            if generate_mip_maps {
-                gl::GenerateMipmap(gl::TEXTURE_2D);
+                if generate_mip_maps {
+                    gl::GenerateMipmap(gl::TEXTURE_2D);
+                }
             }
         }
-        Texture {
+        Ok(Texture {
             index: g_texture.index(),
             name: g_texture.name().map(|s| s.into()),
+            shared_indices: RefCell::new(vec![]),
+            image: g_img.index(),
+            sampler: g_texture.sampler().index(),
             id: texture_id,
+            object: Rc::new(TextureObject(texture_id)),
+            tex_coord,
+            memory_size,
+            pending_mips: RefCell::new(pending_mips),
+            color_space: Cell::new(None),
+        })
+    }
+
+    /// The glTF texture `g_texture` (with the same image and sampler) for the texture coordinate set
+    /// `tex_coord`, using the OpenGL texture of this one. Its memory is counted and any remaining mip
+    /// levels are streamed by this texture.
+    pub fn with_tex_coord(&self, g_texture: &gltf::Texture<'_>, tex_coord: u32) -> Texture {
+        Texture {
+            index: g_texture.index(),
+            name: g_texture.name().map(|s| s.into()),
+            shared_indices: RefCell::new(vec![]),
+            image: self.image,
+            sampler: self.sampler,
+            id: self.id,
+            object: Rc::clone(&self.object),
             tex_coord,
+            memory_size: 0,
+            pending_mips: RefCell::new(None),
+            color_space: Cell::new(self.color_space.get()),
+        }
+    }
+
+    /// Whether this texture is (or is shared by) the glTF texture `index`
+    pub fn has_index(&self, index: usize) -> bool {
+        self.index == index || self.shared_indices.borrow().contains(&index)
+    }
+
+    /// Whether finer mip levels still have to be streamed
+    pub fn is_streaming(&self) -> bool {
+        self.pending_mips.borrow().is_some()
+    }
+
+    /// Uploads the next finer mip level of a streamed texture.
+    /// Returns the number of uploaded bytes (0 if the texture is complete).
+    pub fn stream_next_level(&self) -> usize {
+        let mut pending_mips = self.pending_mips.borrow_mut();
+        let pending = match *pending_mips {
+            Some(ref mut pending) => pending,
+            None => return 0,
+        };
+        pending.base_level -= 1;
+        let size = unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            let size = upload_mip_level(&pending.image, pending.format, pending.base_level);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, pending.base_level as i32);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            size
+        };
+        if pending.base_level == 0 {
+            *pending_mips = None;
         }
+        size
     }
 
     // Returns whether image needs to be Power-Of-Two-sized and whether mip maps should be generated
@@ -181,3 +270,77 @@ impl Texture {
         (needs_power_of_two, mip_maps)
     }
 }
+
+/// Size of the uploaded texture: scaled up to powers of two if required by the sampler,
+/// then halved until it fits into `max_size` (keeping the aspect ratio and powers of two).
+fn texture_size(width: u32, height: u32, needs_power_of_two: bool, max_size: Option<u32>) -> (u32, u32) {
+    let (mut width, mut height) = if needs_power_of_two {
+        (width.next_power_of_two(), height.next_power_of_two())
+    } else {
+        (width, height)
+    };
+    if let Some(max_size) = max_size {
+        while width > max_size.max(1) || height > max_size.max(1) {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+        }
+    }
+    (width, height)
+}
+
+/// Number of mip levels down to 1x1
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn mip_level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Uploads a mip level of `image` (level 0) into the bound texture, returns its size in bytes
+unsafe fn upload_mip_level(image: &DynamicImage, format: gl::types::GLenum, level: u32) -> usize {
+    let (width, height) = mip_level_size(image.width(), image.height(), level);
+    let pixels = if level == 0 {
+        image.raw_pixels()
+    } else {
+        image.resize_exact(width, height, FilterType::Triangle).raw_pixels()
+    };
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl::TexSubImage2D(gl::TEXTURE_2D, level as i32, 0, 0, width as i32, height as i32,
+        format, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const c_void);
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+    pixels.len()
+}
+
+/// Converts decoded image data back to a `DynamicImage` (e.g. for resizing)
+fn dynamic_image(img: &gltf::image::Data) -> DynamicImage {
+    dynamic_image_from_raw(img.format, img.width, img.height, img.pixels.clone())
+}
+
+fn dynamic_image_from_raw(format: Format, width: u32, height: u32, pixels: Vec<u8>) -> DynamicImage {
+    match format {
+        Format::R8 => ImageLuma8(ImageBuffer::from_raw(width, height, pixels).unwrap()),
+        Format::R8G8 => ImageLumaA8(ImageBuffer::from_raw(width, height, pixels).unwrap()),
+        Format::R8G8B8 => ImageRgb8(ImageBuffer::from_raw(width, height, pixels).unwrap()),
+        Format::R8G8B8A8 => ImageRgba8(ImageBuffer::from_raw(width, height, pixels).unwrap()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(texture_size(1000, 600, false, None), (1000, 600));
+        assert_eq!(texture_size(1000, 600, true, None), (1024, 1024));
+        assert_eq!(texture_size(8192, 4096, true, Some(2048)), (2048, 1024));
+        assert_eq!(texture_size(3000, 1000, false, Some(2048)), (1500, 500));
+        assert_eq!(texture_size(4096, 1, false, Some(1024)), (1024, 1));
+
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(1024, 256), 11);
+        assert_eq!(mip_level_size(1024, 256, 10), (1, 1));
+        assert_eq!(mip_level_size(1024, 256, 2), (256, 64));
+    }
+}
@@ -0,0 +1,54 @@
+use std::ptr;
+
+use crate::shader::TRANSMISSION_FRAMEBUFFER_TEXTURE_UNIT;
+
+/// Mip-mapped copy of the opaque scene that transmissive materials
+/// sample from (KHR_materials_transmission)
+pub struct TransmissionTexture {
+    id: u32,
+    width: i32,
+    height: i32,
+}
+
+impl TransmissionTexture {
+    pub fn new() -> TransmissionTexture {
+        let mut id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        }
+        TransmissionTexture { id, width: 0, height: 0 }
+    }
+
+    /// Copies the current viewport of the bound framebuffer and binds the
+    /// result to `TRANSMISSION_FRAMEBUFFER_TEXTURE_UNIT`.
+    pub unsafe fn copy_framebuffer(&mut self) {
+        let mut viewport = [0i32; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let (width, height) = (viewport[2], viewport[3]);
+
+        gl::ActiveTexture(gl::TEXTURE0 + TRANSMISSION_FRAMEBUFFER_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_2D, self.id);
+        if width != self.width || height != self.height {
+            // floating point like the HDR framebuffer of the post-processing (when bound),
+            // so transmitted light isn't clamped to 1.0
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA16F as i32, width, height,
+                0, gl::RGBA, gl::FLOAT, ptr::null());
+            self.width = width;
+            self.height = height;
+        }
+        gl::CopyTexSubImage2D(gl::TEXTURE_2D, 0, 0, 0, viewport[0], viewport[1], width, height);
+        gl::GenerateMipmap(gl::TEXTURE_2D);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+impl Drop for TransmissionTexture {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id) }
+    }
+}
@@ -0,0 +1,99 @@
+use std::os::raw::c_void;
+
+use crate::shader::UV_CHECKER_TEXTURE_UNIT;
+
+const SIZE: usize = 512;
+/// Number of cells per row/column
+const CELLS: usize = 8;
+
+/// Procedural checkerboard that replaces the base color textures to reveal
+/// stretched or distorted UVs. Each cell has its own hue, so flipped or
+/// rotated UV islands are recognizable as well.
+pub struct UvCheckerTexture {
+    id: u32,
+}
+
+impl UvCheckerTexture {
+    pub fn new() -> UvCheckerTexture {
+        let pixels = checker_pixels(SIZE, CELLS);
+        let mut id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as i32, SIZE as i32, SIZE as i32,
+                0, gl::RGB, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const c_void);
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+        UvCheckerTexture { id }
+    }
+
+    /// Binds the texture to `UV_CHECKER_TEXTURE_UNIT`
+    pub unsafe fn bind(&self) {
+        gl::ActiveTexture(gl::TEXTURE0 + UV_CHECKER_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_2D, self.id);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
+
+impl Drop for UvCheckerTexture {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id) }
+    }
+}
+
+/// RGB pixels of a `size` x `size` checkerboard with `cells` x `cells` cells and grid lines
+fn checker_pixels(size: usize, cells: usize) -> Vec<u8> {
+    let cell_size = size / cells;
+    let mut pixels = Vec::with_capacity(size * size * 3);
+    for y in 0..size {
+        for x in 0..size {
+            let (column, row) = (x / cell_size, y / cell_size);
+            let (cx, cy) = (x % cell_size, y % cell_size);
+            let color = if cx < 2 || cy < 2 {
+                [0.1, 0.1, 0.1] // grid line
+            } else {
+                let hue = (row * cells + column) as f32 / (cells * cells) as f32;
+                // alternate light and dark cells
+                let value = if (row + column) % 2 == 0 { 0.9 } else { 0.5 };
+                hsv_to_rgb(hue, 0.6, value)
+            };
+            pixels.extend(color.iter().map(|c| (c * 255.0) as u8));
+        }
+    }
+    pixels
+}
+
+/// All components in 0..1
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let h = hue * 6.0;
+    let f = h - h.floor();
+    let (p, q, t) = (value * (1.0 - saturation), value * (1.0 - saturation * f), value * (1.0 - saturation * (1.0 - f)));
+    match h as usize % 6 {
+        0 => [value, t, p],
+        1 => [q, value, p],
+        2 => [p, value, t],
+        3 => [p, q, value],
+        4 => [t, p, value],
+        _ => [value, p, q],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker() {
+        let pixels = checker_pixels(16, 2);
+        assert_eq!(pixels.len(), 16 * 16 * 3);
+        let pixel = |x: usize, y: usize| &pixels[(y * 16 + x) * 3..(y * 16 + x) * 3 + 3];
+        assert_eq!(pixel(0, 5), &[25, 25, 25]); // grid line
+        // cells are unique and alternate between light and dark
+        assert_ne!(pixel(4, 4), pixel(12, 12));
+        assert!(pixel(4, 4).iter().max() > pixel(12, 4).iter().max());
+    }
+}
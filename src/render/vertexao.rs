@@ -0,0 +1,219 @@
+use std::f32::consts::PI;
+
+use crate::render::math::*;
+use crate::render::Vertex;
+
+/// Maximum number of triangles in a leaf of the BVH
+const LEAF_SIZE: usize = 4;
+
+/// Bakes ambient occlusion into `Vertex::occlusion` (1: unoccluded) by casting `samples`
+/// cosine-weighted rays over the hemisphere around each vertex normal against the triangles
+/// of the list (i.e. the primitive only occludes itself). Rays are limited to half the diagonal
+/// of the bounds, so that distant parts of the geometry don't darken everything.
+pub fn bake_vertex_occlusion(vertices: &mut [Vertex], indices: Option<&[u32]>, samples: u32) {
+    if samples == 0 || vertices.is_empty() {
+        return
+    }
+    let triangles: Vec<[Vector3; 3]> = match indices {
+        Some(indices) => indices.chunks(3)
+            .filter(|t| t.len() == 3)
+            .map(|t| [vertices[t[0] as usize].position, vertices[t[1] as usize].position,
+                vertices[t[2] as usize].position])
+            .collect(),
+        None => vertices.chunks(3)
+            .filter(|t| t.len() == 3)
+            .map(|t| [t[0].position, t[1].position, t[2].position])
+            .collect(),
+    };
+    let bvh = Bvh::new(triangles);
+    let diagonal = match bvh.nodes.first() {
+        Some(node) => (node.max - node.min).magnitude(),
+        None => return,
+    };
+    let max_distance = diagonal / 2.0;
+    // offset along the normal against self-intersections with the vertex's own triangles
+    let bias = diagonal * 1e-4;
+
+    let directions = hemisphere_samples(samples);
+    for vertex in vertices.iter_mut() {
+        if vertex.normal.magnitude2() == 0.0 {
+            continue
+        }
+        let normal = vertex.normal.normalize();
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        let origin = vertex.position + normal * bias;
+        let hits = directions.iter()
+            .filter(|d| bvh.intersects(origin, tangent * d.x + bitangent * d.y + normal * d.z, max_distance))
+            .count();
+        vertex.occlusion = 1.0 - hits as f32 / directions.len() as f32;
+    }
+}
+
+/// Cosine-weighted directions around +Z from a Hammersley sequence (deterministic,
+/// so repeated loads look the same)
+fn hemisphere_samples(count: u32) -> Vec<Vector3> {
+    (0..count)
+        .map(|i| {
+            let u = (i as f32 + 0.5) / count as f32;
+            let phi = 2.0 * PI * (i.reverse_bits() as f32 / 2f32.powi(32));
+            let r = u.sqrt();
+            vec3(r * phi.cos(), r * phi.sin(), (1.0 - u).sqrt())
+        })
+        .collect()
+}
+
+/// Two unit vectors perpendicular to `normal` and each other
+fn orthonormal_basis(normal: Vector3) -> (Vector3, Vector3) {
+    let up = if normal.z.abs() < 0.9 { vec3(0.0, 0.0, 1.0) } else { vec3(1.0, 0.0, 0.0) };
+    let tangent = up.cross(normal).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+struct BvhNode {
+    min: Vector3,
+    max: Vector3,
+    /// Leaf: range of `Bvh::triangles`, inner node: index of the first child (the second one follows it)
+    start: usize,
+    count: usize,
+}
+
+/// Bounding volume hierarchy for ray casts against a triangle soup
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<[Vector3; 3]>,
+}
+
+impl Bvh {
+    fn new(triangles: Vec<[Vector3; 3]>) -> Bvh {
+        let mut bvh = Bvh { nodes: vec![], triangles };
+        if !bvh.triangles.is_empty() {
+            let count = bvh.triangles.len();
+            bvh.build(0, count);
+        }
+        bvh
+    }
+
+    /// Adds the node for the triangles `start..start + count` and its children, returns its index
+    fn build(&mut self, start: usize, count: usize) -> usize {
+        let triangles = &mut self.triangles[start..start + count];
+        let (mut min, mut max) = (triangles[0][0], triangles[0][0]);
+        for p in triangles.iter().flat_map(|t| t.iter()) {
+            min = vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode { min, max, start, count });
+        if count <= LEAF_SIZE {
+            return index
+        }
+
+        // median split along the longest axis
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+        let centroid = |t: &[Vector3; 3]| t[0][axis] + t[1][axis] + t[2][axis];
+        triangles.sort_by(|a, b| centroid(a).partial_cmp(&centroid(b)).unwrap_or(std::cmp::Ordering::Equal));
+        let half = count / 2;
+        let first_child = self.build(start, half);
+        self.build(start + half, count - half);
+        self.nodes[index].start = first_child;
+        self.nodes[index].count = 0;
+        index
+    }
+
+    /// True if the ray hits any triangle within `max_distance`
+    fn intersects(&self, origin: Vector3, direction: Vector3, max_distance: f32) -> bool {
+        if self.nodes.is_empty() {
+            return false
+        }
+        let inv_direction = vec3(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !ray_hits_box(origin, inv_direction, node.min, node.max, max_distance) {
+                continue
+            }
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+            } else if self.triangles[node.start..node.start + node.count].iter()
+                .any(|t| matches!(ray_triangle_distance(origin, direction, t), Some(d) if d <= max_distance))
+            {
+                return true
+            }
+        }
+        false
+    }
+}
+
+/// Slab test
+fn ray_hits_box(origin: Vector3, inv_direction: Vector3, min: Vector3, max: Vector3, max_distance: f32) -> bool {
+    let (mut t_min, mut t_max) = (0.0f32, max_distance);
+    for axis in 0..3 {
+        let t1 = (min[axis] - origin[axis]) * inv_direction[axis];
+        let t2 = (max[axis] - origin[axis]) * inv_direction[axis];
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+    }
+    t_min <= t_max
+}
+
+/// Möller-Trumbore intersection (both sides), `None` if the ray misses the triangle
+fn ray_triangle_distance(origin: Vector3, direction: Vector3, triangle: &[Vector3; 3]) -> Option<f32> {
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < 1e-12 {
+        return None
+    }
+    let inv_determinant = 1.0 / determinant;
+    let s = origin - triangle[0];
+    let u = s.dot(p) * inv_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None
+    }
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inv_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None
+    }
+    let t = edge2.dot(q) * inv_determinant;
+    if t > 0.0 { Some(t) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Quad in the XZ plane (facing up) from `x0` to `x1`
+    fn floor(x0: f32, x1: f32) -> Vec<Vertex> {
+        [vec3(x0, 0.0, -1.0), vec3(x0, 0.0, 1.0), vec3(x1, 0.0, 1.0),
+         vec3(x0, 0.0, -1.0), vec3(x1, 0.0, 1.0), vec3(x1, 0.0, -1.0)].iter()
+            .map(|&position| Vertex { position, normal: vec3(0.0, 1.0, 0.0), ..Vertex::default() })
+            .collect()
+    }
+
+    #[test]
+    fn occlusion() {
+        let mut vertices = floor(0.1, 1.0);
+        bake_vertex_occlusion(&mut vertices, None, 64);
+        assert!(vertices.iter().all(|v| v.occlusion == 1.0));
+
+        // add a wall at x = 0, facing the floor
+        let wall = [vec3(0.0, 0.0, -1.0), vec3(0.0, 1.0, -1.0), vec3(0.0, 1.0, 1.0),
+                    vec3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 1.0), vec3(0.0, 0.0, 1.0)];
+        let mut vertices = floor(0.1, 1.0);
+        vertices.extend(wall.iter().map(|&position| Vertex { position, normal: vec3(1.0, 0.0, 0.0), ..Vertex::default() }));
+        bake_vertex_occlusion(&mut vertices, None, 64);
+        let (near, far) = (vertices[0].occlusion, vertices[2].occlusion);
+        assert!(near < far && far <= 1.0, "near: {}, far: {}", near, far);
+        assert!(near > 0.3 && near < 0.8, "near: {}", near);
+    }
+
+    #[test]
+    fn samples() {
+        let directions = hemisphere_samples(16);
+        assert_eq!(directions.len(), 16);
+        assert!(directions.iter().all(|d| (d.magnitude() - 1.0).abs() < 1e-5 && d.z > 0.0));
+    }
+}
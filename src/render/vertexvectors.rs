@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use crate::render::math::*;
+use crate::render::{LineRenderer, Root, Scene, Vertex};
+
+/// Which vertex vectors are drawn as lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexVectors {
+    Normals,
+    /// Normals, tangents and bitangents
+    TangentSpace,
+}
+
+impl VertexVectors {
+    /// Cycles through normals, tangent space and off
+    pub fn next(current: Option<VertexVectors>) -> Option<VertexVectors> {
+        match current {
+            None => Some(VertexVectors::Normals),
+            Some(VertexVectors::Normals) => Some(VertexVectors::TangentSpace),
+            Some(VertexVectors::TangentSpace) => None,
+        }
+    }
+}
+
+/// Draws a line from each vertex along its normal (blue) and optionally tangent (red)
+/// and bitangent (green). The lines are scaled by the length of the vectors, so
+/// zero-length or unnormalized normals stand out. Skinning and morph targets are ignored.
+pub struct VertexVectorLines {
+    /// Vertices of each mesh (by glTF index), read back from the GPU on first use
+    vertices: HashMap<usize, Vec<Vertex>>,
+    lines: LineRenderer,
+}
+
+impl VertexVectorLines {
+    pub fn new() -> VertexVectorLines {
+        VertexVectorLines { vertices: HashMap::new(), lines: LineRenderer::new() }
+    }
+
+    /// Draws the vectors of all visible nodes, `length` is the world space length of a unit vector
+    pub unsafe fn draw(&mut self, scene: &Scene, root: &Root, vectors: VertexVectors, length: f32,
+        view_projection: &Matrix4)
+    {
+        let (mut normals, mut tangents, mut bitangents) = (vec![], vec![], vec![]);
+        for node_id in scene.visible_nodes(root) {
+            let node = &root.nodes[node_id];
+            let mesh = match node.mesh {
+                Some(ref mesh) => mesh,
+                None => continue,
+            };
+            let vertices = self.vertices.entry(mesh.index).or_insert_with(|| {
+                mesh.primitives.iter().flat_map(|primitive| primitive.read_vertices()).collect()
+            });
+            let transforms = match node.instances {
+                Some(ref instances) => instances.transforms.iter()
+                    .map(|instance| node.final_transform * instance)
+                    .collect(),
+                None => vec![node.final_transform],
+            };
+            for transform in &transforms {
+                add_vector_lines(vertices, transform, length, &mut normals, &mut tangents,
+                    if vectors == VertexVectors::TangentSpace { Some(&mut bitangents) } else { None });
+            }
+        }
+        self.lines.draw(&normals, &vec4(0.2, 0.4, 1.0, 1.0), view_projection, true);
+        if vectors == VertexVectors::TangentSpace {
+            self.lines.draw(&tangents, &vec4(1.0, 0.2, 0.2, 1.0), view_projection, true);
+            self.lines.draw(&bitangents, &vec4(0.2, 1.0, 0.2, 1.0), view_projection, true);
+        }
+    }
+}
+
+/// Appends a line (pair of points) per vertex to `normals` and, if `bitangents` is given,
+/// to `tangents` and `bitangents`. Directions are transformed with the normal matrix
+/// and keep their original length.
+fn add_vector_lines(vertices: &[Vertex], transform: &Matrix4, length: f32,
+    normals: &mut Vec<Vector3>, tangents: &mut Vec<Vector3>, mut bitangents: Option<&mut Vec<Vector3>>)
+{
+    let normal_matrix = transform.invert().unwrap_or_else(Matrix4::identity).transpose();
+    let transform_direction = |matrix: &Matrix4, v: Vector3| {
+        let transformed = (matrix * v.extend(0.0)).truncate();
+        if transformed.magnitude2() > 0.0 { transformed.normalize() * v.magnitude() * length } else { transformed }
+    };
+    let line = |lines: &mut Vec<Vector3>, start: Vector3, direction: Vector3| {
+        lines.push(start);
+        lines.push(start + direction);
+    };
+    for vertex in vertices {
+        let position = transform.transform_point(Point3::from_vec(vertex.position)).to_vec();
+        line(normals, position, transform_direction(&normal_matrix, vertex.normal));
+        if let Some(ref mut bitangents) = bitangents {
+            let tangent = vertex.tangent.truncate();
+            let bitangent = vertex.normal.cross(tangent) * vertex.tangent.w;
+            line(tangents, position, transform_direction(transform, tangent));
+            line(bitangents, position, transform_direction(transform, bitangent));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vector_lines() {
+        let vertices = [
+            Vertex {
+                position: vec3(1.0, 0.0, 0.0),
+                normal: vec3(0.0, 1.0, 0.0),
+                tangent: vec4(1.0, 0.0, 0.0, -1.0),
+                ..Vertex::default()
+            },
+            Vertex { normal: vec3(0.0, 0.0, 0.5), ..Vertex::default() }, // unnormalized, no tangent
+        ];
+        let transform = Matrix4::from_translation(vec3(0.0, 0.0, 1.0)) * Matrix4::from_nonuniform_scale(2.0, 4.0, 1.0);
+        let (mut normals, mut tangents, mut bitangents) = (vec![], vec![], vec![]);
+        add_vector_lines(&vertices, &transform, 0.1, &mut normals, &mut tangents, Some(&mut bitangents));
+
+        assert_eq!(normals.len(), 4);
+        assert_eq!(normals[0], vec3(2.0, 0.0, 1.0));
+        assert!((normals[1] - vec3(2.0, 0.1, 1.0)).magnitude() < 1e-6);
+        assert!((normals[3] - vec3(0.0, 0.0, 1.05)).magnitude() < 1e-6);
+        assert!((tangents[1] - vec3(2.1, 0.0, 1.0)).magnitude() < 1e-6);
+        // y cross x with negative handedness
+        assert!((bitangents[1] - vec3(2.0, 0.0, 1.1)).magnitude() < 1e-6);
+        assert_eq!(tangents[2], tangents[3]); // zero-length tangent
+    }
+}
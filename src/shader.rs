@@ -8,7 +8,7 @@ use std::str;
 use gl;
 use gl::types::*;
 
-use cgmath::{Matrix, Matrix4, Vector3, Vector4};
+use cgmath::{Matrix, Matrix3, Matrix4, Vector3, Vector4};
 use cgmath::prelude::*;
 
 use bitflags::bitflags;
@@ -75,7 +75,7 @@ impl Shader {
         shader
     }
 
-    fn add_defines(source: &str, defines: &[String]) -> String {
+    pub fn add_defines(source: &str, defines: &[String]) -> String {
         // insert preprocessor defines after #version if exists
         // (#version must occur before any other statement in the program)
         let defines = defines.iter()
@@ -109,6 +109,10 @@ impl Shader {
         gl::Uniform1i(location, value);
     }
     /// ------------------------------------------------------------------------
+    pub unsafe fn set_uint(&self, location: i32, value: u32) {
+        gl::Uniform1ui(location, value);
+    }
+    /// ------------------------------------------------------------------------
     pub unsafe fn set_float(&self, location: i32, value: f32) {
         gl::Uniform1f(location, value);
     }
@@ -125,10 +129,15 @@ impl Shader {
         gl::Uniform2f(location, x, y);
     }
     /// ------------------------------------------------------------------------
+    #[allow(dead_code)]
     pub unsafe fn set_vec3(&self, location: i32, x: f32, y: f32, z: f32) {
         gl::Uniform3f(location, x, y, z);
     }
     /// ------------------------------------------------------------------------
+    pub unsafe fn set_mat3(&self, location: i32, mat: &Matrix3<f32>) {
+        gl::UniformMatrix3fv(location, 1, gl::FALSE, mat.as_ptr());
+    }
+    /// ------------------------------------------------------------------------
     pub unsafe fn set_mat4(&self, location: i32, mat: &Matrix4<f32>) {
         gl::UniformMatrix4fv(location, 1, gl::FALSE, mat.as_ptr());
     }
@@ -180,14 +189,28 @@ impl Shader {
     }
 }
 
+impl Drop for Shader {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.id) }
+    }
+}
+
+/// Texture unit of the copy of the opaque scene, see `Scene::draw`
+pub const TRANSMISSION_FRAMEBUFFER_TEXTURE_UNIT: u32 = 5;
+/// Texture unit of the screen-space ambient occlusion, see `Ssao::render`
+pub const SSAO_TEXTURE_UNIT: u32 = 7;
+/// Texture unit of the UV checker that replaces the base color textures, see `UvCheckerTexture`
+pub const UV_CHECKER_TEXTURE_UNIT: u32 = 10;
+
 bitflags! {
     /// Flags matching the defines in the PBR shader
-    pub struct ShaderFlags: u16 {
+    pub struct ShaderFlags: u32 {
         // vertex shader + fragment shader
         const HAS_NORMALS           = 1;
         const HAS_TANGENTS          = 1 << 1;
         const HAS_UV                = 1 << 2;
         const HAS_COLORS            = 1 << 3;
+        const HAS_VERTEX_AO         = 1 << 18;
 
         // fragment shader only
         const USE_IBL               = 1 << 4;
@@ -197,13 +220,23 @@ bitflags! {
         const HAS_METALROUGHNESSMAP = 1 << 8;
         const HAS_OCCLUSIONMAP      = 1 << 9;
         const USE_TEX_LOD           = 1 << 10;
+        const HAS_TRANSMISSION      = 1 << 11;
+        const HAS_TRANSMISSIONMAP   = 1 << 12;
+        const HAS_SHEEN             = 1 << 13;
+        const HAS_SHEENCOLORMAP     = 1 << 14;
+        const HAS_SHEENROUGHNESSMAP = 1 << 15;
+        const HAS_SPECULARGLOSSINESS    = 1 << 16;
+        const HAS_SPECULARGLOSSINESSMAP = 1 << 17;
+        const HAS_DIELECTRICSPECULARMAP      = 1 << 19;
+        const HAS_DIELECTRICSPECULARCOLORMAP = 1 << 20;
+        const UNLIT                 = 1 << 21;
     }
 }
 
 impl ShaderFlags {
     pub fn as_strings(self) -> Vec<String> {
-        (0..15)
-            .map(|i| 1u16 << i)
+        (0..32)
+            .map(|i| 1u32 << i)
             .filter(|i| self.bits & i != 0)
             .map(|i| format!("{:?}", ShaderFlags::from_bits_truncate(i)))
             .collect()
@@ -233,27 +266,85 @@ pub struct PbrUniformLocations {
 
     pub u_BaseColorSampler: i32,
     pub u_BaseColorTexCoord: i32,
+    pub u_BaseColorUVTransform: i32,
     pub u_BaseColorFactor: i32,
 
     pub u_NormalSampler: i32,
     pub u_NormalTexCoord: i32,
+    pub u_NormalUVTransform: i32,
     pub u_NormalScale: i32,
 
     pub u_EmissiveSampler: i32,
     pub u_EmissiveTexCoord: i32,
+    pub u_EmissiveUVTransform: i32,
     pub u_EmissiveFactor: i32,
 
     pub u_MetallicRoughnessSampler: i32,
     pub u_MetallicRoughnessTexCoord: i32,
+    pub u_MetallicRoughnessUVTransform: i32,
     pub u_MetallicRoughnessValues: i32,
 
     pub u_OcclusionSampler: i32,
     pub u_OcclusionTexCoord: i32,
+    pub u_OcclusionUVTransform: i32,
     pub u_OcclusionStrength: i32,
 
     pub u_AlphaBlend: i32,
     pub u_AlphaCutoff: i32,
 
+    pub u_TransmissionFramebufferSampler: i32,
+    pub u_TransmissionFactor: i32,
+    pub u_TransmissionSampler: i32,
+    pub u_TransmissionTexCoord: i32,
+
+    pub u_SSAOSampler: i32,
+    pub u_SSAOStrength: i32,
+
+    pub u_LogDepthFC: i32,
+
+    pub u_FlatShading: i32,
+    pub u_XRayOpacity: i32,
+    pub u_WeightedBlended: i32,
+    pub u_ColorSpaceDecode: i32,
+    pub u_ColorSpaceEncode: i32,
+
+    pub u_DepthView: i32,
+    pub u_DepthRange: i32,
+    pub u_InverseProjection: i32,
+    pub u_TexelDensity: i32,
+
+    pub u_ClipPlane: i32,
+    pub u_Clipping: i32,
+    pub u_DoubleSided: i32,
+
+    pub u_UVChecker: i32,
+    pub u_UVCheckerTexCoord: i32,
+    pub u_UVCheckerSampler: i32,
+
+    pub u_DebugTexture: i32,
+    pub u_DebugChannel: i32,
+
+    pub u_SheenColorFactor: i32,
+    pub u_SheenColorSampler: i32,
+    pub u_SheenColorTexCoord: i32,
+    pub u_SheenRoughnessFactor: i32,
+    pub u_SheenRoughnessSampler: i32,
+    pub u_SheenRoughnessTexCoord: i32,
+
+    pub u_DielectricF0: i32,
+
+    pub u_DielectricSpecularFactor: i32,
+    pub u_DielectricSpecularSampler: i32,
+    pub u_DielectricSpecularTexCoord: i32,
+    pub u_DielectricSpecularColorFactor: i32,
+    pub u_DielectricSpecularColorSampler: i32,
+    pub u_DielectricSpecularColorTexCoord: i32,
+
+    pub u_SpecularFactor: i32,
+    pub u_GlossinessFactor: i32,
+    pub u_SpecularGlossinessSampler: i32,
+    pub u_SpecularGlossinessTexCoord: i32,
+
     // TODO!: use/remove debugging uniforms
     // debugging flags used for shader output of intermediate PBR variables
     pub u_ScaleDiffBaseMR: i32,
@@ -261,6 +352,98 @@ pub struct PbrUniformLocations {
     pub u_ScaleIBLAmbient: i32,
 }
 
+/// Directional light and ambient light of the PBR shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightOptions {
+    /// Direction towards the light
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub ambient_color: Vector3<f32>,
+    pub ambient_intensity: f32,
+}
+
+impl Default for LightOptions {
+    fn default() -> Self {
+        Self {
+            color: Vector3::new(5.0, 5.0, 5.0),
+            // TODO!: optional minus on z
+            direction: Vector3::new(0.0, 0.5, 0.5),
+            ambient_color: Vector3::new(1.0, 1.0, 1.0),
+            ambient_intensity: 0.2,
+        }
+    }
+}
+
+/// Texture shown by the debug view instead of the shaded result
+/// (NOTE: values must match pbr-frag.glsl)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugTexture {
+    BaseColor = 1,
+    MetallicRoughness = 2,
+    Occlusion = 3,
+    Emissive = 4,
+    Normal = 5,
+    /// Texture coordinates as color gradient (R: u, G: v), not a texture
+    TexCoord0 = 6,
+    TexCoord1 = 7,
+}
+
+impl DebugTexture {
+    /// The next texture (`None` after the last one), for cycling through them
+    pub fn next(texture: Option<DebugTexture>) -> Option<DebugTexture> {
+        use self::DebugTexture::*;
+        match texture {
+            None => Some(BaseColor),
+            Some(BaseColor) => Some(MetallicRoughness),
+            Some(MetallicRoughness) => Some(Occlusion),
+            Some(Occlusion) => Some(Emissive),
+            Some(Emissive) => Some(Normal),
+            Some(Normal) => Some(TexCoord0),
+            Some(TexCoord0) => Some(TexCoord1),
+            Some(TexCoord1) => None,
+        }
+    }
+
+    /// The texture coordinate set shown by `TexCoord0` and `TexCoord1`
+    pub fn tex_coord(self) -> Option<u32> {
+        match self {
+            DebugTexture::TexCoord0 => Some(0),
+            DebugTexture::TexCoord1 => Some(1),
+            _ => None,
+        }
+    }
+}
+
+/// Shows a raw (unlit) material texture, optionally only a single channel as grayscale
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DebugView {
+    pub texture: Option<DebugTexture>,
+    /// 0-3: R, G, B, A; `None`: RGB
+    pub channel: Option<usize>,
+}
+
+/// Clipping plane perpendicular to a coordinate axis (in world space)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipPlane {
+    /// 0-2: X, Y, Z
+    pub axis: usize,
+    /// Position of the plane along the axis
+    pub position: f32,
+    /// If false, the part below `position` is cut away, otherwise the part above
+    pub flipped: bool,
+}
+
+impl ClipPlane {
+    /// Plane equation (normal, distance) with the kept side positive (for `gl_ClipDistance`)
+    pub fn equation(&self) -> Vector4<f32> {
+        let sign = if self.flipped { -1.0 } else { 1.0 };
+        let mut equation = Vector4::zero();
+        equation[self.axis] = sign;
+        equation.w = -sign * self.position;
+        equation
+    }
+}
+
 pub struct PbrShader {
     pub shader: Shader,
     pub flags: ShaderFlags,
@@ -268,6 +451,14 @@ pub struct PbrShader {
 }
 
 impl PbrShader {
+    /// The vertex and fragment source of the variant as it is compiled, i.e. with the `#define`s
+    /// of the flags inserted after `#version`
+    pub fn preprocessed_source(flags: ShaderFlags) -> (String, String) {
+        let defines = flags.as_strings();
+        (Shader::add_defines(include_str!("shaders/pbr-vert.glsl"), &defines),
+         Shader::add_defines(include_str!("shaders/pbr-frag.glsl"), &defines))
+    }
+
     pub fn new(flags: ShaderFlags) -> Self {
         let mut shader = Shader::from_source(
             include_str!("shaders/pbr-vert.glsl"),
@@ -298,27 +489,85 @@ impl PbrShader {
 
                 u_BaseColorSampler: shader.uniform_location("u_BaseColorSampler"),
                 u_BaseColorTexCoord: shader.uniform_location("u_BaseColorTexCoord"),
+                u_BaseColorUVTransform: shader.uniform_location("u_BaseColorUVTransform"),
                 u_BaseColorFactor: shader.uniform_location("u_BaseColorFactor"),
 
                 u_NormalSampler: shader.uniform_location("u_NormalSampler"),
                 u_NormalTexCoord: shader.uniform_location("u_NormalTexCoord"),
+                u_NormalUVTransform: shader.uniform_location("u_NormalUVTransform"),
                 u_NormalScale: shader.uniform_location("u_NormalScale"),
 
                 u_EmissiveSampler: shader.uniform_location("u_EmissiveSampler"),
                 u_EmissiveTexCoord: shader.uniform_location("u_EmissiveTexCoord"),
+                u_EmissiveUVTransform: shader.uniform_location("u_EmissiveUVTransform"),
                 u_EmissiveFactor: shader.uniform_location("u_EmissiveFactor"),
 
                 u_MetallicRoughnessSampler: shader.uniform_location("u_MetallicRoughnessSampler"),
                 u_MetallicRoughnessTexCoord: shader.uniform_location("u_MetallicRoughnessTexCoord"),
+                u_MetallicRoughnessUVTransform: shader.uniform_location("u_MetallicRoughnessUVTransform"),
                 u_MetallicRoughnessValues: shader.uniform_location("u_MetallicRoughnessValues"),
 
                 u_OcclusionSampler: shader.uniform_location("u_OcclusionSampler"),
                 u_OcclusionTexCoord: shader.uniform_location("u_OcclusionTexCoord"),
+                u_OcclusionUVTransform: shader.uniform_location("u_OcclusionUVTransform"),
                 u_OcclusionStrength: shader.uniform_location("u_OcclusionStrength"),
 
                 u_AlphaBlend: shader.uniform_location("u_AlphaBlend"),
                 u_AlphaCutoff: shader.uniform_location("u_AlphaCutoff"),
 
+                u_TransmissionFramebufferSampler: shader.uniform_location("u_TransmissionFramebufferSampler"),
+                u_TransmissionFactor: shader.uniform_location("u_TransmissionFactor"),
+                u_TransmissionSampler: shader.uniform_location("u_TransmissionSampler"),
+                u_TransmissionTexCoord: shader.uniform_location("u_TransmissionTexCoord"),
+
+                u_SSAOSampler: shader.uniform_location("u_SSAOSampler"),
+                u_SSAOStrength: shader.uniform_location("u_SSAOStrength"),
+
+                u_LogDepthFC: shader.uniform_location("u_LogDepthFC"),
+
+                u_FlatShading: shader.uniform_location("u_FlatShading"),
+                u_XRayOpacity: shader.uniform_location("u_XRayOpacity"),
+                u_WeightedBlended: shader.uniform_location("u_WeightedBlended"),
+                u_ColorSpaceDecode: shader.uniform_location("u_ColorSpaceDecode"),
+                u_ColorSpaceEncode: shader.uniform_location("u_ColorSpaceEncode"),
+
+                u_DepthView: shader.uniform_location("u_DepthView"),
+                u_DepthRange: shader.uniform_location("u_DepthRange"),
+                u_TexelDensity: shader.uniform_location("u_TexelDensity"),
+                u_InverseProjection: shader.uniform_location("u_InverseProjection"),
+
+                u_ClipPlane: shader.uniform_location("u_ClipPlane"),
+                u_Clipping: shader.uniform_location("u_Clipping"),
+                u_DoubleSided: shader.uniform_location("u_DoubleSided"),
+
+                u_UVChecker: shader.uniform_location("u_UVChecker"),
+                u_UVCheckerTexCoord: shader.uniform_location("u_UVCheckerTexCoord"),
+                u_UVCheckerSampler: shader.uniform_location("u_UVCheckerSampler"),
+
+                u_DebugTexture: shader.uniform_location("u_DebugTexture"),
+                u_DebugChannel: shader.uniform_location("u_DebugChannel"),
+
+                u_SheenColorFactor: shader.uniform_location("u_SheenColorFactor"),
+                u_SheenColorSampler: shader.uniform_location("u_SheenColorSampler"),
+                u_SheenColorTexCoord: shader.uniform_location("u_SheenColorTexCoord"),
+                u_SheenRoughnessFactor: shader.uniform_location("u_SheenRoughnessFactor"),
+                u_SheenRoughnessSampler: shader.uniform_location("u_SheenRoughnessSampler"),
+                u_SheenRoughnessTexCoord: shader.uniform_location("u_SheenRoughnessTexCoord"),
+
+                u_DielectricF0: shader.uniform_location("u_DielectricF0"),
+
+                u_DielectricSpecularFactor: shader.uniform_location("u_DielectricSpecularFactor"),
+                u_DielectricSpecularSampler: shader.uniform_location("u_DielectricSpecularSampler"),
+                u_DielectricSpecularTexCoord: shader.uniform_location("u_DielectricSpecularTexCoord"),
+                u_DielectricSpecularColorFactor: shader.uniform_location("u_DielectricSpecularColorFactor"),
+                u_DielectricSpecularColorSampler: shader.uniform_location("u_DielectricSpecularColorSampler"),
+                u_DielectricSpecularColorTexCoord: shader.uniform_location("u_DielectricSpecularColorTexCoord"),
+
+                u_SpecularFactor: shader.uniform_location("u_SpecularFactor"),
+                u_GlossinessFactor: shader.uniform_location("u_GlossinessFactor"),
+                u_SpecularGlossinessSampler: shader.uniform_location("u_SpecularGlossinessSampler"),
+                u_SpecularGlossinessTexCoord: shader.uniform_location("u_SpecularGlossinessTexCoord"),
+
                 u_ScaleDiffBaseMR: shader.uniform_location("u_ScaleDiffBaseMR"),
                 u_ScaleFGDSpec: shader.uniform_location("u_ScaleFGDSpec"),
                 u_ScaleIBLAmbient: shader.uniform_location("u_ScaleIBLAmbient"),
@@ -329,22 +578,49 @@ impl PbrShader {
             shader.set_int(uniforms.u_NormalSampler, 1);
             shader.set_int(uniforms.u_EmissiveSampler, 2);
             shader.set_int(uniforms.u_MetallicRoughnessSampler, 3);
+            shader.set_int(uniforms.u_SpecularGlossinessSampler, 3); // exclusive with metallic-roughness
             shader.set_int(uniforms.u_OcclusionSampler, 4);
-
-            shader.set_vec3(uniforms.u_LightColor, 5.0, 5.0, 5.0);
-            // TODO!: optional minus on z
-            shader.set_vec3(uniforms.u_LightDirection, 0.0, 0.5, 0.5);
-
-            shader.set_vec3(uniforms.u_AmbientLightColor, 1.0, 1.0, 1.0);
-            shader.set_float(uniforms.u_AmbientLightIntensity, 0.2);
+            shader.set_int(uniforms.u_TransmissionFramebufferSampler, TRANSMISSION_FRAMEBUFFER_TEXTURE_UNIT as i32);
+            shader.set_int(uniforms.u_TransmissionSampler, 6);
+            shader.set_int(uniforms.u_SSAOSampler, SSAO_TEXTURE_UNIT as i32);
+            shader.set_int(uniforms.u_SheenColorSampler, 8);
+            shader.set_int(uniforms.u_SheenRoughnessSampler, 9);
+            shader.set_int(uniforms.u_UVCheckerSampler, UV_CHECKER_TEXTURE_UNIT as i32);
+            shader.set_int(uniforms.u_DielectricSpecularSampler, 11);
+            shader.set_int(uniforms.u_DielectricSpecularColorSampler, 12);
 
             uniforms
         };
 
-        Self {
+        let pbr_shader = Self {
             shader,
             flags,
             uniforms
+        };
+        pbr_shader.set_lights(&LightOptions::default());
+        pbr_shader
+    }
+
+    pub fn set_lights(&self, lights: &LightOptions) {
+        unsafe {
+            self.shader.use_program();
+            self.shader.set_vector3(self.uniforms.u_LightColor, &lights.color);
+            self.shader.set_vector3(self.uniforms.u_LightDirection, &lights.direction);
+            self.shader.set_vector3(self.uniforms.u_AmbientLightColor, &lights.ambient_color);
+            self.shader.set_float(self.uniforms.u_AmbientLightIntensity, lights.ambient_intensity);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defines_after_version() {
+        let defines = (ShaderFlags::HAS_NORMALS | ShaderFlags::HAS_NORMALMAP).as_strings();
+        assert_eq!(defines, ["HAS_NORMALS", "HAS_NORMALMAP"]);
+        let source = Shader::add_defines("#version 330 core\nvoid main() {}", &defines);
+        assert_eq!(source, "#version 330 core\n#define HAS_NORMALS\n#define HAS_NORMALMAP\nvoid main() {}");
+    }
+}
@@ -0,0 +1,27 @@
+#version 330 core
+// Bloom: 5-tap downsampling (dual filtering). The first pass also
+// extracts the pixels brighter than the threshold.
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_Texture;
+uniform vec2 u_TexelSize; // of the source texture
+uniform float u_Threshold; // negative: no thresholding
+
+void main()
+{
+    vec3 color = texture(u_Texture, v_UV).rgb * 4.0;
+    color += texture(u_Texture, v_UV + vec2(-1.0, -1.0) * u_TexelSize).rgb;
+    color += texture(u_Texture, v_UV + vec2( 1.0, -1.0) * u_TexelSize).rgb;
+    color += texture(u_Texture, v_UV + vec2(-1.0,  1.0) * u_TexelSize).rgb;
+    color += texture(u_Texture, v_UV + vec2( 1.0,  1.0) * u_TexelSize).rgb;
+    color /= 8.0;
+
+    if (u_Threshold >= 0.0) {
+        float brightness = max(color.r, max(color.g, color.b));
+        color *= max(brightness - u_Threshold, 0.0) / max(brightness, 0.0001);
+    }
+
+    FragColor = vec4(color, 1.0);
+}
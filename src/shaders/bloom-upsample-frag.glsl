@@ -0,0 +1,22 @@
+#version 330 core
+// Bloom: 3x3 tent filter upsampling, additively blended onto the next larger mip.
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_Texture;
+uniform vec2 u_TexelSize; // of the source texture
+
+void main()
+{
+    vec3 color = texture(u_Texture, v_UV).rgb * 4.0;
+    color += texture(u_Texture, v_UV + vec2(-1.0,  0.0) * u_TexelSize).rgb * 2.0;
+    color += texture(u_Texture, v_UV + vec2( 1.0,  0.0) * u_TexelSize).rgb * 2.0;
+    color += texture(u_Texture, v_UV + vec2( 0.0, -1.0) * u_TexelSize).rgb * 2.0;
+    color += texture(u_Texture, v_UV + vec2( 0.0,  1.0) * u_TexelSize).rgb * 2.0;
+    color += texture(u_Texture, v_UV + vec2(-1.0, -1.0) * u_TexelSize).rgb;
+    color += texture(u_Texture, v_UV + vec2( 1.0, -1.0) * u_TexelSize).rgb;
+    color += texture(u_Texture, v_UV + vec2(-1.0,  1.0) * u_TexelSize).rgb;
+    color += texture(u_Texture, v_UV + vec2( 1.0,  1.0) * u_TexelSize).rgb;
+    FragColor = vec4(color / 16.0, 1.0);
+}
@@ -0,0 +1,42 @@
+#version 330 core
+// Combines the HDR scene color with the post-processing effects.
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_Color;
+uniform sampler2D u_Bloom;
+uniform float u_BloomIntensity; // 0: bloom disabled
+uniform float u_Exposure; // linear factor
+uniform mat3 u_OutputPrimaries; // from linear sRGB to the primaries of the display
+uniform int u_ToneMapping; // 0: none (clamped), 1: Reinhard, 2: ACES
+
+// Narkowicz 2015, "ACES Filmic Tone Mapping Curve"
+vec3 toneMapAces(vec3 color)
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+void main()
+{
+    vec4 color = texture(u_Color, v_UV);
+    if (u_BloomIntensity > 0.0) {
+        color.rgb += texture(u_Bloom, v_UV).rgb * u_BloomIntensity;
+    }
+
+    color.rgb *= u_Exposure;
+    if (u_ToneMapping == 1) {
+        color.rgb = color.rgb / (1.0 + color.rgb);
+    } else if (u_ToneMapping == 2) {
+        color.rgb = toneMapAces(color.rgb);
+    }
+    color.rgb = u_OutputPrimaries * color.rgb;
+
+    // without tone mapping, values are clamped like when rendering without post-processing
+    FragColor = color;
+}
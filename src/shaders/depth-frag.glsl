@@ -0,0 +1,6 @@
+#version 330 core
+// Depth-only pass (e.g. for SSAO), the depth is written implicitly.
+
+void main()
+{
+}
@@ -0,0 +1,22 @@
+#version 330 core
+// Supersampling: averages a grid of bilinear samples over the footprint of each
+// target pixel in the larger source texture.
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_Texture;
+uniform vec2 u_TargetSize; // in pixels
+uniform int u_Samples; // per axis, at least the render scale
+
+void main()
+{
+    vec4 color = vec4(0.0);
+    for (int y = 0; y < u_Samples; y++) {
+        for (int x = 0; x < u_Samples; x++) {
+            vec2 offset = (vec2(x, y) + 0.5) / float(u_Samples) - 0.5;
+            color += texture(u_Texture, (gl_FragCoord.xy + offset) / u_TargetSize);
+        }
+    }
+    FragColor = color / float(u_Samples * u_Samples);
+}
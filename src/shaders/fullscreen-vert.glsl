@@ -0,0 +1,11 @@
+#version 330 core
+// Fullscreen triangle without vertex buffers: draw 3 vertices with an empty VAO.
+
+out vec2 v_UV;
+
+void main()
+{
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    v_UV = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 330 core
+// Farthest depth of each block of u_BlockSize x u_BlockSize texels (base of the Hi-Z pyramid)
+
+out float MaxDepth;
+
+uniform sampler2D u_Depth;
+uniform int u_BlockSize;
+
+void main()
+{
+    ivec2 size = textureSize(u_Depth, 0);
+    ivec2 origin = ivec2(gl_FragCoord.xy) * u_BlockSize;
+    float maxDepth = 0.0;
+    for (int y = 0; y < u_BlockSize; ++y) {
+        for (int x = 0; x < u_BlockSize; ++x) {
+            maxDepth = max(maxDepth, texelFetch(u_Depth, min(origin + ivec2(x, y), size - 1), 0).r);
+        }
+    }
+    MaxDepth = maxDepth;
+}
@@ -0,0 +1,20 @@
+#version 330 core
+// Weighted blended order-independent transparency: composites the accumulated
+// transparent surfaces over the opaque scene (see oit.rs).
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_Accum; // weighted premultiplied colors (rgb), transmittance (a)
+uniform sampler2D u_Weight; // weighted alphas
+
+void main()
+{
+    vec4 accum = texture(u_Accum, v_UV);
+    float transmittance = accum.a;
+    if (transmittance >= 1.0) {
+        discard; // no transparent surfaces
+    }
+    float weight = max(texture(u_Weight, v_UV).r, 1e-5);
+    FragColor = vec4(accum.rgb / weight, 1.0 - transmittance);
+}
@@ -0,0 +1,47 @@
+#version 330 core
+// Outline edges: Sobel filter over the normals and the (logarithmic) depth. The edge
+// strength is averaged over four subpixel positions, which antialiases the outline.
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_NormalDepth; // normal in rgb, linear depth in alpha (0: background)
+uniform vec2 u_Offset; // distance of the kernel samples in uv
+uniform vec3 u_Color;
+
+const float BACKGROUND_DEPTH = 20.0; // log depth, far behind any surface
+const float DEPTH_THRESHOLD = 0.5; // of the log depth gradient, i.e. relative
+const float NORMAL_THRESHOLD = 1.0;
+
+const float SOBEL_X[9] = float[](-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0);
+const float SOBEL_Y[9] = float[](-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0);
+
+float edgeStrength(vec2 uv)
+{
+    float depthX = 0.0, depthY = 0.0;
+    vec3 normalX = vec3(0.0), normalY = vec3(0.0);
+    for (int i = 0; i < 9; ++i) {
+        vec2 offset = vec2(i % 3 - 1, i / 3 - 1) * u_Offset;
+        vec4 normalDepth = texture(u_NormalDepth, uv + offset);
+        float depth = normalDepth.a > 0.0 ? log(normalDepth.a) : BACKGROUND_DEPTH;
+        depthX += SOBEL_X[i] * depth;
+        depthY += SOBEL_Y[i] * depth;
+        normalX += SOBEL_X[i] * normalDepth.rgb;
+        normalY += SOBEL_Y[i] * normalDepth.rgb;
+    }
+    float depthEdge = length(vec2(depthX, depthY));
+    float normalEdge = sqrt(dot(normalX, normalX) + dot(normalY, normalY));
+    return max(smoothstep(DEPTH_THRESHOLD * 0.5, DEPTH_THRESHOLD, depthEdge),
+               smoothstep(NORMAL_THRESHOLD * 0.5, NORMAL_THRESHOLD, normalEdge));
+}
+
+void main()
+{
+    // rotated grid
+    vec2 pixel = 1.0 / vec2(textureSize(u_NormalDepth, 0));
+    float edge = (edgeStrength(v_UV + vec2(0.125, 0.375) * pixel) +
+                  edgeStrength(v_UV + vec2(-0.375, 0.125) * pixel) +
+                  edgeStrength(v_UV + vec2(-0.125, -0.375) * pixel) +
+                  edgeStrength(v_UV + vec2(0.375, -0.125) * pixel)) / 4.0;
+    FragColor = vec4(u_Color, edge);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+// View space normal and linear depth for the outline edge detection
+
+in vec3 v_Normal;
+in float v_Depth;
+
+out vec4 NormalDepth;
+
+void main()
+{
+    // points and lines may have no normals
+    vec3 normal = dot(v_Normal, v_Normal) > 0.0 ? normalize(v_Normal) : vec3(0.0);
+    NormalDepth = vec4(gl_FrontFacing ? normal : -normal, max(v_Depth, 1e-6));
+}
@@ -0,0 +1,17 @@
+#version 330 core
+layout (location = 0) in vec4 a_Position;
+layout (location = 1) in vec3 a_Normal;
+
+uniform mat4 u_MVPMatrix;
+uniform mat4 u_ModelViewMatrix;
+uniform mat4 u_NormalMatrix; // inverse transpose of the model view matrix
+
+out vec3 v_Normal;
+out float v_Depth;
+
+void main()
+{
+    v_Normal = mat3(u_NormalMatrix) * a_Normal;
+    v_Depth = -(u_ModelViewMatrix * a_Position).z;
+    gl_Position = u_MVPMatrix * a_Position;
+}
@@ -0,0 +1,9 @@
+#version 330 core
+// Overdraw: every fragment adds one to the count (with additive blending).
+
+out vec4 FragColor;
+
+void main()
+{
+    FragColor = vec4(1.0);
+}
@@ -0,0 +1,22 @@
+#version 330 core
+// Overdraw: maps the number of fragments per pixel to a heatmap.
+
+in vec2 v_UV;
+out vec4 FragColor;
+
+uniform sampler2D u_Count;
+uniform float u_MaxCount; // shown white
+
+// black (no fragments), blue, cyan, green, yellow, red, white
+const int RAMP_SIZE = 7;
+const vec3 RAMP[RAMP_SIZE] = vec3[](
+    vec3(0.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 1.0), vec3(0.0, 1.0, 0.0),
+    vec3(1.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(1.0));
+
+void main()
+{
+    float count = texture(u_Count, v_UV).r;
+    float t = clamp(count / u_MaxCount, 0.0, 1.0) * float(RAMP_SIZE - 1);
+    int i = min(int(t), RAMP_SIZE - 2);
+    FragColor = vec4(mix(RAMP[i], RAMP[i + 1], t - float(i)), 1.0);
+}
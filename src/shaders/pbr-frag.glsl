@@ -26,6 +26,10 @@ uniform vec3 u_LightColor;
 uniform vec3 u_AmbientLightColor;
 uniform float u_AmbientLightIntensity;
 
+// screen-space ambient occlusion (0: disabled)
+uniform sampler2D u_SSAOSampler;
+uniform float u_SSAOStrength;
+
 #ifdef USE_IBL
 uniform samplerCube u_DiffuseEnvSampler;
 uniform samplerCube u_SpecularEnvSampler;
@@ -35,27 +39,79 @@ uniform sampler2D u_brdfLUT;
 #ifdef HAS_BASECOLORMAP
 uniform sampler2D u_BaseColorSampler;
 uniform int u_BaseColorTexCoord;
+uniform mat3 u_BaseColorUVTransform; // KHR_texture_transform
 #endif
 #ifdef HAS_NORMALMAP
 uniform sampler2D u_NormalSampler;
 uniform int u_NormalTexCoord;
+uniform mat3 u_NormalUVTransform; // KHR_texture_transform
 uniform float u_NormalScale;
 #endif
+uniform vec3 u_EmissiveFactor; // includes KHR_materials_emissive_strength
 #ifdef HAS_EMISSIVEMAP
 uniform sampler2D u_EmissiveSampler;
 uniform int u_EmissiveTexCoord;
-uniform vec3 u_EmissiveFactor;
+uniform mat3 u_EmissiveUVTransform; // KHR_texture_transform
 #endif
 #ifdef HAS_METALROUGHNESSMAP
 uniform sampler2D u_MetallicRoughnessSampler;
 uniform int u_MetallicRoughnessTexCoord;
+uniform mat3 u_MetallicRoughnessUVTransform; // KHR_texture_transform
+#endif
+#ifdef HAS_SPECULARGLOSSINESS
+// KHR_materials_pbrSpecularGlossiness (the diffuse factor and texture are passed as base color)
+uniform vec3 u_SpecularFactor;
+uniform float u_GlossinessFactor;
+#endif
+#ifdef HAS_SPECULARGLOSSINESSMAP
+uniform sampler2D u_SpecularGlossinessSampler;
+uniform int u_SpecularGlossinessTexCoord;
 #endif
 #ifdef HAS_OCCLUSIONMAP
 uniform sampler2D u_OcclusionSampler;
 uniform int u_OcclusionTexCoord;
+uniform mat3 u_OcclusionUVTransform; // KHR_texture_transform
 uniform float u_OcclusionStrength;
 #endif
 
+#ifdef HAS_TRANSMISSION
+// KHR_materials_transmission (thin-walled, i.e. without refraction offset)
+uniform sampler2D u_TransmissionFramebufferSampler; // copy of the opaque scene
+uniform float u_TransmissionFactor;
+#endif
+#ifdef HAS_TRANSMISSIONMAP
+uniform sampler2D u_TransmissionSampler;
+uniform int u_TransmissionTexCoord;
+#endif
+
+// reflectance at normal incidence of the dielectric part, from KHR_materials_ior (default: 0.04 for IOR 1.5)
+uniform float u_DielectricF0;
+// KHR_materials_specular: strength (also at grazing angles) and tint of the dielectric reflectance
+uniform float u_DielectricSpecularFactor;
+uniform vec3 u_DielectricSpecularColorFactor;
+#ifdef HAS_DIELECTRICSPECULARMAP
+uniform sampler2D u_DielectricSpecularSampler;
+uniform int u_DielectricSpecularTexCoord;
+#endif
+#ifdef HAS_DIELECTRICSPECULARCOLORMAP
+uniform sampler2D u_DielectricSpecularColorSampler;
+uniform int u_DielectricSpecularColorTexCoord;
+#endif
+
+#ifdef HAS_SHEEN
+// KHR_materials_sheen
+uniform vec3 u_SheenColorFactor;
+uniform float u_SheenRoughnessFactor;
+#endif
+#ifdef HAS_SHEENCOLORMAP
+uniform sampler2D u_SheenColorSampler;
+uniform int u_SheenColorTexCoord;
+#endif
+#ifdef HAS_SHEENROUGHNESSMAP
+uniform sampler2D u_SheenRoughnessSampler;
+uniform int u_SheenRoughnessTexCoord;
+#endif
+
 uniform vec2 u_MetallicRoughnessValues;
 uniform vec4 u_BaseColorFactor;
 
@@ -64,6 +120,42 @@ uniform vec3 u_Camera;
 uniform float u_AlphaBlend;
 uniform float u_AlphaCutoff;
 
+// debug view: shows a raw texture (see `DebugTexture`, 0: disabled)
+// and optionally only one channel (0-3, -1: RGB)
+uniform int u_DebugTexture;
+uniform int u_DebugChannel;
+
+// depth view: linear view space depth as grayscale, black at near (x), white at far (y)
+uniform bool u_DepthView;
+uniform vec2 u_DepthRange;
+uniform mat4 u_InverseProjection;
+
+// texel density view: texels of the base color texture per world unit relative to this
+// target, from blue (8x lower) over green to red (8x higher) (0: disabled)
+uniform float u_TexelDensity;
+
+// logarithmic depth buffer: 2.0 / log2(far + 1.0) (0: disabled)
+uniform float u_LogDepthFC;
+
+// use geometric face normals instead of the vertex normals and normal map
+uniform bool u_FlatShading;
+// x-ray mode: opacity of all surfaces (0: disabled), see `Scene::draw_xray`
+uniform float u_XRayOpacity;
+// order-independent transparency pass: output weighted colors (see oit.rs)
+uniform bool u_WeightedBlended;
+
+// clipping plane active: back faces of single-sided materials (i.e. the interior
+// visible through the cut) are rendered in a solid color to cap the cut surface
+uniform bool u_Clipping;
+uniform bool u_DoubleSided;
+const vec3 c_ClipCapColor = vec3(0.8, 0.2, 0.2);
+
+// replace the base color by a checkerboard to inspect the UVs
+uniform bool u_UVChecker;
+// texture coordinate set of the checker (-1: the one of the base color texture)
+uniform int u_UVCheckerTexCoord;
+uniform sampler2D u_UVCheckerSampler;
+
 // TODO!: remove or ifdef?
 // debugging flags used for shader output of intermediate PBR variables
 uniform vec4 u_ScaleDiffBaseMR;
@@ -74,8 +166,60 @@ in vec3 v_Position;
 
 in vec2 v_UV[2];
 
+vec2 transformUV(mat3 transform, int texCoord)
+{
+    return (transform * vec3(v_UV[texCoord], 1.0)).xy;
+}
+
+// Ramp of the texel density (texels per world unit, from the ratio of the texture space
+// and world space area covered by the pixel) in stops relative to u_TexelDensity
+vec3 getTexelDensityColor()
+{
+#ifdef HAS_BASECOLORMAP
+    vec2 uv = transformUV(u_BaseColorUVTransform, u_BaseColorTexCoord) * vec2(textureSize(u_BaseColorSampler, 0));
+    vec2 uv_dx = dFdx(uv);
+    vec2 uv_dy = dFdy(uv);
+    float texelArea = abs(uv_dx.x * uv_dy.y - uv_dx.y * uv_dy.x);
+    float worldArea = length(cross(dFdx(v_Position), dFdy(v_Position)));
+    if (worldArea == 0.0 || texelArea == 0.0) {
+        return vec3(0.0); // degenerate UVs or triangles
+    }
+    float stops = clamp(log2(sqrt(texelArea / worldArea) / u_TexelDensity) / 3.0, -1.0, 1.0);
+    return stops < 0.0 ? mix(vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0), -stops)
+                       : mix(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), stops);
+#else
+    return vec3(0.5); // untextured
+#endif
+}
+
+// color space overrides: bits by texture unit of the textures that are sRGB where the slot
+// expects linear data (decoded) or linear where it expects sRGB colors (encoded),
+// see `Material::color_space_conversions`
+uniform int u_ColorSpaceDecode;
+uniform int u_ColorSpaceEncode;
+
+vec4 convertColorSpace(vec4 color, int unit)
+{
+    if ((u_ColorSpaceDecode & (1 << unit)) != 0) {
+        vec3 linear = color.rgb / 12.92;
+        vec3 curve = pow((color.rgb + 0.055) / 1.055, vec3(2.4));
+        color.rgb = mix(curve, linear, step(color.rgb, vec3(0.04045)));
+    } else if ((u_ColorSpaceEncode & (1 << unit)) != 0) {
+        vec3 linear = color.rgb * 12.92;
+        vec3 curve = 1.055 * pow(color.rgb, vec3(1.0 / 2.4)) - 0.055;
+        color.rgb = mix(curve, linear, step(color.rgb, vec3(0.0031308)));
+    }
+    return color;
+}
+
 in vec4 v_Color;
 
+#ifdef HAS_VERTEX_AO
+in float v_Occlusion; // baked at load time
+#endif
+
+in float v_FragDepth;
+
 #ifdef HAS_NORMALS
 #ifdef HAS_TANGENTS
 in mat3 v_TBN;
@@ -84,7 +228,9 @@ in vec3 v_Normal;
 #endif
 #endif
 
-out vec4 FragColor;
+layout(location = 0) out vec4 FragColor;
+// sum of the weighted alphas for order-independent transparency (ignored otherwise)
+layout(location = 1) out float OitWeight;
 
 // Encapsulate the various inputs used by the various functions in the shading equation
 // We store values in this struct to simplify the integration of alternative implementations
@@ -112,12 +258,24 @@ const float c_MinRoughness = 0.04;
 // or from the interpolated mesh normal and tangent attributes.
 vec3 getNormal()
 {
+    if (u_FlatShading) {
+        // the derivatives are constant across a triangle; the result always faces the camera
+        return normalize(cross(dFdx(v_Position), dFdy(v_Position)));
+    }
+
+    // the tangent space follows the texture coordinates of the normal map, which may use another set
+#ifdef HAS_NORMALMAP
+    vec2 uv = transformUV(u_NormalUVTransform, u_NormalTexCoord);
+#else
+    vec2 uv = v_UV[0];
+#endif
+
     // Retrieve the tangent space matrix
 #ifndef HAS_TANGENTS
     vec3 pos_dx = dFdx(v_Position);
     vec3 pos_dy = dFdy(v_Position);
-    vec3 tex_dx = dFdx(vec3(v_UV[0], 0.0));
-    vec3 tex_dy = dFdy(vec3(v_UV[0], 0.0));
+    vec3 tex_dx = dFdx(vec3(uv, 0.0));
+    vec3 tex_dy = dFdy(vec3(uv, 0.0));
     vec3 t = (tex_dy.t * pos_dx - tex_dx.t * pos_dy) / (tex_dx.s * tex_dy.t - tex_dy.s * tex_dx.t);
 
 #ifdef HAS_NORMALS
@@ -134,7 +292,7 @@ vec3 getNormal()
 #endif
 
 #ifdef HAS_NORMALMAP
-    vec3 n = texture(u_NormalSampler, v_UV[u_NormalTexCoord]).rgb;
+    vec3 n = convertColorSpace(texture(u_NormalSampler, uv), 1).rgb;
     n = normalize(tbn * ((2.0 * n - 1.0) * vec3(u_NormalScale, u_NormalScale, 1.0)));
 #else
     // The tbn matrix is linearly interpolated, so we need to re-normalize
@@ -217,6 +375,92 @@ float microfacetDistribution(PBRInfo pbrInputs)
     return roughnessSq / (M_PI * f * f);
 }
 
+#ifdef HAS_SHEEN
+// "Charlie" sheen distribution (Estevez and Kulla 2017), as referenced by KHR_materials_sheen
+float sheenDistribution(float sheenRoughness, float NdotH)
+{
+    float alpha = max(sheenRoughness * sheenRoughness, 0.000001);
+    float invAlpha = 1.0 / alpha;
+    float sin2h = max(1.0 - NdotH * NdotH, 0.0078125); // 2^-7, avoids fp16 underflow
+    return (2.0 + invAlpha) * pow(sin2h, invAlpha * 0.5) / (2.0 * M_PI);
+}
+
+float sheenLambda(float x, float alpha)
+{
+    float oneMinusAlphaSq = (1.0 - alpha) * (1.0 - alpha);
+    float a = mix(21.5473, 25.3245, oneMinusAlphaSq);
+    float b = mix(3.82987, 3.32435, oneMinusAlphaSq);
+    float c = mix(0.19823, 0.16801, oneMinusAlphaSq);
+    float d = mix(-1.97760, -1.27393, oneMinusAlphaSq);
+    float e = mix(-4.32054, -4.85967, oneMinusAlphaSq);
+    return exp(a / (1.0 + b * pow(x, c)) + d * x + e);
+}
+
+float sheenLambdaFull(float cosTheta, float alpha)
+{
+    float l = sheenLambda(cosTheta, alpha);
+    return cosTheta < 0.5 ? l : exp(2.0 * sheenLambda(0.5, alpha) - sheenLambda(1.0 - cosTheta, alpha));
+}
+
+// Visibility term of the Charlie sheen BRDF (including the 1 / (4 NdotL NdotV) denominator)
+float sheenVisibility(float NdotL, float NdotV, float sheenRoughness)
+{
+    float alpha = max(sheenRoughness * sheenRoughness, 0.000001);
+    float visibility = 1.0 / ((1.0 + sheenLambdaFull(NdotV, alpha) + sheenLambdaFull(NdotL, alpha)) * (4.0 * NdotV * NdotL));
+    return clamp(visibility, 0.0, 1.0);
+}
+#endif
+
+#ifdef HAS_TRANSMISSION
+// Light transmitted from behind the surface, blurred according to roughness (via the mip chain)
+vec3 getTransmittedLight(float perceptualRoughness)
+{
+    vec2 framebufferSize = vec2(textureSize(u_TransmissionFramebufferSampler, 0));
+    vec2 uv = gl_FragCoord.xy / framebufferSize;
+    float lod = log2(max(framebufferSize.x, framebufferSize.y)) * perceptualRoughness;
+    return textureLod(u_TransmissionFramebufferSampler, uv, lod).rgb;
+}
+#endif
+
+// Raw texture selected by u_DebugTexture; magenta if the material doesn't have it
+vec4 getDebugTexture()
+{
+    vec4 missing = vec4(1.0, 0.0, 1.0, 1.0);
+    if (u_DebugTexture == 1) {
+#ifdef HAS_BASECOLORMAP
+        return texture(u_BaseColorSampler, transformUV(u_BaseColorUVTransform, u_BaseColorTexCoord));
+#endif
+    } else if (u_DebugTexture == 2) {
+#ifdef HAS_METALROUGHNESSMAP
+        return texture(u_MetallicRoughnessSampler, transformUV(u_MetallicRoughnessUVTransform, u_MetallicRoughnessTexCoord));
+#endif
+#ifdef HAS_SPECULARGLOSSINESSMAP
+        return texture(u_SpecularGlossinessSampler, v_UV[u_SpecularGlossinessTexCoord]);
+#endif
+    } else if (u_DebugTexture == 3) {
+#ifdef HAS_OCCLUSIONMAP
+        return texture(u_OcclusionSampler, transformUV(u_OcclusionUVTransform, u_OcclusionTexCoord));
+#endif
+    } else if (u_DebugTexture == 4) {
+#ifdef HAS_EMISSIVEMAP
+        return texture(u_EmissiveSampler, transformUV(u_EmissiveUVTransform, u_EmissiveTexCoord));
+#endif
+    } else if (u_DebugTexture == 5) {
+#ifdef HAS_NORMALMAP
+        return texture(u_NormalSampler, transformUV(u_NormalUVTransform, u_NormalTexCoord));
+#endif
+    } else if (u_DebugTexture == 6 || u_DebugTexture == 7) {
+        // repeating outside of [0, 1], overlaid with the checker mapped by the same set
+        vec2 uv = v_UV[u_DebugTexture - 6];
+        vec4 gradient = vec4(fract(uv), 0.0, 1.0);
+        if (u_UVChecker) {
+            gradient.rgb *= texture(u_UVCheckerSampler, uv).rgb;
+        }
+        return gradient;
+    }
+    return missing;
+}
+
 void main()
 {
     // Metallic and Roughness material properties are packed together
@@ -227,9 +471,21 @@ void main()
 #ifdef HAS_METALROUGHNESSMAP
     // Roughness is stored in the 'g' channel, metallic is stored in the 'b' channel.
     // This layout intentionally reserves the 'r' channel for (optional) occlusion map data
-    vec4 mrSample = texture(u_MetallicRoughnessSampler, v_UV[u_MetallicRoughnessTexCoord]);
+    vec4 mrSample = convertColorSpace(texture(u_MetallicRoughnessSampler,
+        transformUV(u_MetallicRoughnessUVTransform, u_MetallicRoughnessTexCoord)), 3);
     perceptualRoughness = mrSample.g * perceptualRoughness;
     metallic = mrSample.b * metallic;
+#endif
+#ifdef HAS_SPECULARGLOSSINESS
+    vec3 specular = u_SpecularFactor;
+    float glossiness = u_GlossinessFactor;
+#ifdef HAS_SPECULARGLOSSINESSMAP
+    vec4 sgSample = convertColorSpace(texture(u_SpecularGlossinessSampler, v_UV[u_SpecularGlossinessTexCoord]), 3);
+    specular *= sgSample.rgb;
+    glossiness *= sgSample.a;
+#endif
+    perceptualRoughness = 1.0 - glossiness;
+    metallic = 0.0;
 #endif
     perceptualRoughness = clamp(perceptualRoughness, c_MinRoughness, 1.0);
     metallic = clamp(metallic, 0.0, 1.0);
@@ -239,17 +495,41 @@ void main()
 
     // The albedo may be defined from a base texture or a flat color
 #ifdef HAS_BASECOLORMAP
-    vec4 baseColor = texture(u_BaseColorSampler, v_UV[u_BaseColorTexCoord]) * u_BaseColorFactor;
+    vec4 baseColor = convertColorSpace(texture(u_BaseColorSampler,
+        transformUV(u_BaseColorUVTransform, u_BaseColorTexCoord)), 0) * u_BaseColorFactor;
 #else
     vec4 baseColor = u_BaseColorFactor;
 #endif
+    if (u_UVChecker && u_UVCheckerTexCoord >= 0) {
+        baseColor.rgb = texture(u_UVCheckerSampler, v_UV[u_UVCheckerTexCoord]).rgb;
+    } else if (u_UVChecker) {
+#ifdef HAS_BASECOLORMAP
+        baseColor.rgb = texture(u_UVCheckerSampler, transformUV(u_BaseColorUVTransform, u_BaseColorTexCoord)).rgb;
+#else
+        baseColor.rgb = texture(u_UVCheckerSampler, v_UV[0]).rgb;
+#endif
+    }
     // spec: COLOR_0 ... acts as an additional linear multiplier to baseColor
     baseColor *= v_Color;
 
-    vec3 f0 = vec3(0.04);
+#ifdef HAS_SPECULARGLOSSINESS
+    vec3 specularColor = specular;
+    vec3 diffuseColor = baseColor.rgb * (1.0 - max(max(specular.r, specular.g), specular.b));
+#else
+    float specularWeight = u_DielectricSpecularFactor;
+#ifdef HAS_DIELECTRICSPECULARMAP
+    specularWeight *= convertColorSpace(texture(u_DielectricSpecularSampler, v_UV[u_DielectricSpecularTexCoord]), 11).a;
+#endif
+    vec3 specularTint = u_DielectricSpecularColorFactor;
+#ifdef HAS_DIELECTRICSPECULARCOLORMAP
+    specularTint *= convertColorSpace(texture(u_DielectricSpecularColorSampler, v_UV[u_DielectricSpecularColorTexCoord]), 12).rgb;
+#endif
+    // spec (KHR_materials_specular): min(f0 * color, 1) * weight
+    vec3 f0 = min(vec3(u_DielectricF0) * specularTint, vec3(1.0)) * specularWeight;
     vec3 diffuseColor = baseColor.rgb * (vec3(1.0) - f0);
     diffuseColor *= 1.0 - metallic;
     vec3 specularColor = mix(f0, baseColor.rgb, metallic);
+#endif
 
     // Compute reflectance.
     float reflectance = max(max(specularColor.r, specularColor.g), specularColor.b);
@@ -257,6 +537,10 @@ void main()
     // For typical incident reflectance range (between 4% to 100%) set the grazing reflectance to 100% for typical fresnel effect.
     // For very low reflectance range on highly diffuse objects (below 4%), incrementally reduce grazing reflecance to 0%.
     float reflectance90 = clamp(reflectance * 25.0, 0.0, 1.0);
+#ifndef HAS_SPECULARGLOSSINESS
+    // the dielectric reflectance at grazing angles is scaled as well (KHR_materials_specular)
+    reflectance90 = min(reflectance90, mix(specularWeight, 1.0, metallic));
+#endif
     vec3 specularEnvironmentR0 = specularColor.rgb;
     vec3 specularEnvironmentR90 = vec3(1.0, 1.0, 1.0) * reflectance90;
 
@@ -295,25 +579,74 @@ void main()
     // Calculation of analytical lighting contribution
     vec3 diffuseContrib = (1.0 - F) * diffuse(pbrInputs);
     vec3 specContrib = F * G * D / (4.0 * NdotL * NdotV);
-    vec3 color = NdotL * u_LightColor * (diffuseContrib + specContrib);
+    vec3 diffuseLight = NdotL * u_LightColor * diffuseContrib;
+    vec3 specularLight = NdotL * u_LightColor * specContrib;
+
+    float ambientOcclusion = 1.0;
+    if (u_SSAOStrength > 0.0) {
+        vec2 uv = gl_FragCoord.xy / vec2(textureSize(u_SSAOSampler, 0));
+        ambientOcclusion = mix(1.0, texture(u_SSAOSampler, uv).r, u_SSAOStrength);
+    }
+#ifdef HAS_VERTEX_AO
+    ambientOcclusion *= v_Occlusion;
+#endif
+
+#ifndef USE_IBL
+    // Add simple ambient light
+    diffuseLight += u_AmbientLightColor * u_AmbientLightIntensity * baseColor.xyz * ambientOcclusion;
+#endif
+
+#ifdef HAS_TRANSMISSION
+    // spec: the transmitted light replaces the diffuse part for dielectrics, tinted by the base color
+    float transmission = u_TransmissionFactor;
+#ifdef HAS_TRANSMISSIONMAP
+    transmission *= convertColorSpace(texture(u_TransmissionSampler, v_UV[u_TransmissionTexCoord]), 6).r;
+#endif
+    vec3 transmittedLight = (1.0 - F) * getTransmittedLight(perceptualRoughness) * baseColor.rgb;
+    diffuseLight = mix(diffuseLight, transmittedLight, transmission * (1.0 - metallic));
+#endif
+
+    vec3 color = diffuseLight + specularLight;
+
+#ifdef HAS_SHEEN
+    vec3 sheenColor = u_SheenColorFactor;
+#ifdef HAS_SHEENCOLORMAP
+    sheenColor *= convertColorSpace(texture(u_SheenColorSampler, v_UV[u_SheenColorTexCoord]), 8).rgb;
+#endif
+    float sheenRoughness = u_SheenRoughnessFactor;
+#ifdef HAS_SHEENROUGHNESSMAP
+    sheenRoughness *= convertColorSpace(texture(u_SheenRoughnessSampler, v_UV[u_SheenRoughnessTexCoord]), 9).a;
+#endif
+    // spec: the sheen layer is added on top of the base material, which is scaled by the
+    // sheen albedo; approximated by the max. sheen color component (i.e. energy isn't strictly conserved)
+    float sheenScaling = 1.0 - max(max(sheenColor.r, sheenColor.g), sheenColor.b) * 0.157;
+    vec3 sheenLight = NdotL * u_LightColor * sheenColor
+        * sheenDistribution(sheenRoughness, NdotH) * sheenVisibility(NdotL, NdotV, sheenRoughness);
+    color = color * sheenScaling + sheenLight;
+#endif
 
     // Calculate lighting contribution from image based lighting source (IBL)
 #ifdef USE_IBL
-    color += getIBLContribution(pbrInputs, n, reflection);
-#else
-    // Add simple ambient light
-    color += u_AmbientLightColor * u_AmbientLightIntensity * baseColor.xyz;
+    color += getIBLContribution(pbrInputs, n, reflection) * ambientOcclusion;
 #endif
 
     // Apply optional PBR terms for additional (optional) shading
 #ifdef HAS_OCCLUSIONMAP
-    float ao = texture(u_OcclusionSampler, v_UV[u_OcclusionTexCoord]).r;
+    float ao = convertColorSpace(texture(u_OcclusionSampler,
+        transformUV(u_OcclusionUVTransform, u_OcclusionTexCoord)), 4).r;
     color = mix(color, color * ao, u_OcclusionStrength);
 #endif
 
+    vec3 emissive = u_EmissiveFactor;
 #ifdef HAS_EMISSIVEMAP
-    vec3 emissive = texture(u_EmissiveSampler, v_UV[u_EmissiveTexCoord]).rgb * u_EmissiveFactor;
+    emissive *= convertColorSpace(texture(u_EmissiveSampler,
+        transformUV(u_EmissiveUVTransform, u_EmissiveTexCoord)), 2).rgb;
+#endif
     color += emissive;
+
+#ifdef UNLIT
+    // spec: points and lines without normals aren't lit, but output the (vertex) color
+    color = baseColor.rgb;
 #endif
 
     // // This section uses mix to override final color for reference app visualization
@@ -337,8 +670,48 @@ void main()
     if (alpha == 0.0) {
         discard;
     }
+    if (u_XRayOpacity > 0.0) {
+        alpha *= u_XRayOpacity;
+    }
 
     // TODO!: apply fix from reference shader:
     // https://github.com/KhronosGroup/glTF-WebGL-PBR/pull/55/files#diff-f7232333b020880432a925d5a59e075d
     FragColor = vec4(color, alpha);
+
+    if (u_DebugTexture > 0) {
+        vec4 debugColor = getDebugTexture();
+        FragColor = vec4(u_DebugChannel >= 0 ? vec3(debugColor[u_DebugChannel]) : debugColor.rgb, 1.0);
+    }
+
+    if (u_DepthView) {
+        // reconstruct the view space depth from the non-linear window depth
+        vec4 view = u_InverseProjection * vec4(0.0, 0.0, gl_FragCoord.z * 2.0 - 1.0, 1.0);
+        float depth = -view.z / view.w;
+        FragColor = vec4(vec3(clamp((depth - u_DepthRange.x) / (u_DepthRange.y - u_DepthRange.x), 0.0, 1.0)), 1.0);
+    }
+
+    if (u_TexelDensity > 0.0) {
+        FragColor = vec4(getTexelDensityColor(), 1.0);
+    }
+
+    if (u_Clipping && !u_DoubleSided && !gl_FrontFacing) {
+        FragColor = vec4(c_ClipCapColor, 1.0);
+    }
+
+    if (u_WeightedBlended) {
+        // weight by coverage and depth (equation 10 of McGuire and Bavoil 2013)
+        float a = FragColor.a;
+        float weight = clamp(pow(min(1.0, a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0),
+            1e-2, 3e3);
+        FragColor = vec4(FragColor.rgb * a * weight, a);
+        OitWeight = a * weight;
+    } else {
+        OitWeight = 0.0;
+    }
+
+    if (u_LogDepthFC > 0.0) {
+        gl_FragDepth = log2(v_FragDepth) * u_LogDepthFC * 0.5;
+    } else {
+        gl_FragDepth = gl_FragCoord.z;
+    }
 }
@@ -17,13 +17,25 @@ layout (location = 4) in vec2 a_UV_1; // TEXCOORD_1
 #ifdef HAS_COLORS
 layout (location = 5) in vec4 a_Color; // COLOR_0
 #endif
+#ifdef HAS_VERTEX_AO
+layout (location = 12) in float a_Occlusion; // baked, see `bake_vertex_occlusion`
+#endif
+// EXT_mesh_gpu_instancing (identity for non-instanced draw calls, see `reset_instance_matrix`)
+layout (location = 8) in mat4 a_InstanceMatrix;
 
 uniform mat4 u_MVPMatrix;
 uniform mat4 u_ModelMatrix;
 
+// world space plane equation, see `ClipPlane` (only used if GL_CLIP_DISTANCE0 is enabled)
+uniform vec4 u_ClipPlane;
+
 out vec3 v_Position;
 out vec2 v_UV[2];
 out vec4 v_Color;
+#ifdef HAS_VERTEX_AO
+out float v_Occlusion;
+#endif
+out float v_FragDepth; // for the logarithmic depth buffer
 
 #ifdef HAS_NORMALS
 #ifdef HAS_TANGENTS
@@ -36,18 +48,22 @@ out vec3 v_Normal;
 
 void main()
 {
-  vec4 pos = u_ModelMatrix * a_Position;
+  mat4 modelMatrix = u_ModelMatrix * a_InstanceMatrix;
+  vec4 pos = modelMatrix * a_Position;
   v_Position = vec3(pos.xyz) / pos.w;
+  gl_ClipDistance[0] = dot(vec4(v_Position, 1.0), u_ClipPlane);
 
   #ifdef HAS_NORMALS
   #ifdef HAS_TANGENTS
   // TODO!: the reference shader was updated to use the normal matrix here
-  vec3 normalW = normalize(vec3(u_ModelMatrix * vec4(a_Normal.xyz, 0.0)));
-  vec3 tangentW = normalize(vec3(u_ModelMatrix * vec4(a_Tangent.xyz, 0.0)));
-  vec3 bitangentW = cross(normalW, tangentW) * a_Tangent.w;
+  vec3 normalW = normalize(vec3(modelMatrix * vec4(a_Normal.xyz, 0.0)));
+  vec3 tangentW = normalize(vec3(modelMatrix * vec4(a_Tangent.xyz, 0.0)));
+  // mirroring transforms flip the handedness of the tangent space
+  float mirrored = determinant(mat3(modelMatrix)) < 0.0 ? -1.0 : 1.0;
+  vec3 bitangentW = cross(normalW, tangentW) * a_Tangent.w * mirrored;
   v_TBN = mat3(tangentW, bitangentW, normalW);
   #else // HAS_TANGENTS != 1
-  v_Normal = normalize(vec3(u_ModelMatrix * vec4(a_Normal.xyz, 0.0)));
+  v_Normal = normalize(vec3(modelMatrix * vec4(a_Normal.xyz, 0.0)));
   #endif
   #endif
 
@@ -65,7 +81,12 @@ void main()
   v_Color = vec4(1.0);
   #endif
 
-  gl_Position = u_MVPMatrix * a_Position; // needs w for proper perspective correction
+  #ifdef HAS_VERTEX_AO
+  v_Occlusion = a_Occlusion;
+  #endif
+
+  gl_Position = u_MVPMatrix * a_InstanceMatrix * a_Position; // needs w for proper perspective correction
+  v_FragDepth = 1.0 + gl_Position.w;
 }
 
 
@@ -0,0 +1,15 @@
+#version 330 core
+
+// 0 is reserved for the background
+uniform uint u_PickId;
+
+flat in uint v_FeatureId;
+
+layout (location = 0) out uint PickId;
+layout (location = 1) out uint FeatureId;
+
+void main()
+{
+    PickId = u_PickId;
+    FeatureId = v_FeatureId;
+}
@@ -0,0 +1,13 @@
+#version 330 core
+layout (location = 0) in vec4 a_Position;
+layout (location = 13) in uint a_FeatureId; // see `Vertex::feature_id`
+
+uniform mat4 u_MVPMatrix;
+
+flat out uint v_FeatureId;
+
+void main()
+{
+    v_FeatureId = a_FeatureId;
+    gl_Position = u_MVPMatrix * a_Position;
+}
@@ -0,0 +1,19 @@
+#version 330 core
+// 4x4 box blur to remove the noise of the SSAO kernel rotation.
+
+in vec2 v_UV;
+out float AmbientOcclusion;
+
+uniform sampler2D u_Texture;
+
+void main()
+{
+    vec2 texelSize = 1.0 / vec2(textureSize(u_Texture, 0));
+    float result = 0.0;
+    for (int x = -2; x < 2; ++x) {
+        for (int y = -2; y < 2; ++y) {
+            result += texture(u_Texture, v_UV + vec2(float(x), float(y)) * texelSize).r;
+        }
+    }
+    AmbientOcclusion = result / 16.0;
+}
@@ -0,0 +1,55 @@
+#version 330 core
+// Screen-space ambient occlusion from the depth buffer (normals are reconstructed
+// from the view space position).
+
+#define KERNEL_SIZE 16
+
+in vec2 v_UV;
+out float AmbientOcclusion;
+
+uniform sampler2D u_Depth;
+uniform mat4 u_Projection;
+uniform mat4 u_InverseProjection;
+uniform vec3 u_Kernel[KERNEL_SIZE]; // samples in the +z hemisphere
+uniform float u_Radius; // view space
+uniform float u_Bias;
+
+vec3 getViewPosition(vec2 uv)
+{
+    float depth = texture(u_Depth, uv).r;
+    vec4 position = u_InverseProjection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return position.xyz / position.w;
+}
+
+void main()
+{
+    if (texture(u_Depth, v_UV).r == 1.0) {
+        // background
+        AmbientOcclusion = 1.0;
+        return;
+    }
+
+    vec3 position = getViewPosition(v_UV);
+    vec3 normal = normalize(cross(dFdx(position), dFdy(position)));
+
+    // rotate the kernel randomly per pixel (the noise is removed by the blur pass)
+    float angle = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453) * 6.2831853;
+    vec3 randomVector = vec3(cos(angle), sin(angle), 0.0);
+    vec3 tangent = normalize(randomVector - normal * dot(randomVector, normal));
+    mat3 TBN = mat3(tangent, cross(normal, tangent), normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < KERNEL_SIZE; ++i) {
+        vec3 samplePosition = position + TBN * u_Kernel[i] * u_Radius;
+
+        vec4 offset = u_Projection * vec4(samplePosition, 1.0);
+        vec2 sampleUV = offset.xy / offset.w * 0.5 + 0.5;
+        float sceneDepth = getViewPosition(sampleUV).z;
+
+        // ignore occluders far outside of the radius (e.g. silhouettes of foreground objects)
+        float rangeCheck = smoothstep(0.0, 1.0, u_Radius / abs(position.z - sceneDepth));
+        occlusion += (sceneDepth >= samplePosition.z + u_Bias ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    AmbientOcclusion = 1.0 - occlusion / float(KERNEL_SIZE);
+}
@@ -0,0 +1,13 @@
+#version 330 core
+
+in vec2 v_UV;
+
+uniform sampler2D u_FontSampler; // glyph coverage in the red channel
+uniform vec4 u_Color;
+
+out vec4 FragColor;
+
+void main()
+{
+    FragColor = vec4(u_Color.rgb, u_Color.a * texture(u_FontSampler, v_UV).r);
+}
@@ -138,12 +138,40 @@ pub unsafe fn print_context_info() {
     };
     debug!("Context Flags: Debug: {}, Forward Compatible: {}", debug, forward_compatible);
 
+    debug!("Extensions   : {}", context_extensions().join(", "))
+}
+
+unsafe fn context_extensions() -> Vec<String> {
     let mut num_extensions = 0;
     gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut num_extensions);
-    let extensions: Vec<_> = (0 .. num_extensions).map(|num| {
+    (0 .. num_extensions).map(|num| {
         gl_string(gl::GetStringi(gl::EXTENSIONS, num as gl::types::GLuint))
-    }).collect();
-    debug!("Extensions   : {}", extensions.join(", "))
+    }).collect()
+}
+
+/// Whether per-instance vertex attributes (`glVertexAttribDivisor`) can be used:
+/// core since OpenGL 3.3, before that with `GL_ARB_instanced_arrays`
+pub unsafe fn check_instanced_arrays() -> bool {
+    let (mut major, mut minor) = (0, 0);
+    gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+    gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    let supported = (major, minor) >= (3, 3)
+        || context_extensions().iter().any(|extension| extension == "GL_ARB_instanced_arrays");
+    // some drivers advertise the version or extension without exporting the functions
+    supported && gl::VertexAttribDivisor::is_loaded() && gl::DrawElementsInstanced::is_loaded()
+}
+
+/// Checks that the current context has at least the `required` OpenGL version
+pub unsafe fn check_context_version(required: (i32, i32)) -> Result<(), String> {
+    let (mut major, mut minor) = (0, 0);
+    gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+    gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    if (major, minor) < required {
+        return Err(format!("OpenGL {}.{} is required, but the context only supports {} (renderer: {}). \
+                            Try updating the graphics driver or requesting a newer version with --gl.",
+            required.0, required.1, gl_string(gl::GetString(gl::VERSION)), gl_string(gl::GetString(gl::RENDERER))))
+    }
+    Ok(())
 }
 
 pub unsafe fn gl_string(raw_string: *const GLubyte) -> String {
@@ -0,0 +1,252 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+
+use crate::shader::LightOptions;
+
+/// Viewing setup of a model, stored in a sidecar file next to it (see `sidecar_path`).
+/// All settings are optional; missing ones keep their defaults.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ViewConfig {
+    pub scene: Option<usize>,
+    pub camera: Option<CameraConfig>,
+    pub light: Option<LightConfig>,
+    /// Clear color (RGBA)
+    pub background: Option<[f32; 4]>,
+    /// Manual exposure in stops
+    pub exposure: Option<f32>,
+    pub animation: Option<AnimationConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraConfig {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    /// Vertical field of view in degrees
+    pub fovy: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationConfig {
+    /// glTF index
+    pub index: usize,
+    /// Time in seconds
+    pub time: f32,
+    pub playing: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightConfig {
+    /// Direction towards the light
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    pub ambient_color: [f32; 3],
+    pub ambient_intensity: f32,
+}
+
+impl From<LightOptions> for LightConfig {
+    fn from(lights: LightOptions) -> Self {
+        LightConfig {
+            direction: lights.direction.into(),
+            color: lights.color.into(),
+            ambient_color: lights.ambient_color.into(),
+            ambient_intensity: lights.ambient_intensity,
+        }
+    }
+}
+
+impl From<LightConfig> for LightOptions {
+    fn from(light: LightConfig) -> Self {
+        LightOptions {
+            direction: light.direction.into(),
+            color: light.color.into(),
+            ambient_color: light.ambient_color.into(),
+            ambient_intensity: light.ambient_intensity,
+        }
+    }
+}
+
+/// Path of the sidecar config of a model, e.g. `model.gltf.view.json` for `model.gltf`.
+/// `None` for http(s) URLs (there is no directory to put it in).
+pub fn sidecar_path(model: &str) -> Option<PathBuf> {
+    if model.starts_with("http://") || model.starts_with("https://") {
+        return None
+    }
+    Some(PathBuf::from(format!("{}.view.json", model)))
+}
+
+impl ViewConfig {
+    /// Returns `None` if the file doesn't exist or is invalid (with a warning)
+    pub fn load(path: &Path) -> Option<ViewConfig> {
+        if !path.exists() {
+            return None
+        }
+        let result = fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| serde_json::from_slice(&data).map_err(|err| err.to_string()))
+            .and_then(|json| Self::from_json(&json));
+        match result {
+            Ok(config) => {
+                info!("Loaded view config from {}", path.display());
+                Some(config)
+            },
+            Err(err) => {
+                warn!("Ignoring invalid view config {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.to_json())?;
+        fs::write(path, json + "\n")
+    }
+
+    pub fn from_json(json: &Value) -> Result<ViewConfig, String> {
+        if !json.is_object() {
+            return Err("expected an object".into())
+        }
+        let mut config = ViewConfig::default();
+        if let Some(scene) = json.get("scene") {
+            config.scene = Some(scene.as_u64().ok_or("scene: expected an index")? as usize);
+        }
+        if let Some(camera) = json.get("camera") {
+            config.camera = Some(CameraConfig {
+                position: get_array(camera, "camera.position")?,
+                target: get_array(camera, "camera.target")?,
+                fovy: get_f32(camera, "camera.fovy")?,
+            });
+        }
+        if let Some(light) = json.get("light") {
+            config.light = Some(LightConfig {
+                direction: get_array(light, "light.direction")?,
+                color: get_array(light, "light.color")?,
+                ambient_color: get_array(light, "light.ambient_color")?,
+                ambient_intensity: get_f32(light, "light.ambient_intensity")?,
+            });
+        }
+        if json.get("background").is_some() {
+            config.background = Some(get_array(json, "background")?);
+        }
+        if json.get("exposure").is_some() {
+            config.exposure = Some(get_f32(json, "exposure")?);
+        }
+        if let Some(animation) = json.get("animation") {
+            config.animation = Some(AnimationConfig {
+                index: animation.get("index").and_then(Value::as_u64).ok_or("animation.index: expected an index")?
+                    as usize,
+                time: get_f32(animation, "animation.time")?,
+                playing: animation.get("playing").and_then(Value::as_bool)
+                    .ok_or("animation.playing: expected a boolean")?,
+            });
+        }
+        Ok(config)
+    }
+
+    /// Omits unset settings
+    pub fn to_json(&self) -> Value {
+        let mut json = Map::new();
+        if let Some(scene) = self.scene {
+            json.insert("scene".into(), json!(scene));
+        }
+        if let Some(ref camera) = self.camera {
+            json.insert("camera".into(), json!({
+                "position": camera.position,
+                "target": camera.target,
+                "fovy": camera.fovy,
+            }));
+        }
+        if let Some(ref light) = self.light {
+            json.insert("light".into(), json!({
+                "direction": light.direction,
+                "color": light.color,
+                "ambient_color": light.ambient_color,
+                "ambient_intensity": light.ambient_intensity,
+            }));
+        }
+        if let Some(background) = self.background {
+            json.insert("background".into(), json!(background));
+        }
+        if let Some(exposure) = self.exposure {
+            json.insert("exposure".into(), json!(exposure));
+        }
+        if let Some(ref animation) = self.animation {
+            json.insert("animation".into(), json!({
+                "index": animation.index,
+                "time": animation.time,
+                "playing": animation.playing,
+            }));
+        }
+        Value::Object(json)
+    }
+}
+
+/// `path` is "<object>.<key>" or just "<key>" (for error messages)
+fn get_f32(json: &Value, path: &str) -> Result<f32, String> {
+    let key = path.rsplit('.').next().unwrap();
+    json.get(key)
+        .and_then(Value::as_f64)
+        .map(|v| v as f32)
+        .ok_or_else(|| format!("{}: expected a number", path))
+}
+
+fn get_array<A: Default + AsMut<[f32]>>(json: &Value, path: &str) -> Result<A, String> {
+    let key = path.rsplit('.').next().unwrap();
+    let mut array = A::default();
+    let values = json.get(key).and_then(Value::as_array);
+    match values {
+        Some(values) if values.len() == array.as_mut().len() => {
+            for (target, value) in array.as_mut().iter_mut().zip(values) {
+                *target = value.as_f64().ok_or_else(|| format!("{}: expected numbers", path))? as f32;
+            }
+            Ok(array)
+        }
+        _ => Err(format!("{}: expected an array of {} numbers", path, array.as_mut().len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_config() {
+        let json = json!({ "scene": 1, "background": [0, 0, 0, 1] });
+        let config = ViewConfig::from_json(&json).unwrap();
+        assert_eq!(config.scene, Some(1));
+        assert_eq!(config.background, Some([0.0, 0.0, 0.0, 1.0]));
+        assert!(config.camera.is_none());
+        assert!(config.light.is_none());
+
+        let err = ViewConfig::from_json(&json!({ "camera": { "position": [1, 2] } })).unwrap_err();
+        assert_eq!(err, "camera.position: expected an array of 3 numbers");
+    }
+
+    #[test]
+    fn roundtrip() {
+        let config = ViewConfig {
+            scene: Some(0),
+            camera: Some(CameraConfig { position: [1.0, 2.0, 3.0], target: [0.0; 3], fovy: 60.0 }),
+            light: None,
+            background: None,
+            exposure: Some(-1.5),
+            animation: Some(AnimationConfig { index: 2, time: 0.75, playing: false }),
+        };
+        let json = config.to_json();
+        assert!(json.get("light").is_none());
+        assert_eq!(json["animation"], json!({ "index": 2, "time": 0.75, "playing": false }));
+        assert_eq!(ViewConfig::from_json(&json).unwrap(), config);
+
+        let err = ViewConfig::from_json(&json!({ "animation": { "index": 0, "time": "1s" } })).unwrap_err();
+        assert_eq!(err, "animation.time: expected a number");
+    }
+
+    #[test]
+    fn no_sidecar_for_urls() {
+        assert_eq!(sidecar_path("models/box.glb"), Some(PathBuf::from("models/box.glb.view.json")));
+        assert_eq!(sidecar_path("https://example.com/box.glb"), None);
+    }
+}
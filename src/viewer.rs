@@ -1,37 +1,37 @@
 use std::f32::consts::PI;
+use std::fs::{self, File};
+use std::io::BufWriter;
 use std::os::raw::c_void;
-use std::path::Path;
 use std::process;
-use std::time::Instant;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use cgmath::{ Deg, Point3 };
 use collision::Aabb;
 use gl;
-use gltf;
-use glutin;
-use glutin::{
-    Api,
-    MouseScrollDelta,
-    MouseButton,
-    GlContext,
-    GlRequest,
-    GlProfile,
-    VirtualKeyCode,
-    WindowEvent,
-};
-use glutin::dpi::PhysicalSize;
-use glutin::ElementState::*;
+use glutin::{MouseButton, VirtualKeyCode};
+use glutin::dpi::{PhysicalPosition, PhysicalSize};
 
 use image::{DynamicImage};
 use log::{error, warn, info};
 
-use crate::controls::{OrbitControls, NavState};
+use crate::benchmark::BenchmarkResult;
+use crate::export::{self, ExportFormat, MergedMesh};
+use crate::controls::{CameraParams, OrbitControls, NavState};
 use crate::controls::CameraMovement::*;
-use crate::framebuffer::Framebuffer;
-use crate::importdata::ImportData;
+use crate::file_source::FileSource;
+#[cfg(feature = "gui")]
+use crate::gui::{Inspector, InspectorValues};
+use crate::http_source::HttpSource;
+use crate::importdata::{self, ImportData, ImportError, ImportOptions, Progress};
 use crate::render::*;
 use crate::render::math::*;
-use crate::utils::{print_elapsed, FrameTimer, gl_check_error, print_context_info};
+use crate::shader::{ClipPlane, DebugTexture, DebugView, LightOptions, PbrShader};
+use crate::viewconfig::{AnimationConfig, ViewConfig, CameraConfig};
+use crate::utils::{print_elapsed, FrameTimer, gl_check_error, print_context_info, check_context_version,
+    check_instanced_arrays};
+use crate::window::{GlContextOptions, GlutinHeadless, GlutinWindow, InputEvent, WindowBackend};
 
 // TODO!: complete and pass through draw calls? or get rid of multiple shaders?
 // How about state ordering anyway?
@@ -40,6 +40,20 @@ use crate::utils::{print_elapsed, FrameTimer, gl_check_error, print_context_info
 //     back_face_culling_enabled: bool
 // }
 
+/// Input that is handled by the viewer itself (as opposed to the camera controls)
+enum ViewerInput {
+    /// Left click without dragging (physical pixels)
+    Click(PhysicalPosition),
+    KeyPressed(VirtualKeyCode),
+}
+
+#[derive(Default)]
+struct InputState {
+    cursor_position: Option<PhysicalPosition>,
+    left_button_pressed_at: Option<PhysicalPosition>,
+    events: Vec<ViewerInput>,
+}
+
 #[derive(Copy, Clone)]
 pub struct CameraOptions {
     pub index: i32,
@@ -47,6 +61,17 @@ pub struct CameraOptions {
     pub target: Option<Vector3>,
     pub fovy: Deg<f32>,
     pub straight: bool,
+    /// Overrides of the automatically fitted near/far planes (see `update_depth_range`)
+    pub znear: Option<f32>,
+    pub zfar: Option<f32>,
+}
+
+/// Another model shown next to the main one for comparison, see `GltfViewer::add_model`
+struct ComparisonModel {
+    source: String,
+    root: Root,
+    scene: Scene,
+    visible: bool,
 }
 
 pub struct GltfViewer {
@@ -54,77 +79,149 @@ pub struct GltfViewer {
     dpi_factor: f64,
 
     orbit_controls: OrbitControls,
-    events_loop: Option<glutin::EventsLoop>,
-    gl_window: Option<glutin::GlWindow>,
+    backend: Box<dyn WindowBackend>,
+    input: InputState,
 
+    camera_options: CameraOptions,
+    /// Keep the aspect ratio of glTF cameras with black bars instead of filling the window
+    letterbox: bool,
+
+    /// File of the main model (for reloading)
+    source: String,
     // TODO!: get rid of scene?
     root: Root,
     scene: Scene,
+    scene_index: usize,
+    up_axis: UpAxis,
+    /// Move the scene's center to the origin (see `Scene::recenter`)
+    recenter: bool,
+    /// Multiplier of all coordinates, for models that aren't in meters
+    unit_scale: f32,
+    main_model_visible: bool,
+    /// Placed in a row along X after the main model
+    comparison_models: Vec<ComparisonModel>,
+
+    lights: LightOptions,
+    background: [f32; 4],
+
+    animation_player: AnimationPlayer,
+    /// Time step of the frame-by-frame keys (`None`: step between keyframes)
+    animation_step: Option<f32>,
 
     delta_time: f64, // seconds
+    fps_cap: Option<u32>,
     last_frame: Instant,
 
     render_timer: FrameTimer,
+
+    picker: Option<Picker>,
+    /// Node of the last pick, see `OutlineOptions::selected_only`
+    selected_node: Option<usize>,
+    /// Index of the picked primitive in the mesh of `selected_node`
+    selected_primitive: Option<usize>,
+
+    /// Clicks measure the distance between two surface points instead of picking
+    measure_mode: bool,
+    measure_points: Vec<Point3<f32>>,
+    lines: Option<LineRenderer>,
+    text: Option<TextRenderer>, // created when first used
+    vertex_vectors: Option<VertexVectors>,
+    /// World space length of unit vertex vectors relative to the scene size
+    vertex_vector_length: f32,
+    vertex_vector_lines: Option<VertexVectorLines>, // created when first enabled
+
+    log_depth: bool,
+    flat_shading: bool,
+    xray: bool,
+    /// Opacity of all surfaces in x-ray mode
+    xray_opacity: f32,
+    /// Heatmap of the number of fragments per pixel instead of the shaded scene
+    overdraw_view: bool,
+    overdraw: Option<Overdraw>, // created when first enabled
+    scale_bar_visible: bool,
+    scale_bar: Option<ScaleBar>, // created when first shown
+    outline_options: OutlineOptions,
+    outline: Option<Outline>, // created when first enabled
+    depth_view: bool,
+    texel_density_view: bool,
+    /// Texels per meter shown green in the texel density view
+    texel_density_target: f32,
+    face_culling: FaceCulling,
+    order_independent_transparency: bool,
+    /// Whether the simplified meshes are shown (with `ImportOptions::simplify`)
+    show_simplified: bool,
+    debug_view: DebugView,
+    uv_checker: Option<UvCheckerTexture>, // created when first enabled
+    uv_checker_enabled: bool,
+    clip_plane: Option<ClipPlane>,
+
+    /// Screen coverage below which coarser MSFT_lod levels are used (see `Node::select_lod`)
+    lod_threshold: f32,
+    /// Number of primitives rendered at a lower level of detail in the last frame
+    lower_lod_primitives: usize,
+    occlusion_culling: bool,
+    /// Number of primitives skipped by the occlusion culling in the last frame
+    occluded_primitives: usize,
+
+    import_options: ImportOptions,
+    /// Modification time of the main model's file when it was last loaded, if it is watched
+    /// for changes (see `set_watch`)
+    watched_modified: Option<SystemTime>,
+    last_watch_check: Instant,
+    /// Whether there were incomplete streamed textures in the last frame
+    textures_streaming: bool,
+    /// Number of items skipped in safe mode
+    skipped_items: usize,
+
+    post_process_options: PostProcessOptions,
+    post_processing: Option<PostProcessing>, // created when first enabled
+    /// Supersampling factor (1: render at the window resolution)
+    render_scale: f32,
+    supersampling: Option<Supersampling>, // created when first enabled
+    ssao: Option<Ssao>, // created when first enabled
+    #[cfg(feature = "gui")]
+    inspector: Option<Inspector>, // created by the render loop
 }
 
 /// Note about `headless` and `visible`: True headless rendering doesn't work on
 /// all operating systems, but an invisible window usually works
 impl GltfViewer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         source: &str,
         width: u32,
         height: u32,
         headless: bool,
         visible: bool,
+        vsync: bool,
         camera_options: CameraOptions,
         scene_index: usize,
+        import_options: ImportOptions,
+        gl_context: Option<GlContextOptions>,
     ) -> GltfViewer {
-        let gl_request = GlRequest::Specific(Api::OpenGl, (3, 3));
-        let gl_profile = GlProfile::Core;
-        let (events_loop, gl_window, dpi_factor, inner_size) =
-            if headless {
-                let headless_context = glutin::HeadlessRendererBuilder::new(width, height)
-                    // .with_gl(gl_request)
-                    // .with_gl_profile(gl_profile)
-                    .build()
-                    .unwrap();
-                unsafe { headless_context.make_current().unwrap() }
-                gl::load_with(|symbol| headless_context.get_proc_address(symbol) as *const _);
-                let framebuffer = Framebuffer::new(width, height);
-                framebuffer.bind();
-                unsafe { gl::Viewport(0, 0, width as i32, height as i32); }
-
-                (None, None, 1.0, PhysicalSize::new(width as f64, height as f64)) // TODO: real height (retina? (should be the same as PhysicalSize when headless?))
-            }
-            else {
-                // glutin: initialize and configure
-                let events_loop = glutin::EventsLoop::new();
-                let window_size = glutin::dpi::LogicalSize::new(width as f64, height as f64);
-
-                // TODO?: hints for 4.1, core profile, forward compat
-                let window = glutin::WindowBuilder::new()
-                        .with_title("gltf-viewer")
-                        .with_dimensions(window_size)
-                        .with_visibility(visible);
-
-                let context = glutin::ContextBuilder::new()
-                    .with_gl(gl_request)
-                    .with_gl_profile(gl_profile)
-                    .with_vsync(true);
-                let gl_window = glutin::GlWindow::new(window, context, &events_loop).unwrap();
-
-                // Real dimensions might be much higher on High-DPI displays
-                let dpi_factor = gl_window.get_hidpi_factor();
-                let inner_size = gl_window.get_inner_size().unwrap().to_physical(dpi_factor);
-
-                unsafe { gl_window.make_current().unwrap(); }
-
-                // gl: load all OpenGL function pointers
-                gl::load_with(|symbol| gl_window.get_proc_address(symbol) as *const _);
-
-                (Some(events_loop), Some(gl_window), dpi_factor, inner_size)
-            };
-        
+        let backend: Box<dyn WindowBackend> = if headless {
+            Box::new(GlutinHeadless::new(width, height, gl_context))
+        } else {
+            Box::new(GlutinWindow::new(width, height, visible, vsync, gl_context))
+        };
+        let mut viewer = Self::with_backend(backend, source, camera_options, scene_index, import_options);
+        if headless || !visible {
+            // transparent background for screenshots
+            viewer.set_background([0.0, 0.0, 0.0, 0.0]);
+        }
+        viewer
+    }
+
+    /// Creates the viewer for the current GL context of `backend` (function pointers already loaded)
+    pub fn with_backend(
+        mut backend: Box<dyn WindowBackend>,
+        source: &str,
+        camera_options: CameraOptions,
+        scene_index: usize,
+        import_options: ImportOptions,
+    ) -> GltfViewer {
+        let inner_size = backend.size();
+        let dpi_factor = backend.dpi_factor();
         let mut orbit_controls = OrbitControls::new(
             Point3::new(0.0, 0.0, 2.0),
             inner_size);
@@ -132,44 +229,133 @@ impl GltfViewer {
         orbit_controls.camera.fovy = camera_options.fovy;
         orbit_controls.camera.update_aspect_ratio(inner_size.width as f32 / inner_size.height as f32); // updates projection matrix
 
+        let background = [0.1, 0.2, 0.3, 1.0];
         unsafe {
             print_context_info();
+            if let Err(err) = check_context_version(REQUIRED_GL_VERSION) {
+                error!("{}", err);
+                process::exit(5)
+            }
+            let instanced_arrays = check_instanced_arrays();
+            set_instanced_arrays_supported(instanced_arrays);
+            if instanced_arrays {
+                info!("Instanced meshes: drawn with instanced draw calls");
+            } else {
+                info!("Instanced meshes: instanced arrays are unsupported, drawing each instance separately");
+            }
 
             gl::ClearColor(0.0, 1.0, 0.0, 1.0); // green for debugging
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
-            if headless || !visible {
-                // transparent background for screenshots
-                gl::ClearColor(0.0, 0.0, 0.0, 0.0);
-            }
-            else {
-                gl::ClearColor(0.1, 0.2, 0.3, 1.0);
-            }
+            gl::ClearColor(background[0], background[1], background[2], background[3]);
 
             gl::Enable(gl::DEPTH_TEST);
+            reset_instance_matrix();
 
             // TODO: keyboard switch?
             // draw in wireframe
             // gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
         };
 
-        let (root, scene) = Self::load(source, scene_index);
+        let mut loading_screen = LoadingScreen::new();
+        let mut events = vec![];
+        let (root, scene, skipped_items) = Self::load(source, scene_index, import_options.clone(), &mut |progress| {
+            if unsafe { loading_screen.draw(&progress) } {
+                backend.swap_buffers();
+                // keep the window responsive
+                events.clear();
+                backend.poll_events(&mut events);
+                if events.contains(&InputEvent::CloseRequested) {
+                    process::exit(0)
+                }
+            }
+        });
+        drop(loading_screen);
         let mut viewer = GltfViewer {
             size: inner_size,
             dpi_factor,
 
             orbit_controls,
 
-            events_loop,
-            gl_window,
+            backend,
+            input: InputState::default(),
+
+            camera_options,
+            letterbox: true,
 
+            source: source.to_owned(),
             root,
             scene,
+            scene_index,
+            up_axis: UpAxis::Y,
+            recenter: false,
+            unit_scale: 1.0,
+            main_model_visible: true,
+            comparison_models: vec![],
+
+            lights: LightOptions::default(),
+            background,
 
             delta_time: 0.0, // seconds
+            fps_cap: None,
             last_frame: Instant::now(),
 
             render_timer: FrameTimer::new("rendering", 300),
+
+            picker: None,
+            selected_node: None,
+            selected_primitive: None,
+
+            measure_mode: false,
+            measure_points: vec![],
+            lines: None,
+            text: None,
+            vertex_vectors: None,
+            vertex_vector_length: 0.02,
+            vertex_vector_lines: None,
+
+            log_depth: false,
+            flat_shading: false,
+            xray: false,
+            xray_opacity: 0.2,
+            overdraw_view: false,
+            overdraw: None,
+            scale_bar_visible: false,
+            scale_bar: None,
+            outline_options: OutlineOptions::default(),
+            outline: None,
+            depth_view: false,
+            texel_density_view: false,
+            texel_density_target: 512.0,
+            face_culling: FaceCulling::default(),
+            order_independent_transparency: false,
+            show_simplified: true,
+            debug_view: DebugView::default(),
+            uv_checker: None,
+            uv_checker_enabled: false,
+            clip_plane: None,
+
+            lod_threshold: 0.25,
+            lower_lod_primitives: 0,
+            occlusion_culling: false,
+            occluded_primitives: 0,
+
+            animation_player: AnimationPlayer::default(),
+            animation_step: None,
+
+            import_options,
+            watched_modified: None,
+            last_watch_check: Instant::now(),
+            textures_streaming: false,
+            skipped_items,
+
+            post_process_options: PostProcessOptions::default(),
+            post_processing: None,
+            render_scale: 1.0,
+            supersampling: None,
+            ssao: None,
+            #[cfg(feature = "gui")]
+            inspector: None,
         };
         unsafe { gl_check_error!(); };
 
@@ -178,76 +364,426 @@ impl GltfViewer {
                 camera_options.index, viewer.root.camera_nodes.len() as i32 - 1);
             process::exit(2)
         }
-        if !viewer.root.camera_nodes.is_empty() && camera_options.index != -1 {
+        if viewer.uses_gltf_camera() {
             let cam_node = &viewer.root.get_camera_node(camera_options.index as usize);
             let cam_node_info = format!("{} ({:?})", cam_node.index, cam_node.name);
             let cam = cam_node.camera.as_ref().unwrap();
             info!("Using camera {} on node {}", cam.description(), cam_node_info);
-            viewer.orbit_controls.set_camera(cam, &cam_node.final_transform);
 
             if camera_options.position.is_some() || camera_options.target.is_some() {
                 warn!("Ignoring --cam-pos / --cam-target since --cam-index is given.")
             }
         } else {
             info!("Determining camera view from bounding box");
-            viewer.set_camera_from_bounds(camera_options.straight);
-
-            if let Some(p) = camera_options.position {
-                viewer.orbit_controls.position = Point3::from_vec(p)
-            }
-            if let Some(target) = camera_options.target {
-                viewer.orbit_controls.target = Point3::from_vec(target)
-            }
         }
+        viewer.reset_camera();
+        viewer.set_title(None);
 
         viewer
     }
 
-    pub fn load(source: &str, scene_index: usize) -> (Root, Scene) {
-        let mut start_time = Instant::now();
-        // TODO!: http source
-        // let gltf =
-        if source.starts_with("http") {
-            panic!("not implemented: HTTP support temporarily removed.")
-            // let http_source = HttpSource::new(source);
-            // let import = gltf::Import::custom(http_source, Default::default());
-            // let gltf = import_gltf(import);
-            // println!(); // to end the "progress dots"
-            // gltf
-        }
-        //     else {
-        let (doc, buffers, images) = match gltf::import(source) {
-            Ok(tuple) => tuple,
+    /// Returns the number of items skipped in safe mode along with the scene.
+    /// `on_progress` is called repeatedly while loading (e.g. to show a progress bar).
+    pub fn load(source: &str, scene_index: usize, options: ImportOptions, on_progress: &mut dyn FnMut(Progress))
+        -> (Root, Scene, usize)
+    {
+        let start_time = Instant::now();
+        let imp = match importdata::import_source_with_progress(&*open_source(source), options, on_progress) {
+            Ok(imp) => imp,
             Err(err) => {
-                error!("glTF import failed: {:?}", err);
-                if let gltf::Error::Io(_) = err {
+                error!("glTF import failed: {}", err);
+                if let ImportError::Io(_) = err {
                     error!("Hint: Are the .bin file(s) referenced by the .gltf file available?")
                 }
                 process::exit(1)
             },
         };
-        let imp = ImportData { doc, buffers, images };
 
         print_elapsed("Imported glTF in ", start_time);
-        start_time = Instant::now();
+        Self::load_import_data(imp, scene_index, on_progress)
+    }
+
+    /// Replaces the current scene with one loaded from `source` (e.g. a `MemorySource`).
+    /// If the import fails, the current scene is kept.
+    pub fn load_source(&mut self, source: &dyn importdata::Source, scene_index: usize) -> Result<(), ImportError> {
+        let start_time = Instant::now();
+        let imp = importdata::import_source_with_progress(source, self.import_options.clone(), &mut |_| ())?;
+        let count = imp.doc.scenes().len();
+        if scene_index >= count.max(1) {
+            return Err(ImportError::SceneIndex { index: scene_index, count })
+        }
+        print_elapsed("Imported glTF in ", start_time);
+        self.replace_scene(imp, scene_index);
+        Ok(())
+    }
 
+    /// Loads the main model again from its file (e.g. after editing it), keeping the view
+    /// settings and the camera (unless a glTF camera is used). Can be triggered with F7.
+    /// If the import fails, the current scene is kept.
+    pub fn reload(&mut self) {
+        let start_time = Instant::now();
+        let source = open_source(&self.source);
+        let imp = match importdata::import_source_with_progress(&*source, self.import_options.clone(), &mut |_| ()) {
+            Ok(imp) => imp,
+            Err(err) => {
+                error!("Reloading {} failed, keeping the current scene: {}", self.source, err);
+                return
+            }
+        };
+        if self.scene_index >= imp.doc.scenes().len().max(1) {
+            error!("Reloading {} failed, keeping the current scene: the file has only {} scene(s)",
+                self.source, imp.doc.scenes().len());
+            return
+        }
+        print_elapsed("Reimported glTF in ", start_time);
+
+        let (position, target) = (self.orbit_controls.position, self.orbit_controls.target);
+        self.replace_scene(imp, self.scene_index);
+        if !self.uses_gltf_camera() {
+            self.orbit_controls.position = position;
+            self.orbit_controls.target = target;
+        }
+        info!("Reloaded {}", self.source);
+    }
+
+    /// Reloads the main model whenever its file is modified (checked twice per second).
+    /// Only the .gltf/.glb file itself is watched, not the buffers and images it references.
+    pub fn set_watch(&mut self, enabled: bool) {
+        self.watched_modified = if enabled {
+            // the epoch if unavailable, so that it reloads once the file can be read
+            Some(file_modified(&self.source).unwrap_or(SystemTime::UNIX_EPOCH))
+        } else {
+            None
+        };
+    }
+
+    fn check_watched_file(&mut self) {
+        let watched_modified = match self.watched_modified {
+            Some(modified) => modified,
+            None => return,
+        };
+        if self.last_watch_check.elapsed() < Duration::from_millis(500) {
+            return
+        }
+        self.last_watch_check = Instant::now();
+        if let Some(modified) = file_modified(&self.source) {
+            if modified != watched_modified {
+                self.watched_modified = Some(modified);
+                info!("{} changed, reloading", self.source);
+                self.reload();
+            }
+        }
+    }
+
+    /// Drops the current scene (and its GL resources) and shows the one from `imp` instead
+    fn replace_scene(&mut self, imp: ImportData, scene_index: usize) {
+        let (root, scene, skipped_items) = Self::load_import_data(imp, scene_index, &mut |_| ());
+        self.root = root;
+        self.scene = scene;
+        self.scene_index = scene_index;
+        self.skipped_items = skipped_items;
+        self.picker = None;
+        self.selected_node = None;
+        self.selected_primitive = None;
+        self.vertex_vector_lines = None;
+        self.animation_player = AnimationPlayer::default();
+        self.root.set_lights(&self.lights);
+        self.root.set_simplified(self.show_simplified);
+        self.update_root_transform();
+        self.set_title(None);
+    }
+
+    fn load_import_data(imp: ImportData, scene_index: usize, on_progress: &mut dyn FnMut(Progress))
+        -> (Root, Scene, usize)
+    {
+        let start_time = Instant::now();
         // load first scene
-        if scene_index >= imp.doc.scenes().len() {
+        // files without scenes are valid, they are shown as an empty scene
+        if scene_index >= imp.doc.scenes().len().max(1) {
             error!("Scene index too high - file has only {} scene(s)", imp.doc.scenes().len());
             process::exit(3)
         }
-        let base_path = Path::new(source);
-        let mut root = Root::from_gltf(&imp, base_path);
-        let scene = Scene::from_gltf(&imp.doc.scenes().nth(scene_index).unwrap(), &mut root);
+        if imp.options.geometry_only {
+            info!("Geometry only: skipping {} images and {} materials",
+                imp.doc.images().len(), imp.doc.materials().len());
+        }
+        let mut root = Root::from_gltf_with_progress(&imp, on_progress);
+        let mut scene = match imp.doc.scenes().nth(scene_index) {
+            Some(g_scene) => Scene::from_gltf(&g_scene, &mut root),
+            None => Scene::default(),
+        };
         print_elapsed(&format!("Loaded scene with {} nodes, {} meshes in ",
                 imp.doc.nodes().count(), imp.doc.meshes().len()), start_time);
 
-        (root, scene)
+        if let Some(animation) = root.animations.first() {
+            info!("Playing animation {} ({}) of {}", animation.index,
+                animation.name.as_deref().unwrap_or("unnamed"), root.animations.len());
+        }
+
+        if !scene.has_geometry(&root) {
+            warn!("The scene contains no geometry ({} nodes, {} cameras, {} lights), showing an empty grid",
+                root.nodes.len(), root.camera_nodes.len(), root.light_nodes.len());
+        }
+
+        let size = scene.bounds.max - scene.bounds.min;
+        let largest_dimension = size.x.max(size.y).max(size.z);
+        if let Some(scale) = unit_scale_hint(largest_dimension) {
+            warn!("The scene is {} across, it is probably not in meters (try e.g. --scale {})",
+                format_length(largest_dimension), scale);
+        }
+
+        let skipped_items = imp.skipped.get();
+        if skipped_items > 0 {
+            warn!("Safe mode: skipped {} item(s) that failed to load, see above", skipped_items);
+        }
+
+        let ImportData { doc, json, .. } = imp;
+        scene.set_document(doc, json);
+        let asset = &scene.json()["asset"];
+        if let Some(generator) = asset["generator"].as_str() {
+            info!("Generator: {} (glTF {})", generator, asset["version"].as_str().unwrap_or("?"));
+        }
+        if let Some(copyright) = asset["copyright"].as_str() {
+            info!("Copyright: {}", copyright);
+        }
+
+        (root, scene, skipped_items)
+    }
+
+    /// The loaded scene, e.g. for inspecting the underlying `gltf::Document`
+    #[allow(dead_code)]
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Shows or hides the node with the given index or name (including its children),
+    /// e.g. helper nodes hidden by their extras
+    pub fn set_node_visible(&mut self, node: &str, visible: bool) {
+        let found = match node.parse::<usize>().ok().filter(|index| *index < self.root.nodes.len()) {
+            Some(index) => {
+                self.scene.set_visible(&mut self.root, index, visible);
+                true
+            }
+            None => self.scene.set_node_visible_by_name(&mut self.root, node, visible),
+        };
+        if !found {
+            warn!("No node with index or name '{}' found in glTF file", node);
+        } else if !self.uses_gltf_camera() {
+            self.reset_camera();
+        }
+    }
+
+    fn uses_gltf_camera(&self) -> bool {
+        !self.root.camera_nodes.is_empty() && self.camera_options.index != -1
+    }
+
+    /// Only render the node with the given index or name (including its children)
+    pub fn isolate_node(&mut self, node: &str) {
+        let node_index = node.parse::<usize>().ok()
+            .filter(|index| *index < self.root.nodes.len())
+            .or_else(|| self.root.find_node_by_name(node));
+        let node_index = match node_index {
+            Some(index) => index,
+            None => {
+                error!("No node with index or name '{}' found in glTF file", node);
+                process::exit(4)
+            }
+        };
+        info!("Isolating node {} ({:?})", node_index, self.root.nodes[node_index].name);
+        self.scene.isolate(Some(node_index), true);
+
+        if !self.uses_gltf_camera() {
+            self.reset_camera();
+        }
+    }
+
+    /// Selects a material variant (KHR_materials_variants) by name or index
+    pub fn set_variant(&mut self, variant: &str) {
+        let index = match self.scene.find_variant(variant) {
+            Some(index) => index,
+            None => {
+                error!("No material variant '{}' found in glTF file (available: {:?})", variant, self.scene.variants());
+                process::exit(4)
+            }
+        };
+        info!("Selecting material variant {} ({})", index, self.scene.variants()[index]);
+        self.scene.set_variant(&mut self.root, Some(index));
+    }
+
+    /// Steps through the material variants and the base materials
+    pub fn cycle_variant(&mut self) {
+        let variants = self.scene.variants();
+        if variants.is_empty() {
+            info!("The file has no material variants");
+            return
+        }
+        let next = match self.scene.variant {
+            Some(index) if index + 1 < variants.len() => Some(index + 1),
+            Some(_) => None,
+            None => Some(0),
+        };
+        self.scene.set_variant(&mut self.root, next);
+        match next {
+            Some(index) => {
+                let name = format!("variant {} ({})", index, variants[index]);
+                info!("Material {}", name);
+                self.set_title(Some(&name));
+            }
+            None => {
+                info!("Base materials");
+                self.set_title(None);
+            }
+        }
+    }
+
+    /// Prints the node hierarchy of the scene to stdout
+    pub fn print_hierarchy(&self) {
+        print!("{}", self.scene.hierarchy(&self.root));
+    }
+
+    /// Rotates the scene so that content with the given up axis appears upright
+    pub fn set_up_axis(&mut self, up_axis: UpAxis) {
+        self.up_axis = up_axis;
+        self.update_root_transform();
+    }
+
+    /// Scales all models by `scale`, e.g. 0.001 for models in millimeters. Measurements,
+    /// the scale bar etc. then show corrected meters.
+    pub fn set_unit_scale(&mut self, scale: f32) {
+        self.unit_scale = scale;
+        self.update_root_transform();
+    }
+
+    /// Shows the scale bar and the model size in the bottom left corner
+    pub fn set_scale_bar_visible(&mut self, visible: bool) {
+        self.scale_bar_visible = visible;
+    }
+
+    pub fn set_recenter(&mut self, recenter: bool) {
+        self.recenter = recenter;
+        self.update_root_transform();
+        if recenter {
+            info!("Recentered scene, offset: {:?}", self.origin_offset());
+        }
+    }
+
+    /// Translation removed from the scene by recentering, i.e.
+    /// original position = rendered position + offset
+    pub fn origin_offset(&self) -> Vector3 {
+        self.scene.origin_offset
+    }
+
+    /// Up axis rotation and unit scale applied to all models
+    fn base_transform(&self) -> Matrix4 {
+        self.up_axis.rotation() * Matrix4::from_scale(self.unit_scale)
+    }
+
+    fn update_root_transform(&mut self) {
+        let base_transform = self.base_transform();
+        self.scene.set_root_transform(&mut self.root, base_transform);
+        if self.recenter {
+            self.scene.recenter(&mut self.root);
+        }
+        self.layout_comparison_models();
+        self.reset_camera();
+    }
+
+    /// Loads another model (scene 0) to compare it with the main one. The models keep
+    /// their own transform roots and are placed next to each other along X.
+    pub fn add_model(&mut self, source: &str) {
+        let (root, scene, skipped_items) = Self::load(source, 0, self.import_options.clone(), &mut |_| ());
+        root.set_lights(&self.lights);
+        root.set_simplified(self.show_simplified);
+        self.skipped_items += skipped_items;
+        self.comparison_models.push(ComparisonModel { source: source.to_owned(), root, scene, visible: true });
+        self.update_root_transform();
+    }
+
+    /// Shows or hides a model, 0 is the main model and 1.. the models added with `add_model`
+    pub fn toggle_model(&mut self, index: usize) {
+        let (visible, source) = match index {
+            0 => {
+                self.main_model_visible = !self.main_model_visible;
+                (self.main_model_visible, "main model")
+            }
+            _ => match self.comparison_models.get_mut(index - 1) {
+                Some(model) => {
+                    model.visible = !model.visible;
+                    (model.visible, model.source.as_str())
+                }
+                None => return,
+            },
+        };
+        info!("Model {} ({}) {}", index + 1, source, if visible { "shown" } else { "hidden" });
+    }
+
+    fn layout_comparison_models(&mut self) {
+        let mut max_x = self.scene.bounds.max.x;
+        let base_transform = self.base_transform();
+        for model in &mut self.comparison_models {
+            model.scene.set_root_transform(&mut model.root, base_transform);
+            let bounds = model.scene.bounds;
+            let offset = max_x + (bounds.max.x - bounds.min.x) * 0.1 - bounds.min.x;
+            model.scene.set_root_transform(&mut model.root,
+                Matrix4::from_translation(vec3(offset, 0.0, 0.0)) * base_transform);
+            max_x = model.scene.bounds.max.x;
+        }
+    }
+
+    /// Bounds of the rendered parts of all visible models
+    fn visible_bounds(&self) -> Aabb3 {
+        let mut bounds = if self.main_model_visible {
+            self.scene.visible_bounds(&self.root)
+        } else {
+            Aabb3::zero()
+        };
+        for model in self.comparison_models.iter().filter(|model| model.visible) {
+            bounds = union_bounds(&bounds, &model.scene.bounds);
+        }
+        bounds
+    }
+
+    /// Re-frames the scene with the default camera (respecting the position/target
+    /// overrides) or re-applies the glTF camera
+    fn reset_camera(&mut self) {
+        if self.uses_gltf_camera() {
+            let cam_node = &self.root.get_camera_node(self.camera_options.index as usize);
+            self.orbit_controls.set_camera(cam_node.camera.as_ref().unwrap(), &cam_node.final_transform);
+            if !self.letterbox {
+                self.orbit_controls.camera.fixed_aspect_ratio = None;
+                self.orbit_controls.camera.update_projection_matrix();
+            }
+        } else {
+            self.set_camera_from_bounds(self.camera_options.straight);
+
+            if let Some(p) = self.camera_options.position {
+                self.orbit_controls.position = Point3::from_vec(p)
+            }
+            if let Some(target) = self.camera_options.target {
+                self.orbit_controls.target = Point3::from_vec(target)
+            }
+        }
+    }
+
+    /// `visible_bounds`, or the empty scene grid around them if they have no extent (e.g. without
+    /// geometry), which would put the camera into its target
+    fn framing_bounds(&self) -> Aabb3 {
+        let bounds = self.visible_bounds();
+        if bounds.max != bounds.min {
+            return bounds
+        }
+        let half = EMPTY_SCENE_GRID_SIZE / 2.0;
+        Aabb3::new(bounds.min + vec3(-half, 0.0, -half), bounds.min + vec3(half, 0.0, half))
+    }
+
+    /// Whether there is nothing to render, then a grid and a message are shown instead
+    fn is_empty_scene(&self) -> bool {
+        self.comparison_models.is_empty() && !self.scene.has_geometry(&self.root)
     }
 
     /// determine "nice" camera perspective from bounding box. Inspired by donmccurdy/three-gltf-viewer
     fn set_camera_from_bounds(&mut self, straight: bool) {
-        let bounds = &self.scene.bounds;
+        let bounds = &self.framing_bounds();
         let size = (bounds.max - bounds.min).magnitude();
         let center = bounds.center();
 
@@ -268,53 +804,1107 @@ impl GltfViewer {
 
         self.orbit_controls.position = cam_pos;
         self.orbit_controls.target = center;
-        self.orbit_controls.camera.znear = size / 100.0;
-        self.orbit_controls.camera.zfar = Some(size * 20.0);
-        self.orbit_controls.camera.update_projection_matrix();
+        // near/far: see `update_depth_range`
+    }
+
+    /// Fits the near/far planes to the visible scene from the current camera position
+    /// (if not given by the glTF camera), then applies the overrides.
+    /// Returns the far plane distance (for the logarithmic depth buffer).
+    fn update_depth_range(&mut self) -> f32 {
+        let bounds = self.framing_bounds();
+        let (fitted_near, fitted_far) = fit_depth_range(&bounds, self.orbit_controls.position);
+        let uses_gltf_camera = self.uses_gltf_camera();
+
+        let camera = &mut self.orbit_controls.camera;
+        let (mut znear, mut zfar) = (camera.znear, camera.zfar);
+        if !uses_gltf_camera {
+            znear = fitted_near;
+            zfar = Some(fitted_far);
+        }
+        znear = self.camera_options.znear.unwrap_or(znear);
+        zfar = self.camera_options.zfar.or(zfar);
+        if (znear, zfar) != (camera.znear, camera.zfar) {
+            camera.znear = znear;
+            camera.zfar = zfar;
+            camera.update_projection_matrix();
+        }
+        zfar.unwrap_or(fitted_far)
+    }
+
+    /// Selects the levels of detail and reports changes of the number of lower LOD primitives
+    fn update_lods(&mut self, cam_params: &CameraParams) {
+        let lower_lod_primitives = self.scene.update_lods(&mut self.root, cam_params, self.lod_threshold);
+        if lower_lod_primitives != self.lower_lod_primitives {
+            info!("{} primitive(s) rendered at a lower level of detail", lower_lod_primitives);
+            self.lower_lod_primitives = lower_lod_primitives;
+        }
+    }
+
+    /// Reports changes of the number of primitives skipped by the occlusion culling
+    fn update_occluded_primitives(&mut self) {
+        let main_occluded = if self.main_model_visible { self.scene.occluded_primitives } else { 0 };
+        let occluded_primitives = main_occluded + self.comparison_models.iter()
+            .filter(|model| model.visible)
+            .map(|model| model.scene.occluded_primitives)
+            .sum::<usize>();
+        if occluded_primitives != self.occluded_primitives {
+            info!("{} primitive(s) skipped by occlusion culling", occluded_primitives);
+            self.occluded_primitives = occluded_primitives;
+        }
+    }
+
+    /// Renders at `scale` times the window resolution and downsamples the result
+    /// (supersampling anti-aliasing, also for screenshots)
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale;
+    }
+
+    /// Uses a logarithmic depth buffer, which avoids z-fighting in scenes with an extreme depth range
+    pub fn set_log_depth(&mut self, enabled: bool) {
+        self.log_depth = enabled;
+    }
+
+    /// Shades with geometric face normals, e.g. to show the polygon structure or diagnose bad normals
+    pub fn set_flat_shading(&mut self, enabled: bool) {
+        self.flat_shading = enabled;
+    }
+
+    /// Colors the surfaces by their base color texels per meter: green at `target`, blue for
+    /// up to 8 times fewer texels and red for up to 8 times more, gray without texture
+    pub fn set_texel_density_view(&mut self, enabled: bool, target: f32) {
+        self.texel_density_view = enabled;
+        self.texel_density_target = target;
+    }
+
+    /// Skips opaque primitives whose screen-space bounding box is behind the depth of a
+    /// pre-pass (hierarchical Z), for large scenes with much hidden geometry
+    pub fn set_occlusion_culling(&mut self, enabled: bool) {
+        self.occlusion_culling = enabled;
+    }
+
+    /// Draws all surfaces semi-transparent without depth test to look inside assemblies
+    pub fn set_xray(&mut self, enabled: bool, opacity: f32) {
+        self.xray = enabled;
+        self.xray_opacity = opacity;
+    }
+
+    /// Shows the number of overlapping fragments per pixel as a heatmap (to find fill-rate
+    /// hotspots). Can be toggled with F5.
+    pub fn set_overdraw_view(&mut self, enabled: bool) {
+        self.overdraw_view = enabled;
+    }
+
+    /// Forces the face culling and/or front face winding of all primitives (e.g. to diagnose
+    /// inside-out geometry). Can be cycled with Q and Z.
+    pub fn set_face_culling(&mut self, face_culling: FaceCulling) {
+        self.face_culling = face_culling;
+    }
+
+    /// Draws alpha blended primitives with weighted blended order-independent transparency
+    /// (see `WeightedBlendedOit`), which needs two extra floating point render targets
+    pub fn set_order_independent_transparency(&mut self, enabled: bool) {
+        self.order_independent_transparency = enabled;
+    }
+
+    /// Draws the back faces of transmissive materials before their front faces (see
+    /// `Material::transmission_back_faces`), for the material with the given glTF index or all
+    /// (`None`), in all models
+    pub fn set_transmission_back_faces(&mut self, material: Option<usize>, enabled: bool) {
+        let mut count = self.root.set_transmission_back_faces(material, enabled);
+        for model in &self.comparison_models {
+            count += model.root.set_transmission_back_faces(material, enabled);
+        }
+        if count == 0 {
+            warn!("No transmissive material{} found", material.map(|index| format!(" {}", index)).unwrap_or_default());
+        }
+    }
+
+    pub fn set_lod_threshold(&mut self, threshold: f32) {
+        self.lod_threshold = threshold;
     }
 
     pub fn start_render_loop(&mut self) {
+        #[cfg(feature = "gui")]
+        {
+            self.inspector = Some(Inspector::new(&*self.backend));
+        }
         loop {
             // per-frame time logic
-            // NOTE: Deliberately ignoring the seconds of `elapsed()`
-            self.delta_time = f64::from(self.last_frame.elapsed().subsec_nanos()) / 1_000_000_000.0;
-            self.last_frame = Instant::now();
+            let now = Instant::now();
+            self.delta_time = frame_delta_time(now.duration_since(self.last_frame));
+            self.last_frame = now;
 
             // events
-            let keep_running = process_events(
-                &mut self.events_loop.as_mut().unwrap(),
-                self.gl_window.as_mut().unwrap(),
-                &mut self.orbit_controls,
-                &mut self.dpi_factor,
-                &mut self.size);
-            if !keep_running {
+            let mut events = vec![];
+            self.backend.poll_events(&mut events);
+            #[cfg(feature = "gui")]
+            {
+                if let Some(ref mut inspector) = self.inspector {
+                    let dpi_factor = self.dpi_factor;
+                    events.retain(|event| !inspector.handle_event(event, dpi_factor));
+                }
+            }
+            if !events.into_iter().all(|event| self.process_event(event)) {
                 unsafe { gl_check_error!(); } // final error check so errors don't go unnoticed
                 break
             }
+            for input in self.input.events.drain(..).collect::<Vec<_>>() {
+                self.handle_input(input);
+            }
+            self.check_watched_file();
 
-            self.orbit_controls.frame_update(self.delta_time); // keyboard navigation
+            self.update(self.delta_time);
 
             self.draw();
+            #[cfg(feature = "gui")]
+            self.draw_inspector();
+
+            self.backend.swap_buffers();
+
+            if let Some(fps) = self.fps_cap {
+                // sleep for the rest of the frame
+                let frame_duration = Duration::from_secs(1) / fps;
+                let elapsed = self.last_frame.elapsed();
+                if elapsed < frame_duration {
+                    thread::sleep(frame_duration - elapsed);
+                }
+            }
+        }
+    }
+
+    /// Draws the inspector panel (if shown) over the frame and applies its changes
+    #[cfg(feature = "gui")]
+    fn draw_inspector(&mut self) {
+        let inspector = match self.inspector {
+            Some(ref mut inspector) => inspector,
+            None => return,
+        };
+        let current = InspectorValues {
+            exposure: self.post_process_options.exposure.stops,
+            tone_mapping: self.post_process_options.tone_mapping,
+            lights: self.lights,
+            background: self.background,
+        };
+        let mut values = current;
+        inspector.draw(self.size, self.dpi_factor, &mut values, &self.root.materials);
+        self.post_process_options.exposure.stops = values.exposure;
+        self.post_process_options.tone_mapping = values.tone_mapping;
+        if values.lights != current.lights {
+            self.set_lights(values.lights);
+        }
+        if values.background != current.background {
+            self.set_background(values.background);
+        }
+    }
+
+    pub fn set_lights(&mut self, lights: LightOptions) {
+        self.lights = lights;
+        self.root.set_lights(&lights);
+        for model in &self.comparison_models {
+            model.root.set_lights(&lights);
+        }
+    }
+
+    /// Sets the clear color (RGBA)
+    pub fn set_background(&mut self, color: [f32; 4]) {
+        self.background = color;
+        unsafe { gl::ClearColor(color[0], color[1], color[2], color[3]); }
+    }
+
+    /// The current camera, lights etc. (e.g. for saving as sidecar file)
+    pub fn view_config(&self) -> ViewConfig {
+        ViewConfig {
+            scene: Some(self.scene_index),
+            camera: Some(CameraConfig {
+                position: self.orbit_controls.position.into(),
+                target: self.orbit_controls.target.into(),
+                fovy: self.orbit_controls.camera.fovy.0,
+            }),
+            light: Some(self.lights.into()),
+            background: Some(self.background),
+            exposure: Some(self.post_process_options.exposure.stops),
+            animation: self.root.animations.get(self.animation_player.animation).map(|animation| AnimationConfig {
+                index: animation.index,
+                time: self.animation_player.time,
+                playing: self.animation_player.playing,
+            }),
+        }
+    }
+
+    pub fn set_post_process_options(&mut self, options: PostProcessOptions) {
+        self.post_process_options = options;
+    }
+
+    /// Outlines the silhouettes and creases of the main model (or only the picked node)
+    pub fn set_outline_options(&mut self, options: OutlineOptions) {
+        self.outline_options = options;
+    }
+
+    /// Steps through the outline modes: off, whole scene, picked node
+    fn cycle_outline(&mut self) {
+        let options = &mut self.outline_options;
+        match (options.enabled, options.selected_only) {
+            (false, _) => {
+                options.enabled = true;
+                options.selected_only = false;
+            }
+            (true, false) => options.selected_only = true,
+            (true, true) => options.enabled = false,
+        }
+        info!("Outline {}", match (options.enabled, options.selected_only) {
+            (false, _) => "disabled",
+            (true, false) => "enabled",
+            (true, true) => "enabled for the picked node (click to select)",
+        });
+    }
+
+    /// Changes the manual exposure by the given number of stops
+    pub fn adjust_exposure(&mut self, stops: f32) {
+        let exposure = &mut self.post_process_options.exposure;
+        exposure.stops += stops;
+        info!("Exposure: {:+} stops", exposure.stops);
+    }
+
+    /// Steps through rendering only the primitives of each material (in glTF order) and all primitives again
+    pub fn cycle_solo_material(&mut self) {
+        let mut materials = self.root.materials.clone();
+        materials.sort_by_key(|material| material.index);
+        let next = match self.scene.solo_material {
+            Some(ref current) => materials.iter().position(|material| Rc::ptr_eq(material, current))
+                .map(|i| i + 1),
+            None => Some(0),
+        };
+        self.scene.solo_material = next.and_then(|i| materials.get(i)).cloned();
+
+        match self.scene.solo_material {
+            Some(ref material) => {
+                let primitives = self.root.meshes.iter()
+                    .flat_map(|mesh| mesh.primitives.iter())
+                    .filter(|primitive| self.scene.shows_primitive(primitive))
+                    .count();
+                let name = format!("material {} ({}, {} primitives)",
+                    material.index.map_or("default".to_owned(), |index| index.to_string()),
+                    material.name.as_deref().unwrap_or("unnamed"), primitives);
+                info!("Solo {}", name);
+                self.set_title(Some(&name));
+            }
+            None => {
+                info!("Showing all materials");
+                self.set_title(None);
+            }
+        }
+    }
+
+    /// Cycles the vertex vector lines through normals, normals with tangents and off
+    pub fn cycle_vertex_vectors(&mut self) {
+        self.vertex_vectors = VertexVectors::next(self.vertex_vectors);
+        info!("Vertex vectors: {}", match self.vertex_vectors {
+            Some(VertexVectors::Normals) => "normals",
+            Some(VertexVectors::TangentSpace) => "normals, tangents and bitangents",
+            None => "off",
+        });
+    }
+
+    /// Multiplies the length of the vertex vector lines by `factor`
+    pub fn scale_vertex_vectors(&mut self, factor: f32) {
+        self.vertex_vector_length *= factor;
+        info!("Vertex vector length: {}% of the scene size", self.vertex_vector_length * 100.0);
+    }
+
+    /// Limits the frame rate of the render loop (in addition to vsync)
+    pub fn set_fps_cap(&mut self, fps: Option<u32>) {
+        self.fps_cap = fps;
+    }
+
+    /// Advances everything that moves over time by `delta_time` seconds
+    fn update(&mut self, delta_time: f64) {
+        self.orbit_controls.frame_update(delta_time); // keyboard navigation
+        self.update_animation(delta_time as f32);
+    }
+
+    fn update_animation(&mut self, delta_time: f32) {
+        if self.animation_player.update(&mut self.root, delta_time) {
+            self.scene.update_transforms(&mut self.root);
+        }
+    }
+
+    /// Updates the camera controls and queues the viewer's own input (see `handle_input`).
+    /// Returns whether to keep running.
+    pub fn process_event(&mut self, event: InputEvent) -> bool {
+        let orbit_controls = &mut self.orbit_controls;
+        let input_state = &mut self.input;
+        match event {
+            InputEvent::CloseRequested => return false,
+            InputEvent::Resized(ph) => {
+                // This doesn't seem to be needed on macOS but linux X11, Wayland and Windows
+                // do need it.
+                unsafe { gl::Viewport(0, 0, ph.width as i32, ph.height as i32); }
+
+                self.size = ph;
+                orbit_controls.camera.update_aspect_ratio((ph.width / ph.height) as f32);
+                orbit_controls.screen_size = ph;
+            },
+            InputEvent::HiDpiFactorChanged(f) => {
+                self.dpi_factor = f;
+            },
+            InputEvent::MouseButton { button, pressed: true } => {
+                match button {
+                    MouseButton::Left => {
+                        orbit_controls.state = NavState::Rotating;
+                        input_state.left_button_pressed_at = input_state.cursor_position;
+                    },
+                    MouseButton::Right => {
+                        orbit_controls.state = NavState::Panning;
+                    },
+                    _ => ()
+                }
+            },
+            InputEvent::MouseButton { button, pressed: false } => {
+                if button == MouseButton::Left {
+                    // only a click if the cursor didn't move (i.e. no rotation)
+                    let pressed_at = input_state.left_button_pressed_at.take();
+                    if let (Some(p), Some(c)) = (pressed_at, input_state.cursor_position) {
+                        if (p.x - c.x).abs() < 1.0 && (p.y - c.y).abs() < 1.0 {
+                            input_state.events.push(ViewerInput::Click(c));
+                        }
+                    }
+                }
+                match (button, orbit_controls.state.clone()) {
+                    (MouseButton::Left, NavState::Rotating) | (MouseButton::Right, NavState::Panning) => {
+                        orbit_controls.state = NavState::None;
+                        orbit_controls.handle_mouse_up();
+                    },
+                    _ => ()
+                }
+            }
+            InputEvent::CursorMoved(ph) => {
+                input_state.cursor_position = Some(ph);
+                orbit_controls.handle_mouse_move(ph)
+            },
+            InputEvent::Scroll(delta) => orbit_controls.process_mouse_scroll(delta),
+            InputEvent::Key { key, pressed } => {
+                if pressed {
+                    input_state.events.push(ViewerInput::KeyPressed(key));
+                }
+                match key {
+                    VirtualKeyCode::Escape if pressed => return false,
+                    VirtualKeyCode::W | VirtualKeyCode::Up    => orbit_controls.process_keyboard(FORWARD, pressed),
+                    VirtualKeyCode::S | VirtualKeyCode::Down  => orbit_controls.process_keyboard(BACKWARD, pressed),
+                    VirtualKeyCode::A | VirtualKeyCode::Left  => orbit_controls.process_keyboard(LEFT, pressed),
+                    VirtualKeyCode::D | VirtualKeyCode::Right => orbit_controls.process_keyboard(RIGHT, pressed),
+                    _ => ()
+                }
+            }
+            InputEvent::Character(_) => (),
+        }
+        true
+    }
+
+    /// The playback state of the animation, e.g. for pausing or jumping to a specific time
+    #[allow(dead_code)]
+    pub fn animation_player(&mut self) -> &mut AnimationPlayer {
+        &mut self.animation_player
+    }
 
-            self.gl_window.as_ref().unwrap().swap_buffers().unwrap();
+    fn handle_input(&mut self, input: ViewerInput) {
+        match input {
+            ViewerInput::Click(position) if self.measure_mode => self.measure(position),
+            ViewerInput::Click(position) => self.pick(position),
+            #[cfg(feature = "gui")]
+            ViewerInput::KeyPressed(VirtualKeyCode::Grave) => {
+                if let Some(ref mut inspector) = self.inspector {
+                    inspector.visible = !inspector.visible;
+                }
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::M) => {
+                self.measure_mode = !self.measure_mode;
+                self.measure_points.clear();
+                info!("Measure mode {}", if self.measure_mode { "on: click two points" } else { "off" });
+                if !self.measure_mode {
+                    self.set_title(None);
+                }
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::B) => {
+                let bloom = &mut self.post_process_options.bloom;
+                bloom.enabled = !bloom.enabled;
+                info!("Bloom {}", if bloom.enabled { "enabled" } else { "disabled" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::O) => {
+                let ssao = &mut self.post_process_options.ssao;
+                ssao.enabled = !ssao.enabled;
+                info!("SSAO {}", if ssao.enabled { "enabled" } else { "disabled" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::E) => {
+                let exposure = &mut self.post_process_options.exposure;
+                exposure.auto = !exposure.auto;
+                info!("Auto exposure {}", if exposure.auto { "enabled" } else { "disabled" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::Equals) |
+            ViewerInput::KeyPressed(VirtualKeyCode::Add) => self.adjust_exposure(1.0),
+            ViewerInput::KeyPressed(VirtualKeyCode::Minus) |
+            ViewerInput::KeyPressed(VirtualKeyCode::Subtract) => self.adjust_exposure(-1.0),
+            ViewerInput::KeyPressed(VirtualKeyCode::U) => {
+                let up_axis = if self.up_axis == UpAxis::Z { UpAxis::Y } else { UpAxis::Z };
+                info!("Up axis: {:?}", up_axis);
+                self.set_up_axis(up_axis);
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::T) => {
+                self.debug_view.texture = DebugTexture::next(self.debug_view.texture);
+                self.print_debug_view();
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::L) => {
+                self.debug_view = if self.debug_view.texture == Some(DebugTexture::BaseColor) {
+                    DebugView::default()
+                } else {
+                    DebugView { texture: Some(DebugTexture::BaseColor), channel: None }
+                };
+                self.print_debug_view();
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::F6) => self.toggle_texture_color_space(),
+            ViewerInput::KeyPressed(VirtualKeyCode::Key0) => self.set_debug_channel(None),
+            ViewerInput::KeyPressed(VirtualKeyCode::Key1) => self.set_debug_channel(Some(0)),
+            ViewerInput::KeyPressed(VirtualKeyCode::Key2) => self.set_debug_channel(Some(1)),
+            ViewerInput::KeyPressed(VirtualKeyCode::Key3) => self.set_debug_channel(Some(2)),
+            ViewerInput::KeyPressed(VirtualKeyCode::Key4) => self.set_debug_channel(Some(3)),
+            ViewerInput::KeyPressed(VirtualKeyCode::F) => {
+                self.flat_shading = !self.flat_shading;
+                info!("Flat shading {}", if self.flat_shading { "enabled" } else { "disabled" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::I) => {
+                self.xray = !self.xray;
+                info!("X-ray mode {}", if self.xray { "enabled" } else { "disabled" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::F5) => {
+                self.overdraw_view = !self.overdraw_view;
+                info!("Overdraw view {}", if self.overdraw_view {
+                    format!("enabled (fragments per pixel: black 0, blue 1, cyan 2, green 3, yellow 4, red 5, \
+                             white {} or more)", OVERDRAW_MAX_COUNT)
+                } else {
+                    "disabled".to_string()
+                });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::F7) => self.reload(),
+            ViewerInput::KeyPressed(VirtualKeyCode::F8) => self.cycle_outline(),
+            ViewerInput::KeyPressed(VirtualKeyCode::F9) => {
+                self.scale_bar_visible = !self.scale_bar_visible;
+                info!("Scale bar {}", if self.scale_bar_visible { "shown" } else { "hidden" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::F10) => {
+                self.texel_density_view = !self.texel_density_view;
+                info!("Texel density view {}", if self.texel_density_view {
+                    format!("enabled (green: {} texels/m, blue: 8x fewer, red: 8x more, gray: untextured)",
+                        self.texel_density_target)
+                } else {
+                    "disabled".to_owned()
+                });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::F11) => {
+                self.occlusion_culling = !self.occlusion_culling;
+                info!("Occlusion culling {}", if self.occlusion_culling { "enabled" } else { "disabled" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::F12) => self.dump_selected_shader(),
+            ViewerInput::KeyPressed(VirtualKeyCode::J) => {
+                self.depth_view = !self.depth_view;
+                let camera = &self.orbit_controls.camera;
+                info!("Depth view {}", if self.depth_view {
+                    format!("enabled (black: near plane at {}, white: far plane at {})", camera.znear,
+                        camera.zfar.map_or("infinity (fitted to the scene)".to_owned(), |zfar| zfar.to_string()))
+                } else {
+                    "disabled".to_owned()
+                });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::Q) => {
+                self.face_culling.cull_mode = self.face_culling.cull_mode.next();
+                info!("Face culling: {}", self.face_culling.description());
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::Z) => {
+                self.face_culling.front_face = match self.face_culling.front_face {
+                    None => Some(gl::CCW),
+                    Some(gl::CCW) => Some(gl::CW),
+                    Some(_) => None,
+                };
+                info!("Face culling: {}", self.face_culling.description());
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::Y) => {
+                if self.import_options.simplify.is_none() {
+                    info!("No simplified meshes (see --simplify)");
+                    return
+                }
+                self.show_simplified = !self.show_simplified;
+                self.root.set_simplified(self.show_simplified);
+                for model in &self.comparison_models {
+                    model.root.set_simplified(self.show_simplified);
+                }
+                let (full, simplified) = self.root.triangle_counts();
+                info!("Showing the {} meshes ({} triangles)",
+                    if self.show_simplified { "simplified" } else { "original" },
+                    if self.show_simplified { simplified } else { full });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::C) => {
+                self.uv_checker_enabled = !self.uv_checker_enabled;
+                info!("UV checker {}", if self.uv_checker_enabled { "enabled" } else { "disabled" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::H) => self.print_hierarchy(),
+            ViewerInput::KeyPressed(VirtualKeyCode::F1) => self.toggle_model(0),
+            ViewerInput::KeyPressed(VirtualKeyCode::F2) => self.toggle_model(1),
+            ViewerInput::KeyPressed(VirtualKeyCode::F3) => self.toggle_model(2),
+            ViewerInput::KeyPressed(VirtualKeyCode::F4) => self.toggle_model(3),
+            ViewerInput::KeyPressed(VirtualKeyCode::Tab) => self.cycle_variant(),
+            ViewerInput::KeyPressed(VirtualKeyCode::K) => self.cycle_solo_material(),
+            ViewerInput::KeyPressed(VirtualKeyCode::G) => self.cycle_vertex_vectors(),
+            ViewerInput::KeyPressed(VirtualKeyCode::Comma) => self.scale_vertex_vectors(0.5),
+            ViewerInput::KeyPressed(VirtualKeyCode::Period) => self.scale_vertex_vectors(2.0),
+            ViewerInput::KeyPressed(VirtualKeyCode::V) => self.toggle_orthographic(),
+            ViewerInput::KeyPressed(VirtualKeyCode::X) => {
+                let letterbox = !self.letterbox;
+                self.set_letterbox(letterbox);
+                info!("{}", if letterbox { "Keeping the camera's aspect ratio" } else { "Filling the window" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::P) => self.cycle_clip_plane(),
+            ViewerInput::KeyPressed(VirtualKeyCode::N) => {
+                if let Some(ref mut clip_plane) = self.clip_plane {
+                    clip_plane.flipped = !clip_plane.flipped;
+                }
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::PageUp) => self.move_clip_plane(1.0),
+            ViewerInput::KeyPressed(VirtualKeyCode::PageDown) => self.move_clip_plane(-1.0),
+            ViewerInput::KeyPressed(VirtualKeyCode::Space) => {
+                self.animation_player.toggle_playing();
+                info!("Animation {}", if self.animation_player.playing { "playing" } else { "paused" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::R) => {
+                let looping = !self.animation_player.looping;
+                self.animation_player.set_looping(looping);
+                info!("Animation looping {}", if looping { "on" } else { "off" });
+            }
+            ViewerInput::KeyPressed(VirtualKeyCode::Home) => self.animation_player.jump_to_start(),
+            ViewerInput::KeyPressed(VirtualKeyCode::End) => self.animation_player.jump_to_end(&self.root),
+            ViewerInput::KeyPressed(VirtualKeyCode::Semicolon) => self.step_animation(false),
+            ViewerInput::KeyPressed(VirtualKeyCode::Apostrophe) => self.step_animation(true),
+            ViewerInput::KeyPressed(VirtualKeyCode::LBracket) => self.set_animation_speed(0.5),
+            ViewerInput::KeyPressed(VirtualKeyCode::RBracket) => self.set_animation_speed(2.0),
+            ViewerInput::KeyPressed(_) => (),
+        }
+    }
+
+    /// Whether glTF cameras with an aspect ratio are letterboxed/pillarboxed (default) or fill the window
+    pub fn set_letterbox(&mut self, enabled: bool) {
+        self.letterbox = enabled;
+        if self.uses_gltf_camera() {
+            let cam_node = self.root.get_camera_node(self.camera_options.index as usize);
+            let camera = &mut self.orbit_controls.camera;
+            camera.fixed_aspect_ratio = if enabled { cam_node.camera.as_ref().unwrap().fixed_aspect_ratio } else { None };
+            camera.update_projection_matrix();
+        }
+    }
+
+    /// Switches the default camera between perspective and orthographic projection,
+    /// keeping the apparent size at the target
+    pub fn toggle_orthographic(&mut self) {
+        if self.uses_gltf_camera() {
+            warn!("The projection can only be switched for the default camera (see --cam-index)");
+            return
+        }
+        let focus_distance = (self.orbit_controls.position - self.orbit_controls.target).magnitude();
+        self.orbit_controls.camera.toggle_orthographic(focus_distance);
+        info!("{} projection", if self.orbit_controls.camera.is_perspective() { "Perspective" } else { "Orthographic" });
+    }
+
+    /// Cuts away part of the scene, e.g. to look inside closed meshes (`None`: disabled)
+    pub fn set_clip_plane(&mut self, clip_plane: Option<ClipPlane>) {
+        self.clip_plane = clip_plane;
+    }
+
+    /// Switches between no clipping plane and planes perpendicular to the X, Y and Z axis
+    /// (through the center of the scene)
+    fn cycle_clip_plane(&mut self) {
+        let axis = match self.clip_plane {
+            None => 0,
+            Some(ClipPlane { axis: 2, .. }) => {
+                info!("Clipping plane disabled");
+                self.set_clip_plane(None);
+                return
+            }
+            Some(clip_plane) => clip_plane.axis + 1,
+        };
+        let center = self.scene.bounds.center();
+        info!("Clipping plane perpendicular to the {} axis", ["X", "Y", "Z"][axis]);
+        self.set_clip_plane(Some(ClipPlane { axis, position: center[axis], flipped: false }));
+    }
+
+    /// Moves the clipping plane by 2% of the scene size for each step
+    fn move_clip_plane(&mut self, steps: f32) {
+        let size = self.scene.bounds.dim();
+        if let Some(ref mut clip_plane) = self.clip_plane {
+            clip_plane.position += steps * 0.02 * size[clip_plane.axis];
+        }
+    }
+
+    /// Selects the animation with the glTF index `index` and jumps to `time` (e.g. from a view config)
+    pub fn seek_animation(&mut self, index: usize, time: f32, playing: bool) {
+        let animation = match self.root.animations.iter().position(|animation| animation.index == index) {
+            Some(animation) => animation,
+            None => {
+                warn!("Animation {} not found, the file has {} animation(s)", index, self.root.animations.len());
+                return
+            }
+        };
+        self.animation_player.seek(animation, time);
+        self.animation_player.playing = playing;
+    }
+
+    /// Makes the frame-by-frame keys step by `step` seconds instead of to the adjacent keyframe
+    pub fn set_animation_step(&mut self, step: Option<f32>) {
+        self.animation_step = step;
+    }
+
+    /// Pauses the animation and steps it forward or backward (see `set_animation_step`)
+    fn step_animation(&mut self, forward: bool) {
+        match self.animation_step {
+            Some(step) => self.animation_player.step_time(&self.root, if forward { step } else { -step }),
+            None => self.animation_player.step_keyframe(&self.root, forward),
+        }
+        if let Some(status) = self.animation_status() {
+            info!("{}", status);
+        }
+    }
+
+    /// Current time and nearest keyframe, e.g. "Animation 0: 0.500 s / 2.000 s, keyframe 3/12 (0.500 s)"
+    fn animation_status(&self) -> Option<String> {
+        let animation = self.root.animations.get(self.animation_player.animation)?;
+        let times = animation.keyframe_times();
+        let time = self.animation_player.time;
+        let keyframe = nearest_keyframe(&times, time)
+            .map(|i| format!(", keyframe {}/{} ({:.3} s)", i, times.len(), times[i]))
+            .unwrap_or_default();
+        Some(format!("Animation {}: {:.3} s / {:.3} s{}", animation.index, time, animation.duration, keyframe))
+    }
+
+    /// Multiplies the animation playback speed by `factor`
+    fn set_animation_speed(&mut self, factor: f32) {
+        let speed = self.animation_player.speed * factor;
+        self.animation_player.set_speed(speed);
+        info!("Animation speed: {}x", speed);
+    }
+
+    /// Shows only the given channel (0-3: R, G, B, A; `None`: RGB) of the debug view texture
+    fn set_debug_channel(&mut self, channel: Option<usize>) {
+        self.debug_view.channel = channel;
+        self.print_debug_view();
+    }
+
+    fn print_debug_view(&self) {
+        let texture = match self.debug_view.texture {
+            Some(texture) => texture,
+            None => {
+                info!("Debug view off");
+                return
+            }
+        };
+        let channel = match self.debug_view.channel {
+            Some(channel) => ["R", "G", "B", "A"][channel],
+            None => "RGB",
+        };
+        let tex_coord = match texture.tex_coord() {
+            Some(tex_coord) => tex_coord,
+            None => {
+                info!("Debug view: {:?} texture ({}, magenta if missing)", texture, channel);
+                return
+            }
+        };
+        info!("Debug view: TEXCOORD_{} (R: u, G: v, {}; C overlays the checker mapped by it)", tex_coord, channel);
+        // which textures actually sample this set
+        let mut count = 0;
+        for material in &self.root.materials {
+            let slots: Vec<_> = material.texture_tex_coords().into_iter()
+                .filter(|&(_, slot_tex_coord)| slot_tex_coord == tex_coord)
+                .map(|(slot, _)| slot)
+                .collect();
+            if !slots.is_empty() {
+                info!("  material {} ({}): {}", material.index.map_or("default".to_owned(), |index| index.to_string()),
+                    material.name.as_deref().unwrap_or("unnamed"), slots.join(", "));
+                count += 1;
+            }
+        }
+        if count == 0 {
+            info!("  (not used by any texture)");
+        }
+    }
+
+    /// Toggles the color space of the textures in the slot shown by the texture debug view
+    /// (in all materials of the main model) between the one the slot expects and the other one
+    fn toggle_texture_color_space(&mut self) {
+        let debug_texture = match self.debug_view.texture {
+            Some(texture) => texture,
+            None => {
+                info!("Select a texture slot with T first to override the color space of its textures");
+                return
+            }
+        };
+        // texture units, see `Material::texture_slots`
+        let unit = match debug_texture {
+            DebugTexture::BaseColor => 0,
+            DebugTexture::Normal => 1,
+            DebugTexture::Emissive => 2,
+            DebugTexture::MetallicRoughness => 3,
+            DebugTexture::Occlusion => 4,
+            DebugTexture::TexCoord0 | DebugTexture::TexCoord1 => {
+                info!("Texture coordinates don't have a color space");
+                return
+            }
+        };
+        let mut textures: Vec<(&Rc<_>, ColorSpace)> = vec![];
+        for material in &self.root.materials {
+            for (slot_unit, texture, expected) in material.texture_slots() {
+                if slot_unit == unit && !textures.iter().any(|(t, _)| Rc::ptr_eq(t, texture)) {
+                    textures.push((texture, expected));
+                }
+            }
+        }
+        if textures.is_empty() {
+            info!("No {:?} textures", debug_texture);
+        }
+        for (texture, expected) in textures {
+            let color_space = match texture.color_space.get() {
+                None => Some(expected.other()),
+                Some(_) => None,
+            };
+            texture.color_space.set(color_space);
+            info!("Texture {}{}: {:?}{}", texture.index,
+                texture.name.as_ref().map(|name| format!(" ({})", name)).unwrap_or_default(),
+                color_space.unwrap_or(expected), if color_space.is_some() { " (overridden)" } else { "" });
         }
     }
 
+    /// Overrides the color space of a texture (by glTF index) of the main model in all its uses,
+    /// e.g. for normal maps that were mistakenly saved as sRGB. `None` derives it from the usage
+    /// again (sRGB for colors, linear for other data). Not kept when reloading.
+    #[allow(dead_code)]
+    pub fn set_texture_color_space(&mut self, texture: usize, color_space: Option<ColorSpace>) {
+        if !self.root.set_texture_color_space(texture, color_space) {
+            warn!("Texture {} not found", texture);
+        }
+    }
+
+    fn pick_at(&mut self, position: PhysicalPosition) -> Option<Pick> {
+        let (width, height) = (self.size.width as u32, self.size.height as u32);
+        let picker = self.picker.get_or_insert_with(|| Picker::new(width, height));
+        picker.resize(width, height);
+
+        let cam_params = self.orbit_controls.camera_params();
+        picker.pick(&self.scene, &self.root, &cam_params, position.x as u32, position.y as u32)
+    }
+
+    /// Determine and print what's under the cursor
+    fn pick(&mut self, position: PhysicalPosition) {
+        let pick = self.pick_at(position);
+        self.selected_node = pick.as_ref().map(|pick| pick.node);
+        self.selected_primitive = pick.as_ref().map(|pick| pick.primitive);
+        if let Some(pick) = pick {
+            let node = &self.root.nodes[pick.node];
+            let mesh = node.mesh.as_ref().unwrap();
+            let primitive = &mesh.primitives[pick.primitive];
+            let material = primitive.material();
+            println!("Picked node {} ({:?}), mesh {} ({:?}), primitive {}, material {:?} ({:?}), {} triangles{}",
+                node.index, node.name, mesh.index, mesh.name, primitive.index,
+                material.index, material.name, primitive.triangle_count(),
+                pick.feature_id.map(|id| format!(", feature id {}", id)).unwrap_or_default());
+        }
+    }
+
+    /// Prints the defines of the shader variant of the picked primitive and writes its preprocessed
+    /// source (as compiled) to `shader-node<N>-primitive<M>.vert.glsl` / `.frag.glsl`
+    fn dump_selected_shader(&self) {
+        let (node_id, primitive_index) = match (self.selected_node, self.selected_primitive) {
+            (Some(node_id), Some(primitive_index)) => (node_id, primitive_index),
+            _ => {
+                warn!("No primitive picked (left click on the model first)");
+                return
+            }
+        };
+        let node = &self.root.nodes[node_id];
+        let primitive = &node.mesh.as_ref().unwrap().primitives[primitive_index];
+        let defines = primitive.shader_flags().as_strings();
+        println!("Shader of node {}, primitive {}: {}", node.index, primitive.index,
+            if defines.is_empty() { "no defines".to_owned() } else { defines.join(", ") });
+        let (vertex_source, fragment_source) = PbrShader::preprocessed_source(primitive.shader_flags());
+        for (stage, source) in &[("vert", vertex_source), ("frag", fragment_source)] {
+            let filename = format!("shader-node{}-primitive{}.{}.glsl", node.index, primitive.index, stage);
+            match fs::write(&filename, source) {
+                Ok(()) => println!("Saved {}", filename),
+                Err(err) => error!("Failed to write {}: {}", filename, err),
+            }
+        }
+    }
+
+    /// Adds a measurement point; reports the distance after the second one
+    fn measure(&mut self, position: PhysicalPosition) {
+        let pick = match self.pick_at(position) {
+            Some(pick) => pick,
+            None => return,
+        };
+        if self.measure_points.len() == 2 {
+            self.measure_points.clear();
+        }
+        self.measure_points.push(pick.position);
+        if let [a, b] = self.measure_points[..] {
+            // glTF units are meters
+            let distance = (b - a).magnitude();
+            let offset = self.origin_offset();
+            println!("Distance: {:.4} m (from {:?} to {:?})", distance, a + offset, b + offset);
+            self.set_title(Some(&format!("distance: {:.4} m", distance)));
+        }
+    }
+
+    /// Sets the window title to "gltf-viewer - <detail>", noting skipped items (safe mode)
+    fn set_title(&mut self, detail: Option<&str>) {
+        let mut title = String::from("gltf-viewer");
+        if let Some(detail) = detail {
+            title += &format!(" - {}", detail);
+        }
+        if self.skipped_items > 0 {
+            title += &format!(" ({} items skipped)", self.skipped_items);
+        }
+        self.backend.set_title(&title);
+    }
+
     // Returns whether to keep running
     pub fn draw(&mut self) {
         // render
         unsafe {
             self.render_timer.start();
 
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-
+            let (width, height) = (self.size.width as u32, self.size.height as u32);
+            let supersampled = self.render_scale != 1.0;
+            let (width, height) = if supersampled {
+                let scale = self.render_scale;
+                let supersampling = self.supersampling.get_or_insert_with(|| Supersampling::new(width, height, scale));
+                supersampling.resize(width, height, scale);
+                supersampling.begin();
+                supersampling.size()
+            } else {
+                (width, height)
+            };
+            let zfar = self.update_depth_range();
+            self.configure_root(&self.root, zfar);
+            for model in &self.comparison_models {
+                self.configure_root(&model.root, zfar);
+                model.root.set_ssao_strength(0.0); // SSAO covers the main model only
+            }
+            if self.uv_checker_enabled {
+                self.uv_checker.get_or_insert_with(UvCheckerTexture::new).bind();
+            }
+            if self.clip_plane.is_some() {
+                gl::Enable(gl::CLIP_DISTANCE0);
+            } else {
+                gl::Disable(gl::CLIP_DISTANCE0);
+            }
             let cam_params = self.orbit_controls.camera_params();
-            self.scene.draw(&mut self.root, &cam_params);
+            self.update_lods(&cam_params);
+            if self.import_options.stream_textures {
+                self.stream_textures(&cam_params);
+            }
+
+            let ssao_options = &self.post_process_options.ssao;
+            // the depth of hidden surfaces doesn't matter in x-ray mode (or for the overdraw)
+            if ssao_options.enabled && !self.xray && !self.overdraw_view {
+                let ssao = self.ssao.get_or_insert_with(|| Ssao::new(width, height));
+                ssao.resize(width, height);
+                ssao.render(&self.scene, &self.root, &cam_params, ssao_options);
+                self.root.set_ssao_strength(ssao_options.strength);
+            } else {
+                self.root.set_ssao_strength(0.0);
+            }
+
+            // the heatmap is shown as is
+            let post_processing = if self.post_process_options.enabled() && !self.overdraw_view {
+                let post_processing = self.post_processing.get_or_insert_with(|| PostProcessing::new(width, height));
+                post_processing.resize(width, height);
+                post_processing.begin();
+                Some(post_processing)
+            } else {
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                None
+            };
+
+            // the depth pre-pass ignores the clipping plane, so it would hide what the cut reveals
+            let occlusion_culling = self.occlusion_culling && self.clip_plane.is_none();
+            self.scene.face_culling = self.face_culling;
+            self.scene.order_independent_transparency = self.order_independent_transparency;
+            self.scene.occlusion_culling = occlusion_culling;
+            for model in &mut self.comparison_models {
+                model.scene.face_culling = self.face_culling;
+                model.scene.order_independent_transparency = self.order_independent_transparency;
+                model.scene.occlusion_culling = occlusion_culling;
+            }
+            if self.overdraw_view {
+                let mut scenes = vec![];
+                if self.main_model_visible {
+                    scenes.push((&self.scene, &self.root));
+                }
+                scenes.extend(self.comparison_models.iter().filter(|model| model.visible)
+                    .map(|model| (&model.scene, &model.root)));
+                let overdraw = self.overdraw.get_or_insert_with(|| Overdraw::new(width, height));
+                overdraw.resize(width, height);
+                overdraw.render(&scenes, &cam_params);
+            } else {
+                if self.main_model_visible {
+                    if self.xray {
+                        self.scene.draw_xray(&mut self.root, &cam_params);
+                    } else {
+                        self.scene.draw(&mut self.root, &cam_params);
+                    }
+                }
+                for model in self.comparison_models.iter_mut().filter(|model| model.visible) {
+                    if self.xray {
+                        model.scene.draw_xray(&mut model.root, &cam_params);
+                    } else {
+                        model.scene.draw(&mut model.root, &cam_params);
+                    }
+                }
+            }
+
+            if let Some(post_processing) = post_processing {
+                post_processing.end(&self.post_process_options, self.delta_time as f32);
+            }
+            if !self.xray && !self.overdraw_view {
+                self.update_occluded_primitives();
+            }
+
+            let outline_options = &self.outline_options;
+            if outline_options.enabled && self.main_model_visible && !self.overdraw_view {
+                let mut nodes = self.scene.visible_nodes(&self.root);
+                if outline_options.selected_only {
+                    let selected = self.selected_node.map(|node| self.root.subtree(node)).unwrap_or_default();
+                    nodes.retain(|node| selected.contains(node));
+                }
+                if !nodes.is_empty() {
+                    let outline = self.outline.get_or_insert_with(|| Outline::new(width, height));
+                    outline.resize(width, height);
+                    outline.render(&self.scene, &self.root, &nodes, &cam_params, outline_options,
+                        outline_options.thickness * self.render_scale);
+                }
+            }
+
+            if let [a, b] = self.measure_points[..] {
+                let lines = self.lines.get_or_insert_with(LineRenderer::new);
+                let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+                lines.draw(&[a.to_vec(), b.to_vec()], &vec4(1.0, 1.0, 0.0, 1.0), &view_projection, false);
+
+                // distance label at the middle of the line
+                let center = view_projection * a.midpoint(b).to_homogeneous();
+                if center.w > 0.0 {
+                    let x = (center.x / center.w + 1.0) / 2.0 * width as f32;
+                    let y = (1.0 - center.y / center.w) / 2.0 * height as f32;
+                    self.text.get_or_insert_with(TextRenderer::new).draw_text(x, y,
+                        &format!("{:.4} m", (b - a).magnitude()), 20.0 * self.render_scale, &vec4(1.0, 1.0, 0.0, 1.0));
+                }
+            }
+
+            if let Some(vectors) = self.vertex_vectors {
+                let bounds = self.scene.visible_bounds(&self.root);
+                let length = (bounds.max - bounds.min).magnitude() * self.vertex_vector_length;
+                let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+                self.vertex_vector_lines.get_or_insert_with(VertexVectorLines::new)
+                    .draw(&self.scene, &self.root, vectors, length, &view_projection);
+            }
+
+            if self.is_empty_scene() {
+                let view_projection = cam_params.projection_matrix * cam_params.view_matrix;
+                self.lines.get_or_insert_with(LineRenderer::new).draw(
+                    &grid_lines(EMPTY_SCENE_GRID_SIZE, EMPTY_SCENE_GRID_CELLS), &vec4(0.5, 0.5, 0.5, 1.0),
+                    &view_projection, true);
+                let text_size = 20.0 * self.render_scale;
+                let message = "The scene contains no geometry";
+                let x = (width as f32 - TextRenderer::char_width(text_size) * message.len() as f32) / 2.0;
+                self.text.get_or_insert_with(TextRenderer::new).draw_text(x, height as f32 / 2.0, message,
+                    text_size, &vec4(1.0, 1.0, 1.0, 1.0));
+            }
+
+            self.clear_letterbox_margins(width as i32, height as i32);
+
+            if !self.animation_player.playing {
+                if let Some(status) = self.animation_status() {
+                    let margin = 10.0 * self.render_scale;
+                    self.text.get_or_insert_with(TextRenderer::new).draw_text(margin, margin, &status,
+                        16.0 * self.render_scale, &vec4(1.0, 1.0, 1.0, 1.0));
+                }
+            }
+
+            if self.scale_bar_visible {
+                let bounds = self.visible_bounds();
+                // size of a pixel at the depth of the orbit target
+                let target = cam_params.projection_matrix * cam_params.view_matrix
+                    * self.orbit_controls.target.to_homogeneous();
+                let meters_per_pixel = 2.0 * target.w / (cam_params.projection_matrix[1][1] * height as f32);
+                self.scale_bar.get_or_insert_with(ScaleBar::new)
+                    .draw(meters_per_pixel, bounds.max - bounds.min, self.render_scale);
+            }
+
+            if supersampled {
+                if let Some(ref mut supersampling) = self.supersampling {
+                    supersampling.end();
+                }
+            }
 
             self.render_timer.end();
         }
     }
 
+    /// Uploads the next mip levels of streamed textures of all models
+    fn stream_textures(&mut self, cam_params: &CameraParams) {
+        let camera_position = Point3::from_vec(cam_params.position);
+        let mut remaining = self.scene.stream_textures(&self.root, camera_position, TEXTURE_STREAMING_BUDGET);
+        for model in &self.comparison_models {
+            remaining += model.scene.stream_textures(&model.root, camera_position, TEXTURE_STREAMING_BUDGET);
+        }
+        if remaining > 0 {
+            self.textures_streaming = true;
+        } else if self.textures_streaming {
+            info!("All textures streamed");
+            self.textures_streaming = false;
+        }
+    }
+
+    /// Sets the global render settings in the shaders of `root`
+    fn configure_root(&self, root: &Root, zfar: f32) {
+        root.set_log_depth(if self.log_depth { Some(zfar) } else { None });
+        root.set_flat_shading(self.flat_shading);
+        root.set_debug_view(&self.debug_view);
+        root.set_uv_checker(self.uv_checker_enabled, self.debug_view.texture.and_then(DebugTexture::tex_coord));
+        root.set_clip_plane(self.clip_plane.as_ref());
+        root.set_xray_opacity(if self.xray { self.xray_opacity } else { 0.0 });
+        let camera = &self.orbit_controls.camera;
+        root.set_depth_view(self.depth_view, &camera.projection_matrix, camera.znear, zfar);
+        root.set_texel_density_view(if self.texel_density_view { Some(self.texel_density_target) } else { None });
+    }
+
+    /// Clears the areas outside of the image of a camera with a fixed aspect ratio to black
+    /// (in the current framebuffer of the given size)
+    unsafe fn clear_letterbox_margins(&self, width: i32, height: i32) {
+        let (scale_x, scale_y) = self.orbit_controls.camera.letterbox_scale();
+        if (scale_x, scale_y) == (1.0, 1.0) {
+            return
+        }
+        let image_width = (width as f32 * scale_x).round() as i32;
+        let image_height = (height as f32 * scale_y).round() as i32;
+        let (x, y) = ((width - image_width) / 2, (height - image_height) / 2);
+
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+        let margins = [
+            (0, 0, x, height), (x + image_width, 0, width - x - image_width, height), // pillarbox
+            (0, 0, width, y), (0, y + image_height, width, height - y - image_height), // letterbox
+        ];
+        for &(x, y, width, height) in margins.iter().filter(|margin| margin.2 > 0 && margin.3 > 0) {
+            gl::Scissor(x, y, width, height);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+        gl::Disable(gl::SCISSOR_TEST);
+        let background = self.background;
+        gl::ClearColor(background[0], background[1], background[2], background[3]);
+    }
+
     pub fn screenshot(&mut self, filename: &str) {
         self.draw();
 
@@ -332,9 +1922,59 @@ impl GltfViewer {
             error!("{}", err);
         }
         else {
-            println!("Saved {}x{} screenshot to {}", self.size.width, self.size.height, filename);
+            let supersampling = if self.render_scale != 1.0 {
+                format!(" (rendered at {}x)", self.render_scale)
+            } else {
+                String::new()
+            };
+            println!("Saved {}x{} screenshot{} to {}", self.size.width, self.size.height, supersampling, filename);
         }
     }
+
+    /// Writes the visible triangles of the main model in world space to an OBJ or PLY
+    /// file (by extension), merged into one mesh. Points and lines are skipped.
+    pub fn export(&self, filename: &str) {
+        let format = match ExportFormat::from_path(filename) {
+            Some(format) => format,
+            None => {
+                error!("Unsupported export format: {} (expected .obj or .ply)", filename);
+                process::exit(1)
+            }
+        };
+        let mut mesh = MergedMesh::default();
+        for node_id in self.scene.visible_nodes(&self.root) {
+            let node = &self.root.nodes[node_id];
+            let node_mesh = match node.mesh {
+                Some(ref mesh) => mesh,
+                None => continue,
+            };
+            let transforms = match node.instances {
+                Some(ref instances) => instances.transforms.iter()
+                    .map(|instance| node.final_transform * instance)
+                    .collect(),
+                None => vec![node.final_transform],
+            };
+            for primitive in node_mesh.primitives.iter().filter(|p| self.scene.shows_primitive(p)) {
+                let (vertices, indices) = unsafe { (primitive.read_vertices(), primitive.read_indices()) };
+                let indices = indices.unwrap_or_else(|| (0..vertices.len() as u32).collect());
+                let triangles = match export::triangle_list(primitive.mode(), &indices) {
+                    Some(triangles) => triangles,
+                    None => continue,
+                };
+                for transform in &transforms {
+                    mesh.add(&vertices, Some(&triangles), transform);
+                }
+            }
+        }
+
+        let written = File::create(filename).and_then(|file| mesh.write(&mut BufWriter::new(file), format));
+        match written {
+            Ok(()) => println!("Exported {} vertices and {} triangles to {}", mesh.vertex_count(),
+                mesh.triangle_count(), filename),
+            Err(err) => error!("Failed to export to {}: {}", filename, err),
+        }
+    }
+
     pub fn multiscreenshot(&mut self, filename: &str, count: u32) {
         let min_angle : f32 = 0.0 ;
         let max_angle : f32 =  2.0 * PI ;
@@ -348,103 +1988,68 @@ impl GltfViewer {
             self.screenshot(&actual_name[..]);
         }
     }
-}
 
-#[allow(clippy::too_many_arguments)]
-fn process_events(
-    events_loop: &mut glutin::EventsLoop,
-    gl_window: &glutin::GlWindow,
-    mut orbit_controls: &mut OrbitControls,
-    dpi_factor: &mut f64,
-    size: &mut PhysicalSize) -> bool
-{
-    let mut keep_running = true;
-    #[allow(clippy::single_match)]
-    events_loop.poll_events(|event| {
-        match event {
-            glutin::Event::WindowEvent{ event, .. } => match event {
-                WindowEvent::CloseRequested => {
-                    keep_running = false;
-                },
-                WindowEvent::Destroyed => {
-                    // Log and exit?
-                    panic!("WindowEvent::Destroyed, unimplemented.");
-                },
-                WindowEvent::Resized(logical) => {
-                    let ph = logical.to_physical(*dpi_factor);
-                    gl_window.resize(ph);
-
-                    // This doesn't seem to be needed on macOS but linux X11, Wayland and Windows
-                    // do need it.
-                    unsafe { gl::Viewport(0, 0, ph.width as i32, ph.height as i32); }
-
-                    *size = ph;
-                    orbit_controls.camera.update_aspect_ratio((ph.width / ph.height) as f32);
-                    orbit_controls.screen_size = ph;
-                },
-                WindowEvent::HiDpiFactorChanged(f) => {
-                    *dpi_factor = f;
-                },
-                WindowEvent::DroppedFile(_path_buf) => {
-                    // TODO: drag file in
-                }
-                WindowEvent::MouseInput { button, state: Pressed, ..} => {
-                    match button {
-                        MouseButton::Left => {
-                            orbit_controls.state = NavState::Rotating;
-                        },
-                        MouseButton::Right => {
-                            orbit_controls.state = NavState::Panning;
-                        },
-                        _ => ()
-                    }
-                },
-                WindowEvent::MouseInput { button, state: Released, ..} => {
-                    match (button, orbit_controls.state.clone()) {
-                        (MouseButton::Left, NavState::Rotating) | (MouseButton::Right, NavState::Panning) => {
-                            orbit_controls.state = NavState::None;
-                            orbit_controls.handle_mouse_up();
-                        },
-                        _ => ()
-                    }
-                }
-                WindowEvent::CursorMoved { position, .. } => {
-                    let ph = position.to_physical(*dpi_factor);
-                    orbit_controls.handle_mouse_move(ph)
-                },
-                WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(logical), .. } => {
-                    let ph = logical.to_physical(*dpi_factor);
-                    orbit_controls.process_mouse_scroll(ph.y as f32);
-                }
-                WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_rows, lines), .. } => {
-                    orbit_controls.process_mouse_scroll(lines * 3.0);
-                }
-                WindowEvent::KeyboardInput { input, .. } => {
-                    keep_running = process_input(input, &mut orbit_controls);
-                }
-                _ => ()
-            },
-            _ => ()
+    /// Loads each file and renders `frames` frames while rotating the camera once
+    /// around the scene. Load times include the GPU upload, frame times are measured
+    /// with `glFinish`, so vsync should be disabled.
+    pub fn benchmark(&mut self, files: &[&str], frames: u32) -> Vec<BenchmarkResult> {
+        let mut results = vec![];
+        for &file in files {
+            let start_time = Instant::now();
+            if let Err(err) = self.load_source(&*open_source(file), 0) {
+                error!("Loading {} failed, skipping it: {}", file, err);
+                continue
+            }
+            unsafe { gl::Finish() };
+            let load_time = start_time.elapsed();
+
+            let increment_angle = 2.0 * PI / frames as f32;
+            let mut frame_times = Vec::with_capacity(frames as usize);
+            for _ in 0..frames {
+                let frame_start = Instant::now();
+                self.orbit_controls.rotate_object(increment_angle);
+                self.draw();
+                unsafe { gl::Finish() };
+                frame_times.push(frame_start.elapsed());
+            }
+            results.push(BenchmarkResult { file: file.to_string(), load_time, frame_times });
         }
-    });
+        results
+    }
+}
+
+/// All features used by the renderer (e.g. GLSL 3.30, integer textures) are core in this
+/// version, so no extensions are needed on top. Instancing falls back to one draw call
+/// per instance if the driver doesn't expose it.
+const REQUIRED_GL_VERSION: (i32, i32) = (3, 3);
+
+/// Bytes of texture data uploaded per frame and model when streaming textures
+const TEXTURE_STREAMING_BUDGET: usize = 4 * 1024 * 1024;
+
+/// Upper limit for the frame delta time (seconds), so that motion doesn't jump
+/// after a stall (e.g. while the window is being dragged)
+const MAX_DELTA_TIME: f64 = 0.1;
+
+/// Size (meters) and number of cells of the grid shown for scenes without geometry
+const EMPTY_SCENE_GRID_SIZE: f32 = 10.0;
+const EMPTY_SCENE_GRID_CELLS: u32 = 10;
+
+/// `HttpSource` for http(s) URLs, `FileSource` for everything else
+fn open_source(source: &str) -> Box<dyn importdata::Source> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        Box::new(HttpSource::new(source))
+    } else {
+        Box::new(FileSource::new(source))
+    }
+}
 
-    keep_running
+/// Modification time of a file, `None` if it can't be read (e.g. while it is being replaced)
+fn file_modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
 }
 
-fn process_input(input: glutin::KeyboardInput, controls: &mut OrbitControls) -> bool {
-    let pressed = match input.state {
-        Pressed => true,
-        Released => false
-    };
-    if let Some(code) = input.virtual_keycode {
-        match code {
-            VirtualKeyCode::Escape if pressed => return false,
-            VirtualKeyCode::W | VirtualKeyCode::Up    => controls.process_keyboard(FORWARD, pressed),
-            VirtualKeyCode::S | VirtualKeyCode::Down  => controls.process_keyboard(BACKWARD, pressed),
-            VirtualKeyCode::A | VirtualKeyCode::Left  => controls.process_keyboard(LEFT, pressed),
-            VirtualKeyCode::D | VirtualKeyCode::Right => controls.process_keyboard(RIGHT, pressed),
-            _ => ()
-        }
-    }
-    true
+/// Converts the time since the last frame to seconds, capped at `MAX_DELTA_TIME`
+fn frame_delta_time(elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+    seconds.min(MAX_DELTA_TIME)
 }
@@ -0,0 +1,281 @@
+#[cfg(feature = "gui")]
+use std::os::raw::c_void;
+use std::process;
+use std::str::FromStr;
+
+use glutin::{
+    Api,
+    GlContext,
+    GlRequest,
+    GlProfile,
+    MouseButton,
+    MouseScrollDelta,
+    VirtualKeyCode,
+    WindowEvent,
+};
+use glutin::dpi::{PhysicalPosition, PhysicalSize};
+use glutin::ElementState::*;
+use log::error;
+
+use crate::framebuffer::Framebuffer;
+
+/// Window-system independent input, in physical pixels. The key and mouse button
+/// types are plain data (re-exported by glutin from winit), other backends map their
+/// own events onto them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    CloseRequested,
+    /// Size of the drawable area
+    Resized(PhysicalSize),
+    HiDpiFactorChanged(f64),
+    CursorMoved(PhysicalPosition),
+    MouseButton { button: MouseButton, pressed: bool },
+    /// Positive: zoom in
+    Scroll(f32),
+    Key { key: VirtualKeyCode, pressed: bool },
+    /// Typed text
+    Character(char),
+}
+
+/// Owner of the OpenGL context the viewer renders to: creates (or wraps) the context,
+/// loads the GL function pointers and delivers the input events. `GltfViewer::with_backend`
+/// accepts any implementation, e.g. for embedding the viewer in another application.
+pub trait WindowBackend {
+    /// Appends the events since the last call (called once per frame)
+    fn poll_events(&mut self, events: &mut Vec<InputEvent>);
+    /// Presents the frame
+    fn swap_buffers(&mut self);
+    fn set_title(&mut self, title: &str);
+    /// Size of the drawable area in physical pixels
+    fn size(&self) -> PhysicalSize;
+    fn dpi_factor(&self) -> f64;
+    /// Address of an OpenGL function of the context, for other GL loaders (the inspector panel)
+    #[cfg(feature = "gui")]
+    fn proc_address(&self, symbol: &str) -> *const c_void;
+}
+
+/// OpenGL context version and profile to request, parsed from e.g. `3.3-core` or `4.1-compat`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlContextOptions {
+    pub version: (u8, u8),
+    pub profile: GlProfile,
+}
+
+impl Default for GlContextOptions {
+    fn default() -> Self {
+        GlContextOptions { version: (3, 3), profile: GlProfile::Core }
+    }
+}
+
+impl FromStr for GlContextOptions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (version, profile) = match s.find('-') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, "core"),
+        };
+        let profile = match profile {
+            "core" => GlProfile::Core,
+            "compat" | "compatibility" => GlProfile::Compatibility,
+            _ => return Err(format!("unknown profile '{}' (expected core or compat)", profile)),
+        };
+        let parse = |n: &str| n.parse::<u8>().map_err(|_| format!("invalid version '{}' (expected e.g. 3.3)", version));
+        let version = match version.find('.') {
+            Some(i) => (parse(&version[..i])?, parse(&version[i + 1..])?),
+            None => return Err(format!("invalid version '{}' (expected e.g. 3.3)", version)),
+        };
+        Ok(GlContextOptions { version, profile })
+    }
+}
+
+impl GlContextOptions {
+    fn exit_on_error(&self, err: &dyn std::fmt::Display) -> ! {
+        error!("Failed to create an OpenGL {}.{} {:?} context: {}", self.version.0, self.version.1,
+            self.profile, err);
+        process::exit(5)
+    }
+}
+
+/// glutin window with an OpenGL context
+pub struct GlutinWindow {
+    events_loop: glutin::EventsLoop,
+    gl_window: glutin::GlWindow,
+    dpi_factor: f64,
+    size: PhysicalSize,
+}
+
+impl GlutinWindow {
+    pub fn new(width: u32, height: u32, visible: bool, vsync: bool, gl_context: Option<GlContextOptions>)
+        -> GlutinWindow
+    {
+        let requested_context = gl_context.unwrap_or_default();
+        let events_loop = glutin::EventsLoop::new();
+        let window_size = glutin::dpi::LogicalSize::new(width as f64, height as f64);
+
+        // TODO?: hints for 4.1, core profile, forward compat
+        let window = glutin::WindowBuilder::new()
+                .with_title("gltf-viewer")
+                .with_dimensions(window_size)
+                .with_visibility(visible);
+
+        let context = glutin::ContextBuilder::new()
+            .with_gl(GlRequest::Specific(Api::OpenGl, requested_context.version))
+            .with_gl_profile(requested_context.profile)
+            .with_vsync(vsync);
+        let gl_window = glutin::GlWindow::new(window, context, &events_loop)
+            .unwrap_or_else(|err| requested_context.exit_on_error(&err));
+
+        // Real dimensions might be much higher on High-DPI displays
+        let dpi_factor = gl_window.get_hidpi_factor();
+        let size = gl_window.get_inner_size().unwrap().to_physical(dpi_factor);
+
+        unsafe { gl_window.make_current().unwrap(); }
+
+        // gl: load all OpenGL function pointers
+        gl::load_with(|symbol| gl_window.get_proc_address(symbol) as *const _);
+
+        GlutinWindow { events_loop, gl_window, dpi_factor, size }
+    }
+}
+
+impl WindowBackend for GlutinWindow {
+    fn poll_events(&mut self, events: &mut Vec<InputEvent>) {
+        let gl_window = &self.gl_window;
+        let dpi_factor = &mut self.dpi_factor;
+        let size = &mut self.size;
+        #[allow(clippy::single_match)]
+        self.events_loop.poll_events(|event| {
+            match event {
+                glutin::Event::WindowEvent{ event, .. } => match event {
+                    WindowEvent::CloseRequested => events.push(InputEvent::CloseRequested),
+                    WindowEvent::Destroyed => {
+                        // Log and exit?
+                        panic!("WindowEvent::Destroyed, unimplemented.");
+                    },
+                    WindowEvent::Resized(logical) => {
+                        let ph = logical.to_physical(*dpi_factor);
+                        gl_window.resize(ph);
+                        *size = ph;
+                        events.push(InputEvent::Resized(ph));
+                    },
+                    WindowEvent::HiDpiFactorChanged(f) => {
+                        *dpi_factor = f;
+                        events.push(InputEvent::HiDpiFactorChanged(f));
+                    },
+                    WindowEvent::DroppedFile(_path_buf) => {
+                        // TODO: drag file in
+                    }
+                    WindowEvent::MouseInput { button, state, ..} => {
+                        events.push(InputEvent::MouseButton { button, pressed: state == Pressed });
+                    },
+                    WindowEvent::CursorMoved { position, .. } => {
+                        events.push(InputEvent::CursorMoved(position.to_physical(*dpi_factor)));
+                    },
+                    WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(logical), .. } => {
+                        let ph = logical.to_physical(*dpi_factor);
+                        events.push(InputEvent::Scroll(ph.y as f32));
+                    }
+                    WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_rows, lines), .. } => {
+                        events.push(InputEvent::Scroll(lines * 3.0));
+                    }
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        if let Some(key) = input.virtual_keycode {
+                            events.push(InputEvent::Key { key, pressed: input.state == Pressed });
+                        }
+                    }
+                    WindowEvent::ReceivedCharacter(c) => events.push(InputEvent::Character(c)),
+                    _ => ()
+                },
+                _ => ()
+            }
+        });
+    }
+
+    fn swap_buffers(&mut self) {
+        self.gl_window.swap_buffers().unwrap();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.gl_window.set_title(title);
+    }
+
+    fn size(&self) -> PhysicalSize {
+        self.size
+    }
+
+    fn dpi_factor(&self) -> f64 {
+        self.dpi_factor
+    }
+
+    #[cfg(feature = "gui")]
+    fn proc_address(&self, symbol: &str) -> *const c_void {
+        self.gl_window.get_proc_address(symbol) as *const _
+    }
+}
+
+/// glutin headless context rendering to an offscreen framebuffer (no events)
+pub struct GlutinHeadless {
+    _context: glutin::HeadlessContext,
+    _framebuffer: Framebuffer,
+    width: u32,
+    height: u32,
+}
+
+impl GlutinHeadless {
+    pub fn new(width: u32, height: u32, gl_context: Option<GlContextOptions>) -> GlutinHeadless {
+        let requested_context = gl_context.unwrap_or_default();
+        let mut builder = glutin::HeadlessRendererBuilder::new(width, height);
+        // only with an explicit request, the defaults don't work everywhere for headless contexts
+        if gl_context.is_some() {
+            builder = builder
+                .with_gl(GlRequest::Specific(Api::OpenGl, requested_context.version))
+                .with_gl_profile(requested_context.profile);
+        }
+        let context = builder.build()
+            .unwrap_or_else(|err| requested_context.exit_on_error(&err));
+        unsafe { context.make_current().unwrap() }
+        gl::load_with(|symbol| context.get_proc_address(symbol) as *const _);
+        let framebuffer = Framebuffer::new(width, height);
+        framebuffer.bind();
+        unsafe { gl::Viewport(0, 0, width as i32, height as i32); }
+
+        GlutinHeadless { _context: context, _framebuffer: framebuffer, width, height }
+    }
+}
+
+impl WindowBackend for GlutinHeadless {
+    fn poll_events(&mut self, _events: &mut Vec<InputEvent>) {}
+
+    fn swap_buffers(&mut self) {}
+
+    fn set_title(&mut self, _title: &str) {}
+
+    fn size(&self) -> PhysicalSize {
+        // TODO: real height (retina? (should be the same as PhysicalSize when headless?))
+        PhysicalSize::new(self.width as f64, self.height as f64)
+    }
+
+    fn dpi_factor(&self) -> f64 {
+        1.0
+    }
+
+    #[cfg(feature = "gui")]
+    fn proc_address(&self, symbol: &str) -> *const c_void {
+        self._context.get_proc_address(symbol) as *const _
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gl_context_options() {
+        assert_eq!("3.3-core".parse(), Ok(GlContextOptions::default()));
+        assert_eq!("4.1".parse(), Ok(GlContextOptions { version: (4, 1), profile: GlProfile::Core }));
+        assert_eq!("3.2-compat".parse(), Ok(GlContextOptions { version: (3, 2), profile: GlProfile::Compatibility }));
+        assert!("3.3-es".parse::<GlContextOptions>().is_err());
+        assert!("3-core".parse::<GlContextOptions>().is_err());
+    }
+}